@@ -0,0 +1,50 @@
+#![feature(test)]
+
+extern crate test;
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::{expression, Property};
+use domain_query::expression::Context;
+use domain_query::value::Value;
+use test::{black_box, Bencher};
+
+const RECORDS: usize = 1_000;
+
+fn records() -> Vec<Context<Property>> {
+    (0..RECORDS)
+        .map(|idx| {
+            Context::from_values(vec![
+                (Property::Listeners, Value::Int(idx as i64 % 400)),
+                (Property::Artist, Value::Str(format!("Radiohead #{}", idx))),
+                (Property::Released, Value::Bool(idx % 3 == 0)),
+            ])
+            .unwrap()
+        })
+        .collect()
+}
+
+#[bench]
+fn eval_loop(b: &mut Bencher) {
+    let (expr, records) = (expression(), records());
+    b.iter(|| {
+        let results = records
+            .iter()
+            .map(|context| expr.eval(context).unwrap())
+            .collect::<Vec<_>>();
+        black_box(results)
+    });
+}
+
+#[bench]
+fn eval_batch(b: &mut Bencher) {
+    let (expr, records) = (expression(), records());
+    b.iter(|| black_box(expr.eval_batch(&records).unwrap()));
+}
+
+#[bench]
+fn eval_batch_bool(b: &mut Bencher) {
+    let (expr, records) = (expression(), records());
+    b.iter(|| black_box(expr.eval_batch_bool(&records).unwrap()));
+}
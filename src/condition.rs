@@ -1,11 +1,46 @@
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashSet};
 use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::hash::{Hash, Hasher};
+use std::iter::FromIterator;
 
 use super::domain::Property;
-use super::error::Result;
-use super::value::Value;
+use super::error::{Error, Result};
+use super::value::{Datatype, Value};
 
-#[derive(Debug, Clone)]
+/// Validates an evaluated value, converting it into the property datatype only if it differs.
+fn coerced<Pid: Property>(variable: Pid, actual: &Value) -> Result<Cow<'_, Value>> {
+    if variable.datatype() == actual.datatype() {
+        variable.validate(actual)?;
+        Ok(Cow::Borrowed(actual))
+    } else {
+        variable.coerce(actual.clone()).map(Cow::Owned)
+    }
+}
+
+/// Validates an expected value, placeholders are validated once they're bound.
+fn validated<Pid: Property>(variable: Pid, expected: Value) -> Result<Value> {
+    match expected {
+        Value::Param(_) => Ok(expected),
+        expected => variable.coerce(expected),
+    }
+}
+
+/// Same as `validated`, but values that don't pass are kept as is, see `Is::relaxed`.
+fn relaxed<Pid: Property>(variable: Pid, expected: Value) -> Value {
+    validated(variable, expected.clone()).unwrap_or(expected)
+}
+
+/// Validates an expected value kept as is by a relaxed constructor.
+fn checked<Pid: Property>(variable: Pid, expected: &Value) -> Result<()> {
+    match expected {
+        Value::Param(_) => Ok(()),
+        expected => variable.validate(expected),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Is<Pid: Property> {
     variable: Pid,
     expected: Value,
@@ -19,30 +54,262 @@ impl<Pid: Property> Display for Is<Pid> {
             self.variable.name(),
             self.variable.datatype(),
             self.expected
-        )
+        )?;
+        match self.variable.unit() {
+            Some(unit) => write!(f, " {}", unit),
+            None => Ok(()),
+        }
     }
 }
 
 impl<Pid: Property> Is<Pid> {
     pub fn new(variable: Pid, expected: Value) -> Result<Self> {
-        variable.validate(&expected)?;
+        let expected = validated(variable, expected)?;
         Ok(Is { variable, expected })
     }
 
+    /// Same as `new`, but a value that doesn't match the property is kept as is instead
+    /// of failing, `check` reports it later.
+    pub fn relaxed(variable: Pid, expected: Value) -> Self {
+        let expected = relaxed(variable, expected);
+        Is { variable, expected }
+    }
+
+    /// Fails if the expected value doesn't match the property, see `relaxed`.
+    pub fn check(&self) -> Result<()> {
+        checked(self.variable, &self.expected)
+    }
+
     pub fn variable(&self) -> Pid {
         self.variable
     }
 
+    pub fn expected(&self) -> &Value {
+        &self.expected
+    }
+
     pub fn eval(&self, actual: &Value) -> Result<bool> {
-        self.variable.validate(&actual)?;
-        Ok(&self.expected == actual)
+        let actual = coerced(self.variable, actual)?;
+        Ok(self.expected == *actual)
+    }
+
+    /// Equivalent `In` with the expected value as the only element.
+    pub fn into_in(self) -> In<Pid> {
+        let expected = ValueSet::Hashed(Some(self.expected).into_iter().collect());
+        In { variable: self.variable, expected }
+    }
+}
+
+/// Condition-level inequality, unlike negated `Is` it stays a single leaf condition,
+/// so its semantics for missing values can differ from `!(x == value)`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IsNot<Pid: Property> {
+    variable: Pid,
+    expected: Value,
+}
+
+impl<Pid: Property> Display for IsNot<Pid> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(
+            f,
+            "{0} ({1}) != {2}",
+            self.variable.name(),
+            self.variable.datatype(),
+            self.expected
+        )?;
+        match self.variable.unit() {
+            Some(unit) => write!(f, " {}", unit),
+            None => Ok(()),
+        }
     }
 }
 
+impl<Pid: Property> IsNot<Pid> {
+    pub fn new(variable: Pid, expected: Value) -> Result<Self> {
+        let expected = validated(variable, expected)?;
+        Ok(IsNot { variable, expected })
+    }
+
+    /// Same as `Is::relaxed`.
+    pub fn relaxed(variable: Pid, expected: Value) -> Self {
+        let expected = relaxed(variable, expected);
+        IsNot { variable, expected }
+    }
+
+    /// Same as `Is::check`.
+    pub fn check(&self) -> Result<()> {
+        checked(self.variable, &self.expected)
+    }
+
+    pub fn variable(&self) -> Pid {
+        self.variable
+    }
+
+    pub fn expected(&self) -> &Value {
+        &self.expected
+    }
+
+    pub fn eval(&self, actual: &Value) -> Result<bool> {
+        let actual = coerced(self.variable, actual)?;
+        Ok(self.expected != *actual)
+    }
+}
+
+/// Float equality within an absolute tolerance: `|actual - expected| <= epsilon`,
+/// only applicable to `Datatype::Float` properties.
 #[derive(Debug, Clone)]
+pub struct ApproxIs<Pid: Property> {
+    variable: Pid,
+    expected: f64,
+    epsilon: f64,
+}
+
+impl<Pid: Property> ApproxIs<Pid> {
+    /// Fields compared and hashed as values, see `Value` for how floats compare.
+    fn key(&self) -> (Pid, Value, Value) {
+        (self.variable, Value::Float(self.expected), Value::Float(self.epsilon))
+    }
+}
+
+impl<Pid: Property> PartialEq for ApproxIs<Pid> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl<Pid: Property> Eq for ApproxIs<Pid> {}
+
+impl<Pid: Property> Hash for ApproxIs<Pid> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+impl<Pid: Property> Display for ApproxIs<Pid> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(
+            f,
+            "{0} ({1}) ≈ {2} ±{3}",
+            self.variable.name(),
+            self.variable.datatype(),
+            self.expected,
+            self.epsilon
+        )?;
+        match self.variable.unit() {
+            Some(unit) => write!(f, " {}", unit),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<Pid: Property> ApproxIs<Pid> {
+    /// Fails with `Error::TypeMismatch` for properties that aren't `Datatype::Float`.
+    /// A negative or NaN `epsilon` matches nothing.
+    pub fn new(variable: Pid, expected: f64, epsilon: f64) -> Result<Self> {
+        variable.validate(&Value::Float(expected))?;
+        Ok(ApproxIs { variable, expected, epsilon })
+    }
+
+    pub fn variable(&self) -> Pid {
+        self.variable
+    }
+
+    pub fn expected(&self) -> f64 {
+        self.expected
+    }
+
+    pub fn epsilon(&self) -> f64 {
+        self.epsilon
+    }
+
+    /// Closed range of matching values for exporters, clamped to finite floats,
+    /// `None` if nothing matches.
+    pub fn bounds(&self) -> Option<(f64, f64)> {
+        if self.expected.is_finite() && self.epsilon >= 0.0 {
+            let low = (self.expected - self.epsilon).max(f64::MIN);
+            let high = (self.expected + self.epsilon).min(f64::MAX);
+            Some((low, high))
+        } else {
+            None
+        }
+    }
+
+    pub fn eval(&self, actual: &Value) -> Result<bool> {
+        let actual = coerced(self.variable, actual)?;
+        match *actual {
+            Value::Float(actual) => Ok((actual - self.expected).abs() <= self.epsilon),
+            ref actual => Err(Error::TypeMismatch(
+                self.variable.name(),
+                Datatype::Float,
+                actual.datatype(),
+            )),
+        }
+    }
+}
+
+/// Expected values of `In`: a hash set, or a sorted vector searched with binary search,
+/// which takes less memory for large sets, see `In::sorted`. Both compare as sets.
+#[derive(Debug, Clone)]
+pub enum ValueSet {
+    Hashed(HashSet<Value>),
+    Sorted(Vec<Value>),
+}
+
+impl ValueSet {
+    pub fn len(&self) -> usize {
+        match self {
+            ValueSet::Hashed(values) => values.len(),
+            ValueSet::Sorted(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains(&self, value: &Value) -> bool {
+        match self {
+            ValueSet::Hashed(values) => values.contains(value),
+            ValueSet::Sorted(values) => values.binary_search(value).is_ok(),
+        }
+    }
+
+    /// Values in unspecified order for hashed sets, ascending for sorted ones.
+    pub fn iter(&self) -> impl Iterator<Item = &Value> {
+        let (hashed, sorted) = match self {
+            ValueSet::Hashed(values) => (Some(values.iter()), None),
+            ValueSet::Sorted(values) => (None, Some(values.iter())),
+        };
+        hashed.into_iter().flatten().chain(sorted.into_iter().flatten())
+    }
+
+    /// Set of the same kind with every value replaced, sorted sets need to be sorted again.
+    pub fn map<F: FnMut(&Value) -> Value>(&self, f: F) -> ValueSet {
+        match self {
+            ValueSet::Hashed(_) => ValueSet::Hashed(self.iter().map(f).collect()),
+            ValueSet::Sorted(_) => ValueSet::Sorted(self.iter().map(f).collect()),
+        }
+    }
+}
+
+impl PartialEq for ValueSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|value| other.contains(value))
+    }
+}
+
+impl Eq for ValueSet {}
+
+impl FromIterator<Value> for ValueSet {
+    fn from_iter<I: IntoIterator<Item = Value>>(iter: I) -> Self {
+        ValueSet::Hashed(iter.into_iter().collect())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct In<Pid: Property> {
     variable: Pid,
-    expected: HashSet<Value>,
+    expected: ValueSet,
 }
 
 impl<Pid: Property> Display for In<Pid> {
@@ -54,37 +321,389 @@ impl<Pid: Property> Display for In<Pid> {
             self.variable.datatype()
         )?;
 
-        for item in &self.expected {
-            write!(f, "{}, ", item)?;
+        // sets don't keep any particular order, sort values to make the output stable
+        let mut values = self.expected.iter().collect::<Vec<_>>();
+        values.sort();
+
+        for (idx, item) in values.into_iter().enumerate() {
+            if idx > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", item)?;
         }
         write!(f, "]")
     }
 }
 
+/// Set iteration order is unspecified, so element hashes are combined in an order-independent way.
+impl<Pid: Property> Hash for In<Pid> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.variable.hash(state);
+        let combined = self.expected.iter().fold(0u64, |acc, item| {
+            let mut hasher = DefaultHasher::new();
+            item.hash(&mut hasher);
+            acc.wrapping_add(hasher.finish())
+        });
+        state.write_u64(combined);
+    }
+}
+
 impl<Pid: Property> In<Pid> {
     pub fn new(variable: Pid, expected: HashSet<Value>) -> Result<Self> {
-        for item in expected.iter() {
-            variable.validate(&item)?;
-        }
+        In::from_set(variable, ValueSet::Hashed(expected))
+    }
+
+    /// Same as `new`, but values are kept in a sorted vector and looked up with binary search,
+    /// which is preferred for large sets, e.g. thousands of values loaded from a file.
+    pub fn sorted<I: IntoIterator<Item = Value>>(variable: Pid, expected: I) -> Result<Self> {
+        In::from_set(variable, ValueSet::Sorted(expected.into_iter().collect()))
+    }
+
+    /// Same as `new`, keeping the kind of the set. Sorted ones don't have to be sorted yet.
+    pub fn from_set(variable: Pid, expected: ValueSet) -> Result<Self> {
+        let expected = match expected {
+            ValueSet::Hashed(values) => {
+                let values = values.into_iter().map(|item| validated(variable, item));
+                ValueSet::Hashed(values.collect::<Result<_>>()?)
+            }
+            ValueSet::Sorted(values) => {
+                let values = values.into_iter().map(|item| validated(variable, item));
+                let mut values = values.collect::<Result<Vec<_>>>()?;
+                values.sort();
+                values.dedup();
+                ValueSet::Sorted(values)
+            }
+        };
         Ok(In { variable, expected })
     }
 
+    /// Same as `Is::relaxed`, for every value.
+    pub fn relaxed(variable: Pid, expected: HashSet<Value>) -> Self {
+        let expected = expected.into_iter().map(|item| relaxed(variable, item)).collect();
+        In { variable, expected }
+    }
+
+    /// Same as `Is::check`, for every value.
+    pub fn check(&self) -> Result<()> {
+        self.expected.iter().try_for_each(|item| checked(self.variable, item))
+    }
+
     pub fn variable(&self) -> Pid {
         self.variable
     }
 
+    pub fn expected(&self) -> &ValueSet {
+        &self.expected
+    }
+
+    /// Equivalent `Is` if the set has exactly one value.
+    pub fn as_is(&self) -> Option<Is<Pid>> {
+        match self.expected.iter().next() {
+            Some(expected) if self.expected.len() == 1 => {
+                Some(Is { variable: self.variable, expected: expected.clone() })
+            }
+            _ => None,
+        }
+    }
+
     pub fn eval(&self, actual: &Value) -> Result<bool> {
-        self.variable.validate(&actual)?;
+        let actual = coerced(self.variable, actual)?;
         Ok(self.expected.contains(&actual))
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Contains<Pid: Property> {
+    variable: Pid,
+    needle: String,
+    case_insensitive: bool,
+}
+
+impl<Pid: Property> Display for Contains<Pid> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(
+            f,
+            "{0} ({1}) {2} '{3}'",
+            self.variable.name(),
+            self.variable.datatype(),
+            if self.case_insensitive { "contains_ci" } else { "contains" },
+            self.needle
+        )
+    }
+}
+
+impl<Pid: Property> Contains<Pid> {
+    /// Substring condition, only applicable to `Datatype::Str` properties.
+    pub fn new(variable: Pid, needle: String, case_insensitive: bool) -> Result<Self> {
+        variable.validate(&Value::Str(String::new()))?;
+        Ok(Contains { variable, needle, case_insensitive })
+    }
+
+    pub fn variable(&self) -> Pid {
+        self.variable
+    }
+
+    pub fn needle(&self) -> &str {
+        &self.needle
+    }
+
+    pub fn case_insensitive(&self) -> bool {
+        self.case_insensitive
+    }
+
+    pub fn eval(&self, actual: &Value) -> Result<bool> {
+        self.variable.validate(actual)?;
+        match actual.as_str() {
+            Some(actual) if self.case_insensitive => {
+                Ok(actual.to_lowercase().contains(&self.needle.to_lowercase()))
+            }
+            Some(actual) => Ok(actual.contains(&self.needle)),
+            None => Err(Error::TypeMismatch(self.variable.name(), Datatype::Str, actual.datatype())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Quantifier {
+    /// At least one element matches, false for empty lists.
+    Any,
+    /// Every element matches, true for empty lists.
+    All,
+}
+
+impl Display for Quantifier {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Quantifier::Any => write!(f, "any"),
+            Quantifier::All => write!(f, "all"),
+        }
+    }
+}
+
+/// Condition checked against every element of a list, see `Quantified`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Predicate {
+    Is(Value),
+    In(BTreeSet<Value>),
+    /// Needle and whether the match is case-insensitive, only applicable to `Str` elements.
+    Contains(String, bool),
+}
+
+impl Display for Predicate {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Predicate::Is(expected) => write!(f, "== {}", expected),
+            Predicate::In(expected) => {
+                write!(f, "in [")?;
+                for (idx, item) in expected.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Predicate::Contains(needle, false) => write!(f, "contains '{}'", needle),
+            Predicate::Contains(needle, true) => write!(f, "contains_ci '{}'", needle),
+        }
+    }
+}
+
+impl Predicate {
+    fn eval(&self, element: &Value) -> bool {
+        match (self, element.as_str()) {
+            (Predicate::Is(expected), _) => expected == element,
+            (Predicate::In(expected), _) => expected.contains(element),
+            (Predicate::Contains(needle, true), Some(element)) => {
+                element.to_lowercase().contains(&needle.to_lowercase())
+            }
+            (Predicate::Contains(needle, false), Some(element)) => element.contains(needle),
+            (Predicate::Contains(_, _), _) => false,
+        }
+    }
+}
+
+/// Condition on a list-valued property: `predicate` has to hold for any or all of its elements.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Quantified<Pid: Property> {
+    variable: Pid,
+    quantifier: Quantifier,
+    predicate: Predicate,
+}
+
+impl<Pid: Property> Display for Quantified<Pid> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(
+            f,
+            "{0} ({1}) {2}({3})",
+            self.variable.name(),
+            self.variable.datatype(),
+            self.quantifier,
+            self.predicate
+        )
+    }
+}
+
+impl<Pid: Property> Quantified<Pid> {
+    /// Only applicable to `Datatype::List` properties, predicate values have to be
+    /// of the element datatype.
+    pub fn new(variable: Pid, quantifier: Quantifier, predicate: Predicate) -> Result<Self> {
+        let element = match variable.datatype() {
+            Datatype::List(element) => *element,
+            // scalar properties are reported as expecting a list of their own datatype
+            datatype => {
+                let expected = Datatype::List(datatype.scalar().unwrap_or(&Datatype::Str));
+                return Err(Error::TypeMismatch(variable.name(), datatype, expected));
+            }
+        };
+
+        let provided = match &predicate {
+            Predicate::Is(expected) => Some(expected.datatype()),
+            Predicate::In(expected) => expected.iter().map(Value::datatype).find(|dt| *dt != element),
+            Predicate::Contains(_, _) => Some(Datatype::Str),
+        };
+        match provided {
+            Some(provided) if provided != element => {
+                Err(Error::TypeMismatch(variable.name(), element, provided))
+            }
+            _ => Ok(Quantified { variable, quantifier, predicate }),
+        }
+    }
+
+    pub fn variable(&self) -> Pid {
+        self.variable
+    }
+
+    pub fn quantifier(&self) -> Quantifier {
+        self.quantifier
+    }
+
+    pub fn predicate(&self) -> &Predicate {
+        &self.predicate
+    }
+
+    pub fn eval(&self, actual: &Value) -> Result<bool> {
+        self.variable.validate(actual)?;
+        let items = match actual {
+            Value::List(_, items) => items,
+            _ => {
+                let datatype = self.variable.datatype();
+                return Err(Error::TypeMismatch(self.variable.name(), datatype, actual.datatype()));
+            }
+        };
+        match self.quantifier {
+            Quantifier::Any => Ok(items.iter().any(|item| self.predicate.eval(item))),
+            Quantifier::All => Ok(items.iter().all(|item| self.predicate.eval(item))),
+        }
+    }
+}
+
+// Properties are serialized by their `Debug` form, which for plain enums is the variant name
+// accepted by strum's `EnumString`, so the format doesn't depend on variant order.
+#[cfg(feature = "serde")]
+mod ser {
+    use serde::ser::{SerializeMap, Serializer};
+    use serde::Serialize;
+
+    use super::{ApproxIs, Contains, In, Is, IsNot, Predicate, Quantified};
+    use crate::domain::Property;
+
+    impl<Pid: Property> Is<Pid> {
+        pub(crate) fn serialize_fields<M>(&self, map: &mut M) -> Result<(), M::Error>
+        where
+            M: SerializeMap,
+        {
+            map.serialize_entry("prop", &format!("{:?}", self.variable))?;
+            map.serialize_entry("value", &self.expected)
+        }
+    }
+
+    impl<Pid: Property> IsNot<Pid> {
+        pub(crate) fn serialize_fields<M>(&self, map: &mut M) -> Result<(), M::Error>
+        where
+            M: SerializeMap,
+        {
+            map.serialize_entry("prop", &format!("{:?}", self.variable))?;
+            map.serialize_entry("value", &self.expected)
+        }
+    }
+
+    impl<Pid: Property> ApproxIs<Pid> {
+        pub(crate) fn serialize_fields<M>(&self, map: &mut M) -> Result<(), M::Error>
+        where
+            M: SerializeMap,
+        {
+            map.serialize_entry("prop", &format!("{:?}", self.variable))?;
+            map.serialize_entry("value", &self.expected)?;
+            map.serialize_entry("epsilon", &self.epsilon)
+        }
+    }
+
+    impl<Pid: Property> In<Pid> {
+        /// Values are sorted to keep the output stable.
+        pub(crate) fn serialize_fields<M>(&self, map: &mut M) -> Result<(), M::Error>
+        where
+            M: SerializeMap,
+        {
+            let mut values = self.expected.iter().collect::<Vec<_>>();
+            values.sort();
+
+            map.serialize_entry("prop", &format!("{:?}", self.variable))?;
+            map.serialize_entry("values", &values)
+        }
+    }
+
+    impl<Pid: Property> Contains<Pid> {
+        pub(crate) fn serialize_fields<M>(&self, map: &mut M) -> Result<(), M::Error>
+        where
+            M: SerializeMap,
+        {
+            map.serialize_entry("prop", &format!("{:?}", self.variable))?;
+            map.serialize_entry("needle", &self.needle)?;
+            map.serialize_entry("case_insensitive", &self.case_insensitive)
+        }
+    }
+
+    macro_rules! serialize_condition {
+        ($($cond:ident),*) => {$(
+            impl<Pid: Property> Serialize for $cond<Pid> {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    let mut map = serializer.serialize_map(None)?;
+                    self.serialize_fields(&mut map)?;
+                    map.end()
+                }
+            }
+        )*};
+    }
+
+    impl<Pid: Property> Quantified<Pid> {
+        /// Predicate fields are the same as those of the matching scalar condition.
+        pub(crate) fn serialize_fields<M>(&self, map: &mut M) -> Result<(), M::Error>
+        where
+            M: SerializeMap,
+        {
+            map.serialize_entry("prop", &format!("{:?}", self.variable))?;
+            map.serialize_entry("quantifier", &self.quantifier.to_string())?;
+            match &self.predicate {
+                Predicate::Is(expected) => map.serialize_entry("value", expected),
+                Predicate::In(expected) => map.serialize_entry("values", expected),
+                Predicate::Contains(needle, case_insensitive) => {
+                    map.serialize_entry("needle", needle)?;
+                    map.serialize_entry("case_insensitive", case_insensitive)
+                }
+            }
+        }
+    }
+
+    serialize_condition!(Is, IsNot, In, Contains, Quantified);
+}
+
 #[cfg(test)]
 mod test {
 
     use super::*;
     use crate::{error, value};
-    use crate::testproperty::Property;
+    use crate::testproperty::{Coercible, Listed, Measured, Property, Rated};
 
     #[test]
     fn is_positive() {
@@ -119,6 +738,129 @@ mod test {
         ));
     }
 
+    #[test]
+    fn is_uint() {
+        let is = Is::<Property>::new(Property::UInt, Value::UInt(u64::MAX)).unwrap();
+        assert!(is.eval(&Value::UInt(u64::MAX)).unwrap());
+        assert!(!is.eval(&Value::UInt(42)).unwrap());
+        assert_eq!(format!("{}", is), "Property::UInt (UInt) == 18446744073709551615");
+    }
+
+    #[test]
+    fn is_uint_int_mismatch() {
+        let is = Is::<Property>::new(Property::Int, Value::UInt(42));
+        assert!(matches!(
+            is.unwrap_err(),
+            error::Error::TypeMismatch("Property::Int", value::Datatype::Int, value::Datatype::UInt)
+        ));
+
+        let is = Is::<Property>::new(Property::UInt, Value::UInt(42)).unwrap();
+        assert!(matches!(
+            is.eval(&Value::Int(42)).unwrap_err(),
+            error::Error::TypeMismatch("Property::UInt", value::Datatype::UInt, value::Datatype::Int)
+        ));
+    }
+
+    #[test]
+    fn is_coerced() {
+        let is = Is::<Coercible>::new(Coercible::Plays, Value::Int(5)).unwrap();
+        assert_eq!(is.expected(), &Value::UInt(5));
+        assert!(is.eval(&Value::UInt(5)).unwrap());
+        assert!(is.eval(&Value::Int(5)).unwrap());
+        assert!(!is.eval(&Value::Bool(true)).unwrap());
+
+        let expected = vec![Value::Int(1), Value::UInt(1)].into_iter().collect();
+        let is = In::<Coercible>::new(Coercible::Plays, expected).unwrap();
+        assert_eq!(is.expected().len(), 1);
+        assert!(is.eval(&Value::Bool(true)).unwrap());
+    }
+
+    #[test]
+    fn is_coerced_lossy() {
+        let is = Is::<Coercible>::new(Coercible::Plays, Value::Int(-5));
+        assert!(matches!(
+            is.unwrap_err(),
+            error::Error::TypeMismatch("Coercible::Plays", value::Datatype::UInt, value::Datatype::Int)
+        ));
+
+        let is = Is::<Coercible>::new(Coercible::Plays, Value::UInt(5)).unwrap();
+        assert!(matches!(
+            is.eval(&Value::Str("5".to_owned())).unwrap_err(),
+            error::Error::TypeMismatch("Coercible::Plays", value::Datatype::UInt, value::Datatype::Str)
+        ));
+    }
+
+    #[test]
+    fn is_coercion_disabled() {
+        let is = Is::<Property>::new(Property::UInt, Value::Int(5));
+        assert!(matches!(
+            is.unwrap_err(),
+            error::Error::TypeMismatch("Property::UInt", value::Datatype::UInt, value::Datatype::Int)
+        ));
+        assert_eq!(Value::Int(5).coerce(value::Datatype::UInt), Some(Value::UInt(5)));
+    }
+
+    #[test]
+    fn is_not_positive() {
+        let is_not = IsNot::<Property>::new(Property::Int, Value::Int(42)).unwrap();
+        assert!(is_not.eval(&Value::Int(24)).unwrap());
+        assert!(!is_not.eval(&Value::Int(42)).unwrap());
+        assert_eq!(format!("{}", is_not), "Property::Int (Int) != 42");
+    }
+
+    #[test]
+    fn is_not_mismatch() {
+        let is_not = IsNot::<Property>::new(Property::Int, Value::Bool(false));
+        assert!(matches!(
+            is_not.unwrap_err(),
+            error::Error::TypeMismatch("Property::Int", value::Datatype::Int, value::Datatype::Bool)
+        ));
+
+        let is_not = IsNot::<Property>::new(Property::Int, Value::Int(42)).unwrap();
+        assert!(matches!(
+            is_not.eval(&Value::Str("42".to_owned())).unwrap_err(),
+            error::Error::TypeMismatch("Property::Int", value::Datatype::Int, value::Datatype::Str)
+        ));
+    }
+
+    #[test]
+    fn approx_is_band() {
+        let approx = ApproxIs::<Rated>::new(Rated::Rating, 4.5, 0.1).unwrap();
+        assert!(approx.eval(&Value::Float(4.5)).unwrap());
+        assert!(approx.eval(&Value::Float(4.59)).unwrap());
+        assert!(approx.eval(&Value::Float(4.41)).unwrap());
+        assert!(!approx.eval(&Value::Float(4.61)).unwrap());
+        assert!(!approx.eval(&Value::Float(4.39)).unwrap());
+        assert!(!approx.eval(&Value::Float(f64::NAN)).unwrap());
+        assert_eq!(format!("{}", approx), "Rated::Rating (Float) ≈ 4.5 ±0.1");
+    }
+
+    #[test]
+    fn approx_is_mismatch() {
+        let approx = ApproxIs::<Property>::new(Property::Int, 4.5, 0.1);
+        assert!(matches!(
+            approx.unwrap_err(),
+            error::Error::TypeMismatch("Property::Int", value::Datatype::Int, value::Datatype::Float)
+        ));
+
+        let approx = ApproxIs::<Rated>::new(Rated::Rating, 4.5, 0.1).unwrap();
+        assert!(matches!(
+            approx.eval(&Value::Int(4)).unwrap_err(),
+            error::Error::TypeMismatch("Rated::Rating", value::Datatype::Float, value::Datatype::Int)
+        ));
+    }
+
+    #[test]
+    fn in_uint() {
+        let values = vec![Value::UInt(1), Value::UInt(u64::MAX)].into_iter().collect();
+        let is_in = In::<Property>::new(Property::UInt, values).unwrap();
+        assert!(is_in.eval(&Value::UInt(u64::MAX)).unwrap());
+        assert!(!is_in.eval(&Value::UInt(2)).unwrap());
+
+        let values = vec![Value::UInt(1), Value::Int(2)].into_iter().collect();
+        assert!(In::<Property>::new(Property::UInt, values).is_err());
+    }
+
     #[test]
     fn in_positive() {
         let values = vec![Value::Int(41), Value::Int(42)];
@@ -133,6 +875,72 @@ mod test {
         assert!(!isin.eval(&Value::Int(24)).unwrap());
     }
 
+    #[test]
+    fn in_display_sorted() {
+        let values = vec![
+            Value::Str("c".to_owned()),
+            Value::Str("a".to_owned()),
+            Value::Str("b".to_owned()),
+        ];
+        let isin = In::<Property>::new(Property::Str, values.into_iter().collect()).unwrap();
+        assert_eq!(format!("{}", isin), "Property::Str (Str) in [a, b, c]");
+
+        let values = vec![Value::Int(10), Value::Int(-1), Value::Int(2)];
+        let isin = In::<Property>::new(Property::Int, values.into_iter().collect()).unwrap();
+        assert_eq!(format!("{}", isin), "Property::Int (Int) in [-1, 2, 10]");
+
+        let isin = In::<Property>::new(Property::Int, HashSet::new()).unwrap();
+        assert_eq!(format!("{}", isin), "Property::Int (Int) in []");
+    }
+
+    #[test]
+    fn is_into_in() {
+        let is = Is::<Property>::new(Property::Int, Value::Int(42)).unwrap();
+        let isin = is.clone().into_in();
+        assert_eq!(isin.variable(), Property::Int);
+        assert_eq!(isin.expected().iter().collect::<Vec<_>>(), vec![&Value::Int(42)]);
+        assert!(isin.eval(&Value::Int(42)).unwrap());
+        assert!(!isin.eval(&Value::Int(24)).unwrap());
+        assert_eq!(isin.as_is(), Some(is));
+    }
+
+    #[test]
+    fn in_as_is() {
+        let values = vec![Value::Str("a".to_owned())].into_iter().collect();
+        let isin = In::<Property>::new(Property::Str, values).unwrap();
+        let expected = Is::<Property>::new(Property::Str, Value::Str("a".to_owned())).unwrap();
+        assert_eq!(isin.as_is(), Some(expected));
+
+        let values = vec![Value::Int(1), Value::Int(2)].into_iter().collect();
+        assert_eq!(In::<Property>::new(Property::Int, values).unwrap().as_is(), None);
+        assert_eq!(In::<Property>::new(Property::Int, HashSet::new()).unwrap().as_is(), None);
+    }
+
+    #[test]
+    fn in_sorted() {
+        let values = (0..300).map(|idx| Value::Int(idx * 7 % 1000)).collect::<Vec<_>>();
+        let hashed = In::<Property>::new(Property::Int, values.iter().cloned().collect()).unwrap();
+        let sorted = In::<Property>::sorted(Property::Int, values.into_iter().rev()).unwrap();
+        assert!(matches!(sorted.expected(), ValueSet::Sorted(_)));
+        assert_eq!(sorted, hashed);
+        assert_eq!(format!("{}", sorted), format!("{}", hashed));
+
+        for probe in -5..1005 {
+            let actual = Value::Int(probe);
+            assert_eq!(sorted.eval(&actual).unwrap(), hashed.eval(&actual).unwrap(), "{}", probe);
+        }
+        assert!(sorted.eval(&Value::Int(693)).unwrap());
+        assert!(!sorted.eval(&Value::Int(695)).unwrap());
+
+        let values = vec![Value::Int(2), Value::Int(1), Value::Int(2)];
+        let sorted = In::<Property>::sorted(Property::Int, values).unwrap();
+        assert_eq!(sorted.expected().iter().collect::<Vec<_>>(), vec![&Value::Int(1), &Value::Int(2)]);
+
+        let values = vec![Value::Int(1), Value::Str("2".to_owned())];
+        let result = In::<Property>::sorted(Property::Int, values);
+        assert!(matches!(result, Err(error::Error::TypeMismatch("Property::Int", _, _))));
+    }
+
     #[test]
     fn in_mismatch_new() {
         let values = vec![Value::Int(42), Value::Str("in".to_owned())];
@@ -157,4 +965,171 @@ mod test {
             error::Error::TypeMismatch("Property::Str", value::Datatype::Str, value::Datatype::Bool)
         ));
     }
+
+    #[test]
+    fn contains_positive() {
+        let contains = Contains::<Property>::new(Property::Str, "dio".to_owned(), false).unwrap();
+        assert!(contains.eval(&Value::Str("Radiohead".to_owned())).unwrap());
+    }
+
+    #[test]
+    fn contains_negative() {
+        let contains = Contains::<Property>::new(Property::Str, "DIO".to_owned(), false).unwrap();
+        assert!(!contains.eval(&Value::Str("Radiohead".to_owned())).unwrap());
+    }
+
+    #[test]
+    fn contains_case_insensitive() {
+        let contains = Contains::<Property>::new(Property::Str, "DIO".to_owned(), true).unwrap();
+        assert!(contains.eval(&Value::Str("Radiohead".to_owned())).unwrap());
+        assert_eq!(format!("{}", contains), "Property::Str (Str) contains_ci 'DIO'");
+    }
+
+    #[test]
+    fn contains_empty_needle() {
+        let contains = Contains::<Property>::new(Property::Str, String::new(), false).unwrap();
+        assert!(contains.eval(&Value::Str(String::new())).unwrap());
+        assert!(contains.eval(&Value::Str("Radiohead".to_owned())).unwrap());
+    }
+
+    #[test]
+    fn contains_mismatch_new() {
+        let contains = Contains::<Property>::new(Property::Int, "4".to_owned(), false);
+        assert!(contains.is_err());
+        assert!(matches!(
+            contains.unwrap_err(),
+            error::Error::TypeMismatch("Property::Int", value::Datatype::Int, value::Datatype::Str)
+        ));
+    }
+
+    #[test]
+    fn is_display_unit() {
+        let is = Is::<Measured>::new(Measured::Listeners, Value::Int(1000)).unwrap();
+        assert_eq!(format!("{}", is), "Measured::Listeners (Int) == 1000 listeners");
+
+        let is = Is::<Property>::new(Property::Int, Value::Int(1000)).unwrap();
+        assert_eq!(format!("{}", is), "Property::Int (Int) == 1000");
+    }
+
+    fn tags(items: &[&str]) -> Value {
+        Value::list(value::Datatype::Str, items.iter().copied()).unwrap()
+    }
+
+    #[test]
+    fn quantified_any() {
+        let predicate = Predicate::Is("live".into());
+        let any = Quantified::<Listed>::new(Listed::Tags, Quantifier::Any, predicate).unwrap();
+        assert!(any.eval(&tags(&["studio", "live"])).unwrap());
+        assert!(!any.eval(&tags(&["studio"])).unwrap());
+        assert_eq!(format!("{}", any), "Listed::Tags (List<Str>) any(== live)");
+    }
+
+    #[test]
+    fn quantified_all() {
+        let predicate = Predicate::Contains("ROCK".to_owned(), true);
+        let all = Quantified::<Listed>::new(Listed::Tags, Quantifier::All, predicate).unwrap();
+        assert!(all.eval(&tags(&["Rock", "post-rock"])).unwrap());
+        assert!(!all.eval(&tags(&["Rock", "jazz"])).unwrap());
+        assert_eq!(format!("{}", all), "Listed::Tags (List<Str>) all(contains_ci 'ROCK')");
+    }
+
+    #[test]
+    fn quantified_empty_list() {
+        let predicate = Predicate::In(vec!["live".into()].into_iter().collect());
+        let any = Quantified::<Listed>::new(Listed::Tags, Quantifier::Any, predicate.clone()).unwrap();
+        let all = Quantified::<Listed>::new(Listed::Tags, Quantifier::All, predicate).unwrap();
+        assert!(!any.eval(&tags(&[])).unwrap());
+        assert!(all.eval(&tags(&[])).unwrap());
+    }
+
+    #[test]
+    fn quantified_mismatch() {
+        let predicate = Predicate::Is("live".into());
+        let scalar = Quantified::<Property>::new(Property::Str, Quantifier::Any, predicate.clone());
+        assert!(matches!(
+            scalar.unwrap_err(),
+            error::Error::TypeMismatch(
+                "Property::Str",
+                value::Datatype::Str,
+                value::Datatype::List(&value::Datatype::Str)
+            )
+        ));
+
+        let element = Quantified::<Listed>::new(Listed::Tags, Quantifier::Any, Predicate::Is(Value::Int(1)));
+        assert!(matches!(
+            element.unwrap_err(),
+            error::Error::TypeMismatch("Listed::Tags", value::Datatype::Str, value::Datatype::Int)
+        ));
+
+        let any = Quantified::<Listed>::new(Listed::Tags, Quantifier::Any, predicate).unwrap();
+        let mixed = Value::List(&value::Datatype::Str, vec![Value::Int(1)]);
+        assert!(any.eval(&mixed).is_err());
+        assert!(any.eval(&Value::Str("live".to_owned())).is_err());
+    }
+
+    #[test]
+    fn value_parse() {
+        assert_eq!(Value::parse("42", Datatype::UInt), Some(Value::UInt(42)));
+        assert_eq!(Value::parse("42", Datatype::Str), Some(Value::from("42")));
+        assert_eq!(Value::parse("-1", Datatype::UInt), None);
+        assert_eq!(Value::parse("yes", Datatype::Bool), None);
+        assert_eq!(Value::parse("42", Datatype::List(&Datatype::Int)), None);
+    }
+
+    #[test]
+    fn value_infer() {
+        assert_eq!(Value::infer("true"), Value::Bool(true));
+        assert_eq!(Value::infer("false"), Value::Bool(false));
+        assert_eq!(Value::infer("42"), Value::Int(42));
+        assert_eq!(Value::infer("-7"), Value::Int(-7));
+        assert_eq!(Value::infer("42abc"), Value::from("42abc"));
+        assert_eq!(Value::infer("True"), Value::from("True"));
+        assert_eq!(Value::infer("18446744073709551615"), Value::from("18446744073709551615"));
+        assert_eq!(Value::infer(""), Value::from(""));
+    }
+
+    #[test]
+    fn interned_str() {
+        let mut interner = value::Interner::new();
+        let a = interner.intern("Rock 'n' Roll");
+        let b = interner.intern("Rock 'n' Roll");
+        assert_eq!(interner.len(), 1);
+        match (&a, &b) {
+            (Value::InternedStr(a), Value::InternedStr(b)) => assert!(std::sync::Arc::ptr_eq(a, b)),
+            _ => panic!("not interned: {:?}, {:?}", a, b),
+        }
+
+        // compared, ordered and hashed by content
+        let plain = Value::Str("Rock 'n' Roll".to_owned());
+        assert_eq!(a, b);
+        assert_eq!(a, plain);
+        assert_eq!(a.cmp(&plain), std::cmp::Ordering::Equal);
+        assert!(interner.intern("Pop") < plain);
+        assert_eq!(a.datatype(), Datatype::Str);
+        assert_eq!(a.to_string(), plain.to_string());
+        let set = vec![plain].into_iter().collect::<HashSet<_>>();
+        assert!(set.contains(&a));
+    }
+
+    #[test]
+    fn interned_str_eval() {
+        let mut interner = value::Interner::new();
+        let is = Is::<Property>::new(Property::Str, interner.intern("foo")).unwrap();
+        assert!(is.eval(&Value::Str("foo".to_owned())).unwrap());
+        let expected = vec![Value::from("foo"), Value::from("bar")].into_iter().collect();
+        let in_set = In::<Property>::new(Property::Str, expected).unwrap();
+        assert!(in_set.eval(&interner.intern("bar")).unwrap());
+        let contains = Contains::<Property>::new(Property::Str, "OO".to_owned(), true).unwrap();
+        assert!(contains.eval(&interner.intern("foo")).unwrap());
+
+        let tags = Value::List(&Datatype::Str, vec![interner.intern("live"), interner.intern("demo")]);
+        let any = Quantified::<Listed>::new(
+            Listed::Tags,
+            Quantifier::Any,
+            Predicate::Contains("emo".to_owned(), false),
+        )
+        .unwrap();
+        assert!(any.eval(&tags).unwrap());
+        assert_eq!(interner.len(), 4);
+    }
 }
@@ -14,18 +14,61 @@ pub trait Property: DomainEnum {
     fn name(&self) -> &'static str;
     fn datatype(&self) -> Datatype;
 
+    /// Human-readable label, e.g. for UIs. Defaults to `name()`.
+    fn label(&self) -> &'static str {
+        self.name()
+    }
+
+    /// Optional longer description of the property.
+    fn description(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Optional unit of measurement, e.g. `listeners` or `ms`. Only used for display.
+    fn unit(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Whether values of other datatypes are accepted if `Value::coerce` can convert them.
+    /// Defaults to `false`, i.e. strict validation.
+    fn coercible(&self) -> bool {
+        false
+    }
+
     fn validate(&self, value: &Value) -> Result<()> {
-        if self.datatype() != value.datatype() {
-            Err(Error::TypeMismatch(self.name(), self.datatype(), value.datatype()))
-        } else {
+        let matches = self.datatype() == value.datatype()
+            || self.coercible() && value.coerce(self.datatype()).is_some();
+        if matches && value.is_consistent() {
             Ok(())
+        } else {
+            Err(Error::TypeMismatch(self.name(), self.datatype(), value.datatype()))
+        }
+    }
+
+    /// Validates `value` and converts it into the property datatype.
+    fn coerce(&self, value: Value) -> Result<Value> {
+        self.validate(&value)?;
+        if self.datatype() == value.datatype() {
+            return Ok(value);
         }
+        value
+            .coerce(self.datatype())
+            .ok_or_else(|| Error::TypeMismatch(self.name(), self.datatype(), value.datatype()))
     }
 }
 
 pub trait Entity<Prop: Property>: DomainEnum {
     fn name(&self) -> &str;
     fn properties(&self) -> &[Prop];
+
+    /// References to other entities as `(relation name, target entity name)` pairs.
+    fn relations(&self) -> &[(&'static str, &'static str)] {
+        &[]
+    }
+
+    fn has_property(&self, property: &Prop) -> bool {
+        self.properties().contains(property)
+    }
 }
 
 pub trait Domain<Pid: Property, Eid: Entity<Pid>> {
@@ -48,4 +91,110 @@ impl<Pid: Property + FromStr<Err = ParseError>, Eid: Entity<Pid> + FromStr<Err =
     pub fn entity(name: &str) -> Result<Eid> {
         Ok(Eid::from_str(name)?)
     }
+
+    /// Resolves relation `name` declared by `entity` to the entity it references.
+    pub fn relation(entity: Eid, name: &str) -> Result<Eid> {
+        let (_, target) = entity
+            .relations()
+            .iter()
+            .find(|(relation, _)| *relation == name)
+            .ok_or(Error::IdentifierNotFound(ParseError::VariantNotFound))?;
+        Self::entity(target)
+    }
+}
+
+#[cfg(feature = "serde")]
+pub use self::schema::{schema, EntitySchema, PropertySchema, Schema};
+
+#[cfg(feature = "serde")]
+mod schema {
+    use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+    use super::{Entity, Property};
+    use crate::value::Datatype;
+
+    /// Property description, `id` is the identifier accepted by `FromStr` (the `Debug` form).
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct PropertySchema {
+        pub id: String,
+        pub name: &'static str,
+        pub datatype: Datatype,
+        pub label: &'static str,
+        pub description: Option<&'static str>,
+        pub unit: Option<&'static str>,
+    }
+
+    /// Entity description, properties are referenced by their ids.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct EntitySchema {
+        pub id: String,
+        pub name: String,
+        pub properties: Vec<String>,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct Schema {
+        pub entities: Vec<EntitySchema>,
+        pub properties: Vec<PropertySchema>,
+    }
+
+    impl Serialize for PropertySchema {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut state = serializer.serialize_struct("PropertySchema", 6)?;
+            state.serialize_field("id", &self.id)?;
+            state.serialize_field("name", self.name)?;
+            state.serialize_field("datatype", &self.datatype.to_string())?;
+            state.serialize_field("label", self.label)?;
+            state.serialize_field("description", &self.description)?;
+            state.serialize_field("unit", &self.unit)?;
+            state.end()
+        }
+    }
+
+    impl Serialize for EntitySchema {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut state = serializer.serialize_struct("EntitySchema", 3)?;
+            state.serialize_field("id", &self.id)?;
+            state.serialize_field("name", &self.name)?;
+            state.serialize_field("properties", &self.properties)?;
+            state.end()
+        }
+    }
+
+    impl Serialize for Schema {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut state = serializer.serialize_struct("Schema", 2)?;
+            state.serialize_field("entities", &self.entities)?;
+            state.serialize_field("properties", &self.properties)?;
+            state.end()
+        }
+    }
+
+    /// Describes every entity and property of the domain, in declaration order.
+    pub fn schema<Pid: Property, Eid: Entity<Pid>>() -> Schema {
+        let entities = Eid::iter()
+            .map(|entity| EntitySchema {
+                id: format!("{:?}", entity),
+                name: entity.name().to_owned(),
+                properties: entity
+                    .properties()
+                    .iter()
+                    .map(|prop| format!("{:?}", prop))
+                    .collect(),
+            })
+            .collect();
+
+        let properties = Pid::iter()
+            .map(|prop| PropertySchema {
+                id: format!("{:?}", prop),
+                name: prop.name(),
+                datatype: prop.datatype(),
+                label: prop.label(),
+                description: prop.description(),
+                unit: prop.unit(),
+            })
+            .collect();
+
+        Schema { entities, properties }
+    }
 }
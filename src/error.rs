@@ -13,17 +13,53 @@ pub enum Error {
     #[error("Property type mismatch: property '{0}' is {1}, but provided value is {2}")]
     TypeMismatch(&'static str, Datatype, Datatype),
 
+    #[error("Property '{0}' was not requested")]
+    PropertyNotRequested(&'static str),
+
+    #[error("Property '{0}' doesn't belong to entity '{1}'")]
+    PropertyNotInEntity(&'static str, String),
+
     #[error("Expression is empty")]
     ExpressionNoop,
 
-    #[error("Operation reference {0}/{1} is out of bounds; expression: {2}")]
+    #[error("Operation reference {0}/{1} is out of bounds; {2}")]
     ExpressionOutOfBounds(usize, usize, String),
 
-    #[error("Operation reference {0} is invalid: it points to a operation that's not defined yet ({1}); expression: {2}")]
+    #[error("Operation reference {0} is invalid: it points to a operation that's not defined yet ({1}); {2}")]
     ExpressionFutureReference(usize, usize, String),
 
     #[error("Expression is inconsistent: operation {0} ({1}) is not connected to the root ({2})")]
     ExpressionDisconnected(usize, String, String),
+
+    #[error("Expression is too large: {ops} operations, depth {depth}")]
+    ExpressionTooLarge { ops: usize, depth: usize },
+
+    #[error("Handles of different expression builders can't be combined")]
+    ExpressionForeignHandle,
+
+    #[error("Operation {0} has reference count {1}, but it's referenced {2} times")]
+    ExpressionRefCount(usize, usize, usize),
+
+    #[error("Operation {0} can't be mapped to the target properties: {1}")]
+    ExpressionRetarget(usize, #[source] Box<Error>),
+
+    #[error("Operation {0} can't be expressed in {1}: {2}")]
+    ExpressionUnsupported(usize, &'static str, String),
+
+    #[error("Parameter '{0}' is not bound")]
+    UnboundParameter(String),
+
+    #[error("Parameter '{0}' is not used in the expression")]
+    UnusedParameter(String),
+
+    #[error("Failed to parse expression at offset {offset}: expected {expected}, found {found}")]
+    ExpressionParse { offset: usize, expected: String, found: String },
+
+    #[error("Serialized expression is malformed: {0}")]
+    ExpressionMalformed(String),
+
+    #[error("JsonLogic construct is not supported: {0}")]
+    UnsupportedJsonLogic(String),
 }
 
 pub type Result<T> = StdResult<T, Error>;
@@ -1,10 +1,57 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
 
-use super::condition::{In, Is};
+use strum::ParseError;
+
+use super::condition::{ApproxIs, Contains, In, Is, IsNot, Predicate, Quantified, Quantifier};
 use super::domain::Property;
 use super::error::{Error, Result};
-use super::value::Value;
+use super::value::{Datatype, Value};
+
+pub mod builder;
+pub mod decisive;
+pub mod dot;
+#[cfg(feature = "serde")]
+pub mod elastic;
+pub mod entity;
+pub mod evaluator;
+pub mod incremental;
+pub mod intervals;
+#[cfg(feature = "serde")]
+pub mod jsonlogic;
+#[cfg(feature = "serde")]
+pub mod mongo;
+pub mod normal;
+pub mod odata;
+pub mod parse;
+pub mod partition;
+pub mod pretty;
+pub mod render;
+pub mod satisfiable;
+#[cfg(feature = "serde")]
+pub mod serdes;
+pub mod sexpr;
+pub mod simplify;
+pub mod sql;
+pub mod stats;
+pub mod tree;
+pub mod visit;
+pub mod witness;
+
+pub use self::builder::{ExprBuilder, Handle};
+pub use self::decisive::Decisiveness;
+pub use self::entity::EntityExpression;
+pub use self::evaluator::Evaluator;
+pub use self::incremental::IncrementalEval;
+pub use self::intervals::Infeasibility;
+pub use self::partition::Partitioned;
+pub use self::render::{Explanation, Outcome, RenderOptions};
+pub use self::satisfiable::{Classification, Equivalence, Satisfiability};
+pub use self::stats::EvalStats;
+pub use self::tree::Tree;
 
 #[derive(Debug)]
 pub struct Context<Pid: Property> {
@@ -24,20 +71,27 @@ impl<Pid: Property> Context<Pid> {
     where
         I: IntoIterator<Item = Pid>,
     {
-        return Context {
+        Context {
             requested: props.into_iter().collect(),
             provided: HashMap::new(),
-        };
+        }
     }
 
-    pub fn provide(&mut self, property: Pid, value: Value) -> Result<()> {
-        if property.datatype() != value.datatype() {
-            return Err(Error::TypeMismatch(
-                property.name(),
-                property.datatype(),
-                value.datatype(),
-            ));
+    pub fn from_values<I>(values: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = (Pid, Value)>,
+    {
+        let mut context = Context::empty();
+        for (property, value) in values {
+            let value = property.coerce(value)?;
+            context.requested.insert(property);
+            context.provided.insert(property, value);
         }
+        Ok(context)
+    }
+
+    pub fn provide(&mut self, property: Pid, value: Value) -> Result<()> {
+        let value = property.coerce(value)?;
 
         if let Some(requested) = self.requested.get(&property) {
             self.provided.insert(*requested, value);
@@ -46,6 +100,17 @@ impl<Pid: Property> Context<Pid> {
         Ok(())
     }
 
+    pub fn provide_strict(&mut self, property: Pid, value: Value) -> Result<()> {
+        let value = property.coerce(value)?;
+
+        if self.requested.contains(&property) {
+            self.provided.insert(property, value);
+            Ok(())
+        } else {
+            Err(Error::PropertyNotRequested(property.name()))
+        }
+    }
+
     pub fn requested(&self) -> impl Iterator<Item = &Pid> {
         self.requested.iter()
     }
@@ -57,63 +122,341 @@ impl<Pid: Property> Context<Pid> {
     pub fn value(&self, property: Pid) -> Option<&Value> {
         self.provided.get(&property)
     }
+
+    /// Captures provided values, so that hypothetical ones can be rolled back with `restore`.
+    pub fn snapshot(&self) -> ContextSnapshot<Pid> {
+        ContextSnapshot {
+            provided: self.provided.clone(),
+        }
+    }
+
+    /// Resets provided values to the ones captured by `snapshot`, requested properties are kept.
+    pub fn restore(&mut self, snapshot: ContextSnapshot<Pid>) {
+        self.provided = snapshot.provided;
+    }
+}
+
+/// Provided values of a `Context` at some point, see `Context::snapshot`.
+#[derive(Debug, Clone)]
+pub struct ContextSnapshot<Pid: Property> {
+    provided: HashMap<Pid, Value>,
 }
 
 impl<Pid: Property> Display for Context<Pid> {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
-        write!(
-            f,
-            "requested: {:?}, provided: {:?}",
-            self.requested, self.provided
-        )
+        // hash containers don't keep any particular order, sort by name to make the output stable
+        let mut requested = self
+            .requested
+            .iter()
+            .map(|prop| prop.name())
+            .collect::<Vec<_>>();
+        requested.sort_unstable();
+
+        let mut provided = self
+            .provided
+            .iter()
+            .map(|(prop, val)| (prop.name(), val))
+            .collect::<Vec<_>>();
+        provided.sort_unstable_by_key(|(name, _)| *name);
+
+        write!(f, "requested: [{}], provided: [", requested.join(", "))?;
+        for (idx, (name, val)) in provided.into_iter().enumerate() {
+            if idx > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{0}={1}", name, val)?;
+        }
+        write!(f, "]")
     }
 }
 
 pub type OpRef = usize;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Operation<Pid: Property> {
     Const(bool),
     Is(Is<Pid>),
+    IsNot(IsNot<Pid>),
+    ApproxIs(ApproxIs<Pid>),
     In(In<Pid>),
+    Contains(Contains<Pid>),
+    Quantified(Quantified<Pid>),
     Not(OpRef),
     Or(OpRef, OpRef),
     And(OpRef, OpRef),
+    /// `!(lhs && rhs)`
+    Nand(OpRef, OpRef),
+    /// `!(lhs || rhs)`
+    Nor(OpRef, OpRef),
+    Implies(OpRef, OpRef),
+    IfElse(OpRef, OpRef, OpRef),
+}
+
+impl<Pid: Property> Operation<Pid> {
+    /// References to the operations this one depends on, empty for leaf conditions.
+    pub fn operands(&self) -> Vec<OpRef> {
+        match *self {
+            Operation::Const(_)
+            | Operation::Is(_)
+            | Operation::IsNot(_)
+            | Operation::ApproxIs(_)
+            | Operation::In(_)
+            | Operation::Contains(_)
+            | Operation::Quantified(_) => Vec::new(),
+            Operation::Not(opref) => vec![opref],
+            Operation::Or(lhs, rhs)
+            | Operation::And(lhs, rhs)
+            | Operation::Nand(lhs, rhs)
+            | Operation::Nor(lhs, rhs)
+            | Operation::Implies(lhs, rhs) => vec![lhs, rhs],
+            Operation::IfElse(cond, then, otherwise) => vec![cond, then, otherwise],
+        }
+    }
+
+    pub fn is_condition(&self) -> bool {
+        match self {
+            Operation::Is(_)
+            | Operation::IsNot(_)
+            | Operation::ApproxIs(_)
+            | Operation::In(_)
+            | Operation::Contains(_)
+            | Operation::Quantified(_) => true,
+            Operation::Const(_)
+            | Operation::Not(_)
+            | Operation::Or(_, _)
+            | Operation::And(_, _)
+            | Operation::Nand(_, _)
+            | Operation::Nor(_, _)
+            | Operation::Implies(_, _)
+            | Operation::IfElse(_, _, _) => false,
+        }
+    }
+
+    /// Fails if a condition value doesn't match its property, see `Expression::relaxed`.
+    fn check(&self) -> Result<()> {
+        match self {
+            Operation::Is(cond) => cond.check(),
+            Operation::IsNot(cond) => cond.check(),
+            Operation::In(cond) => cond.check(),
+            _ => Ok(()),
+        }
+    }
+
+    /// The property deciding a condition, `None` for constants, logical operations
+    /// and empty `In` sets, which match nothing regardless of the value.
+    pub fn variable(&self) -> Option<Pid> {
+        match self {
+            Operation::Is(cond) => Some(cond.variable()),
+            Operation::IsNot(cond) => Some(cond.variable()),
+            Operation::ApproxIs(cond) => Some(cond.variable()),
+            Operation::In(cond) if cond.expected().is_empty() => None,
+            Operation::In(cond) => Some(cond.variable()),
+            Operation::Contains(cond) => Some(cond.variable()),
+            Operation::Quantified(cond) => Some(cond.variable()),
+            _ => None,
+        }
+    }
+
+    /// Rough cost of evaluating the operation alone: conditions cost more than constants, `In`
+    /// grows with the number of values, substring and list element checks cost the most.
+    /// Logical operations cost nothing by themselves. See `Expression::reorder_by_cost`.
+    pub fn default_cost(&self) -> u64 {
+        match self {
+            Operation::Const(_) => 0,
+            Operation::Is(_) | Operation::IsNot(_) | Operation::ApproxIs(_) => 1,
+            Operation::In(cond) => 1 + cond.expected().len() as u64,
+            Operation::Contains(_) => 8,
+            Operation::Quantified(_) => 16,
+            _ => 0,
+        }
+    }
+
+    fn remap<F: Fn(OpRef) -> OpRef>(&self, f: F) -> Self {
+        match *self {
+            Operation::Not(opref) => Operation::Not(f(opref)),
+            Operation::Or(lhs, rhs) => Operation::Or(f(lhs), f(rhs)),
+            Operation::And(lhs, rhs) => Operation::And(f(lhs), f(rhs)),
+            Operation::Nand(lhs, rhs) => Operation::Nand(f(lhs), f(rhs)),
+            Operation::Nor(lhs, rhs) => Operation::Nor(f(lhs), f(rhs)),
+            Operation::Implies(lhs, rhs) => Operation::Implies(f(lhs), f(rhs)),
+            Operation::IfElse(cond, then, otherwise) => {
+                Operation::IfElse(f(cond), f(then), f(otherwise))
+            }
+            _ => self.clone(),
+        }
+    }
+}
+
+pub type RefCount = usize;
+pub type Operations<Pid> = Vec<(Operation<Pid>, RefCount)>;
+
+/// What folding an operation needs next: one of its operands, or nothing as it's decided.
+enum Fold {
+    Operand(OpRef),
+    Decided(Option<bool>),
+}
+
+/// Operation on the `fold` stack waiting for its operands, with results of the ones
+/// folded so far in the order they were visited.
+struct Pending {
+    idx: OpRef,
+    results: [Option<bool>; 3],
+    len: usize,
+}
+
+/// Result of three-valued (Kleene) evaluation, see `Expression::eval_3vl`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Tri {
+    True,
+    False,
+    Unknown,
 }
 
-type RefCount = usize;
-type Operations<Pid> = Vec<(Operation<Pid>, RefCount)>;
+impl From<Option<bool>> for Tri {
+    fn from(result: Option<bool>) -> Self {
+        match result {
+            Some(true) => Tri::True,
+            Some(false) => Tri::False,
+            None => Tri::Unknown,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum Evaluated<Pid: Property> {
-    Fully(bool, Operations<Pid>),
+    /// Result, folded operations (see `log`) and operations skipped by short-circuiting.
+    Fully(bool, Operations<Pid>, Vec<OpRef>),
     Partially(Expression<Pid>),
 }
 
+impl<Pid: Property> Evaluated<Pid> {
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Evaluated::Fully(res, _, _) => Some(*res),
+            Evaluated::Partially(_) => None,
+        }
+    }
+
+    /// Operations after folding, where every evaluated one is replaced with its `Const` result.
+    /// Empty for expressions without conditions, their result is known without folding.
+    pub fn log(&self) -> Option<&[(Operation<Pid>, RefCount)]> {
+        match self {
+            Evaluated::Fully(_, ops, _) => Some(ops),
+            Evaluated::Partially(_) => None,
+        }
+    }
+
+    /// Operations that didn't have to be evaluated because the result was already decided
+    /// without them, in ascending order. They're left as is in the log.
+    pub fn skipped(&self) -> Option<&[OpRef]> {
+        match self {
+            Evaluated::Fully(_, _, skipped) => Some(skipped),
+            Evaluated::Partially(_) => None,
+        }
+    }
+
+    /// Properties whose values could still change the result: ones used by conditions in the
+    /// partial expression that undecided operations still depend on, e.g. not those under an `Or`
+    /// that's already `true` or in the branch of `IfElse` not taken. Empty when fully evaluated.
+    pub fn missing(&self) -> HashSet<Pid> {
+        let mut missing = HashSet::new();
+        let expr = match self {
+            Evaluated::Fully(_, _, _) => return missing,
+            Evaluated::Partially(expr) => expr,
+        };
+
+        let mut visited = vec![false; expr.ops.len()];
+        let mut pending = expr.root().into_iter().collect::<Vec<_>>();
+        while let Some(idx) = pending.pop() {
+            match visited.get_mut(idx) {
+                Some(seen) if !*seen => *seen = true,
+                _ => continue,
+            }
+            let op = &expr.ops[idx].0;
+            match *op {
+                Operation::IfElse(cond, then, otherwise) => match expr.ops[cond].0 {
+                    Operation::Const(true) => pending.push(then),
+                    Operation::Const(false) => pending.push(otherwise),
+                    _ => pending.extend(op.operands()),
+                },
+                _ => pending.extend(op.operands()),
+            }
+            missing.extend(op.variable());
+        }
+        missing
+    }
+
+    /// Properties of conditions left in the partial expression, i.e. not evaluated for lack
+    /// of a value, in order of their first condition. Unlike `missing` these include ones that
+    /// can't change the result anymore. Empty when fully evaluated.
+    pub fn unresolved_variables(&self) -> Vec<Pid> {
+        let mut unresolved = Vec::new();
+        if let Evaluated::Partially(expr) = self {
+            for variable in expr.ops.iter().filter_map(|(op, _)| op.variable()) {
+                if !unresolved.contains(&variable) {
+                    unresolved.push(variable);
+                }
+            }
+        }
+        unresolved
+    }
+
+    /// Continues a partial evaluation with values provided since, same as evaluating the partial
+    /// expression: operations folded so far stay as they are, even if `additional` has a new value
+    /// for their properties. Fully evaluated results are returned unchanged.
+    pub fn resume(self, additional: &Context<Pid>) -> Result<Evaluated<Pid>> {
+        match self {
+            Evaluated::Fully(_, _, _) => Ok(self),
+            Evaluated::Partially(expr) => {
+                let root = expr.root().ok_or(Error::ExpressionNoop)?;
+                expr.eval_from(root, additional)
+            }
+        }
+    }
+
+    pub fn into_expression(self) -> Option<Expression<Pid>> {
+        match self {
+            Evaluated::Fully(_, _, _) => None,
+            Evaluated::Partially(expr) => Some(expr),
+        }
+    }
+}
+
 impl<Pid: Property> Display for Evaluated<Pid> {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
         match &self {
-            &Evaluated::Fully(ref res, ref ops) => {
+            Evaluated::Fully(res, ops, skipped) => {
                 write!(f, "Fully evaluated to '{0}', log: [", res)?;
                 for (idx, op) in ops.iter().enumerate() {
                     if let (Operation::Const(res), _) = op {
                         write!(f, "{0}: {1}, ", idx, res)?;
+                    } else if skipped.contains(&idx) {
+                        write!(f, "<skipped>")?;
                     } else {
                         write!(f, "<unevaluated>")?;
                     }
                 }
                 write!(f, "]")
             }
-            &Evaluated::Partially(ref expr) => {
+            Evaluated::Partially(expr) => {
                 write!(f, "Partially evaluated, expresion: {}", expr)
             }
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Expression<Pid: Property> {
     ops: Operations<Pid>,
+    root: Option<OpRef>,
+    limits: Option<(usize, usize)>,
+    // values of conditions are validated on evaluation rather than by builders
+    relaxed: bool,
+    // condition builders return identical conditions pushed before instead of new ones
+    unique_conditions: bool,
+    // depths of the first operations, caught up with by `push` to check the depth limit
+    // without walking the whole expression, in-place changes drop the ones after them
+    depths: Vec<usize>,
 }
 
 impl<Pid: Property> Expression<Pid> {
@@ -124,37 +467,74 @@ impl<Pid: Property> Expression<Pid> {
             self.ops.len() - 1
         };
 
-        let rootref = root.unwrap_or(last);
+        let rootref = root.or(self.root).unwrap_or(last);
+
+        // consistent expressions are rendered by a visitor, the rest shows broken references;
+        // checked upfront, walk errors would display the expression again
+        let consistent = rootref < self.ops.len()
+            && self.ops[..=rootref]
+                .iter()
+                .enumerate()
+                .all(|(idx, (op, _))| op.operands().into_iter().all(|opref| opref < idx));
+        let mut printer = visit::Printer::new(self);
+        if consistent && self.visit_from(rootref, &mut printer).is_ok() {
+            return printer.finish();
+        }
+
+        // operands can only be displayed if they precede the operation, which also rules out cycles
+        let operand = |opref: OpRef| {
+            if opref < rootref {
+                self.display(Some(opref))
+            } else {
+                format!("<badref: {0}/{1}>", opref, rootref)
+            }
+        };
 
         if let Some(op) = self.ops.get(rootref) {
             match &op.0 {
                 &Operation::Const(val) => format!("{}", val),
-                &Operation::Is(ref cond) => format!("{}", cond),
-                &Operation::In(ref cond) => format!("{}", cond),
-                &Operation::Not(opref) => format!("!({})", self.display(Some(opref))),
-                &Operation::Or(lhs, rhs) => format!(
-                    "({0} || {1})",
-                    self.display(Some(lhs)),
-                    self.display(Some(rhs))
-                ),
-                &Operation::And(lhs, rhs) => format!(
-                    "({0} && {1})",
-                    self.display(Some(lhs)),
-                    self.display(Some(rhs))
+                Operation::Is(cond) => format!("{}", cond),
+                Operation::IsNot(cond) => format!("{}", cond),
+                Operation::ApproxIs(cond) => format!("{}", cond),
+                Operation::In(cond) => format!("{}", cond),
+                Operation::Contains(cond) => format!("{}", cond),
+                Operation::Quantified(cond) => format!("{}", cond),
+                &Operation::Not(opref) => format!("!({})", operand(opref)),
+                &Operation::Or(lhs, rhs) => format!("({0} || {1})", operand(lhs), operand(rhs)),
+                &Operation::And(lhs, rhs) => format!("({0} && {1})", operand(lhs), operand(rhs)),
+                &Operation::Nand(lhs, rhs) => format!("!({0} && {1})", operand(lhs), operand(rhs)),
+                &Operation::Nor(lhs, rhs) => format!("!({0} || {1})", operand(lhs), operand(rhs)),
+                &Operation::Implies(premise, conclusion) => {
+                    format!("({0} -> {1})", operand(premise), operand(conclusion))
+                }
+                &Operation::IfElse(cond, then, otherwise) => format!(
+                    "(if {0} then {1} else {2})",
+                    operand(cond),
+                    operand(then),
+                    operand(otherwise)
                 ),
             }
-        } else if rootref == 0 && self.ops.is_empty() {
-            format!("<empty>")
+        } else if self.ops.is_empty() {
+            "<empty>".to_string()
         } else {
             format!("<badref: {0}/{1}>", rootref, last)
         }
     }
 
+    /// The whole expression for error messages, which can't be displayed if it's empty.
+    fn summary(&self) -> String {
+        if self.ops.is_empty() {
+            "expression is empty".to_owned()
+        } else {
+            format!("expression: {}", self.display(None))
+        }
+    }
+
     fn last(&self) -> Result<OpRef> {
         if !self.ops.is_empty() {
             Ok(self.ops.len() - 1)
         } else {
-            Err(Error::ExpressionOutOfBounds(0, 0, self.display(None)))
+            Err(Error::ExpressionOutOfBounds(0, 0, self.summary()))
         }
     }
 
@@ -165,435 +545,4561 @@ impl<Pid: Property> Expression<Pid> {
             Err(Error::ExpressionOutOfBounds(
                 op,
                 self.last().unwrap_or(0),
-                self.display(None),
+                self.summary(),
             ))
         }
     }
 
+    fn reachable(&self, root: OpRef) -> Vec<bool> {
+        let mut reachable = vec![false; self.ops.len()];
+        if let Some(flag) = reachable.get_mut(root) {
+            *flag = true;
+        }
+
+        // operations only reference earlier ones, so a single backward pass is enough
+        for idx in (0..reachable.len()).rev() {
+            if reachable[idx] {
+                for opref in self.ops[idx].0.operands() {
+                    if let Some(flag) = reachable.get_mut(opref) {
+                        *flag = true;
+                    }
+                }
+            }
+        }
+        reachable
+    }
+
+    /// Recomputes reference counts from the operands of every operation, after operations were
+    /// edited or substituted without keeping the counts in sync, e.g. when `subexpression`
+    /// or `dedup` rebuild them. Out of bounds references are ignored, `validate` still
+    /// reports them.
+    pub fn rebuild_refcounts(&mut self) {
+        for op in self.ops.iter_mut() {
+            op.1 = 0;
+        }
+        for idx in 0..self.ops.len() {
+            for opref in self.ops[idx].0.operands() {
+                if let Some(op) = self.ops.get_mut(opref) {
+                    op.1 += 1;
+                }
+            }
+        }
+    }
+
+    /// Counts actual references to every operation, failing on out of bounds or future ones.
+    fn references(&self) -> Result<Vec<RefCount>> {
+        let mut refs = vec![0; self.ops.len()];
+        for (idx, op) in self.ops.iter().enumerate() {
+            for opref in op.0.operands() {
+                self.valid(opref)?;
+                if opref >= idx {
+                    return Err(Error::ExpressionFutureReference(opref, idx, self.summary()));
+                }
+                refs[opref] += 1;
+            }
+        }
+        Ok(refs)
+    }
+
     pub fn new() -> Expression<Pid> {
-        Expression { ops: Vec::new() }
+        Expression {
+            ops: Vec::new(),
+            root: None,
+            limits: None,
+            relaxed: false,
+            unique_conditions: false,
+            depths: Vec::new(),
+        }
     }
 
-    pub fn constant(&mut self, value: bool) -> Result<OpRef> {
-        self.ops.push((Operation::Const(value), 0));
-        Ok(self.last()?)
+    /// Creates an expression whose builders don't fail on condition values that don't match
+    /// the property datatype, for example when properties are only resolved at run time.
+    /// Values are converted where possible and kept as is otherwise, invalid ones are reported
+    /// by evaluation instead, for conditions reachable from the evaluated root.
+    ///
+    /// Mistakes surface later and further from their source, so expressions are strict
+    /// by default. Only `is`, `is_not` and `is_in` are relaxed, other conditions carry
+    /// no values and are still checked right away.
+    pub fn relaxed() -> Expression<Pid> {
+        Expression {
+            relaxed: true,
+            ..Expression::new()
+        }
     }
 
-    pub fn is(&mut self, variable: Pid, value: Value) -> Result<OpRef> {
-        let cond = Is::new(variable, value)?;
-        self.ops.push((Operation::Is(cond), 0));
-        Ok(self.last()?)
+    /// Whether the expression was created with `relaxed`.
+    pub fn is_relaxed(&self) -> bool {
+        self.relaxed
     }
 
-    pub fn is_in<I>(&mut self, variable: Pid, values: I) -> Result<OpRef>
-    where
-        I: IntoIterator<Item = Value>,
-    {
-        let cond = In::new(variable, values.into_iter().collect())?;
-        self.ops.push((Operation::In(cond), 0));
-        Ok(self.last()?)
+    /// Creates an expression whose condition builders return the reference of an identical
+    /// condition if there is one already rather than pushing a duplicate, see `dedup` to collapse
+    /// duplicates of any operation afterwards. Existing conditions are searched linearly.
+    pub fn new_dedup() -> Expression<Pid> {
+        Expression {
+            unique_conditions: true,
+            ..Expression::new()
+        }
     }
 
-    pub fn not(&mut self, opref: OpRef) -> Result<OpRef> {
-        self.ops.push((Operation::Not(self.valid(opref)?), 0));
-        self.ops[opref].1 += 1;
-        Ok(self.last()?)
+    /// Whether the expression was created with `new_dedup`.
+    pub fn is_dedup(&self) -> bool {
+        self.unique_conditions
     }
 
-    pub fn or(&mut self, lhs: OpRef, rhs: OpRef) -> Result<OpRef> {
-        self.ops
-            .push((Operation::Or(self.valid(lhs)?, self.valid(rhs)?), 0));
-        self.ops[lhs].1 += 1;
-        self.ops[rhs].1 += 1;
-        Ok(self.last()?)
+    /// Creates an expression whose builders fail once it grows past `max_ops` operations
+    /// or `max_depth` nesting levels.
+    pub fn with_limits(max_ops: usize, max_depth: usize) -> Expression<Pid> {
+        Expression {
+            limits: Some((max_ops, max_depth)),
+            ..Expression::new()
+        }
     }
 
-    pub fn and(&mut self, lhs: OpRef, rhs: OpRef) -> Result<OpRef> {
-        self.ops
-            .push((Operation::And(self.valid(lhs)?, self.valid(rhs)?), 0));
-        self.ops[lhs].1 += 1;
-        self.ops[rhs].1 += 1;
-        Ok(self.last()?)
+    /// Same as `with_limits`, but only the number of operations is limited.
+    pub fn with_limit(max_ops: usize) -> Expression<Pid> {
+        Expression::with_limits(max_ops, usize::MAX)
     }
 
-    pub fn variables(&self) -> Context<Pid> {
-        Context::request(self.ops.iter().filter_map(|op| match &op.0 {
-            &Operation::Is(ref cond) => Some(cond.variable()),
-            &Operation::In(ref cond) => Some(cond.variable()),
-            _ => None,
-        }))
+    /// Number of operations, including unreachable ones.
+    pub fn len(&self) -> usize {
+        self.ops.len()
     }
 
-    fn eval_single(
-        &self,
-        idx: OpRef,
-        op: &Operation<Pid>,
-        results: &Operations<Pid>,
-        context: &Context<Pid>,
-    ) -> Result<Option<bool>> {
-        match op {
-            &Operation::Is(ref cond) => {
-                if let Some(val) = context.value(cond.variable()) {
-                    cond.eval(val).map(|res| Some(res))
-                } else {
-                    Ok(None)
-                }
-            }
-            &Operation::In(ref cond) => {
-                if let Some(val) = context.value(cond.variable()) {
-                    cond.eval(val).map(|res| Some(res))
-                } else {
-                    Ok(None)
-                }
-            }
-            &Operation::Not(opref) => {
-                let deref = results.get(opref).ok_or(Error::ExpressionFutureReference(
-                    opref,
-                    idx,
-                    self.display(Some(idx)),
-                ))?;
-
-                if let Operation::Const(val) = deref.0 {
-                    Ok(Some(!val))
-                } else {
-                    Ok(None)
-                }
-            }
-            &Operation::Or(lhs, rhs) => {
-                let lop = results.get(lhs).ok_or(Error::ExpressionFutureReference(
-                    lhs,
-                    idx,
-                    self.display(Some(idx)),
-                ))?;
-                let rop = results.get(rhs).ok_or(Error::ExpressionFutureReference(
-                    rhs,
-                    idx,
-                    self.display(Some(idx)),
-                ))?;
-
-                if let (Operation::Const(lval), Operation::Const(rval)) = (&lop.0, &rop.0) {
-                    Ok(Some(*lval || *rval))
-                } else {
-                    Ok(None)
-                }
-            }
-            &Operation::And(lhs, rhs) => {
-                let lop = results.get(lhs).ok_or(Error::ExpressionFutureReference(
-                    lhs,
-                    idx,
-                    self.display(Some(idx)),
-                ))?;
-                let rop = results.get(rhs).ok_or(Error::ExpressionFutureReference(
-                    rhs,
-                    idx,
-                    self.display(Some(idx)),
-                ))?;
-
-                if let (Operation::Const(lval), Operation::Const(rval)) = (&lop.0, &rop.0) {
-                    Ok(Some(*lval && *rval))
-                } else {
-                    Ok(None)
-                }
-            }
-            _ => Ok(None),
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Length of the longest path from the root to a leaf, counting both ends.
+    pub fn depth(&self) -> usize {
+        match self.root() {
+            Some(root) => self.depths().get(root).copied().unwrap_or(0),
+            None => 0,
         }
     }
 
-    pub fn eval(&self, context: &Context<Pid>) -> Result<Evaluated<Pid>> {
-        if self.ops.is_empty() {
-            return Err(Error::ExpressionNoop);
+    /// Root operation: the one set explicitly with `set_root`, or the last one otherwise.
+    pub fn root(&self) -> Option<OpRef> {
+        self.root.or_else(|| self.last().ok())
+    }
+
+    /// Makes `root` the operation that's evaluated and displayed, operations after it are ignored.
+    pub fn set_root(&mut self, root: OpRef) -> Result<()> {
+        self.root = Some(self.valid(root)?);
+        Ok(())
+    }
+
+    fn depths(&self) -> Vec<usize> {
+        let mut depths = Vec::with_capacity(self.ops.len());
+        Self::extend_depths(&self.ops, &mut depths);
+        depths
+    }
+
+    /// Appends depths of operations past the ones `depths` already has.
+    fn extend_depths(ops: &[(Operation<Pid>, RefCount)], depths: &mut Vec<usize>) {
+        for (op, _) in ops.iter().skip(depths.len()) {
+            let deepest = op
+                .operands()
+                .into_iter()
+                .filter_map(|opref| depths.get(opref))
+                .max()
+                .copied();
+            depths.push(1 + deepest.unwrap_or(0));
         }
+    }
 
-        let mut partial: Operations<Pid> = Vec::with_capacity(self.ops.len());
+    fn fits(&self, ops: usize, depth: usize) -> Result<()> {
+        match self.limits {
+            Some((max_ops, max_depth)) if ops > max_ops || depth > max_depth => {
+                Err(Error::ExpressionTooLarge { ops, depth })
+            }
+            _ => Ok(()),
+        }
+    }
 
-        for (idx, op) in self.ops.iter().enumerate() {
-            if op.1 <= 0 && idx != self.ops.len() - 1 {
-                return Err(Error::ExpressionDisconnected(
-                    idx,
-                    self.display(Some(idx)),
-                    self.display(None),
-                ));
+    fn push(&mut self, op: Operation<Pid>) -> Result<OpRef> {
+        if self.unique_conditions && op.is_condition() {
+            if let Some(existing) = self.ops.iter().position(|(existing, _)| *existing == op) {
+                return Ok(existing);
             }
+        }
 
-            partial.push(match self.eval_single(idx, &op.0, &partial, context)? {
-                Some(val) => (Operation::Const(val), op.1),
-                None => op.clone(),
-            });
+        // validate every operand before touching any reference count
+        let operands = op.operands();
+        for &opref in operands.iter() {
+            self.valid(opref)?;
         }
 
-        if let Some((Operation::Const(result), _)) = partial.get(self.last()?) {
-            Ok(Evaluated::Fully(*result, partial))
-        } else {
-            Ok(Evaluated::Partially(Expression { ops: partial }))
+        if self.limits.is_some() {
+            Self::extend_depths(&self.ops, &mut self.depths);
+            let depth = 1 + operands
+                .iter()
+                .map(|&opref| self.depths[opref])
+                .max()
+                .unwrap_or(0);
+            self.fits(self.ops.len() + 1, depth)?;
+            self.depths.push(depth);
+        }
+
+        for opref in operands {
+            self.ops[opref].1 += 1;
         }
+        self.ops.push((op, 0));
+        self.last()
     }
-}
 
-impl<Pid: Property> Display for Expression<Pid> {
-    fn fmt(&self, f: &mut Formatter) -> FmtResult {
-        write!(f, "{}", self.display(None))
+    pub fn constant(&mut self, value: bool) -> Result<OpRef> {
+        self.push(Operation::Const(value))
     }
-}
 
-impl<Pid: Property> Default for Expression<Pid> {
-    fn default() -> Self {
-        Expression::new()
+    pub fn is<V: Into<Value>>(&mut self, variable: Pid, value: V) -> Result<OpRef> {
+        let cond = if self.relaxed {
+            Is::relaxed(variable, value.into())
+        } else {
+            Is::new(variable, value.into())?
+        };
+        self.push(Operation::Is(cond))
     }
-}
 
-#[cfg(test)]
-mod test {
+    /// Condition-level inequality, see `IsNot`.
+    pub fn is_not<V: Into<Value>>(&mut self, variable: Pid, value: V) -> Result<OpRef> {
+        let cond = if self.relaxed {
+            IsNot::relaxed(variable, value.into())
+        } else {
+            IsNot::new(variable, value.into())?
+        };
+        self.push(Operation::IsNot(cond))
+    }
 
-    use super::*;
-    use crate::testproperty::Property;
-    use crate::value::Datatype;
+    /// Float equality within `epsilon` of `value`, see `ApproxIs`.
+    pub fn approx_is(&mut self, variable: Pid, value: f64, epsilon: f64) -> Result<OpRef> {
+        let cond = ApproxIs::new(variable, value, epsilon)?;
+        self.push(Operation::ApproxIs(cond))
+    }
 
-    #[test]
-    fn context_request() {
-        let props = vec![Property::Str, Property::Bool];
-        let context = Context::request(props.clone());
+    /// An empty set of values is kept as is, but evaluates to `false` without requesting
+    /// the variable, `simplify` folds it into a constant.
+    pub fn is_in<I>(&mut self, variable: Pid, values: I) -> Result<OpRef>
+    where
+        I: IntoIterator,
+        I::Item: Into<Value>,
+    {
+        let values = values.into_iter().map(Into::into).collect();
+        let cond = if self.relaxed {
+            In::relaxed(variable, values)
+        } else {
+            In::new(variable, values)?
+        };
+        self.push(Operation::In(cond))
+    }
 
-        let expected = props.iter().collect::<HashSet<_>>();
-        let actual = context.requested().collect::<HashSet<_>>();
-        assert_eq!(expected, actual);
+    /// Same as `is_in`, but values are kept sorted and looked up with binary search, which is
+    /// preferred for large sets. Values are validated even if the expression is relaxed.
+    pub fn is_in_sorted<I>(&mut self, variable: Pid, values: I) -> Result<OpRef>
+    where
+        I: IntoIterator,
+        I::Item: Into<Value>,
+    {
+        let cond = In::sorted(variable, values.into_iter().map(Into::into))?;
+        self.push(Operation::In(cond))
     }
 
-    #[test]
-    fn context_provide_known() {
-        let mut context = Context::request(vec![Property::Str]);
-        let res = context.provide(Property::Str, Value::Str("known".to_owned()));
+    pub fn contains(&mut self, variable: Pid, needle: &str) -> Result<OpRef> {
+        let cond = Contains::new(variable, needle.to_owned(), false)?;
+        self.push(Operation::Contains(cond))
+    }
 
-        assert!(matches!(res, Ok(())));
+    /// Same as `contains`, but ignores case when matching.
+    pub fn contains_ci(&mut self, variable: Pid, needle: &str) -> Result<OpRef> {
+        let cond = Contains::new(variable, needle.to_owned(), true)?;
+        self.push(Operation::Contains(cond))
+    }
 
-        let provided = context.provided().collect::<HashSet<_>>();
-        assert!(provided.contains(&Property::Str));
+    /// Matches list-valued properties with at least one element satisfying `predicate`.
+    pub fn any_element(&mut self, variable: Pid, predicate: Predicate) -> Result<OpRef> {
+        let cond = Quantified::new(variable, Quantifier::Any, predicate)?;
+        self.push(Operation::Quantified(cond))
+    }
 
-        let expected = Value::Str("known".to_owned());
-        assert_eq!(context.value(Property::Str), Some(&expected));
+    /// Matches list-valued properties with every element satisfying `predicate`,
+    /// including empty lists.
+    pub fn all_elements(&mut self, variable: Pid, predicate: Predicate) -> Result<OpRef> {
+        let cond = Quantified::new(variable, Quantifier::All, predicate)?;
+        self.push(Operation::Quantified(cond))
     }
 
-    #[test]
-    fn context_provide_unknown() {
-        let mut context = Context::request(vec![Property::Bool]);
-        let res = context.provide(Property::Int, Value::Int(42));
+    pub fn not(&mut self, opref: OpRef) -> Result<OpRef> {
+        self.push(Operation::Not(opref))
+    }
 
-        assert!(matches!(res, Ok(())));
+    pub fn or(&mut self, lhs: OpRef, rhs: OpRef) -> Result<OpRef> {
+        self.push(Operation::Or(lhs, rhs))
+    }
 
-        let provided = context.provided().collect::<HashSet<_>>();
-        assert!(!provided.contains(&Property::Int));
-        assert!(matches!(context.value(Property::Int), None));
+    pub fn and(&mut self, lhs: OpRef, rhs: OpRef) -> Result<OpRef> {
+        self.push(Operation::And(lhs, rhs))
     }
 
-    #[test]
-    fn context_provide_type_mismatch() {
-        let mut context = Context::request(vec![Property::Bool]);
-        let res = context.provide(Property::Bool, Value::Int(42));
+    /// Negated `and`: `true` unless both operands are.
+    pub fn nand(&mut self, lhs: OpRef, rhs: OpRef) -> Result<OpRef> {
+        self.push(Operation::Nand(lhs, rhs))
+    }
 
-        assert!(matches!(
-            res,
-            Err(Error::TypeMismatch(
-                "Property::Bool",
-                Datatype::Bool,
-                Datatype::Int
-            ))
-        ));
+    /// Negated `or`: `true` only if neither operand is.
+    pub fn nor(&mut self, lhs: OpRef, rhs: OpRef) -> Result<OpRef> {
+        self.push(Operation::Nor(lhs, rhs))
     }
 
-    #[test]
-    fn context_provide_update() {
-        let mut context = Context::request(vec![Property::Str]);
-        context
+    pub fn implies(&mut self, premise: OpRef, conclusion: OpRef) -> Result<OpRef> {
+        self.push(Operation::Implies(premise, conclusion))
+    }
+
+    pub fn if_else(&mut self, cond: OpRef, then: OpRef, otherwise: OpRef) -> Result<OpRef> {
+        self.push(Operation::IfElse(cond, then, otherwise))
+    }
+
+    /// Pushes an operation on top of the current root, an explicitly set root follows it.
+    fn push_root(&mut self, op: impl FnOnce(OpRef) -> Operation<Pid>) -> Result<OpRef> {
+        let root = self.root().ok_or(Error::ExpressionNoop)?;
+        let opref = self.push(op(root))?;
+        if self.root.is_some() {
+            self.root = Some(opref);
+        }
+        Ok(opref)
+    }
+
+    /// Fluent form of `and`: combines the current root with `other` and makes the result the root.
+    pub fn and_with(&mut self, other: OpRef) -> Result<OpRef> {
+        self.push_root(|root| Operation::And(root, other))
+    }
+
+    /// Fluent form of `or`: combines the current root with `other` and makes the result the root.
+    pub fn or_with(&mut self, other: OpRef) -> Result<OpRef> {
+        self.push_root(|root| Operation::Or(root, other))
+    }
+
+    /// Fluent form of `not`: negates the current root and makes the result the root.
+    pub fn not_root(&mut self) -> Result<OpRef> {
+        self.push_root(Operation::Not)
+    }
+
+    /// Complement of the whole expression, e.g. to turn an "include" filter into an "exclude" one:
+    /// wraps the root in `Not`, which becomes the new root. Same as `not_root`,
+    /// see `to_nnf` to push the negation down to conditions.
+    pub fn negate(&mut self) -> Result<OpRef> {
+        self.not_root()
+    }
+
+    /// Copies operations of `other` after the existing ones, returns where its root ended up.
+    ///
+    /// Conditions of a relaxed `other` are validated if this expression is strict, and with
+    /// `new_dedup` identical conditions already here are reused instead of copied.
+    pub fn append(&mut self, other: &Expression<Pid>) -> Result<OpRef> {
+        let root = other.root().ok_or(Error::ExpressionNoop)?;
+        let offset = self.ops.len();
+        self.fits(offset + other.ops.len(), other.depth())?;
+
+        if other.relaxed && !self.relaxed {
+            other.ops.iter().try_for_each(|(op, _)| op.check())?;
+        }
+
+        if self.unique_conditions {
+            let mut remapped = Vec::with_capacity(other.ops.len());
+            for (op, _) in other.ops.iter() {
+                let opref = self.push(op.remap(|opref| remapped[opref]))?;
+                remapped.push(opref);
+            }
+            return Ok(remapped[root]);
+        }
+
+        self.ops.extend(
+            other
+                .ops
+                .iter()
+                .map(|(op, refs)| (op.remap(|opref| opref + offset), *refs)),
+        );
+        Ok(root + offset)
+    }
+
+    /// Copies operations reachable from `root` into a new expression with `root` as its last one.
+    /// Limits, relaxed validation and condition deduplication carry over.
+    pub fn subexpression(&self, root: OpRef) -> Result<Expression<Pid>> {
+        let reachable = self.reachable(self.valid(root)?);
+
+        let mut remapped = vec![0; self.ops.len()];
+        let mut sub = Expression {
+            limits: self.limits,
+            relaxed: self.relaxed,
+            unique_conditions: self.unique_conditions,
+            ..Expression::new()
+        };
+
+        for (idx, op) in self
+            .ops
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| reachable[*idx])
+        {
+            remapped[idx] = sub.ops.len();
+            sub.ops.push((op.0.remap(|opref| remapped[opref]), 0));
+        }
+
+        sub.rebuild_refcounts();
+        Ok(sub)
+    }
+
+    /// Rebuilds the expression for another property enum, conditions are recreated with mapped
+    /// properties and their values validated against them. Structure, reference counts, root
+    /// and limits carry over unchanged.
+    ///
+    /// Errors from `f` and validation are wrapped into `Error::ExpressionRetarget` with the index
+    /// of the offending operation.
+    pub fn map_properties<Q: Property, F: Fn(Pid) -> Result<Q>>(
+        &self,
+        f: F,
+    ) -> Result<Expression<Q>> {
+        let retarget = |op: &Operation<Pid>| -> Result<Operation<Q>> {
+            let op = match *op {
+                Operation::Const(val) => Operation::Const(val),
+                Operation::Is(ref cond) => {
+                    Operation::Is(Is::new(f(cond.variable())?, cond.expected().clone())?)
+                }
+                Operation::IsNot(ref cond) => {
+                    Operation::IsNot(IsNot::new(f(cond.variable())?, cond.expected().clone())?)
+                }
+                Operation::ApproxIs(ref cond) => Operation::ApproxIs(ApproxIs::new(
+                    f(cond.variable())?,
+                    cond.expected(),
+                    cond.epsilon(),
+                )?),
+                Operation::In(ref cond) => {
+                    Operation::In(In::from_set(f(cond.variable())?, cond.expected().clone())?)
+                }
+                Operation::Contains(ref cond) => Operation::Contains(Contains::new(
+                    f(cond.variable())?,
+                    cond.needle().to_owned(),
+                    cond.case_insensitive(),
+                )?),
+                Operation::Quantified(ref cond) => Operation::Quantified(Quantified::new(
+                    f(cond.variable())?,
+                    cond.quantifier(),
+                    cond.predicate().clone(),
+                )?),
+                Operation::Not(opref) => Operation::Not(opref),
+                Operation::Or(lhs, rhs) => Operation::Or(lhs, rhs),
+                Operation::And(lhs, rhs) => Operation::And(lhs, rhs),
+                Operation::Nand(lhs, rhs) => Operation::Nand(lhs, rhs),
+                Operation::Nor(lhs, rhs) => Operation::Nor(lhs, rhs),
+                Operation::Implies(premise, conclusion) => Operation::Implies(premise, conclusion),
+                Operation::IfElse(cond, then, otherwise) => {
+                    Operation::IfElse(cond, then, otherwise)
+                }
+            };
+            Ok(op)
+        };
+
+        let ops = self
+            .ops
+            .iter()
+            .enumerate()
+            .map(|(idx, (op, refs))| match retarget(op) {
+                Ok(op) => Ok((op, *refs)),
+                Err(err) => Err(Error::ExpressionRetarget(idx, Box::new(err))),
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Expression {
+            ops,
+            root: self.root,
+            limits: self.limits,
+            relaxed: self.relaxed,
+            unique_conditions: self.unique_conditions,
+            // structure is the same, so are depths
+            depths: self.depths.clone(),
+        })
+    }
+
+    /// Placeholders used in conditions, along with the operations that use them.
+    fn placeholders(&self) -> impl Iterator<Item = (OpRef, &str)> + '_ {
+        self.ops.iter().enumerate().flat_map(|(idx, (op, _))| {
+            let values = match op {
+                Operation::Is(cond) => vec![cond.expected()],
+                Operation::IsNot(cond) => vec![cond.expected()],
+                Operation::In(cond) => cond.expected().iter().collect(),
+                _ => Vec::new(),
+            };
+            values.into_iter().filter_map(move |value| match value {
+                Value::Param(name) => Some((idx, name.as_str())),
+                _ => None,
+            })
+        })
+    }
+
+    /// Names of `Value::Param` placeholders used in conditions of any operation.
+    pub fn params(&self) -> BTreeSet<&str> {
+        self.placeholders().map(|(_, name)| name).collect()
+    }
+
+    /// Copy of the expression with every placeholder replaced with its value from `params`,
+    /// the values are validated against condition properties.
+    ///
+    /// Every placeholder has to be bound and every parameter has to be used,
+    /// otherwise `Error::UnboundParameter` / `Error::UnusedParameter` is returned.
+    pub fn bind(&self, params: &HashMap<&str, Value>) -> Result<Expression<Pid>> {
+        let used = self.params();
+        if let Some(name) = used.iter().find(|name| !params.contains_key(*name)) {
+            return Err(Error::UnboundParameter((*name).to_owned()));
+        }
+        if let Some(name) = params.keys().filter(|name| !used.contains(*name)).min() {
+            return Err(Error::UnusedParameter((*name).to_owned()));
+        }
+
+        let bound = |value: &Value| match value {
+            Value::Param(name) => params[name.as_str()].clone(),
+            value => value.clone(),
+        };
+        let ops = self
+            .ops
+            .iter()
+            .map(|(op, refs)| {
+                let op = match op {
+                    Operation::Is(cond) => {
+                        Operation::Is(Is::new(cond.variable(), bound(cond.expected()))?)
+                    }
+                    Operation::IsNot(cond) => {
+                        Operation::IsNot(IsNot::new(cond.variable(), bound(cond.expected()))?)
+                    }
+                    Operation::In(cond) => {
+                        let expected = cond.expected().map(bound);
+                        Operation::In(In::from_set(cond.variable(), expected)?)
+                    }
+                    op => op.clone(),
+                };
+                Ok((op, *refs))
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Expression {
+            ops,
+            root: self.root,
+            limits: self.limits,
+            relaxed: self.relaxed,
+            unique_conditions: self.unique_conditions,
+            // structure is the same, so are depths
+            depths: self.depths.clone(),
+        })
+    }
+
+    /// Fails on placeholders: conditions comparing with them can't be evaluated,
+    /// and on values relaxed builders let through.
+    fn checked(&self, reachable: &[bool]) -> Result<()> {
+        let unbound = self.placeholders().filter(|&(idx, _)| reachable[idx]);
+        if let Some(name) = unbound.map(|(_, name)| name).min() {
+            return Err(Error::UnboundParameter(name.to_owned()));
+        }
+        if !self.relaxed {
+            return Ok(());
+        }
+
+        let mut conditions = self
+            .ops
+            .iter()
+            .zip(reachable)
+            .filter(|(_, &reachable)| reachable);
+        conditions.try_for_each(|((op, _), _)| op.check())
+    }
+
+    /// Drops operations that aren't reachable from the root, returns the number of dropped ones.
+    pub fn compact(&mut self) -> usize {
+        let root = match self.root() {
+            Some(root) => root,
+            None => return 0,
+        };
+
+        let mut compacted = match self.subexpression(root) {
+            Ok(compacted) => compacted,
+            Err(_) => return 0,
+        };
+        if self.root.is_some() {
+            compacted.root = compacted.root();
+        }
+
+        let removed = self.ops.len() - compacted.ops.len();
+        *self = compacted;
+        removed
+    }
+
+    /// Checks the structure of the expression: it must not be empty, every operation may only
+    /// reference operations before it and reference counts must match the actual references.
+    pub fn validate(&self) -> Result<()> {
+        let root = self.root().ok_or(Error::ExpressionNoop)?;
+        self.valid(root)?;
+
+        let refs = self.references()?;
+        match self
+            .ops
+            .iter()
+            .zip(refs)
+            .enumerate()
+            .find(|(_, (op, refs))| op.1 != *refs)
+        {
+            Some((idx, (op, refs))) => Err(Error::ExpressionRefCount(idx, op.1, refs)),
+            None => Ok(()),
+        }
+    }
+
+    /// Replaces the operation at `at`, the new operation may only reference operations before it.
+    ///
+    /// Fails if an operand of the replaced operation would lose its last reference,
+    /// unless `force` is set; orphaned operations have to be cleaned up before evaluation.
+    pub fn replace(&mut self, at: OpRef, op: Operation<Pid>, force: bool) -> Result<()> {
+        self.valid(at)?;
+
+        let added = op.operands();
+        if let Some(&opref) = added.iter().find(|&&opref| opref >= at) {
+            return Err(Error::ExpressionFutureReference(opref, at, self.summary()));
+        }
+
+        let removed = self.ops[at].0.operands();
+        if !force {
+            for &opref in removed.iter() {
+                let remaining = self.ops[opref].1 - removed.iter().filter(|&&r| r == opref).count()
+                    + added.iter().filter(|&&r| r == opref).count();
+                if remaining == 0 {
+                    return Err(Error::ExpressionDisconnected(
+                        opref,
+                        self.display(Some(opref)),
+                        self.display(None),
+                    ));
+                }
+            }
+        }
+
+        for opref in removed {
+            self.ops[opref].1 -= 1;
+        }
+        for opref in added {
+            self.ops[opref].1 += 1;
+        }
+        self.ops[at].0 = op;
+        self.depths.truncate(at);
+        Ok(())
+    }
+
+    /// Collapses structurally identical operations into one, the root operation is always kept.
+    pub fn dedup(&mut self) {
+        let root = match self.root() {
+            Some(root) => root,
+            None => return,
+        };
+
+        let mut remapped = Vec::with_capacity(self.ops.len());
+        let mut ops: Operations<Pid> = Vec::with_capacity(self.ops.len());
+
+        for (idx, (op, _)) in self.ops.iter().enumerate() {
+            let op = op.remap(|opref| remapped[opref]);
+            let existing = if idx != root {
+                ops.iter().position(|(other, _)| *other == op)
+            } else {
+                None
+            };
+
+            remapped.push(existing.unwrap_or_else(|| {
+                ops.push((op, 0));
+                ops.len() - 1
+            }));
+        }
+
+        self.ops = ops;
+        self.depths.clear();
+        self.root = self.root.map(|root| remapped[root]);
+        self.rebuild_refcounts();
+    }
+
+    /// Deterministic 64-bit fingerprint of the operation tree reachable from the root.
+    ///
+    /// Unlike `Hash`, the value is stable across runs and processes: it's an FNV-1a hash
+    /// of a canonical encoding that uses property names and sorts `In` values.
+    pub fn fingerprint(&self) -> u64 {
+        const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        fn fnv(bytes: &[u8]) -> u64 {
+            bytes.iter().fold(OFFSET, |hash, byte| {
+                (hash ^ u64::from(*byte)).wrapping_mul(PRIME)
+            })
+        }
+
+        fn encode_str(bytes: &mut Vec<u8>, val: &str) {
+            bytes.extend_from_slice(&(val.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(val.as_bytes());
+        }
+
+        fn encode_value(val: &Value) -> Vec<u8> {
+            let mut bytes = Vec::new();
+            match val {
+                Value::Bool(val) => bytes.extend_from_slice(&[0x10, *val as u8]),
+                Value::Int(val) => {
+                    bytes.push(0x11);
+                    bytes.extend_from_slice(&val.to_le_bytes());
+                }
+                // equal strings are the same whether interned or not
+                Value::Str(val) => {
+                    bytes.push(0x12);
+                    encode_str(&mut bytes, val);
+                }
+                Value::InternedStr(val) => {
+                    bytes.push(0x12);
+                    encode_str(&mut bytes, val);
+                }
+                Value::UInt(val) => {
+                    bytes.push(0x13);
+                    bytes.extend_from_slice(&val.to_le_bytes());
+                }
+                Value::Param(name) => {
+                    bytes.push(0x15);
+                    encode_str(&mut bytes, name);
+                }
+                // zeroes of both signs are equal values
+                Value::Float(val) => {
+                    let val = if *val == 0.0 { 0.0 } else { *val };
+                    bytes.push(0x16);
+                    bytes.extend_from_slice(&val.to_bits().to_le_bytes());
+                }
+                Value::List(element, items) => {
+                    bytes.push(0x14);
+                    encode_str(&mut bytes, &element.to_string());
+                    bytes.extend_from_slice(&(items.len() as u64).to_le_bytes());
+                    for item in items {
+                        bytes.extend(encode_value(item));
+                    }
+                }
+            }
+            bytes
+        }
+
+        let root = match self.root() {
+            Some(root) => root,
+            None => return fnv(&[]),
+        };
+
+        let reachable = self.reachable(root);
+        let mut subtrees = vec![0u64; self.ops.len()];
+
+        for (idx, op) in self.ops.iter().enumerate().take(root + 1) {
+            if !reachable[idx] {
+                continue;
+            }
+
+            let mut bytes = Vec::new();
+            let tag = match &op.0 {
+                Operation::Const(val) => {
+                    bytes.push(*val as u8);
+                    0x01
+                }
+                Operation::Is(cond) => {
+                    encode_str(&mut bytes, cond.variable().name());
+                    bytes.extend(encode_value(cond.expected()));
+                    0x02
+                }
+                Operation::In(cond) => {
+                    encode_str(&mut bytes, cond.variable().name());
+                    let mut values = cond.expected().iter().map(encode_value).collect::<Vec<_>>();
+                    values.sort();
+                    bytes.extend_from_slice(&(values.len() as u64).to_le_bytes());
+                    bytes.extend(values.into_iter().flatten());
+                    0x03
+                }
+                Operation::Contains(cond) => {
+                    encode_str(&mut bytes, cond.variable().name());
+                    encode_str(&mut bytes, cond.needle());
+                    bytes.push(cond.case_insensitive() as u8);
+                    0x09
+                }
+                Operation::IsNot(cond) => {
+                    encode_str(&mut bytes, cond.variable().name());
+                    bytes.extend(encode_value(cond.expected()));
+                    0x0A
+                }
+                Operation::ApproxIs(cond) => {
+                    encode_str(&mut bytes, cond.variable().name());
+                    bytes.extend(encode_value(&Value::Float(cond.expected())));
+                    bytes.extend(encode_value(&Value::Float(cond.epsilon())));
+                    0x0E
+                }
+                Operation::Quantified(cond) => {
+                    encode_str(&mut bytes, cond.variable().name());
+                    bytes.push(cond.quantifier() as u8);
+                    match cond.predicate() {
+                        Predicate::Is(expected) => {
+                            bytes.push(0x02);
+                            bytes.extend(encode_value(expected));
+                        }
+                        Predicate::In(expected) => {
+                            // ordered sets don't need sorting
+                            bytes.push(0x03);
+                            bytes.extend_from_slice(&(expected.len() as u64).to_le_bytes());
+                            bytes.extend(expected.iter().flat_map(encode_value));
+                        }
+                        Predicate::Contains(needle, case_insensitive) => {
+                            bytes.push(0x09);
+                            encode_str(&mut bytes, needle);
+                            bytes.push(*case_insensitive as u8);
+                        }
+                    }
+                    0x0B
+                }
+                Operation::Not(_) => 0x04,
+                Operation::Or(_, _) => 0x05,
+                Operation::And(_, _) => 0x06,
+                Operation::Implies(_, _) => 0x07,
+                Operation::IfElse(_, _, _) => 0x08,
+                Operation::Nand(_, _) => 0x0C,
+                Operation::Nor(_, _) => 0x0D,
+            };
+            for opref in op.0.operands() {
+                bytes.extend_from_slice(&subtrees.get(opref).unwrap_or(&0).to_le_bytes());
+            }
+
+            bytes.insert(0, tag);
+            subtrees[idx] = fnv(&bytes);
+        }
+
+        subtrees[root]
+    }
+
+    /// Estimates the fraction of records the expression selects, given per-property selectivity.
+    ///
+    /// This is a heuristic that assumes conditions are independent: `And` multiplies estimates,
+    /// `Or` uses inclusion-exclusion, `Not` takes the complement and `In` scales the property
+    /// selectivity by the number of values. An empty expression selects nothing.
+    pub fn estimate_cost(&self, selectivity: &dyn Fn(&Pid) -> f64) -> f64 {
+        let root = match self.root() {
+            Some(root) => root,
+            None => return 0.0,
+        };
+
+        let or = |lhs: f64, rhs: f64| lhs + rhs - lhs * rhs;
+        let mut estimates: Vec<f64> = Vec::with_capacity(root + 1);
+
+        for (op, _) in self.ops.iter().take(root + 1) {
+            let get = |opref: OpRef| estimates.get(opref).copied().unwrap_or(1.0);
+            let estimate = match *op {
+                Operation::Const(val) => f64::from(val as u8),
+                Operation::Is(ref cond) => selectivity(&cond.variable()),
+                Operation::IsNot(ref cond) => 1.0 - selectivity(&cond.variable()),
+                Operation::ApproxIs(ref cond) => selectivity(&cond.variable()),
+                Operation::In(ref cond) => {
+                    selectivity(&cond.variable()) * cond.expected().len() as f64
+                }
+                Operation::Contains(ref cond) => selectivity(&cond.variable()),
+                Operation::Quantified(ref cond) => selectivity(&cond.variable()),
+                Operation::Not(opref) => 1.0 - get(opref),
+                Operation::Or(lhs, rhs) => or(get(lhs), get(rhs)),
+                Operation::And(lhs, rhs) => get(lhs) * get(rhs),
+                Operation::Nand(lhs, rhs) => 1.0 - get(lhs) * get(rhs),
+                Operation::Nor(lhs, rhs) => 1.0 - or(get(lhs), get(rhs)),
+                Operation::Implies(premise, conclusion) => or(1.0 - get(premise), get(conclusion)),
+                Operation::IfElse(cond, then, otherwise) => {
+                    get(cond) * get(then) + (1.0 - get(cond)) * get(otherwise)
+                }
+            };
+            estimates.push(estimate.clamp(0.0, 1.0));
+        }
+
+        estimates[root]
+    }
+
+    /// Copy of the expression with the cheaper operand first in every `And`, `Or`, `Nand`
+    /// and `Nor`, so that evaluation can short-circuit before getting to the expensive one.
+    /// Cost of an operand is the total `cost` of the operations it's made of, ties keep
+    /// the original order. The result is the same for any values.
+    pub fn reorder_by_cost(&self, cost: impl Fn(&Operation<Pid>) -> u64) -> Expression<Pid> {
+        let mut totals: Vec<u64> = Vec::with_capacity(self.ops.len());
+        for (op, _) in self.ops.iter() {
+            let total = op
+                .operands()
+                .into_iter()
+                .map(|opref| totals.get(opref).copied().unwrap_or(0))
+                .fold(cost(op), u64::saturating_add);
+            totals.push(total);
+        }
+
+        let mut reordered = self.clone();
+        for (op, _) in reordered.ops.iter_mut() {
+            if let Operation::Or(lhs, rhs)
+            | Operation::And(lhs, rhs)
+            | Operation::Nand(lhs, rhs)
+            | Operation::Nor(lhs, rhs) = op
+            {
+                if totals.get(*rhs) < totals.get(*lhs) {
+                    std::mem::swap(lhs, rhs);
+                }
+            }
+        }
+        reordered
+    }
+
+    /// Iterates over all operations in stored order, along with their reference counts.
+    ///
+    /// Operands always precede their users, so this is also a valid evaluation order.
+    pub fn iter(&self) -> impl Iterator<Item = (OpRef, &Operation<Pid>, usize)> {
+        self.ops
+            .iter()
+            .enumerate()
+            .map(|(idx, (op, refcount))| (idx, op, *refcount))
+    }
+
+    /// Same as `iter`, but skips operations that aren't reachable from the root.
+    pub fn iter_reachable(&self) -> impl Iterator<Item = (OpRef, &Operation<Pid>, usize)> {
+        let reachable = match self.root() {
+            Some(root) => self.reachable(root),
+            None => Vec::new(),
+        };
+        self.iter()
+            .filter(move |(idx, _, _)| reachable.get(*idx).copied().unwrap_or(false))
+    }
+
+    pub fn variables(&self) -> Context<Pid> {
+        Context::request(self.ops.iter().filter_map(|(op, _)| op.variable()))
+    }
+
+    /// Whether no operation depends on a property, same as `variables()` requesting nothing,
+    /// so that the expression can be evaluated with `Context::empty()` without fetching any data.
+    pub fn is_constant(&self) -> bool {
+        self.ops.iter().all(|(op, _)| op.variable().is_none())
+    }
+
+    /// Properties used by conditions along with their datatypes, e.g. to build a fetch query schema.
+    pub fn property_datatypes(&self) -> HashMap<Pid, Datatype> {
+        self.ops
+            .iter()
+            .filter_map(|(op, _)| op.variable())
+            .map(|variable| (variable, variable.datatype()))
+            .collect()
+    }
+
+    /// Whether reachable operations have no conditions, so the context can't affect the result.
+    fn no_conditions(&self, reachable: &[bool]) -> bool {
+        let mut ops = self
+            .ops
+            .iter()
+            .zip(reachable)
+            .filter(|(_, &reachable)| reachable);
+        ops.all(|((op, _), _)| !op.is_condition())
+    }
+
+    /// Returns the root, making sure every operation before it is reachable from it.
+    fn connected(&self) -> Result<OpRef> {
+        let root = self.root().ok_or(Error::ExpressionNoop)?;
+        let reachable = self.reachable(root);
+
+        match reachable
+            .iter()
+            .take(root + 1)
+            .position(|reachable| !reachable)
+        {
+            Some(idx) => Err(Error::ExpressionDisconnected(
+                idx,
+                self.display(Some(idx)),
+                self.display(None),
+            )),
+            None => Ok(root),
+        }
+    }
+
+    fn evaluated(
+        &self,
+        root: OpRef,
+        reachable: &[bool],
+        folded: &[Option<Option<bool>>],
+    ) -> Evaluated<Pid> {
+        // operations that aren't part of the expression aren't skipped either
+        let skipped = folded
+            .iter()
+            .zip(reachable)
+            .enumerate()
+            .filter(|(_, (result, &reachable))| reachable && result.is_none())
+            .map(|(idx, _)| idx)
+            .collect();
+
+        let partial = self.folded(folded);
+        if let Some((Operation::Const(result), _)) = partial.get(root) {
+            Evaluated::Fully(*result, partial, skipped)
+        } else {
+            Evaluated::Partially(Expression {
+                ops: partial,
+                root: if self.root() == Some(root) {
+                    self.root
+                } else {
+                    Some(root)
+                },
+                limits: self.limits,
+                relaxed: self.relaxed,
+                unique_conditions: self.unique_conditions,
+                depths: Vec::new(),
+            })
+        }
+    }
+
+    /// Evaluates the expression on demand from the root: `And`, `Or`, `Implies` and `IfElse`
+    /// stop as soon as their result is decided, for example `Or` with either operand `true`,
+    /// and operands that can't change it anymore aren't evaluated, nor are their properties read.
+    ///
+    /// Fails with `Error::ExpressionDisconnected` if any operation before the root isn't
+    /// reachable from it, see `eval_from` to evaluate expressions with several trees.
+    pub fn eval(&self, context: &Context<Pid>) -> Result<Evaluated<Pid>> {
+        let root = self.connected()?;
+        self.eval_from(root, context)
+    }
+
+    /// Evaluates the expression with Kleene three-valued logic, where conditions on missing
+    /// properties are `Unknown`: `Or(Unknown, True)` is `True`, `And(Unknown, False)` is `False`,
+    /// `Not(Unknown)` is `Unknown`, and `IfElse` with an unknown condition is known if both
+    /// branches agree.
+    ///
+    /// This is the logic `eval` already folds with, so the result is always the same as the one
+    /// of `eval`, `Unknown` exactly when it returns `Evaluated::Partially`. Use this method when
+    /// only the result is needed: neither the folded log nor the partial expression is built.
+    pub fn eval_3vl(&self, context: &Context<Pid>) -> Result<Tri> {
+        let root = self.connected()?;
+        self.checked(&self.reachable(root))?;
+
+        let mut folded = vec![None; self.ops.len()];
+        let mut condition = |idx, variable| self.test_in(idx, variable, context);
+        let result = self.fold(root, &[], &mut condition, &mut folded)?;
+        Ok(result.into())
+    }
+
+    /// Same as `eval`, but from `root` instead of the expression root: only operations reachable
+    /// from it are evaluated, everything else is ignored and left as is.
+    pub fn eval_from(&self, root: OpRef, context: &Context<Pid>) -> Result<Evaluated<Pid>> {
+        self.eval_by(root, &mut |idx, variable| {
+            self.test_in(idx, variable, context)
+        })
+    }
+
+    /// Same as `eval`, but property values come from `provider` instead of a context.
+    /// It's asked for a property only once a condition on it has to be evaluated, at most once
+    /// per property, and returned values are checked against the property datatype.
+    /// `None` leaves the conditions unevaluated, same as values missing from a context.
+    pub fn eval_with<F>(&self, mut provider: F) -> Result<Evaluated<Pid>>
+    where
+        F: FnMut(Pid) -> Option<Value>,
+    {
+        let root = self.connected()?;
+        let mut fetched: HashMap<Pid, Option<Value>> = HashMap::new();
+        self.eval_by(root, &mut |idx, variable| {
+            let actual = match fetched.entry(variable) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let value = provider(variable).map(|value| variable.coerce(value));
+                    entry.insert(value.transpose()?)
+                }
+            };
+            actual
+                .as_ref()
+                .map(|actual| self.test(idx, actual))
+                .transpose()
+        })
+    }
+
+    /// Same as `eval`, but properties missing from `context` take their value from `defaults`,
+    /// if any. Defaults are all checked against property datatypes before evaluation starts,
+    /// `context` is left as is.
+    pub fn eval_with_defaults(
+        &self,
+        context: &Context<Pid>,
+        defaults: &HashMap<Pid, Value>,
+    ) -> Result<Evaluated<Pid>> {
+        let defaults = defaults
+            .iter()
+            .map(|(&prop, value)| Ok((prop, prop.coerce(value.clone())?)))
+            .collect::<Result<HashMap<_, _>>>()?;
+
+        let root = self.connected()?;
+        self.eval_by(root, &mut |idx, variable| {
+            context
+                .value(variable)
+                .or_else(|| defaults.get(&variable))
+                .map(|actual| self.test(idx, actual))
+                .transpose()
+        })
+    }
+
+    /// Same as `eval_from`, conditions are evaluated by `condition` given their property,
+    /// which yields `None` if the property value is unknown.
+    fn eval_by(
+        &self,
+        root: OpRef,
+        condition: &mut dyn FnMut(OpRef, Pid) -> Result<Option<bool>>,
+    ) -> Result<Evaluated<Pid>> {
+        let reachable = self.reachable(self.valid(root)?);
+        self.checked(&reachable)?;
+        let constant = self.no_conditions(&reachable);
+
+        let mut folded = vec![None; self.ops.len()];
+        self.eval_into(root, &reachable, constant, condition, &mut folded)
+    }
+
+    /// Evaluates an already validated expression with cleared `folded` scratch space,
+    /// `constant` tells whether reachable operations have no conditions.
+    fn eval_into(
+        &self,
+        root: OpRef,
+        reachable: &[bool],
+        constant: bool,
+        condition: &mut dyn FnMut(OpRef, Pid) -> Result<Option<bool>>,
+        folded: &mut [Option<Option<bool>>],
+    ) -> Result<Evaluated<Pid>> {
+        // no costs: operands are visited left to right
+        let result = self.fold(root, &[], condition, folded)?;
+
+        // statically decidable expressions don't need a copy of their operations as a log
+        match result {
+            Some(result) if constant => Ok(Evaluated::Fully(result, Vec::new(), Vec::new())),
+            _ => Ok(self.evaluated(root, reachable, folded)),
+        }
+    }
+
+    /// Evaluates the expression against every context in turn, same as calling `eval` for each
+    /// of them, but the expression is checked once and scratch space is reused across contexts.
+    /// Fails with the first error any of the contexts runs into.
+    pub fn eval_batch<'a, I>(&self, contexts: I) -> Result<Vec<Evaluated<Pid>>>
+    where
+        I: IntoIterator<Item = &'a Context<Pid>>,
+        Pid: 'a,
+    {
+        let root = self.connected()?;
+        let reachable = self.reachable(root);
+        self.checked(&reachable)?;
+        let constant = self.no_conditions(&reachable);
+
+        let mut folded = vec![None; self.ops.len()];
+        contexts
+            .into_iter()
+            .map(|context| {
+                folded.iter_mut().for_each(|result| *result = None);
+                let mut condition = |idx, variable| self.test_in(idx, variable, context);
+                self.eval_into(root, &reachable, constant, &mut condition, &mut folded)
+            })
+            .collect()
+    }
+
+    /// Same as `eval_batch`, but only keeps results, `None` for partially evaluated contexts.
+    /// Neither logs nor partial expressions are built, so nothing is allocated per context.
+    pub fn eval_batch_bool<'a, I>(&self, contexts: I) -> Result<Vec<Option<bool>>>
+    where
+        I: IntoIterator<Item = &'a Context<Pid>>,
+        Pid: 'a,
+    {
+        let root = self.connected()?;
+        self.checked(&self.reachable(root))?;
+
+        let mut folded = vec![None; self.ops.len()];
+        contexts
+            .into_iter()
+            .map(|context| {
+                folded.iter_mut().for_each(|result| *result = None);
+                let mut condition = |idx, variable| self.test_in(idx, variable, context);
+                self.fold(root, &[], &mut condition, &mut folded)
+            })
+            .collect()
+    }
+
+    /// Evaluates the condition at `idx` against the value of its property,
+    /// anything else doesn't depend on property values and isn't satisfied by one.
+    fn test(&self, idx: OpRef, actual: &Value) -> Result<bool> {
+        match &self.ops[idx].0 {
+            Operation::Is(cond) => cond.eval(actual),
+            Operation::IsNot(cond) => cond.eval(actual),
+            Operation::ApproxIs(cond) => cond.eval(actual),
+            Operation::In(cond) => cond.eval(actual),
+            Operation::Contains(cond) => cond.eval(actual),
+            Operation::Quantified(cond) => cond.eval(actual),
+            _ => Ok(false),
+        }
+    }
+
+    /// Same as `test` with the value of `variable` in `context`, `None` if it isn't provided.
+    fn test_in(&self, idx: OpRef, variable: Pid, context: &Context<Pid>) -> Result<Option<bool>> {
+        context
+            .value(variable)
+            .map(|actual| self.test(idx, actual))
+            .transpose()
+    }
+
+    /// Result of the constant or condition at `idx`, `None` for operations with operands.
+    fn leaf(
+        &self,
+        idx: OpRef,
+        condition: &mut dyn FnMut(OpRef, Pid) -> Result<Option<bool>>,
+    ) -> Result<Option<Option<bool>>> {
+        let op = &self.ops[idx].0;
+        let decided = match op.variable() {
+            Some(variable) => condition(idx, variable)?,
+            None => None,
+        };
+        Ok(match *op {
+            Operation::Const(val) => Some(Some(val)),
+            Operation::In(ref cond) if cond.expected().is_empty() => Some(Some(false)),
+            Operation::Is(_)
+            | Operation::IsNot(_)
+            | Operation::ApproxIs(_)
+            | Operation::In(_)
+            | Operation::Contains(_)
+            | Operation::Quantified(_) => Some(decided),
+            _ => None,
+        })
+    }
+
+    /// Next step of folding the operation at `idx` given `results` of its operands folded
+    /// so far: cheaper operands go first, and the ones that can't change the result are skipped.
+    fn step(&self, idx: OpRef, costs: &[f64], results: &[Option<bool>]) -> Fold {
+        let cheaper = |lhs: OpRef, rhs: OpRef| {
+            if costs.get(rhs) < costs.get(lhs) {
+                (rhs, lhs)
+            } else {
+                (lhs, rhs)
+            }
+        };
+        // `Or` and `And` are decided by an operand equal to `decisive`, `Nand` and `Nor` negate them
+        let junction =
+            |(first, second): (OpRef, OpRef), decisive: bool, negated: bool| match *results {
+                [] => Fold::Operand(first),
+                [Some(val)] if val == decisive => Fold::Decided(Some(decisive != negated)),
+                [_] => Fold::Operand(second),
+                [_, Some(val)] if val == decisive => Fold::Decided(Some(decisive != negated)),
+                [Some(_), Some(_)] => Fold::Decided(Some(decisive == negated)),
+                _ => Fold::Decided(None),
+            };
+
+        match (&self.ops[idx].0, results) {
+            (&Operation::Not(opref), []) => Fold::Operand(opref),
+            (Operation::Not(_), &[result]) => Fold::Decided(result.map(|val| !val)),
+            (&Operation::Or(lhs, rhs), _) => junction(cheaper(lhs, rhs), true, false),
+            (&Operation::And(lhs, rhs), _) => junction(cheaper(lhs, rhs), false, false),
+            (&Operation::Nand(lhs, rhs), _) => junction(cheaper(lhs, rhs), false, true),
+            (&Operation::Nor(lhs, rhs), _) => junction(cheaper(lhs, rhs), true, true),
+            (&Operation::Implies(premise, _), []) => Fold::Operand(premise),
+            (Operation::Implies(_, _), [Some(false)]) => Fold::Decided(Some(true)),
+            (&Operation::Implies(_, conclusion), [_]) => Fold::Operand(conclusion),
+            (Operation::Implies(_, _), &[premise, conclusion]) => {
+                Fold::Decided(match (premise, conclusion) {
+                    (_, Some(true)) => Some(true),
+                    (Some(true), Some(false)) => Some(false),
+                    _ => None,
+                })
+            }
+            (&Operation::IfElse(cond, _, _), []) => Fold::Operand(cond),
+            (&Operation::IfElse(_, then, _), [Some(true)])
+            | (&Operation::IfElse(_, then, _), [None]) => Fold::Operand(then),
+            (&Operation::IfElse(_, _, otherwise), [Some(false)])
+            | (&Operation::IfElse(_, _, otherwise), [None, _]) => Fold::Operand(otherwise),
+            (Operation::IfElse(_, _, _), &[Some(_), result]) => Fold::Decided(result),
+            (Operation::IfElse(_, _, _), &[None, then, otherwise]) => match (then, otherwise) {
+                (Some(lval), Some(rval)) if lval == rval => Fold::Decided(Some(lval)),
+                _ => Fold::Decided(None),
+            },
+            // leaves are settled by `leaf` and never wait for operands
+            _ => Fold::Decided(None),
+        }
+    }
+
+    /// Folds the operation at `idx` on demand, visiting cheaper operands first
+    /// and skipping the ones that can't change the result anymore. Operations waiting for
+    /// their operands are kept on an explicit stack, so long chains don't overflow the call stack.
+    fn fold(
+        &self,
+        idx: OpRef,
+        costs: &[f64],
+        condition: &mut dyn FnMut(OpRef, Pid) -> Result<Option<bool>>,
+        folded: &mut [Option<Option<bool>>],
+    ) -> Result<Option<bool>> {
+        let mut pending: Vec<Pending> = Vec::new();
+        let mut visiting = idx;
+        loop {
+            let mut settled = match folded[visiting] {
+                Some(result) => Some(result),
+                None => self.leaf(visiting, condition)?,
+            };
+            match settled {
+                Some(result) => folded[visiting] = Some(result),
+                None => pending.push(Pending {
+                    idx: visiting,
+                    results: [None; 3],
+                    len: 0,
+                }),
+            }
+
+            // hand results over to operations waiting for them until one needs another operand
+            loop {
+                let top = match pending.last_mut() {
+                    Some(top) => top,
+                    None => return Ok(settled.unwrap_or(None)),
+                };
+                if let Some(result) = settled.take() {
+                    top.results[top.len] = result;
+                    top.len += 1;
+                }
+                match self.step(top.idx, costs, &top.results[..top.len]) {
+                    Fold::Operand(opref) if opref < top.idx => {
+                        visiting = opref;
+                        break;
+                    }
+                    Fold::Operand(opref) => {
+                        return Err(Error::ExpressionFutureReference(
+                            opref,
+                            top.idx,
+                            self.summary(),
+                        ))
+                    }
+                    Fold::Decided(result) => {
+                        folded[top.idx] = Some(result);
+                        pending.pop();
+                        settled = Some(result);
+                    }
+                }
+            }
+        }
+    }
+
+    fn folded(&self, folded: &[Option<Option<bool>>]) -> Operations<Pid> {
+        self.ops
+            .iter()
+            .zip(folded)
+            .map(|(op, result)| match *result {
+                Some(Some(val)) => (Operation::Const(val), op.1),
+                _ => op.clone(),
+            })
+            .collect()
+    }
+
+    /// Evaluates the expression from the root, visiting cheaper operands of `And`/`Or` first
+    /// so that they can short-circuit the expensive ones. Cost of an operand is the total cost
+    /// of the properties its conditions depend on.
+    ///
+    /// Whenever `eval` fully evaluates an expression, this method yields the same result.
+    pub fn eval_ordered(
+        &self,
+        context: &Context<Pid>,
+        cost: &dyn Fn(&Pid) -> f64,
+    ) -> Result<Evaluated<Pid>> {
+        let root = self.connected()?;
+        let reachable = self.reachable(root);
+        self.checked(&reachable)?;
+
+        let mut costs: Vec<f64> = Vec::with_capacity(self.ops.len());
+        for (op, _) in self.ops.iter() {
+            let own = match op {
+                Operation::Is(cond) => cost(&cond.variable()),
+                Operation::IsNot(cond) => cost(&cond.variable()),
+                Operation::ApproxIs(cond) => cost(&cond.variable()),
+                Operation::In(cond) => cost(&cond.variable()),
+                Operation::Contains(cond) => cost(&cond.variable()),
+                Operation::Quantified(cond) => cost(&cond.variable()),
+                _ => 0.0,
+            };
+            let operands: f64 = op
+                .operands()
+                .into_iter()
+                .filter_map(|opref| costs.get(opref))
+                .sum();
+            costs.push(own + operands);
+        }
+
+        let mut folded = vec![None; self.ops.len()];
+        let mut condition = |idx, variable| self.test_in(idx, variable, context);
+        self.fold(root, &costs, &mut condition, &mut folded)?;
+
+        Ok(self.evaluated(root, &reachable, &folded))
+    }
+}
+
+/// Resolves a property by its name, used by the `expr!` macro.
+#[doc(hidden)]
+pub fn lookup<Pid: Property + FromStr<Err = ParseError>>(name: &str) -> Result<Pid> {
+    Ok(Pid::from_str(name)?)
+}
+
+/// The alternate form (`{:#}`) is multi-line, see `Expression::pretty`.
+impl<Pid: Property> Display for Expression<Pid> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        if f.alternate() {
+            return write!(f, "{}", self.pretty(2));
+        }
+        write!(f, "{}", self.display(None))
+    }
+}
+
+impl<Pid: Property> Expression<Pid> {
+    fn same(
+        &self,
+        lhs: OpRef,
+        other: &Expression<Pid>,
+        rhs: OpRef,
+        equal: &mut HashSet<(OpRef, OpRef)>,
+    ) -> bool {
+        if equal.contains(&(lhs, rhs)) {
+            return true;
+        }
+
+        let (lop, rop) = match (self.ops.get(lhs), other.ops.get(rhs)) {
+            (Some(lop), Some(rop)) => (&lop.0, &rop.0),
+            _ => return false,
+        };
+
+        let same = match (lop, rop) {
+            (Operation::Const(_), _)
+            | (Operation::Is(_), _)
+            | (Operation::IsNot(_), _)
+            | (Operation::ApproxIs(_), _)
+            | (Operation::In(_), _)
+            | (Operation::Contains(_), _)
+            | (Operation::Quantified(_), _) => lop == rop,
+            _ if std::mem::discriminant(lop) == std::mem::discriminant(rop) => lop
+                .operands()
+                .into_iter()
+                .zip(rop.operands())
+                .all(|(l, r)| l < lhs && r < rhs && self.same(l, other, r, equal)),
+            _ => false,
+        };
+
+        if same {
+            equal.insert((lhs, rhs));
+        }
+        same
+    }
+}
+
+/// Expressions are equal when operation trees reachable from their roots are structurally equal.
+///
+/// Unreachable operations, reference counts and the order in which independent subtrees were
+/// built don't matter, operand order does: `a && b` is not equal to `b && a`.
+impl<Pid: Property> PartialEq for Expression<Pid> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.root(), other.root()) {
+            (Some(lhs), Some(rhs)) => self.same(lhs, other, rhs, &mut HashSet::new()),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<Pid: Property> Eq for Expression<Pid> {}
+
+/// Hashes the operation tree reachable from the root, consistently with `PartialEq`.
+///
+/// Hashing is structural: logically equivalent expressions of different shape
+/// (e.g. `a && b` and `b && a`) will most likely hash differently.
+impl<Pid: Property> Hash for Expression<Pid> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let root = match self.root() {
+            Some(root) => root,
+            None => return state.write_u8(0),
+        };
+
+        // operands precede their users, so subtree hashes can be computed in a single pass
+        let reachable = self.reachable(root);
+        let mut subtrees = vec![0u64; self.ops.len()];
+
+        for (idx, op) in self.ops.iter().enumerate().take(root + 1) {
+            if !reachable[idx] {
+                continue;
+            }
+
+            let mut hasher = DefaultHasher::new();
+            match &op.0 {
+                leaf @ Operation::Const(_)
+                | leaf @ Operation::Is(_)
+                | leaf @ Operation::IsNot(_)
+                | leaf @ Operation::ApproxIs(_)
+                | leaf @ Operation::In(_)
+                | leaf @ Operation::Contains(_)
+                | leaf @ Operation::Quantified(_) => leaf.hash(&mut hasher),
+                operator => {
+                    std::mem::discriminant(operator).hash(&mut hasher);
+                    for opref in operator.operands() {
+                        subtrees.get(opref).unwrap_or(&0).hash(&mut hasher);
+                    }
+                }
+            }
+            subtrees[idx] = hasher.finish();
+        }
+
+        state.write_u64(subtrees[root]);
+    }
+}
+
+impl<Pid: Property> Default for Expression<Pid> {
+    fn default() -> Self {
+        Expression::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::testproperty::{Coercible, Measured, Property, Rated};
+
+    #[test]
+    fn context_display() {
+        let mut context = Context::<Property>::request(vec![Property::Str, Property::Int]);
+        context.provide(Property::Int, Value::Int(42)).unwrap();
+        assert_eq!(
+            format!("{}", context),
+            "requested: [Property::Int, Property::Str], provided: [Property::Int=42]"
+        );
+
+        context
+            .provide(Property::Str, Value::Str("foo".to_owned()))
+            .unwrap();
+        assert_eq!(
+            format!("{}", context),
+            "requested: [Property::Int, Property::Str], \
+             provided: [Property::Int=42, Property::Str=foo]"
+        );
+
+        assert_eq!(
+            format!("{}", Context::<Property>::empty()),
+            "requested: [], provided: []"
+        );
+    }
+
+    #[test]
+    fn context_request() {
+        let props = vec![Property::Str, Property::Bool];
+        let context = Context::request(props.clone());
+
+        let expected = props.iter().collect::<HashSet<_>>();
+        let actual = context.requested().collect::<HashSet<_>>();
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn context_provide_known() {
+        let mut context = Context::request(vec![Property::Str]);
+        let res = context.provide(Property::Str, Value::Str("known".to_owned()));
+
+        assert!(matches!(res, Ok(())));
+
+        let provided = context.provided().collect::<HashSet<_>>();
+        assert!(provided.contains(&Property::Str));
+
+        let expected = Value::Str("known".to_owned());
+        assert_eq!(context.value(Property::Str), Some(&expected));
+    }
+
+    #[test]
+    fn context_provide_unknown() {
+        let mut context = Context::request(vec![Property::Bool]);
+        let res = context.provide(Property::Int, Value::Int(42));
+
+        assert!(matches!(res, Ok(())));
+
+        let provided = context.provided().collect::<HashSet<_>>();
+        assert!(!provided.contains(&Property::Int));
+        assert!(context.value(Property::Int).is_none());
+    }
+
+    #[test]
+    fn context_provide_type_mismatch() {
+        let mut context = Context::request(vec![Property::Bool]);
+        let res = context.provide(Property::Bool, Value::Int(42));
+
+        assert!(matches!(
+            res,
+            Err(Error::TypeMismatch(
+                "Property::Bool",
+                Datatype::Bool,
+                Datatype::Int
+            ))
+        ));
+    }
+
+    #[test]
+    fn context_provide_update() {
+        let mut context = Context::request(vec![Property::Str]);
+        context
             .provide(Property::Str, Value::Str("initial".to_owned()))
             .unwrap();
         assert!(
-            matches!(context.value(Property::Str), Some(Value::Str(x)) if x == "initial"),
-            "{}",
-            context
+            matches!(context.value(Property::Str), Some(Value::Str(x)) if x == "initial"),
+            "{}",
+            context
+        );
+
+        context
+            .provide(Property::Str, Value::Str("updated".to_owned()))
+            .unwrap();
+        assert!(
+            matches!(context.value(Property::Str), Some(Value::Str(x)) if x == "updated"),
+            "{}",
+            context
+        );
+    }
+
+    #[test]
+    fn context_snapshot_restore() {
+        let mut context = Context::request(vec![Property::Int, Property::Str]);
+        context.provide(Property::Int, Value::Int(42)).unwrap();
+        let snapshot = context.snapshot();
+
+        context.provide(Property::Int, Value::Int(24)).unwrap();
+        context
+            .provide(Property::Str, Value::from("what if"))
+            .unwrap();
+        context.restore(snapshot);
+
+        assert_eq!(context.value(Property::Int), Some(&Value::Int(42)));
+        assert_eq!(context.value(Property::Str), None);
+        assert_eq!(context.requested().count(), 2);
+
+        // the snapshot can be restored again after further changes
+        let snapshot = context.snapshot();
+        context
+            .provide(Property::Str, Value::from("again"))
+            .unwrap();
+        context.restore(snapshot.clone());
+        context.restore(snapshot);
+        assert_eq!(context.provided().count(), 1);
+    }
+
+    #[test]
+    fn context_provide_strict_known() {
+        let mut context = Context::request(vec![Property::Int]);
+        let res = context.provide_strict(Property::Int, Value::Int(42));
+
+        assert!(matches!(res, Ok(())));
+        assert_eq!(context.value(Property::Int), Some(&Value::Int(42)));
+    }
+
+    #[test]
+    fn context_provide_strict_unknown() {
+        let mut context = Context::request(vec![Property::Bool]);
+        let res = context.provide_strict(Property::Int, Value::Int(42));
+
+        assert!(
+            matches!(res, Err(Error::PropertyNotRequested("Property::Int"))),
+            "{:?}",
+            res
+        );
+        assert!(context.value(Property::Int).is_none());
+
+        // lenient version still ignores it
+        let res = context.provide(Property::Int, Value::Int(42));
+        assert!(matches!(res, Ok(())));
+        assert!(context.value(Property::Int).is_none());
+    }
+
+    #[test]
+    fn context_provide_strict_type_mismatch() {
+        let mut context = Context::request(vec![Property::Bool]);
+
+        // type is checked before the request
+        let res = context.provide_strict(Property::Int, Value::Bool(true));
+        assert!(matches!(
+            res,
+            Err(Error::TypeMismatch(
+                "Property::Int",
+                Datatype::Int,
+                Datatype::Bool
+            ))
+        ));
+    }
+
+    #[test]
+    fn context_from_values() {
+        let values = vec![
+            (Property::Int, Value::Int(42)),
+            (Property::Str, Value::Str("value".to_owned())),
+        ];
+        let context = Context::from_values(values).unwrap();
+
+        let expected = vec![Property::Int, Property::Str]
+            .into_iter()
+            .collect::<HashSet<_>>();
+        assert_eq!(
+            context.requested().copied().collect::<HashSet<_>>(),
+            expected
+        );
+        assert_eq!(
+            context.provided().copied().collect::<HashSet<_>>(),
+            expected
+        );
+        assert_eq!(context.value(Property::Int), Some(&Value::Int(42)));
+    }
+
+    #[test]
+    fn context_from_values_type_mismatch() {
+        let values = vec![
+            (Property::Int, Value::Int(42)),
+            (Property::Bool, Value::Str("value".to_owned())),
+        ];
+        let context = Context::from_values(values);
+
+        assert!(matches!(
+            context,
+            Err(Error::TypeMismatch(
+                "Property::Bool",
+                Datatype::Bool,
+                Datatype::Str
+            ))
+        ));
+    }
+
+    #[test]
+    fn expression_variables() {
+        let mut expr = Expression::<Property>::new();
+
+        let a = expr.constant(true).unwrap();
+        let b = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let c = expr
+            .is_in(
+                Property::Str,
+                vec![
+                    Value::Str("a match".to_owned()),
+                    Value::Str("another match".to_owned()),
+                ],
+            )
+            .unwrap();
+
+        let a_and_b = expr.and(a, b).unwrap();
+        let _root = expr.or(a_and_b, c);
+
+        let requested = expr
+            .variables()
+            .requested()
+            .copied()
+            .collect::<HashSet<_>>();
+        let expected = vec![Property::Int, Property::Str]
+            .into_iter()
+            .collect::<HashSet<_>>();
+        assert_eq!(requested, expected);
+    }
+
+    #[test]
+    fn expression_is_in_sorted() {
+        let values = (0..500).map(|idx| idx * 3).collect::<Vec<i64>>();
+        let mut hashed = Expression::<Property>::new();
+        hashed.is_in(Property::Int, values.clone()).unwrap();
+        let mut sorted = Expression::<Property>::new();
+        sorted.is_in_sorted(Property::Int, values).unwrap();
+        assert_eq!(sorted, hashed);
+
+        for probe in (-10..1600).step_by(7) {
+            let context = Context::from_values(vec![(Property::Int, Value::Int(probe))]).unwrap();
+            assert_eq!(
+                sorted.eval(&context).unwrap().as_bool(),
+                hashed.eval(&context).unwrap().as_bool(),
+                "{}",
+                probe
+            );
+        }
+
+        let result = sorted.is_in_sorted(Property::Int, vec![Value::Int(1), Value::Bool(true)]);
+        assert!(
+            matches!(result, Err(Error::TypeMismatch(_, _, _))),
+            "{:?}",
+            result
+        );
+        assert_eq!(sorted.len(), 1);
+    }
+
+    #[test]
+    fn expression_new_dedup() {
+        let mut expr = Expression::<Property>::new_dedup();
+        assert!(expr.is_dedup());
+        let a = expr.is(Property::Int, 42).unwrap();
+        let b = expr.is_in(Property::Str, vec!["a", "b"]).unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        assert_eq!(expr.is(Property::Int, 42).unwrap(), a);
+        assert_eq!(expr.is_in(Property::Str, vec!["b", "a"]).unwrap(), b);
+        assert_ne!(expr.is_not(Property::Int, 42).unwrap(), a);
+        let c = expr.is(Property::Int, 24).unwrap();
+        let _ = expr.and(a_or_b, c).unwrap();
+
+        // only conditions are deduplicated
+        let a_or_b_again = expr.or(a, b).unwrap();
+        assert_ne!(a_or_b_again, a_or_b);
+        assert_eq!(expr.len(), 7);
+        assert_eq!(expr.ops[a].1, 2);
+
+        let mut expr = Expression::<Property>::new();
+        assert!(!expr.is_dedup());
+        let a = expr.is(Property::Int, 42).unwrap();
+        assert_ne!(expr.is(Property::Int, 42).unwrap(), a);
+    }
+
+    #[test]
+    fn expression_is_constant() {
+        let mut expr = Expression::<Property>::new();
+        let t = expr.constant(true).unwrap();
+        let f = expr.constant(false).unwrap();
+        let empty = expr.is_in(Property::Int, Vec::<i64>::new()).unwrap();
+        let t_and_f = expr.and(t, f).unwrap();
+        let not_empty = expr.not(empty).unwrap();
+        let _ = expr.or(t_and_f, not_empty).unwrap();
+        assert!(expr.is_constant());
+        assert!(expr.variables().requested().next().is_none());
+        assert_eq!(expr.eval(&Context::empty()).unwrap().as_bool(), Some(true));
+
+        // conditions count even if they're not connected to the root yet
+        let _ = expr.contains(Property::Str, "match").unwrap();
+        assert!(!expr.is_constant());
+
+        let mut expr = Expression::<Property>::new();
+        let _ = expr.is(Property::Bool, true).unwrap();
+        assert!(!expr.is_constant());
+    }
+
+    #[test]
+    fn expression_property_datatypes() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 42).unwrap();
+        let b = expr.contains(Property::Str, "match").unwrap();
+        let c = expr.is_not(Property::Int, 7).unwrap();
+        let d = expr.is(Property::Bool, true).unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let c_and_d = expr.and(c, d).unwrap();
+        let _ = expr.implies(a_or_b, c_and_d).unwrap();
+
+        let expected = vec![
+            (Property::Int, Datatype::Int),
+            (Property::Str, Datatype::Str),
+            (Property::Bool, Datatype::Bool),
+        ];
+        assert_eq!(
+            expr.property_datatypes(),
+            expected.into_iter().collect::<HashMap<_, _>>()
+        );
+        assert!(Expression::<Property>::new()
+            .property_datatypes()
+            .is_empty());
+    }
+
+    #[test]
+    fn expression_outofbounds() {
+        let mut expr = Expression::<Property>::new();
+
+        let result = expr.not(99);
+        assert!(
+            matches!(result, Err(Error::ExpressionOutOfBounds(s, e, _)) if s == 99 && e == 0),
+            "{:?}",
+            result
+        );
+
+        expr.constant(true).unwrap();
+        expr.constant(false).unwrap();
+
+        let result = expr.not(99);
+        assert!(
+            matches!(result, Err(Error::ExpressionOutOfBounds(s, e, _)) if s == 99 && e == 1),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn expression_eval_success_no_variables() {
+        let mut expr = Expression::<Property>::new();
+
+        let a = expr.constant(true).unwrap();
+        let b = expr.constant(false).unwrap();
+        let aorb = expr.or(a, b).unwrap();
+        let _ = expr.and(aorb, b);
+
+        let result = expr.eval(&Context::empty());
+        let evaluated = result.unwrap();
+
+        assert!(
+            matches!(evaluated, Evaluated::Fully(x, _, _) if !x),
+            "{:?}",
+            evaluated
+        );
+    }
+
+    #[test]
+    fn expression_eval_success_variables() {
+        let mut expr = Expression::<Property>::new();
+
+        let a = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+        let b = expr
+            .is_in(Property::Int, vec![Value::Int(41), Value::Int(42)])
+            .unwrap();
+
+        let _ = expr.and(a, b).unwrap();
+        let mut context = expr.variables();
+
+        // #1: a is true, b is false
+        context.provide(Property::Bool, Value::Bool(true)).unwrap();
+        context.provide(Property::Int, Value::Int(99)).unwrap();
+
+        let a_not_b = expr.eval(&context);
+        assert!(matches!(a_not_b, Ok(Evaluated::Fully(x, _, _)) if !x));
+
+        // #2: a is false, b is true
+        context.provide(Property::Bool, Value::Bool(false)).unwrap();
+        context.provide(Property::Int, Value::Int(41)).unwrap();
+
+        let b_not_a = expr.eval(&context);
+        assert!(matches!(b_not_a, Ok(Evaluated::Fully(x, _, _)) if !x));
+
+        // #3: a is true, b is true
+        context.provide(Property::Bool, Value::Bool(true)).unwrap();
+        context.provide(Property::Int, Value::Int(42)).unwrap();
+
+        let a_b = expr.eval(&context);
+        assert!(
+            matches!(a_b, Ok(Evaluated::Fully(x, _, _)) if x),
+            "result: '{}', expression: '{}', context: '{}'",
+            a_b.unwrap(),
+            expr,
+            context
+        );
+    }
+
+    #[test]
+    fn expression_eval_success_partial_no_vars() {
+        let mut expr = Expression::<Property>::new();
+
+        let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let b = expr.constant(true).unwrap();
+        let _ = expr.and(a, b).unwrap();
+
+        let requested = expr.variables().requested().copied().collect::<Vec<_>>();
+        assert_eq!(requested, vec![Property::Int]);
+
+        let result = expr.eval(&Context::empty());
+        assert!(matches!(result, Ok(Evaluated::Partially(_))));
+
+        if let Ok(Evaluated::Partially(partexpr)) = result {
+            let requested = partexpr
+                .variables()
+                .requested()
+                .copied()
+                .collect::<Vec<_>>();
+            assert_eq!(requested, vec![Property::Int]);
+        }
+    }
+
+    #[test]
+    fn expression_eval_success_partial_some_vars() {
+        let mut expr = Expression::<Property>::new();
+
+        let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let b = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+        let _ = expr.or(a, b).unwrap();
+
+        let requested = expr
+            .variables()
+            .requested()
+            .copied()
+            .collect::<HashSet<_>>();
+
+        let expected = vec![Property::Int, Property::Bool]
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        assert_eq!(requested, expected);
+
+        // a false operand doesn't decide `Or` on its own
+        let mut context = expr.variables();
+        context.provide(Property::Int, Value::Int(41)).unwrap();
+
+        let result = expr.eval(&context);
+        assert!(matches!(result, Ok(Evaluated::Partially(_))));
+
+        if let Ok(Evaluated::Partially(partexpr)) = result {
+            let context = partexpr.variables();
+            let requested = context.requested().copied().collect::<Vec<_>>();
+
+            assert_eq!(
+                requested,
+                vec![Property::Bool],
+                "expression: {}, context: {}",
+                partexpr,
+                context
+            );
+        }
+    }
+
+    #[test]
+    fn expression_eval_missing() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 42).unwrap();
+        let b = expr.contains(Property::Str, "match").unwrap();
+        let c = expr.is(Property::Bool, true).unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let _ = expr.and(a_or_b, c).unwrap();
+
+        let missing = |context: &Context<Property>| expr.eval(context).unwrap().missing();
+        let expected = |props: &[Property]| props.iter().copied().collect::<HashSet<_>>();
+
+        assert_eq!(
+            missing(&Context::empty()),
+            expected(&[Property::Int, Property::Str, Property::Bool])
+        );
+
+        // `Or` is already true, its other operand can't change the result anymore
+        let context = Context::from_values(vec![(Property::Int, Value::Int(42))]).unwrap();
+        assert_eq!(missing(&context), expected(&[Property::Bool]));
+
+        let context = Context::from_values(vec![(Property::Int, Value::Int(1))]).unwrap();
+        assert_eq!(
+            missing(&context),
+            expected(&[Property::Str, Property::Bool])
+        );
+
+        let context = Context::from_values(vec![(Property::Bool, Value::Bool(false))]).unwrap();
+        assert_eq!(missing(&context), expected(&[]));
+
+        // the branch not taken doesn't matter
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Bool, true).unwrap();
+        let b = expr.is(Property::Int, 42).unwrap();
+        let c = expr.contains(Property::Str, "match").unwrap();
+        let _ = expr.if_else(a, b, c).unwrap();
+
+        let context = Context::from_values(vec![(Property::Bool, Value::Bool(false))]).unwrap();
+        let result = expr.eval(&context).unwrap();
+        assert_eq!(result.missing(), expected(&[Property::Str]));
+    }
+
+    /// Operand with the given three-valued result against an empty context.
+    fn operand(expr: &mut Expression<Property>, value: Tri) -> OpRef {
+        match value {
+            Tri::True => expr.constant(true).unwrap(),
+            Tri::False => expr.constant(false).unwrap(),
+            Tri::Unknown => expr.is(Property::Int, 42).unwrap(),
+        }
+    }
+
+    fn eval_3vl(build: impl FnOnce(&mut Expression<Property>) -> OpRef) -> Tri {
+        let mut expr = Expression::<Property>::new();
+        build(&mut expr);
+        let result = expr.eval_3vl(&Context::empty()).unwrap();
+        assert_eq!(
+            result,
+            expr.eval(&Context::empty()).unwrap().as_bool().into()
+        );
+        result
+    }
+
+    #[test]
+    fn expression_eval_unresolved_variables() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 42).unwrap();
+        let b = expr.is(Property::Bool, true).unwrap();
+        let _ = expr.or(a, b).unwrap();
+
+        let context = Context::from_values(vec![(Property::Int, Value::Int(41))]).unwrap();
+        let result = expr.eval(&context).unwrap();
+        assert_eq!(result.unresolved_variables(), vec![Property::Bool]);
+
+        let context = Context::from_values(vec![(Property::Bool, Value::Bool(true))]).unwrap();
+        assert!(expr
+            .eval(&context)
+            .unwrap()
+            .unresolved_variables()
+            .is_empty());
+
+        // conditions that can't change the result are still unresolved, but not missing
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 42).unwrap();
+        let b = expr.contains(Property::Str, "match").unwrap();
+        let c = expr.is(Property::Bool, true).unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let _ = expr.and(a_or_b, c).unwrap();
+
+        let context = Context::from_values(vec![(Property::Int, Value::Int(42))]).unwrap();
+        let result = expr.eval(&context).unwrap();
+        assert_eq!(
+            result.unresolved_variables(),
+            vec![Property::Str, Property::Bool]
+        );
+        assert_eq!(result.missing(), vec![Property::Bool].into_iter().collect());
+    }
+
+    #[test]
+    fn expression_eval_3vl_truth_tables() {
+        use Tri::{False as F, True as T, Unknown as U};
+        let values = [T, F, U];
+
+        for (value, expected) in values.iter().zip(&[F, T, U]) {
+            let result = eval_3vl(|expr| {
+                let opref = operand(expr, *value);
+                expr.not(opref).unwrap()
+            });
+            assert_eq!(result, *expected, "not {:?}", value);
+        }
+
+        let binary = [
+            // lhs, rhs: and, or, implies
+            (T, T, [T, T, T]),
+            (T, F, [F, T, F]),
+            (T, U, [U, T, U]),
+            (F, T, [F, T, T]),
+            (F, F, [F, F, T]),
+            (F, U, [F, U, T]),
+            (U, T, [U, T, T]),
+            (U, F, [F, U, U]),
+            (U, U, [U, U, U]),
+        ];
+        for &(lhs, rhs, expected) in binary.iter() {
+            let results = [
+                eval_3vl(|expr| {
+                    let (lhs, rhs) = (operand(expr, lhs), operand(expr, rhs));
+                    expr.and(lhs, rhs).unwrap()
+                }),
+                eval_3vl(|expr| {
+                    let (lhs, rhs) = (operand(expr, lhs), operand(expr, rhs));
+                    expr.or(lhs, rhs).unwrap()
+                }),
+                eval_3vl(|expr| {
+                    let (lhs, rhs) = (operand(expr, lhs), operand(expr, rhs));
+                    expr.implies(lhs, rhs).unwrap()
+                }),
+            ];
+            assert_eq!(results, expected, "{:?}, {:?}", lhs, rhs);
+        }
+
+        for &cond in values.iter() {
+            for &then in values.iter() {
+                for &otherwise in values.iter() {
+                    let expected = match cond {
+                        T => then,
+                        F => otherwise,
+                        U if then == otherwise => then,
+                        U => U,
+                    };
+                    let result = eval_3vl(|expr| {
+                        let cond = operand(expr, cond);
+                        let then = operand(expr, then);
+                        let otherwise = operand(expr, otherwise);
+                        expr.if_else(cond, then, otherwise).unwrap()
+                    });
+                    assert_eq!(result, expected, "if {:?} {:?} {:?}", cond, then, otherwise);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn expression_nand_nor_truth_tables() {
+        use Tri::{False as F, True as T, Unknown as U};
+        let binary = [
+            // lhs, rhs: nand, nor
+            (T, T, [F, F]),
+            (T, F, [T, F]),
+            (T, U, [U, F]),
+            (F, T, [T, F]),
+            (F, F, [T, T]),
+            (F, U, [T, U]),
+            (U, T, [U, F]),
+            (U, F, [T, U]),
+            (U, U, [U, U]),
+        ];
+        for &(lhs, rhs, expected) in binary.iter() {
+            let results = [
+                eval_3vl(|expr| {
+                    let (lhs, rhs) = (operand(expr, lhs), operand(expr, rhs));
+                    expr.nand(lhs, rhs).unwrap()
+                }),
+                eval_3vl(|expr| {
+                    let (lhs, rhs) = (operand(expr, lhs), operand(expr, rhs));
+                    expr.nor(lhs, rhs).unwrap()
+                }),
+            ];
+            assert_eq!(results, expected, "{:?}, {:?}", lhs, rhs);
+        }
+    }
+
+    #[test]
+    fn expression_nand_nor_partial() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 42).unwrap();
+        let b = expr.is(Property::Bool, true).unwrap();
+        let nand = expr.nand(a, b).unwrap();
+        let c = expr.contains(Property::Str, "rock").unwrap();
+        let _ = expr.nor(nand, c).unwrap();
+        assert_eq!(
+            expr.to_string(),
+            "!(!(Property::Int (Int) == 42 && Property::Bool (Bool) == true) || \
+             Property::Str (Str) contains 'rock')"
+        );
+
+        // `false` decides `nand`, which in turn decides `nor` without looking at Str
+        let context = Context::from_values(vec![(Property::Bool, Value::Bool(false))]).unwrap();
+        assert!(matches!(
+            expr.eval(&context).unwrap(),
+            Evaluated::Fully(false, _, _)
+        ));
+
+        let context = Context::from_values(vec![
+            (Property::Int, Value::Int(42)),
+            (Property::Bool, Value::Bool(true)),
+        ])
+        .unwrap();
+        match expr.eval(&context).unwrap() {
+            Evaluated::Partially(partial) => assert_eq!(
+                partial.to_string(),
+                "!(false || Property::Str (Str) contains 'rock')"
+            ),
+            result => panic!("unexpected result {}", result),
+        }
+        let context = Context::from_values(vec![
+            (Property::Int, Value::Int(42)),
+            (Property::Bool, Value::Bool(true)),
+            (Property::Str, Value::from("pop")),
+        ])
+        .unwrap();
+        assert!(matches!(
+            expr.eval(&context).unwrap(),
+            Evaluated::Fully(true, _, _)
+        ));
+
+        let simple = expr.simplify().unwrap();
+        let nnf = expr.to_nnf().unwrap();
+        for (_, op, _) in nnf.iter() {
+            assert!(
+                !matches!(op, Operation::Nand(_, _) | Operation::Nor(_, _)),
+                "{}",
+                nnf
+            );
+        }
+        for &(int, flag) in [(42, true), (42, false), (1, true)].iter() {
+            for &needle in ["rock", "pop"].iter() {
+                let context = Context::from_values(vec![
+                    (Property::Int, Value::Int(int)),
+                    (Property::Bool, Value::Bool(flag)),
+                    (Property::Str, Value::from(needle)),
+                ])
+                .unwrap();
+                let expected = expr.eval(&context).unwrap().as_bool();
+                assert_eq!(simple.eval(&context).unwrap().as_bool(), expected);
+                assert_eq!(nnf.eval(&context).unwrap().as_bool(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn expression_eval_3vl_errors() {
+        let result = Expression::<Property>::new().eval_3vl(&Context::empty());
+        assert!(matches!(result, Err(Error::ExpressionNoop)), "{:?}", result);
+
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Bool, true).unwrap();
+        let b = expr.is_in(Property::Int, vec![1, 2]).unwrap();
+        let _ = expr.or(a, b).unwrap();
+        let context = poisoned(vec![(Property::Bool, Value::Bool(true))]);
+        assert_eq!(expr.eval_3vl(&context).unwrap(), Tri::True);
+        let context = poisoned(vec![(Property::Bool, Value::Bool(false))]);
+        assert!(expr.eval_3vl(&context).is_err());
+    }
+
+    #[test]
+    fn expression_eval_constant() {
+        let mut expr = Expression::<Property>::new();
+        let t = expr.constant(true).unwrap();
+        let f = expr.constant(false).unwrap();
+        let not_f = expr.not(f).unwrap();
+        let t_and_not_f = expr.and(t, not_f).unwrap();
+        let _ = expr.implies(t_and_not_f, f).unwrap();
+
+        let result = expr.eval(&Context::empty()).unwrap();
+        assert!(matches!(result, Evaluated::Fully(false, ref log, _) if log.is_empty()));
+        assert_eq!(
+            expr.explain(&Context::empty()).unwrap(),
+            "failed because it's constant false"
+        );
+
+        // conditions anywhere in the expression need the full log
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 42).unwrap();
+        let t = expr.constant(true).unwrap();
+        let _ = expr.or(t, a).unwrap();
+
+        let result = expr.eval(&Context::empty()).unwrap();
+        assert!(matches!(result, Evaluated::Fully(true, ref log, _) if log.len() == 3));
+        assert_eq!(result.skipped(), Some(&[a][..]));
+        let result =
+            expr.eval(&Context::from_values(vec![(Property::Int, Value::Int(1))]).unwrap());
+        assert_eq!(result.unwrap().as_bool(), Some(true));
+    }
+
+    /// Context where reading `Property::Int` fails: the value bypasses validation.
+    fn poisoned(provided: Vec<(Property, Value)>) -> Context<Property> {
+        let mut context = Context::from_values(provided).unwrap();
+        context
+            .provided
+            .insert(Property::Int, Value::Str("poison".to_owned()));
+        context
+    }
+
+    #[test]
+    fn expression_eval_short_circuit() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Bool, true).unwrap();
+        let expensive = expr
+            .is_in(Property::Int, (0..10_000).collect::<Vec<i32>>())
+            .unwrap();
+        let a_or_expensive = expr.or(a, expensive).unwrap();
+        let a_and_expensive = expr.and(a, expensive).unwrap();
+        let _ = expr.if_else(a, a_or_expensive, a_and_expensive).unwrap();
+
+        let context = poisoned(vec![(Property::Bool, Value::Bool(true))]);
+        let result = expr.eval(&context).unwrap();
+        assert_eq!(result.as_bool(), Some(true));
+        assert_eq!(result.skipped(), Some(&[expensive, a_and_expensive][..]));
+        assert!(format!("{}", result).contains("<skipped>"), "{}", result);
+
+        let context = poisoned(vec![(Property::Bool, Value::Bool(false))]);
+        let result = expr.eval(&context).unwrap();
+        assert_eq!(result.as_bool(), Some(false));
+        assert_eq!(result.skipped(), Some(&[expensive, a_or_expensive][..]));
+
+        // the property is read once the result depends on it
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Bool, false).unwrap();
+        let b = expr.is_in(Property::Int, vec![1, 2]).unwrap();
+        let _ = expr.or(a, b).unwrap();
+        let result = expr.eval(&poisoned(vec![(Property::Bool, Value::Bool(true))]));
+        assert!(
+            matches!(result, Err(Error::TypeMismatch("Property::Int", _, _))),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn expression_eval_short_circuit_unknown_operand() {
+        // decided by either operand, even if the one visited first is unknown
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Str, "foo").unwrap();
+        let b = expr.is(Property::Bool, true).unwrap();
+        let c = expr.is(Property::Int, 42).unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let not_b = expr.not(b).unwrap();
+        let c_and_not_b = expr.and(c, not_b).unwrap();
+        let _ = expr.and(a_or_b, c_and_not_b).unwrap();
+
+        let context = Context::from_values(vec![(Property::Bool, Value::Bool(true))]).unwrap();
+        let result = expr.eval(&context).unwrap();
+        assert_eq!(result.as_bool(), Some(false), "{}", result);
+        assert_eq!(result.skipped(), Some(&[][..]));
+
+        let log = result.log().unwrap();
+        assert!(matches!(log[a].0, Operation::Is(_)));
+        assert!(matches!(log[c].0, Operation::Is(_)));
+        assert_eq!(log[a_or_b].0, Operation::Const(true));
+    }
+
+    #[test]
+    fn expression_implies_display() {
+        let mut expr = Expression::<Property>::new();
+
+        let p = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+        let q = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let _ = expr.implies(p, q).unwrap();
+
+        assert_eq!(
+            format!("{}", expr),
+            "(Property::Bool (Bool) == true -> Property::Int (Int) == 42)"
+        );
+    }
+
+    #[test]
+    fn expression_implies_false_premise() {
+        let mut expr = Expression::<Property>::new();
+
+        let p = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+        let q = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let _ = expr.implies(p, q).unwrap();
+
+        let mut context = expr.variables();
+        context.provide(Property::Bool, Value::Bool(false)).unwrap();
+
+        let result = expr.eval(&context);
+        assert!(
+            matches!(result, Ok(Evaluated::Fully(x, _, _)) if x),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn expression_implies_true_premise() {
+        let mut expr = Expression::<Property>::new();
+
+        let p = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+        let q = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let _ = expr.implies(p, q).unwrap();
+
+        let mut context = expr.variables();
+        context.provide(Property::Bool, Value::Bool(true)).unwrap();
+
+        let result = expr.eval(&context);
+        assert!(
+            matches!(result, Ok(Evaluated::Partially(_))),
+            "{:?}",
+            result
+        );
+
+        context.provide(Property::Int, Value::Int(24)).unwrap();
+        let result = expr.eval(&context);
+        assert!(
+            matches!(result, Ok(Evaluated::Fully(x, _, _)) if !x),
+            "{:?}",
+            result
+        );
+
+        context.provide(Property::Int, Value::Int(42)).unwrap();
+        let result = expr.eval(&context);
+        assert!(
+            matches!(result, Ok(Evaluated::Fully(x, _, _)) if x),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn expression_if_else_display() {
+        let mut expr = Expression::<Property>::new();
+
+        let c = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+        let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let b = expr.constant(false).unwrap();
+        let _ = expr.if_else(c, a, b).unwrap();
+
+        assert_eq!(
+            format!("{}", expr),
+            "(if Property::Bool (Bool) == true then Property::Int (Int) == 42 else false)"
+        );
+    }
+
+    #[test]
+    fn expression_if_else_untaken_branch() {
+        let mut expr = Expression::<Property>::new();
+
+        let c = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+        let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let b1 = expr
+            .is_in(
+                Property::Str,
+                vec![Value::Str("a".to_owned()), Value::Str("b".to_owned())],
+            )
+            .unwrap();
+        let b2 = expr.is(Property::Int, Value::Int(24)).unwrap();
+        let b = expr.and(b1, b2).unwrap();
+        let _ = expr.if_else(c, a, b).unwrap();
+
+        // then-branch is taken, else-branch subtree never folds
+        let mut context = expr.variables();
+        context.provide(Property::Bool, Value::Bool(true)).unwrap();
+        context.provide(Property::Int, Value::Int(42)).unwrap();
+
+        let result = expr.eval(&context);
+        assert!(
+            matches!(result, Ok(Evaluated::Fully(x, _, _)) if x),
+            "{:?}",
+            result
+        );
+
+        // else-branch is taken, but it's not fully known yet
+        context.provide(Property::Bool, Value::Bool(false)).unwrap();
+        context.provide(Property::Int, Value::Int(24)).unwrap();
+
+        let result = expr.eval(&context);
+        assert!(
+            matches!(result, Ok(Evaluated::Partially(_))),
+            "{:?}",
+            result
+        );
+
+        context
+            .provide(Property::Str, Value::Str("c".to_owned()))
+            .unwrap();
+
+        let result = expr.eval(&context);
+        assert!(
+            matches!(result, Ok(Evaluated::Fully(x, _, _)) if !x),
+            "{:?}",
+            result
+        );
+        assert_eq!(result.unwrap().skipped(), Some(&[a, b2][..]));
+    }
+
+    #[test]
+    fn expression_macro_matches_builder() {
+        let macroed: Expression<Property> = crate::expr! {
+            (Int == 42) && (Str in ["a", "b"]) || !(Bool == true)
+        }
+        .unwrap();
+
+        let mut built = Expression::<Property>::new();
+        let a = built.is(Property::Int, Value::Int(42)).unwrap();
+        let b = built
+            .is_in(
+                Property::Str,
+                vec![Value::Str("a".to_owned()), Value::Str("b".to_owned())],
+            )
+            .unwrap();
+        let a_and_b = built.and(a, b).unwrap();
+        let c = built.is(Property::Bool, Value::Bool(true)).unwrap();
+        let not_c = built.not(c).unwrap();
+        let _ = built.or(a_and_b, not_c).unwrap();
+
+        assert_eq!(format!("{}", macroed), format!("{}", built));
+
+        let contexts = vec![
+            vec![
+                (Property::Int, Value::Int(42)),
+                (Property::Str, Value::Str("a".to_owned())),
+                (Property::Bool, Value::Bool(true)),
+            ],
+            vec![
+                (Property::Int, Value::Int(24)),
+                (Property::Str, Value::Str("a".to_owned())),
+                (Property::Bool, Value::Bool(true)),
+            ],
+            vec![
+                (Property::Int, Value::Int(24)),
+                (Property::Str, Value::Str("c".to_owned())),
+                (Property::Bool, Value::Bool(false)),
+            ],
+        ];
+
+        for values in contexts {
+            let context = Context::from_values(values).unwrap();
+            let lhs = macroed.eval(&context).unwrap();
+            let rhs = built.eval(&context).unwrap();
+            assert!(
+                matches!((&lhs, &rhs), (Evaluated::Fully(l, _, _), Evaluated::Fully(r, _, _)) if l == r),
+                "macro: {}, builder: {}",
+                lhs,
+                rhs
+            );
+        }
+    }
+
+    #[test]
+    fn expression_macro_precedence() {
+        let expr: Expression<Property> =
+            crate::expr! { !Bool == true || Int == 1 && true }.unwrap();
+        assert_eq!(
+            format!("{}", expr),
+            "(!(Property::Bool (Bool) == true) || (Property::Int (Int) == 1 && true))"
+        );
+    }
+
+    #[test]
+    fn expression_macro_errors() {
+        let notfound: Result<Expression<Property>> = crate::expr! { Float == 42 };
+        assert!(matches!(notfound, Err(Error::IdentifierNotFound(_))));
+
+        let mismatch: Result<Expression<Property>> = crate::expr! { Int in [1, "2"] };
+        assert!(matches!(
+            mismatch,
+            Err(Error::TypeMismatch(
+                "Property::Int",
+                Datatype::Int,
+                Datatype::Str
+            ))
+        ));
+    }
+
+    #[test]
+    fn evaluated_accessors_fully() {
+        let mut expr = Expression::<Property>::new();
+        let _ = expr.is(Property::Int, Value::Int(42)).unwrap();
+
+        let context = Context::from_values(vec![(Property::Int, Value::Int(42))]).unwrap();
+        let evaluated = expr.eval(&context).unwrap();
+
+        assert_eq!(evaluated.as_bool(), Some(true));
+        assert!(evaluated.into_expression().is_none());
+    }
+
+    #[test]
+    fn evaluated_log() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let b = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+        let _ = expr.and(a, b).unwrap();
+
+        let context = Context::from_values(vec![
+            (Property::Int, Value::Int(42)),
+            (Property::Bool, Value::Bool(false)),
+        ])
+        .unwrap();
+        let evaluated = expr.eval(&context).unwrap();
+
+        let log: &[(Operation<Property>, RefCount)] = evaluated.log().unwrap();
+        assert_eq!(log.len(), 3);
+        assert_eq!(log[0], (Operation::Const(true), 1));
+        assert_eq!(
+            log.last(),
+            Some(&(Operation::Const(evaluated.as_bool().unwrap()), 0))
+        );
+    }
+
+    #[test]
+    fn evaluated_accessors_partially() {
+        let mut expr = Expression::<Property>::new();
+        let _ = expr.is(Property::Int, Value::Int(42)).unwrap();
+
+        let evaluated = expr.eval(&Context::empty()).unwrap();
+
+        assert_eq!(evaluated.as_bool(), None);
+        assert!(evaluated.log().is_none());
+        let partial = evaluated.into_expression().unwrap();
+        assert_eq!(format!("{}", partial), format!("{}", expr));
+    }
+
+    #[test]
+    fn expression_append_empty() {
+        let mut expr = Expression::<Property>::new();
+        expr.constant(true).unwrap();
+
+        let result = expr.append(&Expression::new());
+        assert!(matches!(result, Err(Error::ExpressionNoop)), "{:?}", result);
+    }
+
+    #[test]
+    fn expression_append_twice() {
+        let mut fragment = Expression::<Property>::new();
+        let a = fragment.is(Property::Int, Value::Int(42)).unwrap();
+        let b = fragment.is(Property::Bool, Value::Bool(true)).unwrap();
+        let _ = fragment.or(a, b).unwrap();
+
+        let mut expr = Expression::<Property>::new();
+        let own = expr
+            .is(Property::Str, Value::Str("own".to_owned()))
+            .unwrap();
+        let first = expr.append(&fragment).unwrap();
+        let own_and_first = expr.and(own, first).unwrap();
+        let second = expr.append(&fragment).unwrap();
+        let _ = expr.and(own_and_first, second).unwrap();
+
+        assert_eq!(first, 3);
+        assert_eq!(second, 7);
+        assert_eq!(
+            format!("{}", expr),
+            format!("((Property::Str (Str) == own && {0}) && {0})", fragment)
+        );
+
+        let mut context = expr.variables();
+        context
+            .provide(Property::Str, Value::Str("own".to_owned()))
+            .unwrap();
+        context.provide(Property::Int, Value::Int(24)).unwrap();
+        context.provide(Property::Bool, Value::Bool(true)).unwrap();
+
+        let result = expr.eval(&context);
+        assert!(
+            matches!(result, Ok(Evaluated::Fully(x, _, _)) if x),
+            "{:?}",
+            result
+        );
+
+        context.provide(Property::Bool, Value::Bool(false)).unwrap();
+        let result = expr.eval(&context);
+        assert!(
+            matches!(result, Ok(Evaluated::Fully(x, _, _)) if !x),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn expression_append_relaxed() {
+        let mut fragment = Expression::<Property>::relaxed();
+        let a = fragment.is(Property::Bool, true).unwrap();
+        let b = fragment.is(Property::Int, "42").unwrap();
+        let _ = fragment.or(a, b).unwrap();
+
+        let mut strict = Expression::<Property>::new();
+        strict.constant(true).unwrap();
+        let result = strict.append(&fragment);
+        assert!(
+            matches!(
+                result,
+                Err(Error::TypeMismatch(
+                    "Property::Int",
+                    Datatype::Int,
+                    Datatype::Str
+                ))
+            ),
+            "{:?}",
+            result
+        );
+        assert_eq!(strict.len(), 1);
+
+        let mut relaxed = Expression::<Property>::relaxed();
+        assert_eq!(relaxed.append(&fragment).unwrap(), 2);
+
+        let mut valid = Expression::<Property>::relaxed();
+        let _ = valid.is(Property::Int, 42).unwrap();
+        assert_eq!(strict.append(&valid).unwrap(), 1);
+    }
+
+    #[test]
+    fn expression_append_dedup() {
+        let mut fragment = Expression::<Property>::new();
+        let a = fragment.is(Property::Int, Value::Int(42)).unwrap();
+        let b = fragment.is(Property::Bool, Value::Bool(true)).unwrap();
+        let _ = fragment.or(a, b).unwrap();
+
+        let mut expr = Expression::<Property>::new_dedup();
+        let own = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let first = expr.append(&fragment).unwrap();
+        let second = expr.append(&fragment).unwrap();
+        let _ = expr.and(first, second).unwrap();
+
+        // only the operators are copied the second time
+        assert_eq!(expr.len(), 5);
+        assert_eq!((first, second), (2, 3));
+        assert_eq!(expr.ops[own].1, 2);
+        assert!(expr.validate().is_ok());
+        assert_eq!(format!("{}", expr), format!("({0} && {0})", fragment));
+
+        let context = Context::from_values(vec![
+            (Property::Int, Value::Int(42)),
+            (Property::Bool, Value::Bool(false)),
+        ])
+        .unwrap();
+        assert_eq!(expr.eval(&context).unwrap().as_bool(), Some(true));
+    }
+
+    #[test]
+    fn expression_subexpression_outofbounds() {
+        let mut expr = Expression::<Property>::new();
+        expr.constant(true).unwrap();
+
+        let result = expr.subexpression(5);
+        assert!(
+            matches!(result, Err(Error::ExpressionOutOfBounds(s, e, _)) if s == 5 && e == 0),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn expression_subexpression_diamond() {
+        let mut expr = Expression::<Property>::new();
+
+        let unrelated = expr
+            .is(Property::Str, Value::Str("other".to_owned()))
+            .unwrap();
+        let shared = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let not_shared = expr.not(shared).unwrap();
+        let b = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+        let shared_and_b = expr.and(shared, b).unwrap();
+        let root = expr.or(not_shared, shared_and_b).unwrap();
+        let _ = expr.and(unrelated, root).unwrap();
+
+        let sub = expr.subexpression(root).unwrap();
+
+        assert_eq!(sub.ops.len(), 5);
+        assert_eq!(sub.ops[0].1, 2);
+        assert!(matches!(sub.ops[1].0, Operation::Not(0)));
+        assert!(matches!(sub.ops[3].0, Operation::And(0, 2)));
+        assert_eq!(sub.ops[4].1, 0);
+        assert_eq!(format!("{}", sub), expr.display(Some(root)));
+
+        let context = Context::from_values(vec![
+            (Property::Int, Value::Int(42)),
+            (Property::Bool, Value::Bool(true)),
+        ])
+        .unwrap();
+
+        let result = sub.eval(&context);
+        assert!(
+            matches!(result, Ok(Evaluated::Fully(x, _, _)) if x),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn expression_dedup() {
+        let mut expr = Expression::<Property>::new();
+
+        let a1 = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let b = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+        let a1_and_b = expr.and(a1, b).unwrap();
+        let a2 = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let c = expr.is(Property::Int, Value::Int(24)).unwrap();
+        let a2_or_c = expr.or(a2, c).unwrap();
+        let a3 = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let not_a3 = expr.not(a3).unwrap();
+        let lhs = expr.and(a1_and_b, a2_or_c).unwrap();
+        let _ = expr.or(lhs, not_a3).unwrap();
+
+        let mut deduped = Expression {
+            ops: expr.ops.clone(),
+            ..Expression::new()
+        };
+        deduped.dedup();
+
+        assert_eq!(expr.ops.len(), 10);
+        assert_eq!(deduped.ops.len(), 8);
+        assert_eq!(deduped.ops[0].1, 3);
+        assert_eq!(format!("{}", deduped), format!("{}", expr));
+
+        for (int, boolean) in &[(42, true), (42, false), (24, true), (0, false)] {
+            let context = Context::from_values(vec![
+                (Property::Int, Value::Int(*int)),
+                (Property::Bool, Value::Bool(*boolean)),
+            ])
+            .unwrap();
+
+            let expected = expr.eval(&context).unwrap().as_bool();
+            let actual = deduped.eval(&context).unwrap().as_bool();
+            assert!(expected.is_some());
+            assert_eq!(expected, actual, "context: {}", context);
+        }
+    }
+
+    #[test]
+    fn expression_replace_leaf() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let b = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+        let _ = expr.and(a, b).unwrap();
+
+        let cond = Is::new(Property::Int, Value::Int(24)).unwrap();
+        expr.replace(a, Operation::Is(cond), false).unwrap();
+
+        let context = Context::from_values(vec![
+            (Property::Int, Value::Int(24)),
+            (Property::Bool, Value::Bool(true)),
+        ])
+        .unwrap();
+
+        let result = expr.eval(&context);
+        assert!(
+            matches!(result, Ok(Evaluated::Fully(x, _, _)) if x),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn expression_replace_with_const() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let b = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let _ = expr.or(a_and_b, b).unwrap();
+
+        let result = expr.replace(a_and_b, Operation::Const(true), false);
+        assert!(
+            matches!(result, Err(Error::ExpressionDisconnected(x, _, _)) if x == a),
+            "{:?}",
+            result
+        );
+        assert!(matches!(expr.ops[a_and_b].0, Operation::And(_, _)));
+        assert_eq!(expr.ops[a].1, 1);
+        assert_eq!(expr.ops[b].1, 2);
+
+        expr.replace(a_and_b, Operation::Const(true), true).unwrap();
+        assert_eq!(expr.ops[a].1, 0);
+        assert_eq!(expr.ops[b].1, 1);
+        assert_eq!(
+            format!("{}", expr),
+            "(true || Property::Bool (Bool) == true)"
+        );
+    }
+
+    #[test]
+    fn expression_replace_future_reference() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.constant(true).unwrap();
+        let b = expr.constant(false).unwrap();
+        let _ = expr.or(a, b).unwrap();
+
+        let result = expr.replace(a, Operation::Not(b), true);
+        assert!(
+            matches!(result, Err(Error::ExpressionFutureReference(r, at, _)) if r == b && at == a),
+            "{:?}",
+            result
+        );
+
+        let result = expr.replace(b, Operation::Not(b), true);
+        assert!(
+            matches!(result, Err(Error::ExpressionFutureReference(r, at, _)) if r == b && at == b),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn expression_set_root() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let b = expr.constant(true).unwrap();
+        let root = expr.and(a, b).unwrap();
+
+        assert_eq!(expr.root(), Some(root));
+        expr.set_root(root).unwrap();
+
+        // helper operations pushed after the root don't change it
+        let c = expr.constant(false).unwrap();
+        let _ = expr.and(root, c).unwrap();
+
+        assert_eq!(expr.root(), Some(root));
+        assert_eq!(format!("{}", expr), "(Property::Int (Int) == 42 && true)");
+
+        let context = Context::from_values(vec![(Property::Int, Value::Int(42))]).unwrap();
+        let result = expr.eval(&context);
+        assert!(
+            matches!(result, Ok(Evaluated::Fully(x, _, _)) if x),
+            "{:?}",
+            result
+        );
+
+        let result = expr.eval(&Context::empty()).unwrap();
+        let partial = result.into_expression().unwrap();
+        assert_eq!(partial.root(), Some(root));
+    }
+
+    #[test]
+    fn expression_fluent() {
+        let mut explicit = Expression::<Property>::new();
+        let a = explicit.is(Property::Int, 42).unwrap();
+        let b = explicit.is(Property::Bool, true).unwrap();
+        let c = explicit.contains(Property::Str, "head").unwrap();
+        let a_and_b = explicit.and(a, b).unwrap();
+        let a_and_b_or_c = explicit.or(a_and_b, c).unwrap();
+        let _ = explicit.not(a_and_b_or_c).unwrap();
+
+        // operands are built first, the last one of them is the root to start from
+        let mut fluent = Expression::<Property>::new();
+        let b = fluent.is(Property::Bool, true).unwrap();
+        let c = fluent.contains(Property::Str, "head").unwrap();
+        let _ = fluent.is(Property::Int, 42).unwrap();
+        fluent.and_with(b).unwrap();
+        fluent.or_with(c).unwrap();
+        let root = fluent.not_root().unwrap();
+
+        assert_eq!(fluent.root(), Some(root));
+        assert_eq!(fluent, explicit);
+        assert!(fluent.validate().is_ok());
+    }
+
+    #[test]
+    fn expression_negate_complement() {
+        let mut include = Expression::<Property>::new();
+        let a = include.is_in(Property::Int, vec![1, 2]).unwrap();
+        let b = include.contains(Property::Str, "rock").unwrap();
+        let c = include.is(Property::Bool, true).unwrap();
+        let a_or_b = include.or(a, b).unwrap();
+        let _ = include.implies(c, a_or_b).unwrap();
+
+        let mut exclude = include.clone();
+        let old_root = exclude.root().unwrap();
+        let root = exclude.negate().unwrap();
+        assert_eq!(exclude.ops[old_root].1, 1);
+        assert_eq!(exclude.root(), Some(root));
+        assert!(exclude.validate().is_ok());
+        assert!(matches!(
+            Expression::<Property>::new().negate(),
+            Err(Error::ExpressionNoop)
+        ));
+
+        let contexts = [
+            vec![(Property::Bool, Value::Bool(false))],
+            vec![
+                (Property::Bool, Value::Bool(true)),
+                (Property::Int, Value::Int(2)),
+            ],
+            vec![
+                (Property::Bool, Value::Bool(true)),
+                (Property::Int, Value::Int(3)),
+                (Property::Str, Value::from("punk")),
+            ],
+            vec![(Property::Int, Value::Int(3))],
+            vec![],
+        ];
+        for provided in contexts.iter() {
+            let context = Context::from_values(provided.clone()).unwrap();
+            let expected = include.eval(&context).unwrap().as_bool().map(|val| !val);
+            assert_eq!(
+                exclude.eval(&context).unwrap().as_bool(),
+                expected,
+                "{:?}",
+                provided
+            );
+        }
+    }
+
+    #[test]
+    fn expression_fluent_set_root() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.constant(true).unwrap();
+        let b = expr.constant(false).unwrap();
+        expr.set_root(a).unwrap();
+
+        let a_or_b = expr.or_with(b).unwrap();
+        assert_eq!(expr.root(), Some(a_or_b));
+
+        // helper operations pushed after the root still don't change it
+        let c = expr.constant(true).unwrap();
+        let _ = expr.not(c).unwrap();
+        assert_eq!(expr.root(), Some(a_or_b));
+
+        let root = expr.and_with(c).unwrap();
+        assert_eq!(format!("{}", expr), "((true || false) && true)");
+        assert_eq!(expr.root(), Some(root));
+        assert!(expr.validate().is_ok());
+
+        let mut empty = Expression::<Property>::new();
+        let result = empty.not_root();
+        assert!(matches!(result, Err(Error::ExpressionNoop)), "{:?}", result);
+    }
+
+    #[test]
+    fn expression_set_root_disconnected() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.constant(true).unwrap();
+        let b = expr.constant(false).unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let _ = expr.not(a_or_b).unwrap();
+
+        expr.set_root(b).unwrap();
+
+        let result = expr.eval(&Context::empty());
+        assert!(
+            matches!(result, Err(Error::ExpressionDisconnected(x, _, _)) if x == a),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn expression_error_messages() {
+        let mut expr = Expression::<Property>::new();
+        assert_eq!(
+            expr.set_root(0).unwrap_err().to_string(),
+            "Operation reference 0/0 is out of bounds; expression is empty"
+        );
+        assert_eq!(
+            expr.not(2).unwrap_err().to_string(),
+            "Operation reference 2/0 is out of bounds; expression is empty"
+        );
+
+        let a = expr.constant(true).unwrap();
+        let b = expr.is(Property::Int, 42).unwrap();
+        let _ = expr.and(a, b).unwrap();
+        assert_eq!(
+            expr.or(a, 5).unwrap_err().to_string(),
+            "Operation reference 5/2 is out of bounds; \
+             expression: (true && Property::Int (Int) == 42)"
+        );
+        assert_eq!(
+            expr.replace(a, Operation::Not(b), true)
+                .unwrap_err()
+                .to_string(),
+            "Operation reference 1 is invalid: it points to a operation that's not defined yet (0); \
+             expression: (true && Property::Int (Int) == 42)"
+        );
+    }
+
+    #[test]
+    fn expression_eval_from() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 42).unwrap();
+        let b = expr.is(Property::Bool, true).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let c = expr
+            .is(Property::Str, Value::Param("name".to_owned()))
+            .unwrap();
+        let not_c = expr.not(c).unwrap();
+        let d = expr.constant(false).unwrap();
+        let a_or_d = expr.or(a, d).unwrap();
+
+        // strict evaluation rejects the library of independent trees
+        let context = Context::from_values(vec![(Property::Int, Value::Int(42))]).unwrap();
+        let result = expr.eval(&context);
+        assert!(
+            matches!(result, Err(Error::ExpressionDisconnected(_, _, _))),
+            "{:?}",
+            result
+        );
+
+        let result = expr.eval_from(a_or_d, &context).unwrap();
+        assert_eq!(result.as_bool(), Some(true));
+        assert_eq!(result.skipped(), Some(&[d][..]));
+        let log = result.log().unwrap();
+        assert_eq!(log[a_and_b].0, expr.ops[a_and_b].0);
+
+        let result = expr.eval_from(a_and_b, &context).unwrap();
+        let partial = result.into_expression().unwrap();
+        assert_eq!(partial.root(), Some(a_and_b));
+        assert_eq!(
+            format!("{}", partial),
+            "(true && Property::Bool (Bool) == true)"
+        );
+
+        // placeholders only matter in the evaluated tree
+        let result = expr.eval_from(not_c, &context);
+        assert!(
+            matches!(result, Err(Error::UnboundParameter(ref name)) if name == "name"),
+            "{:?}",
+            result
+        );
+
+        let result = expr.eval_from(a_or_d + 1, &context);
+        assert!(
+            matches!(result, Err(Error::ExpressionOutOfBounds(7, 6, _))),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn expression_eval_with() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Bool, true).unwrap();
+        let b = expr.is_in(Property::Int, vec![1, 2]).unwrap();
+        let c = expr.is_not(Property::Int, 2).unwrap();
+        let b_and_c = expr.and(b, c).unwrap();
+        let _ = expr.or(a, b_and_c).unwrap();
+
+        let values = [
+            (Property::Bool, Value::Bool(true)),
+            (Property::Int, Value::Int(1)),
+        ];
+        let mut queried = Vec::new();
+        let mut provider = |prop: Property| {
+            queried.push(prop);
+            values
+                .iter()
+                .find(|(key, _)| *key == prop)
+                .map(|(_, value)| value.clone())
+        };
+
+        // the untaken branch isn't fetched
+        let result = expr.eval_with(&mut provider).unwrap();
+        assert_eq!(result.as_bool(), Some(true));
+        assert_eq!(result.skipped(), Some(&[b, c, b_and_c][..]));
+        assert_eq!(queried, vec![Property::Bool]);
+
+        // both conditions on the same property share a single fetch
+        let values = [
+            (Property::Bool, Value::Bool(false)),
+            (Property::Int, Value::Int(1)),
+        ];
+        let mut queried = Vec::new();
+        let result = expr
+            .eval_with(|prop| {
+                queried.push(prop);
+                values
+                    .iter()
+                    .find(|(key, _)| *key == prop)
+                    .map(|(_, value)| value.clone())
+            })
+            .unwrap();
+        assert_eq!(result.as_bool(), Some(true));
+        assert_eq!(queried, vec![Property::Bool, Property::Int]);
+
+        // missing values leave conditions unevaluated, same as with a context
+        let result = expr
+            .eval_with(|prop| match prop {
+                Property::Bool => Some(Value::Bool(false)),
+                _ => None,
+            })
+            .unwrap();
+        let partial = result.into_expression().unwrap();
+        let context = Context::from_values(vec![(Property::Bool, Value::Bool(false))]).unwrap();
+        let expected = expr.eval(&context).unwrap().into_expression().unwrap();
+        assert_eq!(partial, expected);
+
+        let result = expr.eval_with(|_| Some(Value::Str("poison".to_owned())));
+        assert!(
+            matches!(result, Err(Error::TypeMismatch("Property::Bool", _, _))),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn expression_eval_with_defaults() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Bool, true).unwrap();
+        let b = expr.is_in(Property::Int, vec![1, 2]).unwrap();
+        let c = expr.contains(Property::Str, "rock").unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let _ = expr.or(a_and_b, c).unwrap();
+
+        let context = Context::from_values(vec![(Property::Int, Value::Int(1))]).unwrap();
+        assert_eq!(expr.eval(&context).unwrap().as_bool(), None);
+
+        // a default decides the result, but isn't added to the context
+        let defaults = vec![(Property::Bool, Value::Bool(true))]
+            .into_iter()
+            .collect();
+        let result = expr.eval_with_defaults(&context, &defaults).unwrap();
+        assert_eq!(result.as_bool(), Some(true));
+        assert_eq!(context.value(Property::Bool), None);
+
+        // provided values take precedence
+        let defaults = vec![
+            (Property::Bool, Value::Bool(true)),
+            (Property::Int, Value::Int(5)),
+        ]
+        .into_iter()
+        .collect();
+        let result = expr.eval_with_defaults(&context, &defaults).unwrap();
+        assert_eq!(result.as_bool(), Some(true));
+
+        // missing from both, still partial
+        let defaults = vec![(Property::Bool, Value::Bool(false))]
+            .into_iter()
+            .collect();
+        let result = expr.eval_with_defaults(&context, &defaults).unwrap();
+        assert_eq!(result.missing(), vec![Property::Str].into_iter().collect());
+
+        // checked before evaluation, even if the property isn't used
+        let defaults = vec![
+            (Property::Bool, Value::Bool(true)),
+            (Property::UInt, Value::from("none")),
+        ]
+        .into_iter()
+        .collect();
+        let result = expr.eval_with_defaults(&context, &defaults);
+        assert!(
+            matches!(result, Err(Error::TypeMismatch("Property::UInt", _, _))),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn expression_eval_batch() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 42).unwrap();
+        let b = expr.is(Property::Bool, true).unwrap();
+        let c = expr.contains(Property::Str, "rock").unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let _ = expr.or(a_and_b, c).unwrap();
+
+        let contexts = vec![
+            Context::from_values(vec![
+                (Property::Int, Value::Int(42)),
+                (Property::Bool, Value::Bool(true)),
+            ]),
+            Context::from_values(vec![
+                (Property::Int, Value::Int(7)),
+                (Property::Str, Value::from("punk")),
+            ]),
+            Context::from_values(vec![(Property::Str, Value::from("rock'n'roll"))]),
+            Context::from_values(vec![(Property::Bool, Value::Bool(true))]),
+        ]
+        .into_iter()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+
+        let batch = expr.eval_batch(&contexts).unwrap();
+        assert_eq!(batch.len(), contexts.len());
+        for (context, evaluated) in contexts.iter().zip(&batch) {
+            let expected = expr.eval(context).unwrap();
+            assert_eq!(format!("{:?}", evaluated), format!("{:?}", expected));
+        }
+
+        let results = expr.eval_batch_bool(&contexts).unwrap();
+        assert_eq!(results, vec![Some(true), Some(false), Some(true), None]);
+
+        // the first failing context fails the whole batch
+        let poison = poisoned(Vec::new());
+        let result = expr.eval_batch(contexts.iter().chain(Some(&poison)));
+        assert!(
+            matches!(result, Err(Error::TypeMismatch("Property::Int", _, _))),
+            "{:?}",
+            result
+        );
+        assert!(expr
+            .eval_batch_bool(contexts.iter().chain(Some(&poison)))
+            .is_err());
+
+        let empty = Expression::<Property>::new();
+        assert!(matches!(
+            empty.eval_batch(&contexts),
+            Err(Error::ExpressionNoop)
+        ));
+    }
+
+    #[test]
+    fn expression_eval_long_chain() {
+        // operands are folded without recursion, deep chains don't run out of stack
+        let mut expr = Expression::<Property>::new();
+        let mut chain = expr.is(Property::Int, 0).unwrap();
+        for val in 1..10_000 {
+            let cond = expr.is(Property::Int, val).unwrap();
+            chain = expr.or(chain, cond).unwrap();
+        }
+        for _ in 0..1_000 {
+            chain = expr.not(chain).unwrap();
+        }
+        assert!(expr.len() > 20_000);
+
+        let found = Context::from_values(vec![(Property::Int, Value::Int(9_999))]).unwrap();
+        let missed = Context::from_values(vec![(Property::Int, Value::Int(-1))]).unwrap();
+        assert_eq!(expr.eval(&found).unwrap().as_bool(), Some(true));
+        assert_eq!(expr.eval(&missed).unwrap().as_bool(), Some(false));
+        assert_eq!(expr.eval_3vl(&Context::empty()).unwrap(), Tri::Unknown);
+        assert_eq!(
+            expr.eval_batch_bool(vec![&found, &missed]).unwrap(),
+            vec![Some(true), Some(false)]
+        );
+    }
+
+    #[test]
+    fn expression_set_root_outofbounds() {
+        let mut expr = Expression::<Property>::new();
+
+        let result = expr.set_root(0);
+        assert!(
+            matches!(result, Err(Error::ExpressionOutOfBounds(0, 0, _))),
+            "{:?}",
+            result
+        );
+
+        expr.constant(true).unwrap();
+        let result = expr.set_root(3);
+        assert!(
+            matches!(result, Err(Error::ExpressionOutOfBounds(3, 0, _))),
+            "{:?}",
+            result
+        );
+        assert_eq!(expr.root(), Some(0));
+    }
+
+    #[test]
+    fn expression_validate_valid() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let not_a = expr.not(a).unwrap();
+        let b = expr.constant(true).unwrap();
+        let _ = expr.if_else(b, a, not_a).unwrap();
+
+        assert!(matches!(expr.validate(), Ok(())));
+
+        let manual = Expression::<Property> {
+            ops: vec![
+                (Operation::Const(true), 2),
+                (Operation::Not(0), 1),
+                (Operation::Or(0, 1), 0),
+            ],
+            ..Expression::new()
+        };
+        assert!(matches!(manual.validate(), Ok(())));
+    }
+
+    #[test]
+    fn expression_validate_corrupted() {
+        let empty = Expression::<Property>::new();
+        assert!(matches!(empty.validate(), Err(Error::ExpressionNoop)));
+
+        let outofbounds = Expression::<Property> {
+            ops: vec![(Operation::Const(true), 0), (Operation::Not(5), 0)],
+            ..Expression::new()
+        };
+        let result = outofbounds.validate();
+        assert!(
+            matches!(result, Err(Error::ExpressionOutOfBounds(5, 1, _))),
+            "{:?}",
+            result
+        );
+
+        let future = Expression::<Property> {
+            ops: vec![
+                (Operation::Not(1), 0),
+                (Operation::Const(true), 1),
+                (Operation::Not(0), 0),
+            ],
+            ..Expression::new()
+        };
+        let result = future.validate();
+        assert!(
+            matches!(result, Err(Error::ExpressionFutureReference(1, 0, _))),
+            "{:?}",
+            result
+        );
+
+        let refcount = Expression::<Property> {
+            ops: vec![
+                (Operation::Const(true), 1),
+                (Operation::Const(false), 0),
+                (Operation::And(0, 1), 0),
+            ],
+            ..Expression::new()
+        };
+        let result = refcount.validate();
+        assert!(
+            matches!(result, Err(Error::ExpressionRefCount(1, 0, 1))),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn expression_rebuild_refcounts() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 42).unwrap();
+        let b = expr.is(Property::Bool, true).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let _ = expr.or(a_and_b, a).unwrap();
+        let expected = expr.clone();
+
+        expr.ops[a].1 = 1;
+        expr.ops[b].1 = 3;
+        expr.ops[a_and_b].1 = 0;
+        assert!(matches!(
+            expr.validate(),
+            Err(Error::ExpressionRefCount(0, 1, 2))
+        ));
+        // `a` looks referenced once, so replacing one of its users seems to disconnect it
+        let result = expr.replace(a_and_b, Operation::Not(b), false);
+        assert!(
+            matches!(result, Err(Error::ExpressionDisconnected(x, _, _)) if x == a),
+            "{:?}",
+            result
+        );
+
+        expr.rebuild_refcounts();
+        assert_eq!(expr, expected);
+        assert!(expr.validate().is_ok());
+        expr.replace(a_and_b, Operation::Not(b), false).unwrap();
+        let context = Context::from_values(vec![(Property::Int, Value::Int(42))]).unwrap();
+        assert!(matches!(
+            expr.eval(&context),
+            Ok(Evaluated::Fully(true, _, _))
+        ));
+
+        let mut dangling = Expression::<Property> {
+            ops: vec![(Operation::Const(true), 5), (Operation::Not(3), 2)],
+            ..Expression::new()
+        };
+        dangling.rebuild_refcounts();
+        assert_eq!(dangling.ops[0].1, 0);
+        assert_eq!(dangling.ops[1].1, 0);
+    }
+
+    #[test]
+    fn expression_validate_cycles() {
+        let selfref = Expression::<Property> {
+            ops: vec![(Operation::Const(true), 1), (Operation::And(0, 1), 1)],
+            ..Expression::new()
+        };
+        let result = selfref.validate();
+        assert!(
+            matches!(result, Err(Error::ExpressionFutureReference(1, 1, _))),
+            "{:?}",
+            result
+        );
+
+        let cycle = Expression::<Property> {
+            ops: vec![
+                (Operation::Not(1), 1),
+                (Operation::Not(0), 1),
+                (Operation::Or(0, 1), 0),
+            ],
+            ..Expression::new()
+        };
+        let result = cycle.validate();
+        assert!(
+            matches!(result, Err(Error::ExpressionFutureReference(1, 0, _))),
+            "{:?}",
+            result
+        );
+        // evaluation and display don't follow the cycle either
+        assert!(cycle.eval(&Context::empty()).is_err());
+        assert!(cycle.to_string().contains("<badref: 1/0>"));
+    }
+
+    #[test]
+    fn expression_refcount_diamond() {
+        let mut expr = Expression::<Property>::new();
+        let shared = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let not_shared = expr.not(shared).unwrap();
+        let b = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+        let shared_or_b = expr.or(shared, b).unwrap();
+        let _ = expr.and(not_shared, shared_or_b).unwrap();
+
+        assert_eq!(expr.ops[shared].1, 2);
+        assert_eq!(expr.ops[not_shared].1, 1);
+        assert_eq!(expr.ops[shared_or_b].1, 1);
+        assert!(matches!(expr.validate(), Ok(())));
+
+        let context = Context::from_values(vec![
+            (Property::Int, Value::Int(24)),
+            (Property::Bool, Value::Bool(true)),
+        ])
+        .unwrap();
+
+        let result = expr.eval(&context);
+        assert!(
+            matches!(result, Ok(Evaluated::Fully(x, _, _)) if x),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn expression_refcount_invalid_operand() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.constant(true).unwrap();
+
+        let result = expr.and(a, 42);
+        assert!(matches!(
+            result,
+            Err(Error::ExpressionOutOfBounds(42, 0, _))
+        ));
+        assert_eq!(expr.ops[a].1, 0);
+        assert_eq!(expr.ops.len(), 1);
+
+        let _ = expr.or(a, a).unwrap();
+        assert_eq!(expr.ops[a].1, 2);
+        assert!(matches!(expr.validate(), Ok(())));
+    }
+
+    #[test]
+    fn expression_compact() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let b = expr.is(Property::Str, Value::Str("b".to_owned())).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let c = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+        let _ = expr.or(a_and_b, c).unwrap();
+
+        expr.replace(a_and_b, Operation::Const(false), true)
+            .unwrap();
+
+        let context = Context::from_values(vec![(Property::Bool, Value::Bool(true))]).unwrap();
+        assert!(matches!(
+            expr.eval(&context),
+            Err(Error::ExpressionDisconnected(_, _, _))
+        ));
+
+        let display = format!("{}", expr);
+        assert_eq!(expr.compact(), 2);
+        assert_eq!(expr.ops.len(), 3);
+        assert_eq!(format!("{}", expr), display);
+        assert!(matches!(expr.validate(), Ok(())));
+
+        let result = expr.eval(&context);
+        assert!(
+            matches!(result, Ok(Evaluated::Fully(x, _, _)) if x),
+            "{:?}",
+            result
+        );
+
+        assert_eq!(expr.compact(), 0);
+    }
+
+    #[test]
+    fn expression_compact_explicit_root() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.constant(true).unwrap();
+        let b = expr.constant(false).unwrap();
+        let root = expr.or(a, b).unwrap();
+        let _ = expr.not(root).unwrap();
+        expr.set_root(root).unwrap();
+
+        assert_eq!(expr.compact(), 1);
+        assert_eq!(expr.root(), Some(root));
+        assert_eq!(expr.root, Some(root));
+
+        assert_eq!(Expression::<Property>::new().compact(), 0);
+    }
+
+    #[test]
+    fn expression_eq_same_order() {
+        let build = || {
+            let mut expr = Expression::<Property>::new();
+            let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+            let b = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+            let _ = expr.and(a, b).unwrap();
+            expr
+        };
+
+        assert_eq!(build(), build());
+        assert_eq!(Expression::<Property>::new(), Expression::new());
+        assert_ne!(build(), Expression::new());
+    }
+
+    #[test]
+    fn expression_eq_different_order() {
+        let mut lhs = Expression::<Property>::new();
+        let a = lhs.is(Property::Int, Value::Int(42)).unwrap();
+        let not_a = lhs.not(a).unwrap();
+        let b = lhs.is(Property::Bool, Value::Bool(true)).unwrap();
+        let _ = lhs.or(not_a, b).unwrap();
+
+        let mut rhs = Expression::<Property>::new();
+        let dead = rhs.constant(false).unwrap();
+        let b = rhs.is(Property::Bool, Value::Bool(true)).unwrap();
+        let a = rhs.is(Property::Int, Value::Int(42)).unwrap();
+        let not_a = rhs.not(a).unwrap();
+        let root = rhs.or(not_a, b).unwrap();
+        let _ = rhs.and(root, dead).unwrap();
+        rhs.set_root(root).unwrap();
+
+        assert_eq!(lhs, rhs);
+
+        let mut swapped = Expression::<Property>::new();
+        let a = swapped.is(Property::Int, Value::Int(42)).unwrap();
+        let not_a = swapped.not(a).unwrap();
+        let b = swapped.is(Property::Bool, Value::Bool(true)).unwrap();
+        let _ = swapped.or(b, not_a).unwrap();
+
+        assert_ne!(lhs, swapped);
+    }
+
+    #[test]
+    fn expression_eq_different_values() {
+        let mut lhs = Expression::<Property>::new();
+        let a = lhs.is(Property::Int, Value::Int(42)).unwrap();
+        let b = lhs
+            .is_in(Property::Int, vec![Value::Int(1), Value::Int(2)])
+            .unwrap();
+        let _ = lhs.and(a, b).unwrap();
+
+        let mut rhs = Expression::<Property>::new();
+        let a = rhs.is(Property::Int, Value::Int(42)).unwrap();
+        let b = rhs
+            .is_in(Property::Int, vec![Value::Int(2), Value::Int(1)])
+            .unwrap();
+        let _ = rhs.and(a, b).unwrap();
+
+        assert_eq!(lhs, rhs);
+
+        let mut other = Expression::<Property>::new();
+        let a = other.is(Property::Int, Value::Int(24)).unwrap();
+        let b = other
+            .is_in(Property::Int, vec![Value::Int(1), Value::Int(2)])
+            .unwrap();
+        let _ = other.and(a, b).unwrap();
+
+        assert_ne!(lhs, other);
+
+        let mut operator = Expression::<Property>::new();
+        let a = operator.is(Property::Int, Value::Int(42)).unwrap();
+        let b = operator
+            .is_in(Property::Int, vec![Value::Int(1), Value::Int(2)])
+            .unwrap();
+        let _ = operator.or(a, b).unwrap();
+
+        assert_ne!(lhs, operator);
+    }
+
+    #[test]
+    fn expression_hash() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let b = expr
+            .is_in(
+                Property::Str,
+                vec![
+                    Value::Str("a".to_owned()),
+                    Value::Str("b".to_owned()),
+                    Value::Str("c".to_owned()),
+                ],
+            )
+            .unwrap();
+        let _ = expr.and(a, b).unwrap();
+
+        let mut reordered = Expression::<Property>::new();
+        let b = reordered
+            .is_in(
+                Property::Str,
+                vec![
+                    Value::Str("c".to_owned()),
+                    Value::Str("b".to_owned()),
+                    Value::Str("a".to_owned()),
+                ],
+            )
+            .unwrap();
+        let a = reordered.is(Property::Int, Value::Int(42)).unwrap();
+        let _ = reordered.and(a, b).unwrap();
+
+        let mut cache = HashMap::new();
+        cache.insert(expr.clone(), "cached");
+
+        assert_eq!(cache.get(&expr), Some(&"cached"));
+        assert_eq!(cache.get(&reordered), Some(&"cached"));
+
+        let mut other = expr.clone();
+        let c = other.constant(true).unwrap();
+        let root = other.root().unwrap();
+        let _ = other.and(root, c).unwrap();
+
+        assert_eq!(cache.get(&other), None);
+    }
+
+    #[test]
+    fn expression_fingerprint_pinned() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let b = expr
+            .is_in(
+                Property::Str,
+                vec![Value::Str("a".to_owned()), Value::Str("b".to_owned())],
+            )
+            .unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let c = expr.constant(false).unwrap();
+        let not_c = expr.not(c).unwrap();
+        let _ = expr.or(a_and_b, not_c).unwrap();
+
+        assert_eq!(expr.fingerprint(), 10854933821259226455);
+        assert_eq!(expr.fingerprint(), expr.clone().fingerprint());
+        assert_eq!(
+            Expression::<Property>::new().fingerprint(),
+            0xcbf2_9ce4_8422_2325
+        );
+    }
+
+    #[test]
+    fn expression_fingerprint_differs() {
+        let build = |int: i64, string: &str| {
+            let mut expr = Expression::<Property>::new();
+            let a = expr.is(Property::Int, Value::Int(int)).unwrap();
+            let b = expr
+                .is(Property::Str, Value::Str(string.to_owned()))
+                .unwrap();
+            let _ = expr.and(a, b).unwrap();
+            expr
+        };
+
+        assert_eq!(build(42, "a").fingerprint(), build(42, "a").fingerprint());
+        assert_ne!(build(42, "a").fingerprint(), build(24, "a").fingerprint());
+        assert_ne!(build(42, "a").fingerprint(), build(42, "b").fingerprint());
+    }
+
+    #[test]
+    fn expression_estimate_cost() {
+        let selectivity = |prop: &Property| match prop {
+            Property::Bool => 0.5,
+            Property::Int | Property::UInt => 0.1,
+            Property::Str => 0.01,
+        };
+
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+        let b = expr
+            .is_in(Property::Int, vec![Value::Int(1), Value::Int(2)])
+            .unwrap();
+
+        let mut root = expr.or(a, b).unwrap();
+        let mut estimate = expr.estimate_cost(&selectivity);
+        assert!((estimate - 0.6).abs() < 1e-9, "{}", estimate);
+
+        let conjuncts = vec![
+            expr.is(Property::Str, Value::Str("a".to_owned())).unwrap(),
+            expr.constant(true).unwrap(),
+            expr.not(a).unwrap(),
+        ];
+
+        // adding conjuncts never increases selectivity
+        for conjunct in conjuncts {
+            root = expr.and(root, conjunct).unwrap();
+            let next = expr.estimate_cost(&selectivity);
+            assert!(
+                next <= estimate,
+                "{} > {}, expression: {}",
+                next,
+                estimate,
+                expr
+            );
+            estimate = next;
+        }
+
+        let mut constant = Expression::<Property>::new();
+        let t = constant.constant(true).unwrap();
+        let _ = constant.not(t).unwrap();
+        assert_eq!(constant.estimate_cost(&selectivity), 0.0);
+        assert_eq!(
+            Expression::<Property>::new().estimate_cost(&selectivity),
+            0.0
+        );
+    }
+
+    #[test]
+    fn expression_len_depth() {
+        let mut expr = Expression::<Property>::new();
+        assert_eq!((expr.len(), expr.depth()), (0, 0));
+
+        let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+        assert_eq!((expr.len(), expr.depth()), (1, 1));
+
+        let not_a = expr.not(a).unwrap();
+        let not_not_a = expr.not(not_a).unwrap();
+        let b = expr.constant(true).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let _ = expr.or(not_not_a, a_and_b).unwrap();
+
+        // the shared leaf is counted once per path, the longest one wins
+        assert_eq!((expr.len(), expr.depth()), (6, 4));
+    }
+
+    #[test]
+    fn expression_limits_ops() {
+        let mut expr = Expression::<Property>::with_limits(3, 10);
+        let a = expr.constant(true).unwrap();
+        let b = expr.constant(false).unwrap();
+        let _ = expr.or(a, b).unwrap();
+
+        let result = expr.constant(true);
+        assert!(
+            matches!(result, Err(Error::ExpressionTooLarge { ops: 4, depth: 1 })),
+            "{:?}",
+            result
+        );
+        assert_eq!(expr.len(), 3);
+
+        let result = expr.append(&expr.clone());
+        assert!(
+            matches!(result, Err(Error::ExpressionTooLarge { ops: 6, depth: 2 })),
+            "{:?}",
+            result
+        );
+        assert_eq!(expr.len(), 3);
+    }
+
+    #[test]
+    fn expression_limit_builders() {
+        let mut expr = Expression::<Property>::with_limit(6);
+        let a = expr.constant(true).unwrap();
+        let b = expr.is(Property::Int, 1).unwrap();
+        let c = expr.is_in(Property::Str, vec!["foo"]).unwrap();
+        let not_c = expr.not(c).unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let _ = expr.and(a_or_b, not_c).unwrap();
+        assert_eq!(expr.len(), 6);
+
+        let results = vec![
+            expr.clone().constant(false),
+            expr.clone().is(Property::Int, 2),
+            expr.clone().is_in(Property::Int, vec![2]),
+            expr.clone().not(a),
+            expr.clone().or(a, b),
+            expr.clone().and(a, b),
+        ];
+        for result in results {
+            assert!(
+                matches!(result, Err(Error::ExpressionTooLarge { ops: 7, .. })),
+                "{:?}",
+                result
+            );
+        }
+        assert_eq!(expr.len(), 6);
+    }
+
+    #[test]
+    fn expression_map_properties() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 42).unwrap();
+        let b = expr.is_in(Property::Int, vec![1, 2]).unwrap();
+        let t = expr.constant(true).unwrap();
+        let b_and_t = expr.and(b, t).unwrap();
+        let _ = expr.or(a, b_and_t).unwrap();
+
+        let mapped = expr
+            .map_properties(|prop| match prop {
+                Property::Int => Ok(Measured::Listeners),
+                _ => Err(Error::PropertyNotRequested("unmapped")),
+            })
+            .unwrap();
+        assert_eq!(mapped.len(), expr.len());
+        assert_eq!(
+            mapped.ops.iter().map(|op| op.1).collect::<Vec<_>>(),
+            expr.ops.iter().map(|op| op.1).collect::<Vec<_>>()
+        );
+        assert_eq!(mapped.ops[t].0, Operation::Const(true));
+
+        for &val in &[42, 2, 3] {
+            let context = Context::from_values(vec![(Property::Int, Value::Int(val))]).unwrap();
+            let mapped_context =
+                Context::from_values(vec![(Measured::Listeners, Value::Int(val))]).unwrap();
+            assert_eq!(
+                expr.eval(&context).unwrap().as_bool(),
+                mapped.eval(&mapped_context).unwrap().as_bool()
+            );
+        }
+    }
+
+    #[test]
+    fn expression_map_properties_mismatch() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.constant(false).unwrap();
+        let b = expr.is(Property::Str, "foo").unwrap();
+        let _ = expr.or(a, b).unwrap();
+
+        let result = expr.map_properties(|_| Ok(Measured::Listeners));
+        match result {
+            Err(Error::ExpressionRetarget(1, err)) => assert!(
+                matches!(
+                    *err,
+                    Error::TypeMismatch("Measured::Listeners", Datatype::Int, Datatype::Str)
+                ),
+                "{:?}",
+                err
+            ),
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    fn template() -> Expression<Property> {
+        let mut expr = Expression::<Property>::new();
+        let a = expr
+            .is(Property::Str, Value::Param("tenant".to_owned()))
+            .unwrap();
+        let b = expr
+            .is_in(
+                Property::Int,
+                vec![Value::Int(0), Value::Param("plan".to_owned())],
+            )
+            .unwrap();
+        let _ = expr.and(a, b).unwrap();
+        expr
+    }
+
+    #[test]
+    fn expression_bind() {
+        let expr = template();
+        assert_eq!(
+            expr.params().into_iter().collect::<Vec<_>>(),
+            vec!["plan", "tenant"]
+        );
+        assert_eq!(
+            format!("{}", expr),
+            "(Property::Str (Str) == $tenant && Property::Int (Int) in [0, $plan])"
+        );
+
+        let context = Context::from_values(vec![
+            (Property::Str, Value::Str("acme".to_owned())),
+            (Property::Int, Value::Int(2)),
+        ])
+        .unwrap();
+
+        let params = vec![("tenant", Value::from("acme")), ("plan", Value::Int(2))];
+        let bound = expr.bind(&params.into_iter().collect()).unwrap();
+        assert!(bound.params().is_empty());
+        assert_eq!(bound.eval(&context).unwrap().as_bool(), Some(true));
+
+        // the template is left as is and can be bound again
+        let params = vec![("tenant", Value::from("acme")), ("plan", Value::Int(3))];
+        let rebound = expr.bind(&params.into_iter().collect()).unwrap();
+        assert_eq!(rebound.eval(&context).unwrap().as_bool(), Some(false));
+        assert_ne!(rebound, bound);
+    }
+
+    #[test]
+    fn expression_bind_errors() {
+        let expr = template();
+
+        let params = vec![("tenant", Value::from("acme"))].into_iter().collect();
+        let result = expr.bind(&params);
+        assert!(
+            matches!(result, Err(Error::UnboundParameter(ref name)) if name == "plan"),
+            "{:?}",
+            result
+        );
+
+        let params = vec![
+            ("tenant", Value::from("acme")),
+            ("plan", Value::Int(2)),
+            ("region", Value::from("eu")),
+        ];
+        let result = expr.bind(&params.into_iter().collect());
+        assert!(
+            matches!(result, Err(Error::UnusedParameter(ref name)) if name == "region"),
+            "{:?}",
+            result
+        );
+
+        let params = vec![("tenant", Value::Int(1)), ("plan", Value::Int(2))];
+        let result = expr.bind(&params.into_iter().collect());
+        assert!(
+            matches!(
+                result,
+                Err(Error::TypeMismatch(
+                    "Property::Str",
+                    Datatype::Str,
+                    Datatype::Int
+                ))
+            ),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn expression_relaxed() {
+        // strict expressions reject mismatching values right away
+        let mut strict = Expression::<Property>::new();
+        let result = strict.is(Property::Int, "42");
+        assert!(
+            matches!(
+                result,
+                Err(Error::TypeMismatch(
+                    "Property::Int",
+                    Datatype::Int,
+                    Datatype::Str
+                ))
+            ),
+            "{:?}",
+            result
+        );
+        let result = strict.is_in(Property::Int, vec![Value::Int(1), Value::from("2")]);
+        assert!(
+            matches!(result, Err(Error::TypeMismatch(_, _, _))),
+            "{:?}",
+            result
         );
+        assert!(!strict.is_relaxed());
 
-        context
-            .provide(Property::Str, Value::Str("updated".to_owned()))
-            .unwrap();
+        let mut relaxed = Expression::<Property>::relaxed();
+        assert!(relaxed.is_relaxed());
+        let a = relaxed.is(Property::Bool, true).unwrap();
+        let b = relaxed.is(Property::Int, "42").unwrap();
+        let _ = relaxed.and(a, b).unwrap();
+
+        // reported once evaluated, even if short-circuiting wouldn't reach the condition
+        let context = Context::from_values(vec![(Property::Bool, Value::Bool(false))]).unwrap();
+        let result = relaxed.eval(&context);
         assert!(
-            matches!(context.value(Property::Str), Some(Value::Str(x)) if x == "updated"),
-            "{}",
-            context
+            matches!(
+                result,
+                Err(Error::TypeMismatch(
+                    "Property::Int",
+                    Datatype::Int,
+                    Datatype::Str
+                ))
+            ),
+            "{:?}",
+            result
         );
+        assert!(relaxed.eval_batch(Some(&context)).is_err());
+        assert_eq!(
+            relaxed.eval_from(a, &context).unwrap().as_bool(),
+            Some(false)
+        );
+
+        // valid values evaluate the same as in strict expressions, converted if need be
+        let mut relaxed = Expression::<Coercible>::relaxed();
+        let a = relaxed.is_in(Coercible::Plays, vec![true]).unwrap();
+        let b = relaxed.is_not(Coercible::Plays, 7u64).unwrap();
+        let _ = relaxed.and(a, b).unwrap();
+        let mut strict = Expression::<Coercible>::new();
+        let a = strict.is_in(Coercible::Plays, vec![true]).unwrap();
+        let b = strict.is_not(Coercible::Plays, 7u64).unwrap();
+        let _ = strict.and(a, b).unwrap();
+        assert_eq!(relaxed, strict);
+
+        let context = Context::from_values(vec![(Coercible::Plays, Value::UInt(1))]).unwrap();
+        assert_eq!(relaxed.eval(&context).unwrap().as_bool(), Some(true));
     }
 
     #[test]
-    fn expression_variables() {
-        let mut expr = Expression::<Property>::new();
+    fn expression_relaxed_derived() {
+        let mut relaxed = Expression::<Property>::relaxed();
+        let a = relaxed.is(Property::Int, "42").unwrap();
+        let not_a = relaxed.not(a).unwrap();
+        let _ = relaxed.not(not_a).unwrap();
+        let context = Context::from_values(vec![(Property::Int, Value::Int(42))]).unwrap();
 
-        let a = expr.constant(true).unwrap();
-        let b = expr.is(Property::Int, Value::Int(42)).unwrap();
-        let c = expr
-            .is_in(
-                Property::Str,
-                vec![
-                    Value::Str("a match".to_owned()),
-                    Value::Str("another match".to_owned()),
-                ],
-            )
-            .unwrap();
+        let derived = vec![
+            relaxed.simplify().unwrap(),
+            relaxed.subexpression(not_a).unwrap(),
+            relaxed.subexpression(a).unwrap(),
+        ];
+        let expected = format!("{:?}", relaxed.eval(&context));
+        assert!(expected.contains("TypeMismatch"), "{}", expected);
+        for expr in derived {
+            assert!(expr.is_relaxed(), "{}", expr);
+            assert_eq!(format!("{:?}", expr.eval(&context)), expected, "{}", expr);
+        }
 
-        let a_and_b = expr.and(a, b).unwrap();
-        let _root = expr.or(a_and_b, c);
+        // other modes carry over as well
+        let mut limited = Expression::<Property>::with_limits(3, 2);
+        let a = limited.is(Property::Int, 42).unwrap();
+        let not_a = limited.not(a).unwrap();
+        let mut sub = limited.subexpression(not_a).unwrap();
+        assert!(sub.not(not_a).is_err());
+        let mut simple = limited.simplify().unwrap();
+        assert!(simple.not(not_a).is_err());
 
-        let requested = expr
-            .variables()
-            .requested()
-            .map(|op| op.clone())
-            .collect::<HashSet<_>>();
-        let expected = vec![Property::Int, Property::Str]
-            .into_iter()
-            .collect::<HashSet<_>>();
-        assert_eq!(requested, expected);
+        let mut dedup = Expression::<Property>::new_dedup();
+        let a = dedup.is(Property::Int, 42).unwrap();
+        let _ = dedup.not(a).unwrap();
+        for mut expr in [dedup.subexpression(a).unwrap(), dedup.simplify().unwrap()] {
+            assert!(expr.is_dedup());
+            assert_eq!(expr.is(Property::Int, 42).unwrap(), 0);
+        }
+        assert!(Expression::<Property>::relaxed()
+            .simplify()
+            .unwrap()
+            .is_relaxed());
     }
 
     #[test]
-    fn expression_outofbounds() {
-        let mut expr = Expression::<Property>::new();
+    fn expression_eval_unbound() {
+        let expr = template();
 
-        let result = expr.not(99);
+        // placeholders fail evaluation even when conditions using them would be skipped
+        let context = Context::from_values(vec![(Property::Int, Value::Int(1))]).unwrap();
+        for result in &[expr.eval(&context), expr.eval_ordered(&context, &|_| 1.0)] {
+            assert!(
+                matches!(result, Err(Error::UnboundParameter(ref name)) if name == "plan"),
+                "{:?}",
+                result
+            );
+        }
+
+        // and they can't sneak in as provided values
+        let result = Context::from_values(vec![(Property::Int, Value::Param("plan".to_owned()))]);
         assert!(
-            matches!(result, Err(Error::ExpressionOutOfBounds(s, e, _)) if s == 99 && e == 0),
+            matches!(
+                result,
+                Err(Error::TypeMismatch(
+                    "Property::Int",
+                    Datatype::Int,
+                    Datatype::Param
+                ))
+            ),
             "{:?}",
             result
         );
+    }
 
-        expr.constant(true).unwrap();
-        expr.constant(false).unwrap();
+    #[test]
+    fn expression_limits_depth() {
+        let mut expr = Expression::<Property>::with_limits(100, 3);
+        let a = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+        let not_a = expr.not(a).unwrap();
+        let not_not_a = expr.not(not_a).unwrap();
 
-        let result = expr.not(99);
+        let result = expr.not(not_not_a);
         assert!(
-            matches!(result, Err(Error::ExpressionOutOfBounds(s, e, _)) if s == 99 && e == 1),
+            matches!(result, Err(Error::ExpressionTooLarge { ops: 4, depth: 4 })),
             "{:?}",
             result
         );
+        assert_eq!(expr.ops[not_not_a].1, 0);
+
+        // shallower operations still fit
+        let _ = expr.and(a, not_a).unwrap();
+        assert_eq!(expr.depth(), 3);
     }
 
     #[test]
-    fn expression_eval_success_no_variables() {
+    fn expression_limits_unlimited() {
         let mut expr = Expression::<Property>::new();
+        let mut root = expr.constant(true).unwrap();
+        for _ in 0..1000 {
+            root = expr.not(root).unwrap();
+        }
+        assert_eq!((expr.len(), expr.depth()), (1001, 1001));
+    }
 
+    #[test]
+    fn expression_limits_stored_depths() {
+        // depths of earlier operations aren't walked again on every push
+        let mut expr = Expression::<Property>::with_limits(100_001, 50_001);
+        let mut root = expr.constant(true).unwrap();
+        for _ in 0..50_000 {
+            let leaf = expr.constant(false).unwrap();
+            root = expr.or(root, leaf).unwrap();
+        }
+        assert_eq!((expr.len(), expr.depth()), (100_001, 50_001));
+        assert!(expr.constant(true).is_err());
+
+        // operations replaced in place change depths of the ones after them
+        let mut expr = Expression::<Property>::with_limits(10, 3);
         let a = expr.constant(true).unwrap();
+        let not_a = expr.not(a).unwrap();
         let b = expr.constant(false).unwrap();
-        let aorb = expr.or(a, b).unwrap();
-        let _ = expr.and(aorb, b);
-
-        let result = expr.eval(&Context::empty());
-        let evaluated = result.unwrap();
-
+        let _ = expr.or(not_a, b).unwrap();
+        expr.replace(b, Operation::Not(not_a), true).unwrap();
+        let result = expr.not(b);
         assert!(
-            matches!(evaluated, Evaluated::Fully(x, _) if x == false),
+            matches!(result, Err(Error::ExpressionTooLarge { ops: 5, depth: 4 })),
             "{:?}",
-            evaluated
+            result
         );
+        assert!(expr.not(not_a).is_ok());
     }
 
     #[test]
-    fn expression_eval_success_variables() {
-        let mut expr = Expression::<Property>::new();
+    fn expression_eval_ordered_matches_eval() {
+        let cost = |prop: &Property| match prop {
+            Property::Bool => 1.0,
+            Property::Int | Property::UInt => 10.0,
+            Property::Str => 100.0,
+        };
 
-        let a = expr.is(Property::Bool, Value::Bool(true)).unwrap();
-        let b = expr
-            .is_in(Property::Int, vec![Value::Int(41), Value::Int(42)])
+        let mut expr = Expression::<Property>::new();
+        let a = expr
+            .is_in(
+                Property::Str,
+                vec![Value::Str("a".to_owned()), Value::Str("b".to_owned())],
+            )
             .unwrap();
+        let b = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let c = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+        let _ = expr.or(a_and_b, c).unwrap();
 
-        let _ = expr.and(a, b).unwrap();
-        let mut context = expr.variables();
+        for string in &["a", "c"] {
+            for int in &[42, 24] {
+                for boolean in &[true, false] {
+                    let context = Context::from_values(vec![
+                        (Property::Str, Value::Str(string.to_string())),
+                        (Property::Int, Value::Int(*int)),
+                        (Property::Bool, Value::Bool(*boolean)),
+                    ])
+                    .unwrap();
 
-        // #1: a is true, b is false
-        context.provide(Property::Bool, Value::Bool(true)).unwrap();
-        context.provide(Property::Int, Value::Int(99)).unwrap();
+                    let expected = expr.eval(&context).unwrap();
+                    let actual = expr.eval_ordered(&context, &cost).unwrap();
+                    assert!(expected.as_bool().is_some());
+                    assert_eq!(expected.as_bool(), actual.as_bool(), "{}", context);
+                }
+            }
+        }
+    }
 
-        let a_not_b = expr.eval(&context);
-        assert!(matches!(a_not_b, Ok(Evaluated::Fully(x, _)) if x == false));
+    #[test]
+    fn expression_reorder_by_cost() {
+        let mut expr = Expression::<Property>::new();
+        let expensive = expr.is_in(Property::Int, 0..100_000).unwrap();
+        let cheap = expr.is(Property::Bool, true).unwrap();
+        let a = expr.and(expensive, cheap).unwrap();
+        let b = expr.contains(Property::Str, "rock").unwrap();
+        let c = expr.is_not(Property::UInt, 7u64).unwrap();
+        let b_or_c = expr.or(b, c).unwrap();
+        let _ = expr.or(a, b_or_c).unwrap();
 
-        // #2: a is false, b is true
-        context.provide(Property::Bool, Value::Bool(false)).unwrap();
-        context.provide(Property::Int, Value::Int(41)).unwrap();
+        let reordered = expr.reorder_by_cost(Operation::default_cost);
+        assert_eq!(reordered.len(), expr.len());
+        assert_eq!(reordered.root(), expr.root());
+        assert_eq!(reordered.ops[a].0, Operation::And(cheap, expensive));
+        assert_eq!(reordered.ops[b_or_c].0, Operation::Or(c, b));
+        assert_eq!(reordered.ops[b_or_c + 1].0, Operation::Or(b_or_c, a));
+        assert_eq!(expr.reorder_by_cost(|_| 1), expr);
 
-        let b_not_a = expr.eval(&context);
-        assert!(matches!(b_not_a, Ok(Evaluated::Fully(x, _)) if x == false));
+        // with Bool decided first, the Int value is never asked for
+        let eval = |expr: &Expression<Property>| {
+            let mut queried = Vec::new();
+            let values = [
+                (Property::Bool, Value::Bool(false)),
+                (Property::Int, Value::Int(42)),
+                (Property::Str, Value::from("pop")),
+                (Property::UInt, Value::UInt(7)),
+            ];
+            let result = expr
+                .eval_with(|prop| {
+                    queried.push(prop);
+                    values
+                        .iter()
+                        .find(|(key, _)| *key == prop)
+                        .map(|(_, value)| value.clone())
+                })
+                .unwrap();
+            (result.as_bool(), queried)
+        };
+        assert_eq!(
+            eval(&expr),
+            (
+                Some(false),
+                vec![Property::Int, Property::Bool, Property::Str, Property::UInt]
+            )
+        );
+        assert_eq!(
+            eval(&reordered),
+            (
+                Some(false),
+                vec![Property::UInt, Property::Str, Property::Bool]
+            )
+        );
+    }
 
-        // #3: a is true, b is true
-        context.provide(Property::Bool, Value::Bool(true)).unwrap();
-        context.provide(Property::Int, Value::Int(42)).unwrap();
+    #[test]
+    fn expression_eval_ordered_short_circuit() {
+        let cost = |prop: &Property| match prop {
+            Property::Str => 100.0,
+            _ => 1.0,
+        };
 
-        let a_b = expr.eval(&context);
+        let mut expr = Expression::<Property>::new();
+        let expensive = expr
+            .is_in(
+                Property::Str,
+                vec![Value::Str("a".to_owned()), Value::Str("b".to_owned())],
+            )
+            .unwrap();
+        let cheap = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let _ = expr.and(expensive, cheap).unwrap();
+
+        // the expensive condition isn't needed once the cheap one is false
+        let context = Context::from_values(vec![(Property::Int, Value::Int(24))]).unwrap();
+
+        let result = expr.eval_ordered(&context, &cost);
         assert!(
-            matches!(a_b, Ok(Evaluated::Fully(x, _)) if x == true),
-            "result: '{}', expression: '{}', context: '{}'",
-            a_b.unwrap(),
-            expr,
-            context
+            matches!(&result, Ok(Evaluated::Fully(x, _, _)) if !x),
+            "{:?}",
+            result
+        );
+        if let Ok(Evaluated::Fully(_, log, _)) = result {
+            assert!(matches!(log[expensive].0, Operation::In(_)));
+            assert!(matches!(log[cheap].0, Operation::Const(false)));
+        }
+
+        let context = Context::from_values(vec![(Property::Int, Value::Int(42))]).unwrap();
+        let result = expr.eval_ordered(&context, &cost);
+        assert!(
+            matches!(result, Ok(Evaluated::Partially(_))),
+            "{:?}",
+            result
         );
     }
 
     #[test]
-    fn expression_eval_success_partial_no_vars() {
+    fn expression_contains() {
         let mut expr = Expression::<Property>::new();
+        let a = expr.contains(Property::Str, "dio").unwrap();
+        let b = expr.contains_ci(Property::Str, "HEAD").unwrap();
+        let _ = expr.and(a, b).unwrap();
 
-        let a = expr.is(Property::Int, Value::Int(42)).unwrap();
-        let b = expr.constant(true).unwrap();
-        let _ = expr.or(a, b).unwrap();
+        assert_eq!(
+            format!("{}", expr),
+            "(Property::Str (Str) contains 'dio' && Property::Str (Str) contains_ci 'HEAD')"
+        );
 
-        let requested = expr.variables().requested().map(|v| *v).collect::<Vec<_>>();
-        assert_eq!(requested, vec![Property::Int]);
+        let context =
+            Context::from_values(vec![(Property::Str, Value::Str("Radiohead".to_owned()))])
+                .unwrap();
+        let result = expr.eval(&context);
+        assert!(
+            matches!(result, Ok(Evaluated::Fully(x, _, _)) if x),
+            "{:?}",
+            result
+        );
 
-        let result = expr.eval(&Context::empty());
-        assert!(matches!(result, Ok(Evaluated::Partially(_))));
+        let context =
+            Context::from_values(vec![(Property::Str, Value::Str("Portishead".to_owned()))])
+                .unwrap();
+        let result = expr.eval(&context);
+        assert!(
+            matches!(result, Ok(Evaluated::Fully(x, _, _)) if !x),
+            "{:?}",
+            result
+        );
+    }
 
-        if let Ok(Evaluated::Partially(partexpr)) = result {
-            let requested = partexpr
-                .variables()
-                .requested()
-                .map(|v| *v)
-                .collect::<Vec<_>>();
-            assert_eq!(requested, vec![Property::Int]);
-        }
+    #[test]
+    fn expression_contains_type_mismatch() {
+        let mut expr = Expression::<Property>::new();
+        let result = expr.contains(Property::Int, "42");
+
+        assert!(matches!(
+            result,
+            Err(Error::TypeMismatch(
+                "Property::Int",
+                Datatype::Int,
+                Datatype::Str
+            ))
+        ));
+        assert!(expr.is_empty());
     }
 
     #[test]
-    fn expression_eval_success_partial_some_vars() {
+    fn expression_iter() {
         let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let b = expr.constant(true).unwrap();
+        let _ = expr.and(a, b).unwrap();
+
+        let ops = expr.iter().collect::<Vec<_>>();
+        assert_eq!(ops.len(), 3);
+        assert!(matches!(ops[0], (0, Operation::Is(_), 1)));
+        assert!(matches!(ops[1], (1, Operation::Const(true), 1)));
+        assert!(matches!(ops[2], (2, Operation::And(0, 1), 0)));
+    }
 
+    #[test]
+    fn expression_iter_reachable() {
+        let mut expr = Expression::<Property>::new();
         let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let orphan = expr.constant(false).unwrap();
         let b = expr.is(Property::Bool, Value::Bool(true)).unwrap();
-        let _ = expr.or(a, b).unwrap();
+        let not_b = expr.not(b).unwrap();
+        let a_or_b = expr.or(a, not_b).unwrap();
+        let root = expr.and(a, a_or_b).unwrap();
+        let _after_root = expr.constant(true).unwrap();
+        expr.set_root(root).unwrap();
 
-        let requested = expr
-            .variables()
-            .requested()
-            .map(|v| *v)
-            .collect::<HashSet<_>>();
+        let reachable = expr
+            .iter_reachable()
+            .map(|(idx, _, _)| idx)
+            .collect::<Vec<_>>();
+        assert_eq!(reachable, vec![a, b, not_b, a_or_b, root]);
+        assert!(!reachable.contains(&orphan));
 
-        let expected = vec![Property::Int, Property::Bool]
-            .into_iter()
-            .collect::<HashSet<_>>();
+        // operands appear before their users
+        let mut seen = HashSet::new();
+        for (idx, op, _) in expr.iter_reachable() {
+            assert!(op.operands().iter().all(|opref| seen.contains(opref)));
+            seen.insert(idx);
+        }
 
-        assert_eq!(requested, expected);
+        assert_eq!(Expression::<Property>::new().iter_reachable().count(), 0);
+    }
 
-        let mut context = expr.variables();
-        context.provide(Property::Int, Value::Int(42)).unwrap();
+    #[test]
+    fn expression_builder_literals() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 42).unwrap();
+        let b = expr.is(Property::Bool, true).unwrap();
+        let c = expr.is(Property::Str, "foo").unwrap();
+        let d = expr.is_in(Property::Int, vec![41, 42]).unwrap();
+        let e = expr.is_in(Property::Str, vec!["foo", "bar"]).unwrap();
+        let f = expr.is_in(Property::Bool, Some(false)).unwrap();
 
-        let result = expr.eval(&Context::empty());
-        assert!(matches!(result, Ok(Evaluated::Partially(_))));
+        let mut explicit = Expression::<Property>::new();
+        let _ = explicit.is(Property::Int, Value::Int(42)).unwrap();
+        let _ = explicit.is(Property::Bool, Value::Bool(true)).unwrap();
+        let _ = explicit
+            .is(Property::Str, Value::Str("foo".to_owned()))
+            .unwrap();
+        let _ = explicit
+            .is_in(Property::Int, vec![Value::Int(41), Value::Int(42)])
+            .unwrap();
+        let _ = explicit
+            .is_in(
+                Property::Str,
+                vec![Value::Str("foo".to_owned()), Value::Str("bar".to_owned())],
+            )
+            .unwrap();
+        let _ = explicit
+            .is_in(Property::Bool, vec![Value::Bool(false)])
+            .unwrap();
 
-        if let Ok(Evaluated::Partially(partexpr)) = result {
-            let context = partexpr.variables();
-            let requested = context.requested().map(|v| *v).collect::<Vec<_>>();
+        for opref in &[a, b, c, d, e, f] {
+            assert_eq!(
+                expr.subexpression(*opref).unwrap(),
+                explicit.subexpression(*opref).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn expression_builder_literals_type_mismatch() {
+        let mut expr = Expression::<Property>::new();
+        assert!(matches!(
+            expr.is(Property::Int, "42"),
+            Err(Error::TypeMismatch(
+                "Property::Int",
+                Datatype::Int,
+                Datatype::Str
+            ))
+        ));
+        assert!(matches!(
+            expr.is_in(Property::Bool, vec![1, 0]),
+            Err(Error::TypeMismatch(
+                "Property::Bool",
+                Datatype::Bool,
+                Datatype::Int
+            ))
+        ));
+    }
+
+    #[test]
+    fn expression_is_not() {
+        let mut expr = Expression::<Property>::new();
+        let _ = expr.is_not(Property::Int, 5).unwrap();
+        assert_eq!(format!("{}", expr), "Property::Int (Int) != 5");
+        assert_eq!(
+            expr.variables().requested().collect::<Vec<_>>(),
+            vec![&Property::Int]
+        );
 
+        let mut negated = Expression::<Property>::new();
+        let is = negated.is(Property::Int, 5).unwrap();
+        let _ = negated.not(is).unwrap();
+        assert_ne!(expr, negated);
+
+        for int in &[5, 6] {
+            let context = Context::from_values(vec![(Property::Int, Value::Int(*int))]).unwrap();
             assert_eq!(
-                requested,
-                vec![Property::Bool],
-                "expression: {}, context: {}",
-                partexpr,
-                context
+                expr.eval(&context).unwrap().as_bool(),
+                negated.eval(&context).unwrap().as_bool()
             );
         }
+
+        // without a value both stay unknown, nulls aren't supported yet
+        let context = Context::request(vec![Property::Int]);
+        assert!(expr.eval(&context).unwrap().as_bool().is_none());
+        assert!(negated.eval(&context).unwrap().as_bool().is_none());
+
+        assert!(matches!(
+            expr.is_not(Property::Int, "5"),
+            Err(Error::TypeMismatch(
+                "Property::Int",
+                Datatype::Int,
+                Datatype::Str
+            ))
+        ));
+    }
+
+    #[test]
+    fn expression_approx_is() {
+        let mut expr = Expression::<Rated>::new();
+        let _ = expr.approx_is(Rated::Rating, 4.5, 0.1).unwrap();
+        assert_eq!(format!("{}", expr), "Rated::Rating (Float) ≈ 4.5 ±0.1");
+
+        for (rating, expected) in &[(4.55, true), (4.45, true), (4.65, false), (4.35, false)] {
+            let context =
+                Context::from_values(vec![(Rated::Rating, Value::Float(*rating))]).unwrap();
+            assert_eq!(expr.eval(&context).unwrap().as_bool(), Some(*expected));
+        }
+
+        let mut expr = Expression::<Property>::new();
+        assert!(matches!(
+            expr.approx_is(Property::Int, 4.5, 0.1),
+            Err(Error::TypeMismatch(
+                "Property::Int",
+                Datatype::Int,
+                Datatype::Float
+            ))
+        ));
+    }
+
+    #[test]
+    fn expression_is_in_empty() {
+        let mut expr = Expression::<Property>::new();
+        let _ = expr.is_in(Property::Int, Vec::<Value>::new()).unwrap();
+        assert_eq!(expr.variables().requested().count(), 0);
+
+        let context = Context::empty();
+        assert!(matches!(
+            expr.eval(&context).unwrap(),
+            Evaluated::Fully(false, _, _)
+        ));
+        assert_eq!(
+            expr.eval_ordered(&context, &|_| 1.0).unwrap().as_bool(),
+            Some(false)
+        );
+
+        let a = expr.is(Property::Bool, true).unwrap();
+        let _ = expr.and(0, a).unwrap();
+        assert_eq!(format!("{}", expr.simplify().unwrap()), "false");
     }
 
     // Tests TODO:
@@ -0,0 +1,312 @@
+use std::cell::RefCell;
+use std::ops::{BitAnd, BitOr, Not};
+
+use super::{Expression, OpRef};
+use crate::condition::Predicate;
+use crate::domain::Property;
+use crate::error::{Error, Result};
+use crate::value::Value;
+
+/// Builds an expression by composing condition handles with `&`, `|` and `!`.
+///
+/// This is sugar over the regular `Expression` builder methods: every operator applied to handles
+/// pushes a new operation. Errors raised while composing are kept and reported by `build`,
+/// operators return a poisoned handle instead, and anything composed from it is poisoned too.
+pub struct ExprBuilder<Pid: Property> {
+    expr: RefCell<Expression<Pid>>,
+    error: RefCell<Option<Error>>,
+}
+
+/// Operation pushed into an `ExprBuilder`, or a poisoned one if composing it failed.
+#[derive(Debug)]
+pub struct Handle<'a, Pid: Property> {
+    builder: &'a ExprBuilder<Pid>,
+    opref: Option<OpRef>,
+}
+
+impl<'a, Pid: Property> Clone for Handle<'a, Pid> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, Pid: Property> Copy for Handle<'a, Pid> {}
+
+impl<'a, Pid: Property> Handle<'a, Pid> {
+    /// Reference of the operation, `None` for poisoned handles, see `ExprBuilder::build` for why.
+    pub fn opref(&self) -> Option<OpRef> {
+        self.opref
+    }
+
+    /// Pushes an operation on both handles' operations, `other` defaults to this one.
+    fn combine<F>(self, other: Option<Self>, push: F) -> Self
+    where
+        F: FnOnce(&mut Expression<Pid>, OpRef, OpRef) -> Result<OpRef>,
+    {
+        let other = other.unwrap_or(self);
+        if !std::ptr::eq(self.builder, other.builder) {
+            // either of the builders may be the one that's built
+            self.builder.fail(Error::ExpressionForeignHandle);
+            other.builder.fail(Error::ExpressionForeignHandle);
+            return Handle {
+                builder: self.builder,
+                opref: None,
+            };
+        }
+        match (self.opref, other.opref) {
+            (Some(lhs), Some(rhs)) => self.builder.handle(|expr| push(expr, lhs, rhs)),
+            // the error that poisoned an operand is already kept
+            _ => Handle {
+                builder: self.builder,
+                opref: None,
+            },
+        }
+    }
+}
+
+impl<'a, Pid: Property> BitAnd for Handle<'a, Pid> {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        self.combine(Some(rhs), |expr, lhs, rhs| expr.and(lhs, rhs))
+    }
+}
+
+impl<'a, Pid: Property> BitOr for Handle<'a, Pid> {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        self.combine(Some(rhs), |expr, lhs, rhs| expr.or(lhs, rhs))
+    }
+}
+
+impl<'a, Pid: Property> Not for Handle<'a, Pid> {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        self.combine(None, |expr, opref, _| expr.not(opref))
+    }
+}
+
+impl<Pid: Property> std::fmt::Debug for ExprBuilder<Pid> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ExprBuilder({:?})", self.expr.borrow())
+    }
+}
+
+impl<Pid: Property> ExprBuilder<Pid> {
+    pub fn new() -> Self {
+        ExprBuilder {
+            expr: RefCell::new(Expression::new()),
+            error: RefCell::new(None),
+        }
+    }
+
+    pub fn with_limits(max_ops: usize, max_depth: usize) -> Self {
+        ExprBuilder {
+            expr: RefCell::new(Expression::with_limits(max_ops, max_depth)),
+            error: RefCell::new(None),
+        }
+    }
+
+    /// Remembers the first error so that `build` can report it.
+    fn fail(&self, err: Error) {
+        self.error.borrow_mut().get_or_insert(err);
+    }
+
+    /// Pushes an operation, the handle is poisoned if it fails.
+    fn handle<F>(&self, push: F) -> Handle<'_, Pid>
+    where
+        F: FnOnce(&mut Expression<Pid>) -> Result<OpRef>,
+    {
+        let opref = match push(&mut self.expr.borrow_mut()) {
+            Ok(opref) => Some(opref),
+            Err(err) => {
+                self.fail(err);
+                None
+            }
+        };
+        Handle {
+            builder: self,
+            opref,
+        }
+    }
+
+    pub fn constant(&self, value: bool) -> Result<Handle<'_, Pid>> {
+        let opref = self.expr.borrow_mut().constant(value)?;
+        Ok(Handle {
+            builder: self,
+            opref: Some(opref),
+        })
+    }
+
+    pub fn is<V: Into<Value>>(&self, variable: Pid, value: V) -> Result<Handle<'_, Pid>> {
+        let opref = self.expr.borrow_mut().is(variable, value)?;
+        Ok(Handle {
+            builder: self,
+            opref: Some(opref),
+        })
+    }
+
+    pub fn is_not<V: Into<Value>>(&self, variable: Pid, value: V) -> Result<Handle<'_, Pid>> {
+        let opref = self.expr.borrow_mut().is_not(variable, value)?;
+        Ok(Handle {
+            builder: self,
+            opref: Some(opref),
+        })
+    }
+
+    pub fn is_in<I>(&self, variable: Pid, values: I) -> Result<Handle<'_, Pid>>
+    where
+        I: IntoIterator,
+        I::Item: Into<Value>,
+    {
+        let opref = self.expr.borrow_mut().is_in(variable, values)?;
+        Ok(Handle {
+            builder: self,
+            opref: Some(opref),
+        })
+    }
+
+    pub fn contains(&self, variable: Pid, needle: &str) -> Result<Handle<'_, Pid>> {
+        let opref = self.expr.borrow_mut().contains(variable, needle)?;
+        Ok(Handle {
+            builder: self,
+            opref: Some(opref),
+        })
+    }
+
+    pub fn any_element(&self, variable: Pid, predicate: Predicate) -> Result<Handle<'_, Pid>> {
+        let opref = self.expr.borrow_mut().any_element(variable, predicate)?;
+        Ok(Handle {
+            builder: self,
+            opref: Some(opref),
+        })
+    }
+
+    pub fn all_elements(&self, variable: Pid, predicate: Predicate) -> Result<Handle<'_, Pid>> {
+        let opref = self.expr.borrow_mut().all_elements(variable, predicate)?;
+        Ok(Handle {
+            builder: self,
+            opref: Some(opref),
+        })
+    }
+
+    /// Returns the composed expression, rooted at the last pushed operation,
+    /// or the first error raised by an operator.
+    pub fn build(self) -> Result<Expression<Pid>> {
+        match self.error.into_inner() {
+            Some(err) => Err(err),
+            None => Ok(self.expr.into_inner()),
+        }
+    }
+}
+
+impl<Pid: Property> Default for ExprBuilder<Pid> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::expression::Context;
+    use crate::testproperty::Property;
+
+    #[test]
+    fn builder_operators() {
+        let builder = ExprBuilder::<Property>::new();
+        let a = builder.is(Property::Int, 42).unwrap();
+        let b = builder.is(Property::Bool, true).unwrap();
+        let c = builder.contains(Property::Str, "foo").unwrap();
+        let _ = (a & b) | !c;
+        let built = builder.build().unwrap();
+
+        let mut manual = Expression::<Property>::new();
+        let a = manual.is(Property::Int, 42).unwrap();
+        let b = manual.is(Property::Bool, true).unwrap();
+        let c = manual.contains(Property::Str, "foo").unwrap();
+        let a_and_b = manual.and(a, b).unwrap();
+        let not_c = manual.not(c).unwrap();
+        let _ = manual.or(a_and_b, not_c).unwrap();
+
+        assert_eq!(built, manual);
+        for int in &[42, 24] {
+            for boolean in &[true, false] {
+                for string in &["foo", "bar"] {
+                    let context = Context::from_values(vec![
+                        (Property::Int, Value::Int(*int)),
+                        (Property::Bool, Value::Bool(*boolean)),
+                        (Property::Str, Value::Str(string.to_string())),
+                    ])
+                    .unwrap();
+
+                    assert_eq!(
+                        built.eval(&context).unwrap().as_bool(),
+                        manual.eval(&context).unwrap().as_bool()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn builder_keeps_first_error() {
+        let builder = ExprBuilder::<Property>::with_limits(10, 2);
+        let a = builder.is(Property::Int, 42).unwrap();
+        let b = builder.constant(true).unwrap();
+        let a_and_b = a & b;
+        let _ = !a_and_b;
+        let _ = !a;
+
+        let result = builder.build();
+        assert!(
+            matches!(result, Err(Error::ExpressionTooLarge { ops: 4, depth: 3 })),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn builder_poisoned_handles() {
+        // the failed `!` doesn't silently leave `a` in its place
+        let builder = ExprBuilder::<Property>::with_limits(10, 2);
+        let a = builder.is(Property::Int, 42).unwrap();
+        let b = builder.constant(true).unwrap();
+        let not_a_and_b = !(a & b);
+        assert_eq!(not_a_and_b.opref(), None);
+        let c = builder.is(Property::Bool, true).unwrap();
+        let poisoned = not_a_and_b | c;
+        assert_eq!(poisoned.opref(), None);
+        assert_eq!(builder.expr.borrow().len(), 4);
+        assert!(matches!(
+            builder.build(),
+            Err(Error::ExpressionTooLarge { ops: 4, depth: 3 })
+        ));
+
+        // handles of different builders poison both of them instead of panicking
+        let lhs = ExprBuilder::<Property>::new();
+        let rhs = ExprBuilder::<Property>::new();
+        let a = lhs.is(Property::Int, 42).unwrap();
+        let b = rhs.is(Property::Int, 42).unwrap();
+        assert_eq!((a & b).opref(), None);
+        assert_eq!(lhs.expr.borrow().len(), 1);
+        for builder in [lhs, rhs] {
+            let result = builder.build();
+            assert!(
+                matches!(result, Err(Error::ExpressionForeignHandle)),
+                "{:?}",
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn builder_leaf_error() {
+        let builder = ExprBuilder::<Property>::new();
+        let result = builder.is(Property::Int, "42");
+        assert!(matches!(result, Err(Error::TypeMismatch(_, _, _))));
+    }
+}
@@ -0,0 +1,178 @@
+use super::incremental::settle;
+use super::{Evaluated, Expression, OpRef, Operation};
+use crate::domain::Property;
+
+/// What providing a single missing property can do for a partially evaluated expression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Decisiveness {
+    /// Some value of the property alone could decide the result.
+    Decisive,
+    /// The property could still change the result, but only along with other ones.
+    Needed,
+}
+
+impl<Pid: Property> Expression<Pid> {
+    /// Missing properties of `partial`, the result of evaluating this expression, ranked by what
+    /// providing each of them alone could do: decisive ones first, then by name.
+    ///
+    /// Conditions on the property are assumed to be able to turn out either way independently,
+    /// so a property may be reported decisive even if no single value satisfies them all at once.
+    /// Empty for fully evaluated expressions.
+    pub fn next_properties(&self, partial: &Evaluated<Pid>) -> Vec<(Pid, Decisiveness)> {
+        let expr = match partial {
+            Evaluated::Fully(_, _, _) => return Vec::new(),
+            Evaluated::Partially(expr) => expr,
+        };
+        let root = match expr.root() {
+            Some(root) if root < expr.ops.len() => root,
+            _ => return Vec::new(),
+        };
+
+        let mut properties = partial
+            .missing()
+            .into_iter()
+            .map(|prop| {
+                let decisiveness = if expr.decidable_by(root, prop) {
+                    Decisiveness::Decisive
+                } else {
+                    Decisiveness::Needed
+                };
+                (prop, decisiveness)
+            })
+            .collect::<Vec<_>>();
+        properties.sort_by_key(|(prop, decisiveness)| (*decisiveness, prop.name()));
+        properties
+    }
+
+    /// Whether the root can be decided with only the value of `property` known,
+    /// by tracking every result each operation up to it could have.
+    fn decidable_by(&self, root: OpRef, property: Pid) -> bool {
+        let mut outcomes: Vec<Vec<Option<bool>>> = Vec::with_capacity(root + 1);
+        // scratch results of operands for `settle`
+        let mut results = vec![None; root + 1];
+
+        for (op, _) in self.ops.iter().take(root + 1) {
+            let possible = match op {
+                _ if op.variable() == Some(property) => vec![Some(true), Some(false)],
+                Operation::Not(_)
+                | Operation::Or(_, _)
+                | Operation::And(_, _)
+                | Operation::Nand(_, _)
+                | Operation::Nor(_, _)
+                | Operation::Implies(_, _)
+                | Operation::IfElse(_, _, _) => {
+                    let mut possible = Vec::new();
+                    let operands = op.operands();
+                    let mut combination = vec![0; operands.len()];
+                    loop {
+                        for (&opref, &choice) in operands.iter().zip(&combination) {
+                            results[opref] = outcomes[opref][choice];
+                        }
+                        let result = settle(op, &results);
+                        if !possible.contains(&result) {
+                            possible.push(result);
+                        }
+
+                        // next combination of operand outcomes, like an odometer
+                        let mut pos = 0;
+                        while pos < operands.len() {
+                            combination[pos] += 1;
+                            if combination[pos] < outcomes[operands[pos]].len() {
+                                break;
+                            }
+                            combination[pos] = 0;
+                            pos += 1;
+                        }
+                        if pos == operands.len() {
+                            break;
+                        }
+                    }
+                    possible
+                }
+                _ => vec![settle(op, &results)],
+            };
+            outcomes.push(possible);
+        }
+
+        outcomes[root].iter().any(Option::is_some)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::expression::Context;
+    use crate::testproperty::Property;
+    use crate::value::Value;
+
+    #[test]
+    fn next_properties_or_branch() {
+        // Bool decides the `Or` alone, Int and Str only decide it together
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Bool, true).unwrap();
+        let b = expr.is(Property::Int, 42).unwrap();
+        let c = expr.contains(Property::Str, "match").unwrap();
+        let b_and_c = expr.and(b, c).unwrap();
+        let _ = expr.or(a, b_and_c).unwrap();
+
+        let partial = expr.eval(&Context::empty()).unwrap();
+        assert_eq!(
+            expr.next_properties(&partial),
+            vec![
+                (Property::Bool, Decisiveness::Decisive),
+                (Property::Int, Decisiveness::Needed),
+                (Property::Str, Decisiveness::Needed),
+            ]
+        );
+
+        // with Int matching, Str decides the `And` and so the root
+        let context = Context::from_values(vec![(Property::Int, Value::Int(42))]).unwrap();
+        let partial = expr.eval(&context).unwrap();
+        assert_eq!(
+            expr.next_properties(&partial),
+            vec![
+                (Property::Bool, Decisiveness::Decisive),
+                (Property::Str, Decisiveness::Decisive),
+            ]
+        );
+
+        let context = Context::from_values(vec![(Property::Bool, Value::Bool(true))]).unwrap();
+        let evaluated = expr.eval(&context).unwrap();
+        assert!(expr.next_properties(&evaluated).is_empty());
+    }
+
+    #[test]
+    fn next_properties_and_chain() {
+        // the chain needs several properties, and the root no single one of them
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Bool, true).unwrap();
+        let b = expr.is(Property::Int, 42).unwrap();
+        let c = expr.is(Property::UInt, 7u64).unwrap();
+        let d = expr.contains(Property::Str, "match").unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let chain = expr.and(a_and_b, c).unwrap();
+        let not_d = expr.not(d).unwrap();
+        let d_implies_chain = expr.implies(not_d, chain).unwrap();
+        let _ = expr.if_else(b, d_implies_chain, a).unwrap();
+
+        let partial = expr.eval(&Context::empty()).unwrap();
+        assert_eq!(
+            expr.next_properties(&partial),
+            vec![
+                (Property::Bool, Decisiveness::Needed),
+                (Property::Int, Decisiveness::Needed),
+                (Property::Str, Decisiveness::Needed),
+                (Property::UInt, Decisiveness::Needed),
+            ]
+        );
+
+        // once Int isn't 42 only the `else` branch is left
+        let context = Context::from_values(vec![(Property::Int, Value::Int(1))]).unwrap();
+        let partial = expr.eval(&context).unwrap();
+        assert_eq!(
+            expr.next_properties(&partial),
+            vec![(Property::Bool, Decisiveness::Decisive)]
+        );
+    }
+}
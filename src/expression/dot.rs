@@ -0,0 +1,116 @@
+use std::fmt::Write;
+
+use super::{Expression, Operation};
+use crate::domain::Property;
+
+/// Longest condition summary in node labels, in characters.
+const MAX_LABEL: usize = 40;
+
+fn label<Pid: Property>(op: &Operation<Pid>) -> String {
+    let summary = match op {
+        Operation::Const(val) => val.to_string(),
+        Operation::Is(cond) => cond.to_string(),
+        Operation::IsNot(cond) => cond.to_string(),
+        Operation::ApproxIs(cond) => cond.to_string(),
+        Operation::In(cond) => cond.to_string(),
+        Operation::Contains(cond) => cond.to_string(),
+        Operation::Quantified(cond) => cond.to_string(),
+        Operation::Not(_) => "Not".to_owned(),
+        Operation::Or(..) => "Or".to_owned(),
+        Operation::And(..) => "And".to_owned(),
+        Operation::Nand(..) => "Nand".to_owned(),
+        Operation::Nor(..) => "Nor".to_owned(),
+        Operation::Implies(..) => "Implies".to_owned(),
+        Operation::IfElse(..) => "IfElse".to_owned(),
+    };
+
+    let mut truncated = summary.chars().take(MAX_LABEL).collect::<String>();
+    if truncated.len() < summary.len() {
+        truncated.push('…');
+    }
+    truncated.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl<Pid: Property> Expression<Pid> {
+    /// Graphviz DOT form of the operation graph, for debugging large expressions.
+    ///
+    /// Every operation is a node labeled with its index and a short summary, edges go from
+    /// operations to their operands, so shared operations have several incoming edges.
+    /// The root is drawn bold, operations unreachable from it are grayed out.
+    /// References to missing operations are skipped.
+    pub fn to_dot(&self) -> String {
+        let reachable = match self.root() {
+            Some(root) => self.reachable(root),
+            None => vec![false; self.ops.len()],
+        };
+
+        let mut dot = String::from("digraph expression {\n    node [shape=box];\n");
+        for (idx, (op, _)) in self.ops.iter().enumerate() {
+            let style = if Some(idx) == self.root() {
+                ", style=bold, penwidth=2"
+            } else if !reachable[idx] {
+                ", color=gray, fontcolor=gray"
+            } else {
+                ""
+            };
+            let _ = writeln!(
+                dot,
+                "    op{0} [label=\"#{0}: {1}\"{2}];",
+                idx,
+                label(op),
+                style
+            );
+        }
+        for (idx, (op, _)) in self.ops.iter().enumerate() {
+            for opref in op.operands() {
+                if opref < self.ops.len() {
+                    let _ = writeln!(dot, "    op{} -> op{};", idx, opref);
+                }
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::testproperty::Property;
+
+    #[test]
+    fn to_dot_shared() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr
+            .is(Property::Str, "a \"quoted\" and rather long string value")
+            .unwrap();
+        let b = expr.is(Property::Int, 1).unwrap();
+        let c = expr.constant(false).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let _ = expr.if_else(a_and_b, a_or_b, a).unwrap();
+        // dangling operation, never referenced by the root
+        let _ = expr.not(c).unwrap();
+        expr.set_root(5).unwrap();
+
+        let dot = expr.to_dot();
+        let nodes = dot.lines().filter(|line| line.contains("[label=")).count();
+        let edges = dot.lines().filter(|line| line.contains("->")).count();
+        let operands = expr
+            .ops
+            .iter()
+            .map(|(op, _)| op.operands().len())
+            .sum::<usize>();
+        assert_eq!(nodes, expr.len());
+        assert_eq!(edges, operands);
+        assert_eq!(dot.matches("-> op0;").count(), 3);
+
+        assert!(dot.starts_with("digraph expression {"));
+        assert!(dot.contains(r##"op0 [label="#0: Property::Str (Str) == a \"quoted\" and ra…"];"##));
+        assert!(dot.contains(r##"op5 [label="#5: IfElse", style=bold, penwidth=2];"##));
+        assert!(dot.contains(r##"op2 [label="#2: false", color=gray, fontcolor=gray];"##));
+        assert!(dot.contains(r##"op6 [label="#6: Not", color=gray, fontcolor=gray];"##));
+        assert!(dot.contains(r##"op3 [label="#3: And"];"##));
+    }
+}
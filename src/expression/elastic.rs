@@ -0,0 +1,246 @@
+use serde_json::{json, Map, Value as Json};
+
+use super::{Expression, OpRef, Operation};
+use crate::condition::{Predicate, Quantifier};
+use crate::domain::Property;
+use crate::error::{Error, Result};
+use crate::value::Value;
+
+fn field(name: &str, condition: Json) -> Json {
+    let mut doc = Map::new();
+    doc.insert(name.to_owned(), condition);
+    Json::Object(doc)
+}
+
+fn wildcard_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if "\\*?".contains(ch) {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+fn must_not(query: Json) -> Json {
+    json!({ "bool": { "must_not": [query] } })
+}
+
+fn should(lhs: Json, rhs: Json) -> Json {
+    json!({ "bool": { "should": [lhs, rhs], "minimum_should_match": 1 } })
+}
+
+fn terms<'v>(name: &str, expected: impl Iterator<Item = &'v Value>) -> Json {
+    // sets don't keep any particular order, sort values to make the output stable
+    let mut values = expected.collect::<Vec<_>>();
+    values.sort();
+    let values = values.into_iter().map(Json::from).collect::<Vec<_>>();
+    json!({ "terms": field(name, Json::from(values)) })
+}
+
+fn wildcard(name: &str, needle: &str, case_insensitive: bool) -> Json {
+    let pattern = format!("*{}*", wildcard_escape(needle));
+    let condition = if case_insensitive {
+        json!({ "value": pattern, "case_insensitive": true })
+    } else {
+        json!({ "value": pattern })
+    };
+    json!({ "wildcard": field(name, condition) })
+}
+
+fn render<Pid: Property, F: Fn(Pid) -> String>(
+    expr: &Expression<Pid>,
+    opref: OpRef,
+    mapper: &F,
+) -> Result<Json> {
+    let render = |opref| render(expr, opref, mapper);
+    let query = match &expr.ops[opref].0 {
+        Operation::Const(true) => json!({ "match_all": {} }),
+        Operation::Const(false) => json!({ "match_none": {} }),
+        Operation::Is(cond) => json!({
+            "term": field(&mapper(cond.variable()), Json::from(cond.expected()))
+        }),
+        Operation::IsNot(cond) => must_not(json!({
+            "term": field(&mapper(cond.variable()), Json::from(cond.expected()))
+        })),
+        Operation::ApproxIs(cond) => match cond.bounds() {
+            Some((low, high)) => json!({
+                "range": field(&mapper(cond.variable()), json!({ "gte": low, "lte": high }))
+            }),
+            None => json!({ "match_none": {} }),
+        },
+        Operation::In(cond) => terms(&mapper(cond.variable()), cond.expected().iter()),
+        Operation::Contains(cond) => wildcard(
+            &mapper(cond.variable()),
+            cond.needle(),
+            cond.case_insensitive(),
+        ),
+        // array fields are indexed as separate values, so element queries match any element
+        Operation::Quantified(cond) if cond.quantifier() == Quantifier::Any => {
+            let name = mapper(cond.variable());
+            match cond.predicate() {
+                Predicate::Is(expected) => json!({ "term": field(&name, Json::from(expected)) }),
+                Predicate::In(expected) => terms(&name, expected.iter()),
+                Predicate::Contains(needle, case_insensitive) => {
+                    wildcard(&name, needle, *case_insensitive)
+                }
+            }
+        }
+        Operation::Quantified(cond) => {
+            return Err(Error::ExpressionUnsupported(
+                opref,
+                "Elasticsearch",
+                cond.to_string(),
+            ))
+        }
+        &Operation::Not(opref) => must_not(render(opref)?),
+        &Operation::Or(lhs, rhs) => should(render(lhs)?, render(rhs)?),
+        &Operation::And(lhs, rhs) => json!({ "bool": { "must": [render(lhs)?, render(rhs)?] } }),
+        &Operation::Nand(lhs, rhs) => {
+            must_not(json!({ "bool": { "must": [render(lhs)?, render(rhs)?] } }))
+        }
+        &Operation::Nor(lhs, rhs) => {
+            json!({ "bool": { "must_not": [render(lhs)?, render(rhs)?] } })
+        }
+        &Operation::Implies(premise, conclusion) => {
+            should(must_not(render(premise)?), render(conclusion)?)
+        }
+        &Operation::IfElse(cond, then, otherwise) => should(
+            json!({ "bool": { "must": [render(cond)?, render(then)?] } }),
+            json!({ "bool": { "must": [must_not(render(cond)?), render(otherwise)?] } }),
+        ),
+    };
+    Ok(query)
+}
+
+/// Translates an expression into an Elasticsearch `bool` query, field names come from
+/// `Property::name()`.
+///
+/// Conditions become `term` / `terms` queries, substring conditions become `wildcard` queries
+/// with wildcards in the needle escaped. `And` maps to `must`, `Or` to `should` with
+/// `minimum_should_match: 1` and `Not` to `must_not`, constants to `match_all` / `match_none`.
+/// Conditions on any list element map to the same queries, conditions on all elements
+/// can't be expressed and fail with `Error::ExpressionUnsupported`.
+pub fn to_query<Pid: Property>(expr: &Expression<Pid>) -> Result<Json> {
+    to_query_with(expr, &|prop: Pid| prop.name().to_owned())
+}
+
+/// Same as `to_query`, but field names come from `mapper`.
+pub fn to_query_with<Pid: Property, F: Fn(Pid) -> String>(
+    expr: &Expression<Pid>,
+    mapper: &F,
+) -> Result<Json> {
+    let root = expr.root().ok_or(Error::ExpressionNoop)?;
+    render(expr, expr.valid(root)?, mapper)
+}
+
+impl<Pid: Property> Expression<Pid> {
+    /// Elasticsearch `bool` query for the expression, see `elastic::to_query`.
+    pub fn to_elasticsearch(&self) -> Result<Json> {
+        to_query(self)
+    }
+
+    /// Same as `to_elasticsearch`, but field names come from `mapper`.
+    pub fn to_elasticsearch_with(&self, mapper: &impl Fn(Pid) -> String) -> Result<Json> {
+        to_query_with(self, mapper)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::condition::Predicate;
+    use crate::testproperty::{Listed, Property};
+
+    #[test]
+    fn to_query_nested() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 42).unwrap();
+        let b = expr.is_in(Property::Str, vec!["b", "a"]).unwrap();
+        let c = expr.is_not(Property::Bool, true).unwrap();
+        let d = expr.contains_ci(Property::Str, "*x?").unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let not_d = expr.not(d).unwrap();
+        let c_and_not_d = expr.and(c, not_d).unwrap();
+        let _ = expr.and(a_or_b, c_and_not_d).unwrap();
+
+        assert_eq!(
+            expr.to_elasticsearch().unwrap(),
+            json!({ "bool": { "must": [
+                { "bool": {
+                    "should": [
+                        { "term": { "Property::Int": 42 } },
+                        { "terms": { "Property::Str": ["a", "b"] } },
+                    ],
+                    "minimum_should_match": 1,
+                }},
+                { "bool": { "must": [
+                    { "bool": { "must_not": [{ "term": { "Property::Bool": true } }] } },
+                    { "bool": { "must_not": [{ "wildcard": { "Property::Str": {
+                        "value": "*\\*x\\?*",
+                        "case_insensitive": true,
+                    }}}]}},
+                ]}},
+            ]}})
+        );
+    }
+
+    #[test]
+    fn to_query_constants_and_mapper() {
+        let mut expr = Expression::<Property>::new();
+        let t = expr.constant(true).unwrap();
+        let f = expr.constant(false).unwrap();
+        let a = expr.contains(Property::Str, "x").unwrap();
+        let _ = expr.if_else(a, t, f).unwrap();
+
+        let column = |prop: Property| format!("{:?}", prop).to_lowercase();
+        let wildcard = json!({ "wildcard": { "str": { "value": "*x*" } } });
+        assert_eq!(
+            expr.to_elasticsearch_with(&column).unwrap(),
+            json!({ "bool": {
+                "should": [
+                    { "bool": { "must": [wildcard, { "match_all": {} }] } },
+                    { "bool": { "must": [
+                        { "bool": { "must_not": [wildcard] } },
+                        { "match_none": {} },
+                    ]}},
+                ],
+                "minimum_should_match": 1,
+            }})
+        );
+    }
+
+    #[test]
+    fn to_query_empty() {
+        let result = to_query(&Expression::<Property>::new());
+        assert!(matches!(result, Err(Error::ExpressionNoop)), "{:?}", result);
+    }
+
+    #[test]
+    fn to_query_quantified() {
+        let mut expr = Expression::<Listed>::new();
+        let a = expr
+            .any_element(Listed::Tags, Predicate::Is("live".into()))
+            .unwrap();
+        assert_eq!(
+            to_query(&expr).unwrap(),
+            json!({ "term": { "Listed::Tags": "live" } })
+        );
+
+        let b = expr
+            .all_elements(Listed::Tags, Predicate::Is("live".into()))
+            .unwrap();
+        let _ = expr.or(a, b).unwrap();
+        let result = to_query(&expr);
+        assert!(
+            matches!(
+                result,
+                Err(Error::ExpressionUnsupported(1, "Elasticsearch", _))
+            ),
+            "{:?}",
+            result
+        );
+    }
+}
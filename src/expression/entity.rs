@@ -0,0 +1,170 @@
+use super::{Expression, OpRef};
+use crate::condition::Predicate;
+use crate::domain::{Entity, Property};
+use crate::error::{Error, Result};
+use crate::value::Value;
+
+/// Expression builder scoped to an entity: conditions on properties the entity doesn't own
+/// fail with `Error::PropertyNotInEntity` before anything is pushed.
+#[derive(Debug)]
+pub struct EntityExpression<Pid: Property, Eid: Entity<Pid>> {
+    entity: Eid,
+    expr: Expression<Pid>,
+}
+
+impl<Pid: Property> Expression<Pid> {
+    pub fn for_entity<Eid: Entity<Pid>>(entity: Eid) -> EntityExpression<Pid, Eid> {
+        EntityExpression {
+            entity,
+            expr: Expression::new(),
+        }
+    }
+}
+
+impl<Pid: Property, Eid: Entity<Pid>> EntityExpression<Pid, Eid> {
+    fn owned(&self, variable: Pid) -> Result<()> {
+        if self.entity.has_property(&variable) {
+            Ok(())
+        } else {
+            let entity = self.entity.name().to_owned();
+            Err(Error::PropertyNotInEntity(variable.name(), entity))
+        }
+    }
+
+    pub fn entity(&self) -> Eid {
+        self.entity
+    }
+
+    pub fn expression(&self) -> &Expression<Pid> {
+        &self.expr
+    }
+
+    pub fn into_expression(self) -> Expression<Pid> {
+        self.expr
+    }
+
+    pub fn constant(&mut self, value: bool) -> Result<OpRef> {
+        self.expr.constant(value)
+    }
+
+    pub fn is<V: Into<Value>>(&mut self, variable: Pid, value: V) -> Result<OpRef> {
+        self.owned(variable)?;
+        self.expr.is(variable, value)
+    }
+
+    pub fn is_not<V: Into<Value>>(&mut self, variable: Pid, value: V) -> Result<OpRef> {
+        self.owned(variable)?;
+        self.expr.is_not(variable, value)
+    }
+
+    pub fn is_in<I>(&mut self, variable: Pid, values: I) -> Result<OpRef>
+    where
+        I: IntoIterator,
+        I::Item: Into<Value>,
+    {
+        self.owned(variable)?;
+        self.expr.is_in(variable, values)
+    }
+
+    pub fn contains(&mut self, variable: Pid, needle: &str) -> Result<OpRef> {
+        self.owned(variable)?;
+        self.expr.contains(variable, needle)
+    }
+
+    pub fn contains_ci(&mut self, variable: Pid, needle: &str) -> Result<OpRef> {
+        self.owned(variable)?;
+        self.expr.contains_ci(variable, needle)
+    }
+
+    pub fn any_element(&mut self, variable: Pid, predicate: Predicate) -> Result<OpRef> {
+        self.owned(variable)?;
+        self.expr.any_element(variable, predicate)
+    }
+
+    pub fn all_elements(&mut self, variable: Pid, predicate: Predicate) -> Result<OpRef> {
+        self.owned(variable)?;
+        self.expr.all_elements(variable, predicate)
+    }
+
+    pub fn not(&mut self, opref: OpRef) -> Result<OpRef> {
+        self.expr.not(opref)
+    }
+
+    pub fn or(&mut self, lhs: OpRef, rhs: OpRef) -> Result<OpRef> {
+        self.expr.or(lhs, rhs)
+    }
+
+    pub fn and(&mut self, lhs: OpRef, rhs: OpRef) -> Result<OpRef> {
+        self.expr.and(lhs, rhs)
+    }
+
+    pub fn nand(&mut self, lhs: OpRef, rhs: OpRef) -> Result<OpRef> {
+        self.expr.nand(lhs, rhs)
+    }
+
+    pub fn nor(&mut self, lhs: OpRef, rhs: OpRef) -> Result<OpRef> {
+        self.expr.nor(lhs, rhs)
+    }
+
+    pub fn implies(&mut self, premise: OpRef, conclusion: OpRef) -> Result<OpRef> {
+        self.expr.implies(premise, conclusion)
+    }
+
+    pub fn if_else(&mut self, cond: OpRef, then: OpRef, otherwise: OpRef) -> Result<OpRef> {
+        self.expr.if_else(cond, then, otherwise)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::testproperty::{Entity, Property};
+
+    #[test]
+    fn entity_expression_owned_properties() {
+        let mut scoped = Expression::for_entity(Entity::Album);
+        let a = scoped.is(Property::Int, 42).unwrap();
+        let b = scoped.contains_ci(Property::Str, "head").unwrap();
+        let c = scoped.is_in(Property::Int, vec![1, 2]).unwrap();
+        let not_c = scoped.not(c).unwrap();
+        let a_or_b = scoped.or(a, b).unwrap();
+        let _ = scoped.and(a_or_b, not_c).unwrap();
+        assert_eq!(scoped.entity(), Entity::Album);
+
+        let mut expected = Expression::<Property>::new();
+        let a = expected.is(Property::Int, 42).unwrap();
+        let b = expected.contains_ci(Property::Str, "head").unwrap();
+        let c = expected.is_in(Property::Int, vec![1, 2]).unwrap();
+        let not_c = expected.not(c).unwrap();
+        let a_or_b = expected.or(a, b).unwrap();
+        let _ = expected.and(a_or_b, not_c).unwrap();
+        assert_eq!(scoped.into_expression(), expected);
+    }
+
+    #[test]
+    fn entity_expression_foreign_property() {
+        let mut scoped = Expression::for_entity(Entity::Album);
+        scoped.is(Property::Int, 42).unwrap();
+
+        let result = scoped.is(Property::Bool, true);
+        assert!(
+            matches!(result, Err(Error::PropertyNotInEntity("Property::Bool", _))),
+            "{:?}",
+            result
+        );
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Property 'Property::Bool' doesn't belong to entity 'Entity::Album'"
+        );
+        let result = scoped.is_not(Property::UInt, 7u64);
+        assert!(
+            matches!(result, Err(Error::PropertyNotInEntity("Property::UInt", _))),
+            "{:?}",
+            result
+        );
+
+        // nothing is pushed for rejected conditions
+        assert_eq!(scoped.expression().len(), 1);
+    }
+}
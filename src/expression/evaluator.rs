@@ -0,0 +1,154 @@
+use super::{Context, Evaluated, Expression};
+use crate::domain::Property;
+use crate::error::Result;
+use crate::value::Value;
+
+/// Evaluates the same expression against many contexts, remembering the result of every
+/// condition along with the value it was evaluated for: conditions whose property still has
+/// that value aren't evaluated again. Results are the same as with `Expression::eval`.
+#[derive(Debug)]
+pub struct Evaluator<'e, Pid: Property> {
+    expr: &'e Expression<Pid>,
+    // indexed by operation, only conditions ever have results
+    cache: Vec<Option<(Value, bool)>>,
+    // conditions evaluated so far, cached results excluded
+    evals: usize,
+}
+
+impl<'e, Pid: Property> Evaluator<'e, Pid> {
+    pub fn new(expr: &'e Expression<Pid>) -> Self {
+        Evaluator {
+            expr,
+            cache: vec![None; expr.len()],
+            evals: 0,
+        }
+    }
+
+    /// Same as `Expression::eval`, reusing results of conditions on unchanged values.
+    pub fn eval(&mut self, context: &Context<Pid>) -> Result<Evaluated<Pid>> {
+        let expr = self.expr;
+        let root = expr.connected()?;
+
+        let cache = &mut self.cache;
+        let evals = &mut self.evals;
+        expr.eval_by(root, &mut |idx, variable| {
+            let actual = match context.value(variable) {
+                Some(actual) => actual,
+                None => return Ok(None),
+            };
+            if let Some((value, result)) = &cache[idx] {
+                if value == actual {
+                    return Ok(Some(*result));
+                }
+            }
+
+            *evals += 1;
+            let result = expr.test(idx, actual)?;
+            cache[idx] = Some((actual.clone(), result));
+            Ok(Some(result))
+        })
+    }
+
+    /// Forgets results of conditions on `property`, for example to drop large values.
+    pub fn invalidate(&mut self, property: Pid) {
+        for (cached, (op, _)) in self.cache.iter_mut().zip(self.expr.ops.iter()) {
+            if op.variable() == Some(property) {
+                *cached = None;
+            }
+        }
+    }
+
+    /// Forgets all remembered results.
+    pub fn invalidate_all(&mut self) {
+        for cached in self.cache.iter_mut() {
+            *cached = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::testproperty::Property;
+
+    fn sample() -> Expression<Property> {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Bool, true).unwrap();
+        let b = expr.is_in(Property::Int, vec![1, 2, 3]).unwrap();
+        let c = expr.contains(Property::Str, "rock").unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let _ = expr.or(a_and_b, c).unwrap();
+        expr
+    }
+
+    fn context(flag: bool, number: i64, text: &str) -> Context<Property> {
+        Context::from_values(vec![
+            (Property::Bool, Value::Bool(flag)),
+            (Property::Int, Value::Int(number)),
+            (Property::Str, Value::Str(text.to_owned())),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn evaluator_same_results() {
+        let expr = sample();
+        let mut evaluator = Evaluator::new(&expr);
+
+        let mut partial = Context::request(vec![Property::Int]);
+        partial.provide(Property::Int, Value::Int(2)).unwrap();
+        let contexts = [
+            context(true, 2, "punk"),
+            context(true, 5, "rock'n'roll"),
+            context(false, 2, "punk"),
+            context(true, 2, "punk"),
+            partial,
+        ];
+
+        for context in contexts.iter().chain(contexts.iter()) {
+            let expected = expr.eval(context).unwrap();
+            let actual = evaluator.eval(context).unwrap();
+            assert_eq!(format!("{:?}", actual), format!("{:?}", expected));
+        }
+    }
+
+    #[test]
+    fn evaluator_unchanged_context() {
+        let expr = sample();
+        let mut evaluator = Evaluator::new(&expr);
+        let context = context(true, 5, "punk");
+
+        let first = evaluator.eval(&context).unwrap();
+        assert_eq!(evaluator.evals, 3);
+
+        let second = evaluator.eval(&context).unwrap();
+        assert_eq!(evaluator.evals, 3);
+        assert_eq!(format!("{:?}", first), format!("{:?}", second));
+
+        // only the condition on the changed property is evaluated again
+        let changed = self::context(true, 5, "rock");
+        assert_eq!(evaluator.eval(&changed).unwrap().as_bool(), Some(true));
+        assert_eq!(evaluator.evals, 4);
+    }
+
+    #[test]
+    fn evaluator_invalidate() {
+        let expr = sample();
+        let mut evaluator = Evaluator::new(&expr);
+        let context = context(true, 5, "punk");
+        evaluator.eval(&context).unwrap();
+
+        evaluator.invalidate(Property::Int);
+        evaluator.eval(&context).unwrap();
+        assert_eq!(evaluator.evals, 4);
+
+        evaluator.invalidate(Property::UInt);
+        evaluator.eval(&context).unwrap();
+        assert_eq!(evaluator.evals, 4);
+
+        evaluator.invalidate_all();
+        assert_eq!(evaluator.eval(&context).unwrap().as_bool(), Some(false));
+        assert_eq!(evaluator.evals, 7);
+    }
+}
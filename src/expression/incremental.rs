@@ -0,0 +1,308 @@
+use std::collections::{BTreeSet, HashMap};
+
+use super::{Expression, OpRef, Operation};
+use crate::domain::Property;
+use crate::error::Result;
+use crate::value::Value;
+
+/// Result of a logical operation given the results of its operands, `None` while undecided.
+/// Conditions are only decided by values of their properties.
+pub(super) fn settle<Pid: Property>(op: &Operation<Pid>, results: &[Option<bool>]) -> Option<bool> {
+    let operand = |opref: OpRef| results.get(opref).copied().flatten();
+    match *op {
+        Operation::Const(val) => Some(val),
+        Operation::In(ref cond) if cond.expected().is_empty() => Some(false),
+        Operation::Is(_)
+        | Operation::IsNot(_)
+        | Operation::ApproxIs(_)
+        | Operation::In(_)
+        | Operation::Contains(_)
+        | Operation::Quantified(_) => None,
+        Operation::Not(opref) => operand(opref).map(|val| !val),
+        Operation::Or(lhs, rhs) => match (operand(lhs), operand(rhs)) {
+            (Some(true), _) | (_, Some(true)) => Some(true),
+            (Some(false), Some(false)) => Some(false),
+            _ => None,
+        },
+        Operation::And(lhs, rhs) => match (operand(lhs), operand(rhs)) {
+            (Some(false), _) | (_, Some(false)) => Some(false),
+            (Some(true), Some(true)) => Some(true),
+            _ => None,
+        },
+        Operation::Nand(lhs, rhs) => match (operand(lhs), operand(rhs)) {
+            (Some(false), _) | (_, Some(false)) => Some(true),
+            (Some(true), Some(true)) => Some(false),
+            _ => None,
+        },
+        Operation::Nor(lhs, rhs) => match (operand(lhs), operand(rhs)) {
+            (Some(true), _) | (_, Some(true)) => Some(false),
+            (Some(false), Some(false)) => Some(true),
+            _ => None,
+        },
+        Operation::Implies(premise, conclusion) => match (operand(premise), operand(conclusion)) {
+            (Some(false), _) | (_, Some(true)) => Some(true),
+            (Some(true), Some(false)) => Some(false),
+            _ => None,
+        },
+        Operation::IfElse(cond, then, otherwise) => match operand(cond) {
+            Some(true) => operand(then),
+            Some(false) => operand(otherwise),
+            None => match (operand(then), operand(otherwise)) {
+                (Some(lval), Some(rval)) if lval == rval => Some(lval),
+                _ => None,
+            },
+        },
+    }
+}
+
+/// Evaluates an expression while values are provided one at a time, for example as a user
+/// fills in a form. Each `provide` only settles conditions on the property and the operations
+/// depending on them, rather than the whole expression.
+///
+/// Results are the same as `Expression::eval` would give with all values provided so far.
+#[derive(Debug)]
+pub struct IncrementalEval<'e, Pid: Property> {
+    expr: &'e Expression<Pid>,
+    root: OpRef,
+    // conditions on every property
+    conditions: HashMap<Pid, Vec<OpRef>>,
+    // operations using every operation as an operand
+    parents: Vec<Vec<OpRef>>,
+    // result of every operation given the values provided so far
+    results: Vec<Option<bool>>,
+    // operations settled by the last `provide`
+    updates: usize,
+}
+
+impl<'e, Pid: Property> IncrementalEval<'e, Pid> {
+    /// Fails the same way `Expression::eval` does if the expression can't be evaluated.
+    pub fn new(expr: &'e Expression<Pid>) -> Result<Self> {
+        let root = expr.connected()?;
+        expr.checked(&expr.reachable(root))?;
+
+        let mut conditions: HashMap<Pid, Vec<OpRef>> = HashMap::new();
+        let mut parents = vec![Vec::new(); root + 1];
+        let mut results = Vec::with_capacity(root + 1);
+        for (idx, (op, _)) in expr.ops.iter().take(root + 1).enumerate() {
+            if let Some(variable) = op.variable() {
+                conditions.entry(variable).or_default().push(idx);
+            }
+            for opref in op.operands() {
+                parents[opref].push(idx);
+            }
+            results.push(settle(op, &results));
+        }
+
+        Ok(IncrementalEval {
+            expr,
+            root,
+            conditions,
+            parents,
+            results,
+            updates: 0,
+        })
+    }
+
+    /// Result of the expression given the values provided so far, `None` while undecided.
+    pub fn result(&self) -> Option<bool> {
+        self.results[self.root]
+    }
+
+    /// Provides a value, replacing the previous one if any, and returns the updated result.
+    /// The value is checked against the property datatype, same as with `Context::provide`.
+    pub fn provide(&mut self, property: Pid, value: Value) -> Result<Option<bool>> {
+        let value = property.coerce(value)?;
+
+        // conditions are evaluated first, so that a failing one leaves the state as is
+        let mut settled = Vec::new();
+        for &idx in self.conditions.get(&property).into_iter().flatten() {
+            settled.push((idx, Some(self.expr.test(idx, &value)?)));
+        }
+
+        // operands come before operations using them, so everything an operation
+        // depends on is settled by the time it's popped
+        self.updates = 0;
+        let mut pending = BTreeSet::new();
+        for (idx, result) in settled {
+            self.update(idx, result, &mut pending);
+        }
+        while let Some(idx) = pending.pop_first() {
+            let result = settle(&self.expr.ops[idx].0, &self.results);
+            self.update(idx, result, &mut pending);
+        }
+
+        Ok(self.result())
+    }
+
+    fn update(&mut self, idx: OpRef, result: Option<bool>, pending: &mut BTreeSet<OpRef>) {
+        self.updates += 1;
+        if self.results[idx] != result {
+            self.results[idx] = result;
+            pending.extend(self.parents[idx].iter().copied());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::error::Error;
+    use crate::expression::Context;
+    use crate::testproperty::Property;
+
+    fn sample() -> Expression<Property> {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Bool, true).unwrap();
+        let b = expr.is_in(Property::Int, vec![1, 2]).unwrap();
+        let c = expr.contains(Property::Str, "rock").unwrap();
+        let d = expr.is(Property::UInt, 7u64).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let not_d = expr.not(d).unwrap();
+        let c_implies_not_d = expr.implies(c, not_d).unwrap();
+        let either = expr.or(a_and_b, c_implies_not_d).unwrap();
+        let _ = expr.if_else(b, either, a).unwrap();
+        expr
+    }
+
+    fn permutations<T: Clone>(items: &[T]) -> Vec<Vec<T>> {
+        if items.is_empty() {
+            return vec![Vec::new()];
+        }
+        let mut all = Vec::new();
+        for idx in 0..items.len() {
+            let mut rest = items.to_vec();
+            let first = rest.remove(idx);
+            for mut tail in permutations(&rest) {
+                tail.insert(0, first.clone());
+                all.push(tail);
+            }
+        }
+        all
+    }
+
+    #[test]
+    fn incremental_matches_eval() {
+        let expr = sample();
+        let samples = [
+            [
+                Value::Bool(true),
+                Value::Int(1),
+                Value::Str("rock".to_owned()),
+                Value::UInt(7),
+            ],
+            [
+                Value::Bool(false),
+                Value::Int(2),
+                Value::Str("punk".to_owned()),
+                Value::UInt(7),
+            ],
+            [
+                Value::Bool(false),
+                Value::Int(5),
+                Value::Str("rock".to_owned()),
+                Value::UInt(1),
+            ],
+        ];
+        let props = [Property::Bool, Property::Int, Property::Str, Property::UInt];
+
+        for values in samples.iter() {
+            let provided = props
+                .iter()
+                .copied()
+                .zip(values.iter().cloned())
+                .collect::<Vec<_>>();
+            for order in permutations(&provided) {
+                let mut incremental = IncrementalEval::new(&expr).unwrap();
+                let mut context = Context::request(props.iter().copied());
+                assert_eq!(incremental.result(), expr.eval(&context).unwrap().as_bool());
+
+                for (prop, value) in order.iter().cloned() {
+                    context.provide(prop, value.clone()).unwrap();
+                    let expected = expr.eval(&context).unwrap().as_bool();
+                    assert_eq!(
+                        incremental.provide(prop, value).unwrap(),
+                        expected,
+                        "{:?}",
+                        order
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn incremental_replaced_value() {
+        let expr = sample();
+        let mut incremental = IncrementalEval::new(&expr).unwrap();
+        assert_eq!(
+            incremental.provide(Property::Int, Value::Int(5)).unwrap(),
+            None
+        );
+        assert_eq!(
+            incremental
+                .provide(Property::Bool, Value::Bool(true))
+                .unwrap(),
+            Some(true)
+        );
+        assert_eq!(
+            incremental.provide(Property::Int, Value::Int(1)).unwrap(),
+            Some(true)
+        );
+        assert_eq!(
+            incremental
+                .provide(Property::Bool, Value::Bool(false))
+                .unwrap(),
+            None
+        );
+
+        // the condition and its dependents are settled, nothing else
+        assert_eq!(
+            incremental.provide(Property::UInt, Value::UInt(7)).unwrap(),
+            None
+        );
+        assert_eq!(incremental.updates, 3);
+        assert_eq!(
+            incremental.provide(Property::UInt, Value::UInt(7)).unwrap(),
+            None
+        );
+        assert_eq!(incremental.updates, 1);
+        assert_eq!(
+            incremental
+                .provide(Property::Str, Value::from("punk"))
+                .unwrap(),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn incremental_errors() {
+        let expr = sample();
+        let mut incremental = IncrementalEval::new(&expr).unwrap();
+        incremental
+            .provide(Property::Bool, Value::Bool(true))
+            .unwrap();
+        let result = incremental.provide(Property::Int, Value::from("one"));
+        assert!(
+            matches!(result, Err(Error::TypeMismatch(_, _, _))),
+            "{:?}",
+            result
+        );
+        assert_eq!(incremental.result(), None);
+
+        let empty = Expression::<Property>::new();
+        let result = IncrementalEval::new(&empty);
+        assert!(matches!(result, Err(Error::ExpressionNoop)), "{:?}", result);
+
+        let mut template = Expression::<Property>::new();
+        template
+            .is(Property::Int, Value::Param("limit".to_owned()))
+            .unwrap();
+        let result = IncrementalEval::new(&template);
+        assert!(
+            matches!(result, Err(Error::UnboundParameter(ref name)) if name == "limit"),
+            "{:?}",
+            result
+        );
+    }
+}
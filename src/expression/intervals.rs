@@ -0,0 +1,339 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use super::{Expression, OpRef, Operation};
+use crate::domain::Property;
+use crate::value::{Datatype, Value};
+
+/// Integer property that can't have any value satisfying conditions that all have to hold,
+/// see `Expression::check_intervals`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Infeasibility<Pid: Property> {
+    pub property: Pid,
+    /// Conditions on the property that contradict each other, in expression order.
+    pub ops: Vec<OpRef>,
+}
+
+/// Values a property may still have: within `allowed` if it's bounded, none of `excluded`.
+#[derive(Debug, Clone, Default)]
+struct Feasible {
+    allowed: Option<BTreeSet<Value>>,
+    excluded: BTreeSet<Value>,
+    ops: Vec<OpRef>,
+}
+
+impl Feasible {
+    fn is_empty(&self) -> bool {
+        match &self.allowed {
+            Some(allowed) => allowed.iter().all(|value| self.excluded.contains(value)),
+            None => false,
+        }
+    }
+}
+
+/// Operation along with whether it's negated.
+type Literal = (OpRef, bool);
+
+impl<Pid: Property> Expression<Pid> {
+    /// Splits the operation into parts that all have to hold: conditions on integer properties,
+    /// and disjunctions where only one of the branches has to. Anything else is skipped.
+    /// Operations shared by several parents are only split once, tracked in `seen`.
+    fn requirements(
+        &self,
+        (idx, negated): Literal,
+        seen: &mut HashSet<Literal>,
+        literals: &mut Vec<Literal>,
+        disjunctions: &mut Vec<[Literal; 2]>,
+    ) {
+        let op = &self.ops[idx].0;
+        if op.operands().into_iter().any(|opref| opref >= idx) || !seen.insert((idx, negated)) {
+            return;
+        }
+
+        let mut all = |lhs: Literal, rhs: Literal| {
+            self.requirements(lhs, seen, literals, disjunctions);
+            self.requirements(rhs, seen, literals, disjunctions);
+        };
+        match (op, negated) {
+            (&Operation::Not(opref), _) => {
+                self.requirements((opref, !negated), seen, literals, disjunctions)
+            }
+            (&Operation::And(lhs, rhs), false) | (&Operation::Nand(lhs, rhs), true) => {
+                all((lhs, false), (rhs, false))
+            }
+            (&Operation::Or(lhs, rhs), true) | (&Operation::Nor(lhs, rhs), false) => {
+                all((lhs, true), (rhs, true))
+            }
+            (&Operation::Implies(premise, conclusion), true) => {
+                all((premise, false), (conclusion, true))
+            }
+            (&Operation::Or(lhs, rhs), false) | (&Operation::Nor(lhs, rhs), true) => {
+                disjunctions.push([(lhs, false), (rhs, false)])
+            }
+            (&Operation::And(lhs, rhs), true) | (&Operation::Nand(lhs, rhs), false) => {
+                disjunctions.push([(lhs, true), (rhs, true)])
+            }
+            (&Operation::Implies(premise, conclusion), false) => {
+                disjunctions.push([(premise, true), (conclusion, false)])
+            }
+            (Operation::Is(_), _) | (Operation::IsNot(_), _) | (Operation::In(_), _) => {
+                let integer = op.variable().map(|prop| prop.datatype());
+                if matches!(integer, Some(Datatype::Int) | Some(Datatype::UInt)) {
+                    literals.push((idx, negated));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Narrows down values of the property of a condition that has to hold.
+    fn constrain(&self, feasible: &mut HashMap<Pid, Feasible>, (idx, negated): Literal) {
+        let (variable, values, excluding) = match &self.ops[idx].0 {
+            Operation::Is(cond) => (cond.variable(), vec![cond.expected()], negated),
+            Operation::IsNot(cond) => (cond.variable(), vec![cond.expected()], !negated),
+            Operation::In(cond) => (cond.variable(), cond.expected().iter().collect(), negated),
+            _ => return,
+        };
+        // unbound placeholders could be anything
+        if values.iter().any(|value| matches!(value, Value::Param(_))) {
+            return;
+        }
+
+        let entry = feasible.entry(variable).or_default();
+        entry.ops.push(idx);
+        if excluding {
+            entry.excluded.extend(values.into_iter().cloned());
+        } else {
+            let allowed = match entry.allowed.take() {
+                Some(allowed) => allowed
+                    .into_iter()
+                    .filter(|value| values.contains(&value))
+                    .collect(),
+                None => values.into_iter().cloned().collect(),
+            };
+            entry.allowed = Some(allowed);
+        }
+    }
+
+    fn infeasible(
+        &self,
+        literal: Literal,
+        inherited: &HashMap<Pid, Feasible>,
+    ) -> Vec<Infeasibility<Pid>> {
+        let (mut literals, mut disjunctions) = (Vec::new(), Vec::new());
+        let mut seen = HashSet::new();
+        self.requirements(literal, &mut seen, &mut literals, &mut disjunctions);
+
+        let mut feasible = inherited.clone();
+        for literal in literals {
+            self.constrain(&mut feasible, literal);
+        }
+
+        let mut empty = feasible
+            .iter()
+            .filter(|(_, feasible)| feasible.is_empty())
+            .map(|(&property, feasible)| {
+                let mut ops = feasible.ops.clone();
+                ops.sort_unstable();
+                ops.dedup();
+                Infeasibility { property, ops }
+            })
+            .collect::<Vec<_>>();
+        if !empty.is_empty() {
+            // hash maps don't keep any particular order, sort by name to make the output stable
+            empty.sort_by_key(|infeasible| infeasible.property.name());
+            return empty;
+        }
+
+        // a disjunction is only as infeasible as the last of its branches, branches shared
+        // by several disjunctions are checked once as they all inherit the same constraints
+        let mut checked = HashMap::new();
+        let mut check = |branch: Literal| {
+            let result = checked.entry(branch);
+            result
+                .or_insert_with(|| self.infeasible(branch, &feasible))
+                .clone()
+        };
+        for [lhs, rhs] in disjunctions {
+            let mut lhs = check(lhs);
+            if lhs.is_empty() {
+                continue;
+            }
+            let rhs = check(rhs);
+            if !rhs.is_empty() {
+                for infeasible in rhs {
+                    if !lhs.contains(&infeasible) {
+                        lhs.push(infeasible);
+                    }
+                }
+                return lhs;
+            }
+        }
+        Vec::new()
+    }
+
+    /// Finds integer properties that no value can satisfy, without evaluating the expression:
+    /// conditions joined by `And` (or by `Or` under negation) have to hold at once, so sets of
+    /// values they allow are intersected. An `Or` branch inherits the conditions around it
+    /// and is checked on its own, the `Or` is only reported if none of its branches are feasible.
+    ///
+    /// `Is`, `IsNot` and `In` conditions on `Int` and `UInt` properties are taken into account,
+    /// anything else is assumed to be satisfiable, so a reported property is always infeasible,
+    /// but not every infeasible expression is reported. Empty for empty or invalid expressions.
+    pub fn check_intervals(&self) -> Vec<Infeasibility<Pid>> {
+        match self.root() {
+            Some(root) if root < self.ops.len() => self.infeasible((root, false), &HashMap::new()),
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::testproperty::Property;
+
+    fn infeasible(property: Property, ops: Vec<OpRef>) -> Infeasibility<Property> {
+        Infeasibility { property, ops }
+    }
+
+    #[test]
+    fn check_intervals_conjunction() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 1).unwrap();
+        let b = expr.is_in(Property::Int, vec![2, 3]).unwrap();
+        let c = expr.is(Property::Bool, true).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let _ = expr.and(c, a_and_b).unwrap();
+        assert_eq!(
+            expr.check_intervals(),
+            vec![infeasible(Property::Int, vec![a, b])]
+        );
+
+        // every allowed value is excluded one way or another
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is_in(Property::UInt, vec![1u64, 2]).unwrap();
+        let b = expr.is_not(Property::UInt, 1u64).unwrap();
+        let c = expr.is(Property::UInt, 2u64).unwrap();
+        let not_c = expr.not(c).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let _ = expr.and(a_and_b, not_c).unwrap();
+        assert_eq!(
+            expr.check_intervals(),
+            vec![infeasible(Property::UInt, vec![a, b, c])]
+        );
+
+        // `nor` of inequalities requires both values at once
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is_not(Property::Int, 1).unwrap();
+        let b = expr.is_not(Property::Int, 2).unwrap();
+        let _ = expr.nor(a, b).unwrap();
+        assert_eq!(
+            expr.check_intervals(),
+            vec![infeasible(Property::Int, vec![a, b])]
+        );
+    }
+
+    #[test]
+    fn check_intervals_feasible() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is_in(Property::Int, vec![1, 2]).unwrap();
+        let b = expr.is_not(Property::Int, 1).unwrap();
+        let c = expr.is(Property::UInt, 1u64).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let _ = expr.and(a_and_b, c).unwrap();
+        assert!(expr.check_intervals().is_empty());
+
+        // negated conjunctions, unbound placeholders and other datatypes don't constrain values
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 1).unwrap();
+        let b = expr.is(Property::Int, 2).unwrap();
+        let c = expr
+            .is(Property::Int, Value::Param("int".to_owned()))
+            .unwrap();
+        let d = expr.is(Property::Str, "a").unwrap();
+        let e = expr.is(Property::Str, "b").unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let not_a_and_b = expr.not(a_and_b).unwrap();
+        let a_and_c = expr.and(a, c).unwrap();
+        let d_and_e = expr.and(d, e).unwrap();
+        let lhs = expr.and(not_a_and_b, a_and_c).unwrap();
+        let _ = expr.and(lhs, d_and_e).unwrap();
+        assert!(expr.check_intervals().is_empty());
+
+        assert!(Expression::<Property>::new().check_intervals().is_empty());
+    }
+
+    #[test]
+    fn check_intervals_or_branches() {
+        // one of the branches is feasible along with the condition around the `Or`
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 1).unwrap();
+        let b = expr.is(Property::Int, 2).unwrap();
+        let c = expr.is_in(Property::Int, vec![1, 5]).unwrap();
+        let b_or_c = expr.or(b, c).unwrap();
+        let _ = expr.and(a, b_or_c).unwrap();
+        assert!(expr.check_intervals().is_empty());
+
+        // none of them is
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 1).unwrap();
+        let b = expr.is(Property::Int, 2).unwrap();
+        let c = expr.is(Property::Int, 3).unwrap();
+        let b_or_c = expr.or(b, c).unwrap();
+        let _ = expr.and(a, b_or_c).unwrap();
+        assert_eq!(
+            expr.check_intervals(),
+            vec![
+                infeasible(Property::Int, vec![a, b]),
+                infeasible(Property::Int, vec![a, c]),
+            ]
+        );
+
+        // an empty branch is fine as long as the other one isn't
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 1).unwrap();
+        let b = expr.is(Property::Int, 2).unwrap();
+        let c = expr.is(Property::Bool, true).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let _ = expr.or(a_and_b, c).unwrap();
+        assert!(expr.check_intervals().is_empty());
+        let _ = expr.and_with(b).unwrap();
+        assert!(expr.check_intervals().is_empty());
+        let _ = expr.and_with(a).unwrap();
+        assert_eq!(
+            expr.check_intervals(),
+            vec![infeasible(Property::Int, vec![a, b])]
+        );
+    }
+
+    #[test]
+    fn check_intervals_shared() {
+        // every operation is referenced twice, paths through them aren't walked one by one
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 1).unwrap();
+        let b = expr.is(Property::Int, 2).unwrap();
+        let mut and_chain = expr.and(a, b).unwrap();
+        for _ in 0..64 {
+            and_chain = expr.and(and_chain, and_chain).unwrap();
+        }
+        assert_eq!(
+            expr.check_intervals(),
+            vec![infeasible(Property::Int, vec![a, b])]
+        );
+
+        let mut or_chain = and_chain;
+        for _ in 0..64 {
+            let nor = expr.nor(or_chain, or_chain).unwrap();
+            or_chain = expr.not(nor).unwrap();
+        }
+        assert_eq!(
+            expr.check_intervals(),
+            vec![infeasible(Property::Int, vec![a, b])]
+        );
+
+        let c = expr.is(Property::Bool, true).unwrap();
+        let _ = expr.or(or_chain, c).unwrap();
+        assert!(expr.check_intervals().is_empty());
+    }
+}
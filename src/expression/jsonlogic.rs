@@ -0,0 +1,516 @@
+use serde_json::{json, Value as Json};
+use strum::ParseError;
+
+use super::{Expression, OpRef, Operation};
+use crate::condition::{Predicate, Quantifier};
+use crate::domain::Property;
+use crate::error::{Error, Result};
+use crate::value::{Datatype, Value};
+
+fn unsupported(json: &Json) -> Error {
+    Error::UnsupportedJsonLogic(json.to_string())
+}
+
+fn property<Pid: Property>(json: &Json) -> Result<Pid> {
+    match json {
+        Json::Object(var) if var.len() == 1 => match var.get("var") {
+            Some(Json::String(name)) => Pid::from_str(name)
+                .ok()
+                .or_else(|| Pid::iter().find(|prop| prop.name() == name))
+                .ok_or(Error::IdentifierNotFound(ParseError::VariantNotFound)),
+            _ => Err(unsupported(json)),
+        },
+        _ => Err(unsupported(json)),
+    }
+}
+
+fn literal<Pid: Property>(variable: Pid, json: &Json) -> Result<Value> {
+    typed_literal(variable.name(), variable.datatype(), json)
+}
+
+fn typed_literal(name: &'static str, datatype: Datatype, json: &Json) -> Result<Value> {
+    let provided = match json {
+        Json::Bool(_) => Datatype::Bool,
+        Json::Number(val) if val.is_i64() => Datatype::Int,
+        Json::Number(val) if val.is_u64() => Datatype::UInt,
+        Json::Number(_) => Datatype::Float,
+        Json::String(_) => Datatype::Str,
+        _ => return Err(unsupported(json)),
+    };
+
+    let coerced = match (datatype, json) {
+        (Datatype::Bool, Json::Bool(val)) => Some(Value::Bool(*val)),
+        (Datatype::Bool, Json::String(val)) => val.parse().ok().map(Value::Bool),
+        (Datatype::Int, Json::Number(val)) => val.as_i64().map(Value::Int),
+        (Datatype::Int, Json::String(val)) => val.parse().ok().map(Value::Int),
+        (Datatype::UInt, Json::Number(val)) => val.as_u64().map(Value::UInt),
+        (Datatype::UInt, Json::String(val)) => val.parse().ok().map(Value::UInt),
+        (Datatype::Float, Json::Number(val)) => val.as_f64().map(Value::Float),
+        (Datatype::Float, Json::String(val)) => val.parse().ok().map(Value::Float),
+        (Datatype::Str, Json::String(val)) => Some(Value::Str(val.clone())),
+        (Datatype::Str, Json::Number(val)) => Some(Value::Str(val.to_string())),
+        (Datatype::Str, Json::Bool(val)) => Some(Value::Str(val.to_string())),
+        _ => None,
+    };
+
+    coerced.ok_or(Error::TypeMismatch(name, datatype, provided))
+}
+
+fn args(json: &Json) -> &[Json] {
+    match json {
+        Json::Array(args) => args,
+        single => std::slice::from_ref(single),
+    }
+}
+
+fn is_element(json: &Json) -> bool {
+    matches!(json, Json::Object(var) if var.len() == 1 && var.get("var") == Some(&json!("")))
+}
+
+/// Parses a `some` / `none` predicate, where the current element is referred to as `{"var": ""}`.
+fn predicate<Pid: Property>(variable: Pid, json: &Json) -> Result<Predicate> {
+    let (op, operands) = match json {
+        Json::Object(obj) if obj.len() == 1 => obj.iter().next().unwrap(),
+        _ => return Err(unsupported(json)),
+    };
+
+    // predicate literals are coerced to the element type, non-lists are rejected later
+    let datatype = match variable.datatype() {
+        Datatype::List(element) => *element,
+        datatype => datatype,
+    };
+    let literal = |value| typed_literal(variable.name(), datatype, value);
+
+    match (op.as_str(), args(operands)) {
+        ("==", [var, value]) if is_element(var) => Ok(Predicate::Is(literal(value)?)),
+        ("in", [var, Json::Array(values)]) if is_element(var) => {
+            let values = values.iter().map(literal).collect::<Result<_>>()?;
+            Ok(Predicate::In(values))
+        }
+        ("in", [Json::String(needle), var]) if is_element(var) => {
+            Ok(Predicate::Contains(needle.clone(), false))
+        }
+        ("contains_ci", [var, Json::String(needle)]) if is_element(var) => {
+            Ok(Predicate::Contains(needle.clone(), true))
+        }
+        _ => Err(unsupported(json)),
+    }
+}
+
+/// Unwraps `{"!": operand}`.
+fn negation(json: &Json) -> Option<&Json> {
+    match json {
+        Json::Object(obj) if obj.len() == 1 => match obj.get("!").map(args) {
+            Some([operand]) => Some(operand),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn build<Pid: Property>(expr: &mut Expression<Pid>, json: &Json) -> Result<OpRef> {
+    let (op, operands) = match json {
+        Json::Bool(val) => return expr.constant(*val),
+        Json::Object(obj) if obj.len() == 1 => obj.iter().next().unwrap(),
+        _ => return Err(unsupported(json)),
+    };
+
+    match (op.as_str(), args(operands)) {
+        ("==", [var, value]) => {
+            let variable = property(var)?;
+            expr.is(variable, literal(variable, value)?)
+        }
+        ("!=", [var, value]) => {
+            let variable = property(var)?;
+            expr.is_not(variable, literal(variable, value)?)
+        }
+        ("in", [var, Json::Array(values)]) => {
+            let variable = property(var)?;
+            let values = values
+                .iter()
+                .map(|value| literal(variable, value))
+                .collect::<Result<Vec<_>>>()?;
+            expr.is_in(variable, values)
+        }
+        ("in", [Json::String(needle), var]) => expr.contains(property(var)?, needle),
+        ("contains_ci", [var, Json::String(needle)]) => expr.contains_ci(property(var)?, needle),
+        ("some", [var, pred]) => {
+            let variable = property(var)?;
+            expr.any_element(variable, predicate(variable, pred)?)
+        }
+        // `none` of the negated predicate is how `all` is rendered, see `to_json_logic`
+        ("none", [var, pred]) => {
+            let variable = property(var)?;
+            match negation(pred) {
+                Some(pred) => expr.all_elements(variable, predicate(variable, pred)?),
+                None => {
+                    let opref = expr.any_element(variable, predicate(variable, pred)?)?;
+                    expr.not(opref)
+                }
+            }
+        }
+        ("if", [cond, then, otherwise]) => {
+            let cond = build(expr, cond)?;
+            let then = build(expr, then)?;
+            let otherwise = build(expr, otherwise)?;
+            expr.if_else(cond, then, otherwise)
+        }
+        ("!", [operand]) => {
+            let opref = build(expr, operand)?;
+            expr.not(opref)
+        }
+        ("and", []) => expr.constant(true),
+        ("or", []) => expr.constant(false),
+        ("and", [first, rest @ ..]) | ("or", [first, rest @ ..]) => {
+            let mut acc = build(expr, first)?;
+            for operand in rest {
+                let rhs = build(expr, operand)?;
+                acc = if op == "and" {
+                    expr.and(acc, rhs)?
+                } else {
+                    expr.or(acc, rhs)?
+                };
+            }
+            Ok(acc)
+        }
+        _ => Err(unsupported(json)),
+    }
+}
+
+/// Translates a JsonLogic document into an expression.
+///
+/// Supports `==`, `!=`, `in` (both membership and substring), `if`, `!`, `and`, `or`, `var`,
+/// boolean literals and the custom `contains_ci` operation (see `to_json_logic`),
+/// `some` and `none` over list properties with one of those conditions on `{"var": ""}`,
+/// condition literals are coerced to the datatype of the property they're compared with.
+/// `var` names are resolved with `FromStr` first, then matched against `Property::name()`.
+pub fn from_json_logic<Pid: Property>(value: &Json) -> Result<Expression<Pid>> {
+    let mut expr = Expression::new();
+    build(&mut expr, value)?;
+    Ok(expr)
+}
+
+fn render_predicate(predicate: &Predicate) -> Json {
+    let element = json!({"var": ""});
+    match predicate {
+        Predicate::Is(expected) => json!({"==": [element, Json::from(expected)]}),
+        Predicate::In(expected) => json!({
+            "in": [element, expected.iter().map(Json::from).collect::<Vec<_>>()]
+        }),
+        Predicate::Contains(needle, true) => json!({"contains_ci": [element, needle]}),
+        Predicate::Contains(needle, false) => json!({"in": [needle, element]}),
+    }
+}
+
+fn render<Pid: Property>(expr: &Expression<Pid>, opref: OpRef) -> Json {
+    match &expr.ops[opref].0 {
+        Operation::Const(val) => Json::Bool(*val),
+        Operation::Is(cond) => json!({
+            "==": [{"var": cond.variable().name()}, Json::from(cond.expected())]
+        }),
+        Operation::IsNot(cond) => json!({
+            "!=": [{"var": cond.variable().name()}, Json::from(cond.expected())]
+        }),
+        // JsonLogic has no tolerance, the band becomes a between check
+        Operation::ApproxIs(cond) => match cond.bounds() {
+            Some((low, high)) => json!({
+                "<=": [low, {"var": cond.variable().name()}, high]
+            }),
+            None => Json::Bool(false),
+        },
+        Operation::In(cond) => {
+            // sets don't keep any particular order, sort values to make the output stable
+            let mut values = cond.expected().iter().collect::<Vec<_>>();
+            values.sort();
+            json!({
+                "in": [
+                    {"var": cond.variable().name()},
+                    values.into_iter().map(Json::from).collect::<Vec<_>>()
+                ]
+            })
+        }
+        Operation::Contains(cond) if cond.case_insensitive() => json!({
+            "contains_ci": [{"var": cond.variable().name()}, cond.needle()]
+        }),
+        Operation::Contains(cond) => json!({
+            "in": [cond.needle(), {"var": cond.variable().name()}]
+        }),
+        Operation::Quantified(cond) => {
+            let var = json!({"var": cond.variable().name()});
+            let predicate = render_predicate(cond.predicate());
+            match cond.quantifier() {
+                Quantifier::Any => json!({"some": [var, predicate]}),
+                Quantifier::All => json!({"none": [var, {"!": [predicate]}]}),
+            }
+        }
+        &Operation::Not(opref) => json!({"!": [render(expr, opref)]}),
+        &Operation::Or(lhs, rhs) => json!({"or": [render(expr, lhs), render(expr, rhs)]}),
+        &Operation::And(lhs, rhs) => json!({"and": [render(expr, lhs), render(expr, rhs)]}),
+        &Operation::Nand(lhs, rhs) => json!({
+            "!": [{"and": [render(expr, lhs), render(expr, rhs)]}]
+        }),
+        &Operation::Nor(lhs, rhs) => json!({
+            "!": [{"or": [render(expr, lhs), render(expr, rhs)]}]
+        }),
+        &Operation::Implies(premise, conclusion) => json!({
+            "or": [{"!": [render(expr, premise)]}, render(expr, conclusion)]
+        }),
+        &Operation::IfElse(cond, then, otherwise) => json!({
+            "if": [render(expr, cond), render(expr, then), render(expr, otherwise)]
+        }),
+    }
+}
+
+/// Translates an expression into a JsonLogic document, `var` names come from `Property::name()`.
+///
+/// An empty expression is rendered as `null`. JsonLogic has no case-insensitive substring test,
+/// so `contains_ci` conditions are rendered as a custom `contains_ci` operation
+/// that has to be registered on the consumer side. Conditions on list elements are rendered
+/// as `some`, and `all` as `none` of the negated predicate: JsonLogic `all` is false
+/// for empty lists.
+pub fn to_json_logic<Pid: Property>(expr: &Expression<Pid>) -> Json {
+    match expr.root() {
+        Some(root) => render(expr, root),
+        None => Json::Null,
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::condition::Predicate;
+    use crate::expression::{Context, Evaluated};
+    use crate::testproperty::{Listed, Property};
+
+    #[test]
+    fn from_json_logic_nested() {
+        let doc = json!({
+            "and": [
+                {"==": [{"var": "Str"}, "foo"]},
+                {"or": [
+                    {"in": [{"var": "Int"}, [41, "42"]]},
+                    {"!": {"==": [{"var": "Bool"}, "true"]}},
+                ]},
+            ]
+        });
+
+        let expr = from_json_logic::<Property>(&doc).unwrap();
+        assert_eq!(
+            format!("{}", expr),
+            format!(
+                "(Property::Str (Str) == foo && ({} || !(Property::Bool (Bool) == true)))",
+                expr.display(Some(1))
+            )
+        );
+
+        let context = Context::from_values(vec![
+            (Property::Str, Value::Str("foo".to_owned())),
+            (Property::Int, Value::Int(42)),
+            (Property::Bool, Value::Bool(true)),
+        ])
+        .unwrap();
+
+        let result = expr.eval(&context);
+        assert!(
+            matches!(result, Ok(Evaluated::Fully(x, _, _)) if x),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn to_json_logic_document() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let b = expr.constant(false).unwrap();
+        let not_b = expr.not(b).unwrap();
+        let _ = expr.and(a, not_b).unwrap();
+
+        assert_eq!(
+            to_json_logic(&expr),
+            json!({"and": [{"==": [{"var": "Property::Int"}, 42]}, {"!": [false]}]})
+        );
+        assert_eq!(to_json_logic(&Expression::<Property>::new()), Json::Null);
+    }
+
+    #[test]
+    fn to_json_logic_sorted_in() {
+        let docs = (0..4)
+            .map(|attempt| {
+                let mut values = (0..32).collect::<Vec<i64>>();
+                values.rotate_left(attempt * 7);
+                let mut expr = Expression::<Property>::new();
+                let _ = expr.is_in(Property::Int, values).unwrap();
+                to_json_logic(&expr)
+            })
+            .collect::<Vec<_>>();
+
+        let expected = json!({"in": [{"var": "Property::Int"}, (0..32).collect::<Vec<_>>()]});
+        for doc in docs {
+            assert_eq!(doc, expected);
+        }
+    }
+
+    #[test]
+    fn json_logic_roundtrip() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let b = expr
+            .is_in(
+                Property::Str,
+                vec![Value::Str("a".to_owned()), Value::Str("b".to_owned())],
+            )
+            .unwrap();
+        let c = expr.is(Property::Bool, Value::Bool(false)).unwrap();
+        let t = expr.constant(true).unwrap();
+        let not_c = expr.not(c).unwrap();
+        let c_and_t = expr.and(not_c, t).unwrap();
+        let not_a = expr.is_not(Property::Int, Value::Int(42)).unwrap();
+        let a_or_b = expr.or(not_a, b).unwrap();
+        let a_or_b_then_c = expr.implies(a_or_b, c_and_t).unwrap();
+        let _ = expr.if_else(c, a, a_or_b_then_c).unwrap();
+
+        let roundtrip = from_json_logic::<Property>(&to_json_logic(&expr)).unwrap();
+
+        for int in &[42, 24] {
+            for string in &["a", "c"] {
+                for boolean in &[true, false] {
+                    let context = Context::from_values(vec![
+                        (Property::Int, Value::Int(*int)),
+                        (Property::Str, Value::Str(string.to_string())),
+                        (Property::Bool, Value::Bool(*boolean)),
+                    ])
+                    .unwrap();
+
+                    let expected = expr.eval(&context).unwrap().as_bool();
+                    let actual = roundtrip.eval(&context).unwrap().as_bool();
+                    assert!(expected.is_some());
+                    assert_eq!(expected, actual, "context: {}", context);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn json_logic_contains() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.contains(Property::Str, "dio").unwrap();
+        let b = expr.contains_ci(Property::Str, "HEAD").unwrap();
+        let _ = expr.or(a, b).unwrap();
+
+        let doc = to_json_logic(&expr);
+        assert_eq!(
+            doc,
+            json!({"or": [
+                {"in": ["dio", {"var": "Property::Str"}]},
+                {"contains_ci": [{"var": "Property::Str"}, "HEAD"]},
+            ]})
+        );
+        assert_eq!(from_json_logic::<Property>(&doc).unwrap(), expr);
+    }
+
+    #[test]
+    fn from_json_logic_unsupported() {
+        let doc = json!({"and": [{">": [{"var": "Int"}, 42]}, true]});
+        let result = from_json_logic::<Property>(&doc);
+
+        assert!(
+            matches!(&result, Err(Error::UnsupportedJsonLogic(op)) if op.contains(">")),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn from_json_logic_type_mismatch() {
+        let doc = json!({"==": [{"var": "Int"}, "forty two"]});
+        let result = from_json_logic::<Property>(&doc);
+
+        assert!(matches!(
+            result,
+            Err(Error::TypeMismatch(
+                "Property::Int",
+                Datatype::Int,
+                Datatype::Str
+            ))
+        ));
+    }
+
+    #[test]
+    fn from_json_logic_float() {
+        let doc = json!({"==": [{"var": "Int"}, 4.2]});
+        let result = from_json_logic::<Property>(&doc);
+        assert!(
+            matches!(
+                result,
+                Err(Error::TypeMismatch(
+                    "Property::Int",
+                    Datatype::Int,
+                    Datatype::Float
+                ))
+            ),
+            "{:?}",
+            result
+        );
+
+        let doc = json!({"==": [{"var": "Int"}, 18_446_744_073_709_551_615u64]});
+        let result = from_json_logic::<Property>(&doc);
+        assert!(
+            matches!(
+                result,
+                Err(Error::TypeMismatch(
+                    "Property::Int",
+                    Datatype::Int,
+                    Datatype::UInt
+                ))
+            ),
+            "{:?}",
+            result
+        );
+
+        let doc = json!({"==": [{"var": "Str"}, 4.2]});
+        let expr = from_json_logic::<Property>(&doc).unwrap();
+        let mut expected = Expression::<Property>::new();
+        let _ = expected.is(Property::Str, "4.2").unwrap();
+        assert_eq!(expr, expected);
+    }
+
+    #[test]
+    fn from_json_logic_unknown_property() {
+        let doc = json!({"==": [{"var": "Float"}, 4.2]});
+        let result = from_json_logic::<Property>(&doc);
+
+        assert!(matches!(result, Err(Error::IdentifierNotFound(_))));
+    }
+
+    #[test]
+    fn json_logic_quantified() {
+        let mut expr = Expression::<Listed>::new();
+        let a = expr
+            .any_element(Listed::Tags, Predicate::Is("live".into()))
+            .unwrap();
+        let b = expr
+            .all_elements(Listed::Tags, Predicate::Contains("rock".to_owned(), false))
+            .unwrap();
+        let _ = expr.and(a, b).unwrap();
+
+        let doc = to_json_logic(&expr);
+        assert_eq!(
+            doc,
+            json!({"and": [
+                {"some": [{"var": "Listed::Tags"}, {"==": [{"var": ""}, "live"]}]},
+                {"none": [{"var": "Listed::Tags"}, {"!": [{"in": ["rock", {"var": ""}]}]}]},
+            ]})
+        );
+        assert_eq!(from_json_logic::<Listed>(&doc).unwrap(), expr);
+
+        let none = json!({"none": [{"var": "Tags"}, {"in": [{"var": ""}, ["a"]]}]});
+        assert_eq!(
+            format!("{}", from_json_logic::<Listed>(&none).unwrap()),
+            "!(Listed::Tags (List<Str>) any(in [a]))"
+        );
+    }
+}
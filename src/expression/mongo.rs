@@ -0,0 +1,312 @@
+use std::collections::BTreeSet;
+
+use serde_json::{json, Map, Value as Json};
+
+use super::{Expression, OpRef, Operation};
+use crate::condition::{ApproxIs, In, Predicate, Quantified, Quantifier};
+use crate::domain::Property;
+use crate::error::{Error, Result};
+use crate::value::Value;
+
+fn field(name: &str, condition: Json) -> Json {
+    let mut doc = Map::new();
+    doc.insert(name.to_owned(), condition);
+    Json::Object(doc)
+}
+
+fn regex_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if "\\.+*?()|[]{}^$".contains(ch) {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+fn render<Pid: Property, F: Fn(Pid) -> String>(
+    expr: &Expression<Pid>,
+    opref: OpRef,
+    mapper: &F,
+) -> Json {
+    let render = |opref| render(expr, opref, mapper);
+    match &expr.ops[opref].0 {
+        Operation::Const(val) => json!({ "$expr": val }),
+        Operation::Is(cond) => field(&mapper(cond.variable()), Json::from(cond.expected())),
+        Operation::IsNot(cond) => field(
+            &mapper(cond.variable()),
+            json!({ "$ne": Json::from(cond.expected()) }),
+        ),
+        Operation::ApproxIs(cond) => match band(cond) {
+            Some(band) => field(&mapper(cond.variable()), band),
+            None => json!({ "$expr": false }),
+        },
+        Operation::In(cond) => field(&mapper(cond.variable()), json!({ "$in": values(cond) })),
+        Operation::Contains(cond) => field(
+            &mapper(cond.variable()),
+            regex(cond.needle(), cond.case_insensitive()),
+        ),
+        Operation::Quantified(cond) => quantified(cond, false, mapper),
+        &Operation::Not(opref) => negated(expr, opref, mapper),
+        &Operation::Or(lhs, rhs) => json!({ "$or": [render(lhs), render(rhs)] }),
+        &Operation::And(lhs, rhs) => json!({ "$and": [render(lhs), render(rhs)] }),
+        &Operation::Nand(lhs, rhs) => json!({ "$nor": [{ "$and": [render(lhs), render(rhs)] }] }),
+        &Operation::Nor(lhs, rhs) => json!({ "$nor": [render(lhs), render(rhs)] }),
+        &Operation::Implies(premise, conclusion) => json!({
+            "$or": [negated(expr, premise, mapper), render(conclusion)]
+        }),
+        &Operation::IfElse(cond, then, otherwise) => json!({
+            "$or": [
+                { "$and": [render(cond), render(then)] },
+                { "$and": [negated(expr, cond, mapper), render(otherwise)] },
+            ]
+        }),
+    }
+}
+
+/// Sets don't keep any particular order, values are sorted to make the output stable.
+fn values<Pid: Property>(cond: &In<Pid>) -> Json {
+    let mut values = cond.expected().iter().collect::<Vec<_>>();
+    values.sort();
+    Json::Array(values.into_iter().map(Json::from).collect())
+}
+
+fn band<Pid: Property>(cond: &ApproxIs<Pid>) -> Option<Json> {
+    cond.bounds()
+        .map(|(low, high)| json!({ "$gte": low, "$lte": high }))
+}
+
+fn regex(needle: &str, case_insensitive: bool) -> Json {
+    let pattern = regex_escape(needle);
+    if case_insensitive {
+        json!({ "$regex": pattern, "$options": "i" })
+    } else {
+        json!({ "$regex": pattern })
+    }
+}
+
+/// Element-level operator for a predicate, or for its negation.
+fn element(predicate: &Predicate, negated: bool) -> Json {
+    let values = |values: &BTreeSet<Value>| values.iter().map(Json::from).collect::<Vec<_>>();
+    match (predicate, negated) {
+        (Predicate::Is(expected), false) => json!({ "$eq": Json::from(expected) }),
+        (Predicate::Is(expected), true) => json!({ "$ne": Json::from(expected) }),
+        (Predicate::In(expected), false) => json!({ "$in": values(expected) }),
+        (Predicate::In(expected), true) => json!({ "$nin": values(expected) }),
+        (Predicate::Contains(needle, case_insensitive), false) => regex(needle, *case_insensitive),
+        (Predicate::Contains(needle, case_insensitive), true) => {
+            json!({ "$not": regex(needle, *case_insensitive) })
+        }
+    }
+}
+
+/// Some element matches: `$elemMatch`, every element matches: no element fails to match.
+fn quantified<Pid: Property, F: Fn(Pid) -> String>(
+    cond: &Quantified<Pid>,
+    negated: bool,
+    mapper: &F,
+) -> Json {
+    let all = cond.quantifier() == Quantifier::All;
+    let elem_match = json!({ "$elemMatch": element(cond.predicate(), all) });
+    let condition = if all != negated {
+        json!({ "$not": elem_match })
+    } else {
+        elem_match
+    };
+    field(&mapper(cond.variable()), condition)
+}
+
+/// Negates conditions with field-level operators, anything compound is wrapped into `$nor`.
+fn negated<Pid: Property, F: Fn(Pid) -> String>(
+    expr: &Expression<Pid>,
+    opref: OpRef,
+    mapper: &F,
+) -> Json {
+    match &expr.ops[opref].0 {
+        Operation::Const(val) => json!({ "$expr": !val }),
+        Operation::Is(cond) => field(
+            &mapper(cond.variable()),
+            json!({ "$ne": Json::from(cond.expected()) }),
+        ),
+        Operation::IsNot(cond) => field(&mapper(cond.variable()), Json::from(cond.expected())),
+        Operation::ApproxIs(cond) => match band(cond) {
+            Some(band) => field(&mapper(cond.variable()), json!({ "$not": band })),
+            None => json!({ "$expr": true }),
+        },
+        Operation::In(cond) => field(&mapper(cond.variable()), json!({ "$nin": values(cond) })),
+        Operation::Contains(cond) => field(
+            &mapper(cond.variable()),
+            json!({ "$not": regex(cond.needle(), cond.case_insensitive()) }),
+        ),
+        Operation::Quantified(cond) => quantified(cond, true, mapper),
+        &Operation::Not(opref) => render(expr, opref, mapper),
+        &Operation::Nand(lhs, rhs) => json!({
+            "$and": [render(expr, lhs, mapper), render(expr, rhs, mapper)]
+        }),
+        &Operation::Nor(lhs, rhs) => json!({
+            "$or": [render(expr, lhs, mapper), render(expr, rhs, mapper)]
+        }),
+        _ => json!({ "$nor": [render(expr, opref, mapper)] }),
+    }
+}
+
+/// Translates an expression into a MongoDB query document, field names come from `Property::name()`.
+///
+/// Constants are rendered as `{ "$expr": true }` / `{ "$expr": false }`, which match every
+/// or no document respectively. Substring conditions become anchorless `$regex` matches.
+///
+/// Negated conditions use field-level operators: `$ne`, `$nin` and `$not` for regular
+/// expressions, negated constants are flipped and double negations cancel out. MongoDB has
+/// no top-level `$not`, so negated compound conditions are rendered as a single-clause `$nor`.
+/// Conditions on list elements use `$elemMatch`, `All` as a negated `$elemMatch` of the
+/// negated predicate.
+/// Like `$nor`, field-level negations also match documents that don't have the field at all.
+pub fn to_query<Pid: Property>(expr: &Expression<Pid>) -> Result<Json> {
+    to_query_with(expr, &|prop: Pid| prop.name().to_owned())
+}
+
+/// Same as `to_query`, but field names come from `mapper`.
+pub fn to_query_with<Pid: Property, F: Fn(Pid) -> String>(
+    expr: &Expression<Pid>,
+    mapper: &F,
+) -> Result<Json> {
+    let root = expr.root().ok_or(Error::ExpressionNoop)?;
+    Ok(render(expr, expr.valid(root)?, mapper))
+}
+
+impl<Pid: Property> Expression<Pid> {
+    /// MongoDB filter document for the expression, see `mongo::to_query`.
+    pub fn to_mongo_filter(&self) -> Result<Json> {
+        to_query(self)
+    }
+
+    /// Same as `to_mongo_filter`, but field names come from `mapper`.
+    pub fn to_mongo_filter_with(&self, mapper: &impl Fn(Pid) -> String) -> Result<Json> {
+        to_query_with(self, mapper)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::condition::Predicate;
+    use crate::testproperty::{Listed, Property};
+    use crate::value::Value;
+
+    #[test]
+    fn to_query_document() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let b = expr
+            .is_in(Property::Str, vec![Value::Str("a".to_owned())])
+            .unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let c = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+        let not_c = expr.not(c).unwrap();
+        let f = expr.constant(false).unwrap();
+        let not_c_or_f = expr.or(not_c, f).unwrap();
+        let _ = expr.and(a_or_b, not_c_or_f).unwrap();
+
+        assert_eq!(
+            expr.to_mongo_filter().unwrap(),
+            json!({
+                "$and": [
+                    { "$or": [
+                        { "Property::Int": 42 },
+                        { "Property::Str": { "$in": ["a"] } },
+                    ]},
+                    { "$or": [
+                        { "Property::Bool": { "$ne": true } },
+                        { "$expr": false },
+                    ]},
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn to_query_contains() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.contains(Property::Str, "a.b").unwrap();
+        let b = expr.contains_ci(Property::Str, "(c)").unwrap();
+        let _ = expr.and(a, b).unwrap();
+
+        assert_eq!(
+            to_query(&expr).unwrap(),
+            json!({
+                "$and": [
+                    { "Property::Str": { "$regex": "a\\.b" } },
+                    { "Property::Str": { "$regex": "\\(c\\)", "$options": "i" } },
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn to_query_negations() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is_in(Property::Int, vec![1, 2]).unwrap();
+        let b = expr.contains_ci(Property::Str, "x").unwrap();
+        let t = expr.constant(true).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let not_a = expr.not(a).unwrap();
+        let not_b = expr.not(b).unwrap();
+        let not_t = expr.not(t).unwrap();
+        let not_a_and_b = expr.not(a_and_b).unwrap();
+        let not_not_a = expr.not(not_a).unwrap();
+        let lhs = expr.or(not_b, not_t).unwrap();
+        let rhs = expr.or(not_a_and_b, not_not_a).unwrap();
+        let implies = expr.implies(lhs, rhs).unwrap();
+        let _ = expr.and(implies, not_a).unwrap();
+
+        let column = |prop: Property| format!("{:?}", prop).to_lowercase();
+
+        assert_eq!(
+            expr.to_mongo_filter_with(&column).unwrap(),
+            json!({ "$and": [{
+                "$or": [
+                    { "$nor": [{ "$or": [
+                        { "str": { "$not": { "$regex": "x", "$options": "i" } } },
+                        { "$expr": false },
+                    ]}]},
+                    { "$or": [
+                        { "$nor": [{ "$and": [
+                            { "int": { "$in": [1, 2] } },
+                            { "str": { "$regex": "x", "$options": "i" } },
+                        ]}]},
+                        { "int": { "$in": [1, 2] } },
+                    ]},
+                ]},
+                { "int": { "$nin": [1, 2] } },
+            ]})
+        );
+    }
+
+    #[test]
+    fn to_query_empty() {
+        let result = to_query(&Expression::<Property>::new());
+        assert!(matches!(result, Err(Error::ExpressionNoop)), "{:?}", result);
+    }
+
+    #[test]
+    fn to_query_quantified() {
+        let mut expr = Expression::<Listed>::new();
+        let a = expr
+            .any_element(Listed::Tags, Predicate::Is("live".into()))
+            .unwrap();
+        let in_set = Predicate::In(vec!["b".into(), "a".into()].into_iter().collect());
+        let b = expr.all_elements(Listed::Tags, in_set).unwrap();
+        let not_b = expr.not(b).unwrap();
+        let _ = expr.and(a, not_b).unwrap();
+
+        assert_eq!(
+            to_query(&expr).unwrap(),
+            json!({ "$and": [
+                { "Listed::Tags": { "$elemMatch": { "$eq": "live" } } },
+                { "Listed::Tags": { "$elemMatch": { "$nin": ["a", "b"] } } },
+            ]})
+        );
+    }
+}
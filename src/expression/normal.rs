@@ -0,0 +1,518 @@
+use super::{Expression, Tree};
+use crate::domain::Property;
+use crate::error::{Error, Result};
+
+type Clauses<Pid> = Vec<Vec<Tree<Pid>>>;
+
+/// Pushes negations down to the leaf conditions, expanding `Nand`, `Nor`, `Implies` and `IfElse`.
+fn nnf<Pid: Property>(tree: &Tree<Pid>, negated: bool) -> Tree<Pid> {
+    let both = |lhs: &Tree<Pid>, lneg: bool, rhs: &Tree<Pid>, rneg: bool| {
+        (Box::new(nnf(lhs, lneg)), Box::new(nnf(rhs, rneg)))
+    };
+
+    match tree {
+        Tree::Const(val) => Tree::Const(*val != negated),
+        Tree::Not(operand) => nnf(operand, !negated),
+        Tree::Or(lhs, rhs) if negated => {
+            let (lhs, rhs) = both(lhs, true, rhs, true);
+            Tree::And(lhs, rhs)
+        }
+        Tree::Or(lhs, rhs) => {
+            let (lhs, rhs) = both(lhs, false, rhs, false);
+            Tree::Or(lhs, rhs)
+        }
+        Tree::And(lhs, rhs) if negated => {
+            let (lhs, rhs) = both(lhs, true, rhs, true);
+            Tree::Or(lhs, rhs)
+        }
+        Tree::And(lhs, rhs) => {
+            let (lhs, rhs) = both(lhs, false, rhs, false);
+            Tree::And(lhs, rhs)
+        }
+        Tree::Nand(lhs, rhs) if negated => {
+            let (lhs, rhs) = both(lhs, false, rhs, false);
+            Tree::And(lhs, rhs)
+        }
+        Tree::Nand(lhs, rhs) => {
+            let (lhs, rhs) = both(lhs, true, rhs, true);
+            Tree::Or(lhs, rhs)
+        }
+        Tree::Nor(lhs, rhs) if negated => {
+            let (lhs, rhs) = both(lhs, false, rhs, false);
+            Tree::Or(lhs, rhs)
+        }
+        Tree::Nor(lhs, rhs) => {
+            let (lhs, rhs) = both(lhs, true, rhs, true);
+            Tree::And(lhs, rhs)
+        }
+        Tree::Implies(premise, conclusion) if negated => {
+            let (premise, conclusion) = both(premise, false, conclusion, true);
+            Tree::And(premise, conclusion)
+        }
+        Tree::Implies(premise, conclusion) => {
+            let (premise, conclusion) = both(premise, true, conclusion, false);
+            Tree::Or(premise, conclusion)
+        }
+        Tree::IfElse(cond, then, otherwise) => {
+            let (cond, then) = both(cond, false, then, negated);
+            let (not_cond, otherwise) = both(cond.as_ref(), true, otherwise, negated);
+            Tree::Or(
+                Box::new(Tree::And(cond, then)),
+                Box::new(Tree::And(not_cond, otherwise)),
+            )
+        }
+        leaf if negated => Tree::Not(Box::new(leaf.clone())),
+        leaf => leaf.clone(),
+    }
+}
+
+/// Depth of a left-folded chain of binary operations over operands of the given depths.
+fn chain_depth<I: Iterator<Item = usize>>(depths: I) -> usize {
+    let depths = depths.collect::<Vec<_>>();
+    let len = depths.len();
+    depths
+        .iter()
+        .enumerate()
+        .map(|(idx, depth)| depth + len - idx.max(1))
+        .max()
+        .unwrap_or(1)
+}
+
+/// Number of operations and depth of an expression built from the clauses.
+fn size<Pid: Property>(clauses: &[Vec<Tree<Pid>>]) -> (usize, usize) {
+    let literal = |lit: &Tree<Pid>| match lit {
+        Tree::Not(_) => 2,
+        _ => 1,
+    };
+
+    let ops = clauses
+        .iter()
+        .map(|clause| clause.iter().map(literal).sum::<usize>() + clause.len().saturating_sub(1))
+        .sum::<usize>()
+        + clauses.len().saturating_sub(1);
+    let depth = chain_depth(
+        clauses
+            .iter()
+            .map(|clause| chain_depth(clause.iter().map(literal))),
+    );
+    (ops, depth)
+}
+
+/// Splits a tree in negation normal form into clauses of literals. Clauses are joined
+/// with `And` and literals with `Or` if `conjunctive`, the other way around otherwise.
+fn distribute<Pid: Property>(
+    tree: &Tree<Pid>,
+    conjunctive: bool,
+    max_ops: usize,
+) -> Result<Clauses<Pid>> {
+    let clauses = match tree {
+        // an empty clause list is the identity of the outer operation, an empty clause of the inner
+        Tree::Const(val) if *val == conjunctive => Vec::new(),
+        Tree::Const(_) => vec![Vec::new()],
+        Tree::And(lhs, rhs) | Tree::Or(lhs, rhs) => {
+            let lhs = distribute(lhs, conjunctive, max_ops)?;
+            let rhs = distribute(rhs, conjunctive, max_ops)?;
+
+            if matches!(tree, Tree::And(_, _)) == conjunctive {
+                lhs.into_iter().chain(rhs).collect()
+            } else {
+                let mut clauses = Vec::with_capacity(lhs.len() * rhs.len());
+                for lclause in lhs.iter() {
+                    for rclause in rhs.iter() {
+                        let mut clause = lclause.clone();
+                        for literal in rclause {
+                            if !clause.contains(literal) {
+                                clause.push(literal.clone());
+                            }
+                        }
+                        clauses.push(clause);
+                    }
+                }
+                clauses
+            }
+        }
+        literal => vec![vec![literal.clone()]],
+    };
+
+    match size(&clauses) {
+        (ops, depth) if ops > max_ops => Err(Error::ExpressionTooLarge { ops, depth }),
+        _ => Ok(clauses),
+    }
+}
+
+impl<Pid: Property> Expression<Pid> {
+    fn normal_form(&self, conjunctive: bool, max_ops: usize) -> Result<Expression<Pid>> {
+        let clauses = distribute(&nnf(&self.to_tree()?, false), conjunctive, max_ops)?;
+
+        let join = |lhs: Tree<Pid>, rhs: Tree<Pid>, and: bool| {
+            if and {
+                Tree::And(Box::new(lhs), Box::new(rhs))
+            } else {
+                Tree::Or(Box::new(lhs), Box::new(rhs))
+            }
+        };
+        let clause = |clause: &Vec<Tree<Pid>>| {
+            let mut literals = clause.iter().cloned();
+            let first = literals.next().unwrap_or(Tree::Const(!conjunctive));
+            literals.fold(first, |acc, literal| join(acc, literal, !conjunctive))
+        };
+
+        let tree = if clauses.iter().any(|clause| clause.is_empty()) {
+            Tree::Const(!conjunctive)
+        } else {
+            let mut clauses = clauses.iter().map(clause);
+            let first = clauses.next().unwrap_or(Tree::Const(conjunctive));
+            clauses.fold(first, |acc, clause| join(acc, clause, conjunctive))
+        };
+
+        Ok(Expression::from_tree(&tree))
+    }
+
+    /// Converts the expression into negation normal form: `Not` only wraps leaf conditions,
+    /// double negations are removed and negated constants are folded.
+    ///
+    /// `Nand`, `Nor`, `Implies` and `IfElse` are expanded into `Or`s and `And`s, since negations
+    /// can't be pushed through them otherwise. Shared subtrees are expanded into copies.
+    pub fn to_nnf(&self) -> Result<Expression<Pid>> {
+        Ok(Expression::from_tree(&nnf(&self.to_tree()?, false)))
+    }
+
+    /// Converts the expression into disjunctive normal form: an `Or` of `And`s of leaf conditions,
+    /// where `Not` can only wrap a leaf. Fully constant expressions become a single constant.
+    ///
+    /// The conversion can blow up exponentially, `Error::ExpressionTooLarge` is returned
+    /// as soon as the result is known to exceed `max_ops` operations.
+    pub fn to_dnf(&self, max_ops: usize) -> Result<Expression<Pid>> {
+        self.normal_form(false, max_ops)
+    }
+
+    /// Converts the expression into conjunctive normal form: an `And` of `Or`s of leaf conditions,
+    /// where `Not` can only wrap a leaf. Fully constant expressions become a single constant.
+    ///
+    /// Uses plain distribution without auxiliary variables, so the result is equivalent to the
+    /// original, but can be exponentially larger: `Error::ExpressionTooLarge` is returned as soon
+    /// as it's known to exceed `max_ops` operations. Shared subtrees are expanded into copies.
+    pub fn to_cnf(&self, max_ops: usize) -> Result<Expression<Pid>> {
+        self.normal_form(true, max_ops)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::expression::{Context, OpRef, Operation};
+    use crate::random::Random;
+    use crate::value::Value;
+    use crate::{domain, value};
+    use strum_macros::{EnumIter, EnumString};
+
+    #[derive(PartialEq, Clone, Copy, Hash, Eq, Debug, EnumIter, EnumString)]
+    enum Flag {
+        A,
+        B,
+        C,
+        D,
+    }
+
+    impl std::fmt::Display for Flag {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "{:?}", self)
+        }
+    }
+
+    impl domain::DomainEnum for Flag {}
+
+    impl domain::Property for Flag {
+        fn name(&self) -> &'static str {
+            match self {
+                Flag::A => "A",
+                Flag::B => "B",
+                Flag::C => "C",
+                Flag::D => "D",
+            }
+        }
+
+        fn datatype(&self) -> value::Datatype {
+            value::Datatype::Bool
+        }
+    }
+
+    fn contexts() -> Vec<Context<Flag>> {
+        (0..16)
+            .map(|bits| {
+                let flags = vec![Flag::A, Flag::B, Flag::C, Flag::D];
+                Context::from_values(
+                    flags
+                        .into_iter()
+                        .enumerate()
+                        .map(|(idx, flag)| (flag, Value::Bool(bits & (1 << idx) != 0))),
+                )
+                .unwrap()
+            })
+            .collect()
+    }
+
+    /// Checks that the expression is a chain of clauses made of (negated) leaves,
+    /// clauses are joined with `And` if `conjunctive`, with `Or` otherwise
+    fn is_normal(expr: &Expression<Flag>, conjunctive: bool) -> bool {
+        fn literal(expr: &Expression<Flag>, opref: OpRef) -> bool {
+            match &expr.ops[opref].0 {
+                Operation::Not(opref) => expr.ops[*opref].0.operands().is_empty(),
+                op => op.operands().is_empty(),
+            }
+        }
+        fn chain(
+            expr: &Expression<Flag>,
+            opref: OpRef,
+            and: bool,
+            item: &dyn Fn(OpRef) -> bool,
+        ) -> bool {
+            match expr.ops[opref].0 {
+                Operation::And(lhs, rhs) if and => chain(expr, lhs, and, item) && item(rhs),
+                Operation::Or(lhs, rhs) if !and => chain(expr, lhs, and, item) && item(rhs),
+                _ => item(opref),
+            }
+        }
+
+        let clause = |opref: OpRef| chain(expr, opref, !conjunctive, &|opref| literal(expr, opref));
+        chain(expr, expr.root().unwrap(), conjunctive, &clause)
+    }
+
+    fn assert_equivalent(expr: &Expression<Flag>, normal: &Expression<Flag>, conjunctive: bool) {
+        assert!(is_normal(normal, conjunctive), "{}", normal);
+        assert!(normal.validate().is_ok(), "{}", normal);
+        for context in contexts() {
+            assert_eq!(
+                expr.eval(&context).unwrap().as_bool(),
+                normal.eval(&context).unwrap().as_bool(),
+                "{} / {} / {}",
+                expr,
+                normal,
+                context
+            );
+        }
+    }
+
+    #[test]
+    fn dnf_distributes_and() {
+        let mut expr = Expression::<Flag>::new();
+        let a = expr.is(Flag::A, Value::Bool(true)).unwrap();
+        let b = expr.is(Flag::B, Value::Bool(true)).unwrap();
+        let c = expr.is(Flag::C, Value::Bool(false)).unwrap();
+        let d = expr.is(Flag::D, Value::Bool(true)).unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let c_or_d = expr.or(c, d).unwrap();
+        let both = expr.and(a_or_b, c_or_d).unwrap();
+        let _ = expr.not(both).unwrap();
+
+        let dnf = expr.to_dnf(100).unwrap();
+        assert_eq!(
+            format!("{}", dnf),
+            "((!(A (Bool) == true) && !(B (Bool) == true)) || (!(C (Bool) == false) && !(D (Bool) == true)))"
+        );
+        assert_equivalent(&expr, &dnf, false);
+
+        let mut expr = Expression::<Flag>::new();
+        let a = expr.is(Flag::A, Value::Bool(true)).unwrap();
+        let b = expr.is(Flag::B, Value::Bool(true)).unwrap();
+        let c = expr.is(Flag::C, Value::Bool(false)).unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let not_a_or_b = expr.not(a_or_b).unwrap();
+        let _ = expr.and(not_a_or_b, c).unwrap();
+        assert_equivalent(&expr, &expr.to_dnf(100).unwrap(), false);
+    }
+
+    #[test]
+    fn dnf_implies_if_else() {
+        let mut expr = Expression::<Flag>::new();
+        let a = expr.is(Flag::A, Value::Bool(true)).unwrap();
+        let b = expr.is(Flag::B, Value::Bool(true)).unwrap();
+        let c = expr.is(Flag::C, Value::Bool(true)).unwrap();
+        let d = expr.is(Flag::D, Value::Bool(false)).unwrap();
+        let a_then_b = expr.implies(a, b).unwrap();
+        let choice = expr.if_else(c, a_then_b, d).unwrap();
+        let not_choice = expr.not(choice).unwrap();
+
+        assert_equivalent(&expr, &expr.to_dnf(100).unwrap(), false);
+
+        let t = expr.constant(true).unwrap();
+        let _ = expr.and(not_choice, t).unwrap();
+        assert_equivalent(&expr, &expr.to_dnf(100).unwrap(), false);
+    }
+
+    #[test]
+    fn dnf_constants() {
+        let mut expr = Expression::<Flag>::new();
+        let a = expr.is(Flag::A, Value::Bool(true)).unwrap();
+        let t = expr.constant(true).unwrap();
+        let _ = expr.or(a, t).unwrap();
+
+        let dnf = expr.to_dnf(100).unwrap();
+        assert_eq!(format!("{}", dnf), "true");
+
+        let f = expr.constant(false).unwrap();
+        let _ = expr.and(a, f).unwrap();
+
+        let dnf = expr.to_dnf(100).unwrap();
+        assert_eq!(format!("{}", dnf), "false");
+    }
+
+    #[test]
+    fn dnf_too_large() {
+        // (A || B) && (C || D) && (A || C) && (B || D) expands into 16 clauses
+        let mut expr = Expression::<Flag>::new();
+        let a = expr.is(Flag::A, Value::Bool(true)).unwrap();
+        let b = expr.is(Flag::B, Value::Bool(true)).unwrap();
+        let c = expr.is(Flag::C, Value::Bool(true)).unwrap();
+        let d = expr.is(Flag::D, Value::Bool(true)).unwrap();
+        let mut acc = expr.or(a, b).unwrap();
+        for (lhs, rhs) in &[(c, d), (a, c), (b, d)] {
+            let or = expr.or(*lhs, *rhs).unwrap();
+            acc = expr.and(acc, or).unwrap();
+        }
+
+        let result = expr.to_dnf(10);
+        assert!(
+            matches!(result, Err(Error::ExpressionTooLarge { ops, .. }) if ops > 10),
+            "{:?}",
+            result
+        );
+        assert_equivalent(&expr, &expr.to_dnf(1000).unwrap(), false);
+    }
+
+    #[test]
+    fn cnf_distributes_or() {
+        let mut expr = Expression::<Flag>::new();
+        let a = expr.is(Flag::A, Value::Bool(true)).unwrap();
+        let b = expr.is(Flag::B, Value::Bool(true)).unwrap();
+        let c = expr.is(Flag::C, Value::Bool(false)).unwrap();
+        let d = expr.is(Flag::D, Value::Bool(true)).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let c_and_d = expr.and(c, d).unwrap();
+        let _ = expr.or(a_and_b, c_and_d).unwrap();
+
+        let cnf = expr.to_cnf(100).unwrap();
+        assert_eq!(
+            format!("{}", cnf),
+            "((((A (Bool) == true || C (Bool) == false) && (A (Bool) == true || D (Bool) == true)) \
+             && (B (Bool) == true || C (Bool) == false)) && (B (Bool) == true || D (Bool) == true))"
+        );
+        assert_equivalent(&expr, &cnf, true);
+    }
+
+    #[test]
+    fn cnf_implies_if_else() {
+        let mut expr = Expression::<Flag>::new();
+        let a = expr.is(Flag::A, Value::Bool(true)).unwrap();
+        let b = expr.is(Flag::B, Value::Bool(true)).unwrap();
+        let c = expr.is(Flag::C, Value::Bool(true)).unwrap();
+        let d = expr.is(Flag::D, Value::Bool(false)).unwrap();
+        let a_then_b = expr.implies(a, b).unwrap();
+        let choice = expr.if_else(c, a_then_b, d).unwrap();
+        let _ = expr.not(choice).unwrap();
+        assert_equivalent(&expr, &expr.to_cnf(100).unwrap(), true);
+
+        let mut expr = Expression::<Flag>::new();
+        let a = expr.is(Flag::A, Value::Bool(true)).unwrap();
+        let f = expr.constant(false).unwrap();
+        let _ = expr.and(a, f).unwrap();
+        assert_eq!(format!("{}", expr.to_cnf(100).unwrap()), "false");
+    }
+
+    #[test]
+    fn cnf_too_large() {
+        // (A && B) || (C && D) || (A && C) || (B && D) expands into 16 clauses
+        let mut expr = Expression::<Flag>::new();
+        let a = expr.is(Flag::A, Value::Bool(true)).unwrap();
+        let b = expr.is(Flag::B, Value::Bool(true)).unwrap();
+        let c = expr.is(Flag::C, Value::Bool(true)).unwrap();
+        let d = expr.is(Flag::D, Value::Bool(true)).unwrap();
+        let mut acc = expr.and(a, b).unwrap();
+        for (lhs, rhs) in &[(c, d), (a, c), (b, d)] {
+            let and = expr.and(*lhs, *rhs).unwrap();
+            acc = expr.or(acc, and).unwrap();
+        }
+
+        let result = expr.to_cnf(10);
+        assert!(
+            matches!(result, Err(Error::ExpressionTooLarge { ops, .. }) if ops > 10),
+            "{:?}",
+            result
+        );
+        assert_equivalent(&expr, &expr.to_cnf(1000).unwrap(), true);
+    }
+
+    #[test]
+    fn nnf_randomized() {
+        let mut random = Random::new(42);
+        let mut next = |bound: usize| random.below(bound);
+
+        for _ in 0..50 {
+            let mut expr = Expression::<Flag>::new();
+            for flag in &[Flag::A, Flag::B, Flag::C, Flag::D] {
+                expr.is(*flag, Value::Bool(next(2) == 0)).unwrap();
+            }
+            for _ in 0..8 {
+                let (lhs, rhs, cond) = (next(expr.len()), next(expr.len()), next(expr.len()));
+                match next(6) {
+                    0 | 1 => expr.not(lhs),
+                    2 => expr.or(lhs, rhs),
+                    3 => expr.and(lhs, rhs),
+                    4 => expr.implies(lhs, rhs),
+                    _ => expr.if_else(cond, lhs, rhs),
+                }
+                .unwrap();
+            }
+            let expr = expr.subexpression(expr.root().unwrap()).unwrap();
+
+            let nnf = expr.to_nnf().unwrap();
+            assert!(nnf.validate().is_ok(), "{}", nnf);
+            for (_, op, _) in nnf.iter() {
+                assert!(
+                    !matches!(op, Operation::Implies(_, _) | Operation::IfElse(_, _, _)),
+                    "{}",
+                    nnf
+                );
+            }
+            for context in contexts() {
+                assert_eq!(
+                    expr.eval(&context).unwrap().as_bool(),
+                    nnf.eval(&context).unwrap().as_bool(),
+                    "{} / {} / {}",
+                    expr,
+                    nnf,
+                    context
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn nnf_not_only_above_leaves() {
+        let mut expr = Expression::<Flag>::new();
+        let a = expr.is(Flag::A, Value::Bool(true)).unwrap();
+        let b = expr.is(Flag::B, Value::Bool(true)).unwrap();
+        let t = expr.constant(true).unwrap();
+        let not_a = expr.not(a).unwrap();
+        let not_not_a = expr.not(not_a).unwrap();
+        let not_not_not_a = expr.not(not_not_a).unwrap();
+        let a_or_b = expr.or(not_not_not_a, b).unwrap();
+        let not_t = expr.not(t).unwrap();
+        let and = expr.and(a_or_b, not_t).unwrap();
+        let _ = expr.not(and).unwrap();
+
+        let nnf = expr.to_nnf().unwrap();
+        assert_eq!(
+            format!("{}", nnf),
+            "((A (Bool) == true && !(B (Bool) == true)) || true)"
+        );
+        for (_, op, _) in nnf.iter() {
+            if let Operation::Not(opref) = op {
+                let operand = &nnf.ops[*opref].0;
+                assert!(operand.operands().is_empty(), "{}", nnf);
+                assert!(!matches!(operand, Operation::Const(_)), "{}", nnf);
+            }
+        }
+    }
+}
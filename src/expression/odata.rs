@@ -0,0 +1,367 @@
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use super::parse::{error, Nesting};
+use super::{Expression, OpRef};
+use crate::domain::Property;
+use crate::error::Result;
+use crate::value::{Datatype, Value};
+
+/// Comparison and arithmetic operators that have no counterpart in expressions.
+const UNSUPPORTED: &[&str] = &[
+    "gt", "ge", "lt", "le", "has", "add", "sub", "mul", "div", "divby", "mod",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    // wide enough for both `Int` and `UInt` values, ranges are checked against the property
+    Int(i128),
+    LParen,
+    RParen,
+    Comma,
+    End,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Token::Ident(ident) => write!(f, "'{}'", ident),
+            Token::Str(val) => write!(f, "'{}'", val.replace('\'', "''")),
+            Token::Int(val) => write!(f, "{}", val),
+            Token::LParen => write!(f, "'('"),
+            Token::RParen => write!(f, "')'"),
+            Token::Comma => write!(f, "','"),
+            Token::End => write!(f, "end of input"),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((offset, ch)) = chars.next() {
+        let token = match ch {
+            _ if ch.is_whitespace() => continue,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ',' => Token::Comma,
+            // quotes inside string literals are doubled: 'Rock ''n'' Roll'
+            '\'' => {
+                let mut val = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '\'')) => match chars.peek() {
+                            Some((_, '\'')) => {
+                                chars.next();
+                                val.push('\'');
+                            }
+                            _ => break,
+                        },
+                        Some((_, next)) => val.push(next),
+                        None => return Err(error(input.len(), "closing '", Token::End)),
+                    }
+                }
+                Token::Str(val)
+            }
+            '-' | '0'..='9' => {
+                let mut end = offset + ch.len_utf8();
+                while let Some((idx, next)) = chars.peek() {
+                    if !next.is_ascii_digit() {
+                        break;
+                    }
+                    end = idx + next.len_utf8();
+                    chars.next();
+                }
+                let literal = &input[offset..end];
+                Token::Int(
+                    literal
+                        .parse()
+                        .map_err(|_| error(offset, "integer", literal))?,
+                )
+            }
+            _ if ch.is_alphabetic() || ch == '_' => {
+                let mut end = offset + ch.len_utf8();
+                while let Some((idx, next)) = chars.peek() {
+                    if !(next.is_alphanumeric() || *next == '_') {
+                        break;
+                    }
+                    end = idx + next.len_utf8();
+                    chars.next();
+                }
+                Token::Ident(input[offset..end].to_owned())
+            }
+            _ => return Err(error(offset, "token", format!("'{}'", ch))),
+        };
+        tokens.push((offset, token));
+    }
+
+    tokens.push((input.len(), Token::End));
+    Ok(tokens)
+}
+
+fn is_keyword(token: &Token, keyword: &str) -> bool {
+    matches!(token, Token::Ident(ident) if ident == keyword)
+}
+
+struct Parser<'e, Pid: Property> {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    depth: usize,
+    expr: &'e mut Expression<Pid>,
+}
+
+impl<'e, Pid: Property> Nesting for Parser<'e, Pid> {
+    fn depth(&mut self) -> &mut usize {
+        &mut self.depth
+    }
+}
+
+impl<'e, Pid: Property> Parser<'e, Pid> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].1
+    }
+
+    fn next(&mut self) -> (usize, Token) {
+        let token = self.tokens[self.pos].clone();
+        if token.1 != Token::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            (_, token) if token == expected => Ok(()),
+            (offset, token) => Err(error(offset, expected, token)),
+        }
+    }
+
+    fn or(&mut self) -> Result<OpRef> {
+        let mut acc = self.and()?;
+        while is_keyword(self.peek(), "or") {
+            self.next();
+            let rhs = self.and()?;
+            acc = self.expr.or(acc, rhs)?;
+        }
+        Ok(acc)
+    }
+
+    fn and(&mut self) -> Result<OpRef> {
+        let mut acc = self.unary()?;
+        while is_keyword(self.peek(), "and") {
+            self.next();
+            let rhs = self.unary()?;
+            acc = self.expr.and(acc, rhs)?;
+        }
+        Ok(acc)
+    }
+
+    fn unary(&mut self) -> Result<OpRef> {
+        match self.next() {
+            (offset, Token::Ident(ref ident)) if ident == "not" => {
+                let opref = self.nested(offset, Self::unary)?;
+                self.expr.not(opref)
+            }
+            (offset, Token::LParen) => {
+                let opref = self.nested(offset, Self::or)?;
+                self.expect(Token::RParen)?;
+                Ok(opref)
+            }
+            (_, Token::Ident(ref ident)) if ident == "true" => self.expr.constant(true),
+            (_, Token::Ident(ref ident)) if ident == "false" => self.expr.constant(false),
+            (offset, Token::Ident(ident)) => self.condition(offset, &ident),
+            (offset, token) => Err(error(offset, "condition", token)),
+        }
+    }
+
+    fn property(&mut self, offset: usize, ident: &str) -> Result<Pid> {
+        if *self.peek() == Token::LParen {
+            return Err(error(offset, "property", format!("function '{}'", ident)));
+        }
+        Pid::from_str(ident)
+            .ok()
+            .ok_or_else(|| error(offset, "property", format!("'{}'", ident)))
+    }
+
+    fn value(&mut self, variable: Pid) -> Result<Value> {
+        let (offset, token) = self.next();
+        let value = match (variable.datatype(), &token) {
+            (Datatype::Bool, Token::Ident(ident)) if ident == "true" => Value::Bool(true),
+            (Datatype::Bool, Token::Ident(ident)) if ident == "false" => Value::Bool(false),
+            (Datatype::Int, Token::Int(val)) if i64::try_from(*val).is_ok() => {
+                Value::Int(*val as i64)
+            }
+            (Datatype::UInt, Token::Int(val)) if u64::try_from(*val).is_ok() => {
+                Value::UInt(*val as u64)
+            }
+            (Datatype::Str, Token::Str(val)) => Value::Str(val.clone()),
+            (datatype, token) => return Err(error(offset, datatype, token)),
+        };
+        Ok(value)
+    }
+
+    fn condition(&mut self, offset: usize, ident: &str) -> Result<OpRef> {
+        let variable = self.property(offset, ident)?;
+
+        match self.peek().clone() {
+            Token::Ident(ref keyword) if keyword == "eq" => {
+                self.next();
+                let value = self.value(variable)?;
+                self.expr.is(variable, value)
+            }
+            Token::Ident(ref keyword) if keyword == "ne" => {
+                self.next();
+                let value = self.value(variable)?;
+                self.expr.is_not(variable, value)
+            }
+            Token::Ident(ref keyword) if keyword == "in" => {
+                self.next();
+                self.expect(Token::LParen)?;
+                let mut values = vec![self.value(variable)?];
+                while *self.peek() == Token::Comma {
+                    self.next();
+                    values.push(self.value(variable)?);
+                }
+                self.expect(Token::RParen)?;
+                self.expr.is_in(variable, values)
+            }
+            // a bare boolean property is a shorthand for `property eq true`
+            ref token
+                if variable.datatype() == Datatype::Bool
+                    && !UNSUPPORTED.iter().any(|op| is_keyword(token, op)) =>
+            {
+                self.expr.is(variable, true)
+            }
+            token => Err(error(self.tokens[self.pos].0, "'eq', 'ne' or 'in'", token)),
+        }
+    }
+}
+
+/// Parses an OData `$filter` string, for example
+/// `AlbumArtist eq 'Radiohead' and (AlbumListeners in (100, 200) or not Released)`.
+///
+/// Supports `eq`, `ne`, `in (...)`, `not` (binds tighter than `and`), `and` (binds tighter
+/// than `or`), `or` and parentheses, a bare `Bool` property is a shorthand for `Property eq true`.
+/// Properties are resolved with `FromStr`, literals are checked against the property datatype:
+/// strings are single-quoted with quotes inside doubled, ints and bools are plain.
+///
+/// Functions, arithmetic, ordering comparisons and all other syntax errors are reported
+/// as `Error::ExpressionParse` with the offending token and its byte offset, so is nesting
+/// deeper than `MAX_PARSE_DEPTH`, same as `Expression::parse`.
+pub fn parse<Pid: Property>(filter: &str) -> Result<Expression<Pid>> {
+    let mut expr = Expression::new();
+    let mut parser = Parser {
+        tokens: tokenize(filter)?,
+        pos: 0,
+        depth: 0,
+        expr: &mut expr,
+    };
+
+    parser.or()?;
+    match parser.next() {
+        (_, Token::End) => Ok(expr),
+        (offset, token) => Err(error(offset, "'and', 'or' or end of input", token)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::error::Error;
+    use crate::expression::parse::MAX_PARSE_DEPTH;
+    use crate::testproperty::Property;
+
+    #[test]
+    fn odata_parse() {
+        let expr = parse::<Property>(
+            "Str eq 'Rock ''n'' Roll' and (Int in (100, -200) or not Bool) and Int ne 5",
+        )
+        .unwrap();
+
+        let mut manual = Expression::<Property>::new();
+        let a = manual.is(Property::Str, "Rock 'n' Roll").unwrap();
+        let b = manual.is_in(Property::Int, vec![100, -200]).unwrap();
+        let c = manual.is(Property::Bool, true).unwrap();
+        let not_c = manual.not(c).unwrap();
+        let b_or_c = manual.or(b, not_c).unwrap();
+        let a_and_b = manual.and(a, b_or_c).unwrap();
+        let d = manual.is_not(Property::Int, 5).unwrap();
+        let _ = manual.and(a_and_b, d).unwrap();
+        assert_eq!(expr, manual);
+    }
+
+    #[test]
+    fn odata_precedence() {
+        let expr = parse::<Property>("not Bool eq false and Int eq 1 or true").unwrap();
+        assert_eq!(
+            format!("{}", expr),
+            "((!(Property::Bool (Bool) == false) && Property::Int (Int) == 1) || true)"
+        );
+    }
+
+    #[test]
+    fn odata_errors() {
+        let cases = vec![
+            ("Int gt 100", 4, "'eq', 'ne' or 'in'", "'gt'"),
+            ("Bool gt true", 5, "'eq', 'ne' or 'in'", "'gt'"),
+            ("Int eq 1 add 2", 9, "'and', 'or' or end of input", "'add'"),
+            ("contains(Str, 'a')", 0, "property", "function 'contains'"),
+            ("Str eq 42", 7, "Str", "42"),
+            ("Int eq '42'", 7, "Int", "'42'"),
+            ("Str eq 'foo", 11, "closing '", "end of input"),
+            ("Int in (1, 2", 12, "')'", "end of input"),
+            ("Int eq 1 and", 12, "condition", "end of input"),
+            ("Int eq 1 && Bool", 9, "token", "'&'"),
+            ("Float eq 1", 0, "property", "'Float'"),
+        ];
+
+        for (input, offset, expected, found) in cases {
+            match parse::<Property>(input) {
+                Err(Error::ExpressionParse {
+                    offset: actual_offset,
+                    expected: actual_expected,
+                    found: actual_found,
+                }) => {
+                    assert_eq!(
+                        (
+                            actual_offset,
+                            actual_expected.as_str(),
+                            actual_found.as_str()
+                        ),
+                        (offset, expected, found),
+                        "{}",
+                        input
+                    );
+                }
+                result => panic!("{}: unexpected result {:?}", input, result),
+            }
+        }
+    }
+
+    #[test]
+    fn odata_depth_limit() {
+        let deepest = "not ".repeat(MAX_PARSE_DEPTH) + "true";
+        assert!(parse::<Property>(&deepest).is_ok());
+
+        let inputs = vec![
+            ("not ".repeat(100_000) + "true", 4 * MAX_PARSE_DEPTH),
+            (
+                "(".repeat(100_000) + "true" + &")".repeat(100_000),
+                MAX_PARSE_DEPTH,
+            ),
+        ];
+        for (input, expected) in inputs {
+            let result = parse::<Property>(&input);
+            assert!(
+                matches!(&result, Err(Error::ExpressionParse { offset, found, .. })
+                    if *offset == expected && found == "deeper nesting"),
+                "{:?}",
+                result.map(|expr| expr.len())
+            );
+        }
+    }
+}
@@ -0,0 +1,525 @@
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use super::{Expression, OpRef};
+use crate::domain::Property;
+use crate::error::{Error, Result};
+use crate::value::{Datatype, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    // wide enough for both `Int` and `UInt` values, ranges are checked against the property
+    Int(i128),
+    Eq,
+    Ne,
+    Not,
+    And,
+    Or,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    End,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Token::Ident(ident) => write!(f, "'{}'", ident),
+            Token::Str(val) => write!(f, "\"{}\"", val),
+            Token::Int(val) => write!(f, "{}", val),
+            Token::Eq => write!(f, "'=='"),
+            Token::Ne => write!(f, "'!='"),
+            Token::Not => write!(f, "'!'"),
+            Token::And => write!(f, "'&&'"),
+            Token::Or => write!(f, "'||'"),
+            Token::LParen => write!(f, "'('"),
+            Token::RParen => write!(f, "')'"),
+            Token::LBracket => write!(f, "'['"),
+            Token::RBracket => write!(f, "']'"),
+            Token::Comma => write!(f, "','"),
+            Token::End => write!(f, "end of input"),
+        }
+    }
+}
+
+/// Levels of nesting, parentheses or negations, parsers accept before giving up,
+/// so that untrusted input can't exhaust the stack.
+pub const MAX_PARSE_DEPTH: usize = 256;
+
+pub(super) fn error<E: ToString, F: ToString>(offset: usize, expected: E, found: F) -> Error {
+    Error::ExpressionParse {
+        offset,
+        expected: expected.to_string(),
+        found: found.to_string(),
+    }
+}
+
+/// Parsers that count nesting levels, to fail on input nested deeper than `MAX_PARSE_DEPTH`.
+pub(super) trait Nesting: Sized {
+    /// Current nesting level.
+    fn depth(&mut self) -> &mut usize;
+
+    /// Runs `parse` one nesting level deeper, the level starts at `offset`.
+    fn nested(&mut self, offset: usize, parse: fn(&mut Self) -> Result<OpRef>) -> Result<OpRef> {
+        if *self.depth() >= MAX_PARSE_DEPTH {
+            let expected = format!("at most {} nesting levels", MAX_PARSE_DEPTH);
+            return Err(error(offset, expected, "deeper nesting"));
+        }
+        *self.depth() += 1;
+        let result = parse(self);
+        *self.depth() -= 1;
+        result
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((offset, ch)) = chars.next() {
+        let token = match ch {
+            _ if ch.is_whitespace() => continue,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '[' => Token::LBracket,
+            ']' => Token::RBracket,
+            ',' => Token::Comma,
+            '!' => match chars.peek() {
+                Some((_, '=')) => {
+                    chars.next();
+                    Token::Ne
+                }
+                _ => Token::Not,
+            },
+            '=' | '&' | '|' => match chars.next() {
+                Some((_, next)) if next == ch => match ch {
+                    '=' => Token::Eq,
+                    '&' => Token::And,
+                    _ => Token::Or,
+                },
+                _ => return Err(error(offset, format!("'{0}{0}'", ch), format!("'{}'", ch))),
+            },
+            '"' | '\'' => {
+                let mut val = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, next)) if next == ch => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, escaped)) => val.push(escaped),
+                            None => {
+                                return Err(error(input.len(), "escaped character", Token::End))
+                            }
+                        },
+                        Some((_, next)) => val.push(next),
+                        None => {
+                            return Err(error(input.len(), format!("closing {}", ch), Token::End))
+                        }
+                    }
+                }
+                Token::Str(val)
+            }
+            '-' | '0'..='9' => {
+                let mut end = offset + ch.len_utf8();
+                while let Some((idx, next)) = chars.peek() {
+                    if !next.is_ascii_digit() {
+                        break;
+                    }
+                    end = idx + next.len_utf8();
+                    chars.next();
+                }
+                let literal = &input[offset..end];
+                Token::Int(
+                    literal
+                        .parse()
+                        .map_err(|_| error(offset, "integer", literal))?,
+                )
+            }
+            _ if ch.is_alphabetic() || ch == '_' => {
+                let mut end = offset + ch.len_utf8();
+                while let Some((idx, next)) = chars.peek() {
+                    if !(next.is_alphanumeric() || *next == '_' || *next == ':') {
+                        break;
+                    }
+                    end = idx + next.len_utf8();
+                    chars.next();
+                }
+                Token::Ident(input[offset..end].to_owned())
+            }
+            _ => return Err(error(offset, "token", format!("'{}'", ch))),
+        };
+        tokens.push((offset, token));
+    }
+
+    tokens.push((input.len(), Token::End));
+    Ok(tokens)
+}
+
+struct Parser<'e, Pid: Property> {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    depth: usize,
+    expr: &'e mut Expression<Pid>,
+}
+
+impl<'e, Pid: Property> Nesting for Parser<'e, Pid> {
+    fn depth(&mut self) -> &mut usize {
+        &mut self.depth
+    }
+}
+
+impl<'e, Pid: Property> Parser<'e, Pid> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].1
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens[self.pos].0
+    }
+
+    fn next(&mut self) -> (usize, Token) {
+        let token = self.tokens[self.pos].clone();
+        if token.1 != Token::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            (_, token) if token == expected => Ok(()),
+            (offset, token) => Err(error(offset, expected, token)),
+        }
+    }
+
+    fn or(&mut self) -> Result<OpRef> {
+        let mut acc = self.and()?;
+        while *self.peek() == Token::Or {
+            self.next();
+            let rhs = self.and()?;
+            acc = self.expr.or(acc, rhs)?;
+        }
+        Ok(acc)
+    }
+
+    fn and(&mut self) -> Result<OpRef> {
+        let mut acc = self.unary()?;
+        while *self.peek() == Token::And {
+            self.next();
+            let rhs = self.unary()?;
+            acc = self.expr.and(acc, rhs)?;
+        }
+        Ok(acc)
+    }
+
+    fn unary(&mut self) -> Result<OpRef> {
+        match self.next() {
+            (offset, Token::Not) => {
+                let opref = self.nested(offset, Self::unary)?;
+                self.expr.not(opref)
+            }
+            (offset, Token::LParen) => {
+                let opref = self.nested(offset, Self::or)?;
+                self.expect(Token::RParen)?;
+                Ok(opref)
+            }
+            (_, Token::Ident(ref ident)) if ident == "true" => self.expr.constant(true),
+            (_, Token::Ident(ref ident)) if ident == "false" => self.expr.constant(false),
+            (offset, Token::Ident(ident)) => self.condition(offset, &ident),
+            (offset, token) => Err(error(offset, "condition", token)),
+        }
+    }
+
+    fn property(&mut self, offset: usize, ident: &str) -> Result<Pid> {
+        let variable = Pid::from_str(ident)
+            .ok()
+            .or_else(|| Pid::iter().find(|prop| prop.name() == ident))
+            .ok_or_else(|| error(offset, "property", format!("'{}'", ident)))?;
+
+        // optional datatype annotation, as printed by `Display`
+        if let (Token::LParen, Some((_, Token::Ident(datatype)))) =
+            (self.peek(), self.tokens.get(self.pos + 1))
+        {
+            let (offset, datatype) = (self.tokens[self.pos + 1].0, datatype.clone());
+            self.next();
+            self.next();
+            if datatype != variable.datatype().to_string() {
+                return Err(error(offset, variable.datatype(), datatype));
+            }
+            self.expect(Token::RParen)?;
+        }
+        Ok(variable)
+    }
+
+    fn value(&mut self, variable: Pid) -> Result<Value> {
+        let (offset, token) = self.next();
+        let value = match (variable.datatype(), &token) {
+            (Datatype::Bool, Token::Ident(ident)) if ident == "true" => Value::Bool(true),
+            (Datatype::Bool, Token::Ident(ident)) if ident == "false" => Value::Bool(false),
+            (Datatype::Int, Token::Int(val)) if i64::try_from(*val).is_ok() => {
+                Value::Int(*val as i64)
+            }
+            (Datatype::UInt, Token::Int(val)) if u64::try_from(*val).is_ok() => {
+                Value::UInt(*val as u64)
+            }
+            (Datatype::Str, Token::Str(val)) | (Datatype::Str, Token::Ident(val)) => {
+                Value::Str(val.clone())
+            }
+            (datatype, token) => return Err(error(offset, datatype, token)),
+        };
+        Ok(value)
+    }
+
+    /// Display appends units after compared values, skips them to accept the output back.
+    fn unit(&mut self, variable: Pid) {
+        if let (Some(unit), Token::Ident(ident)) = (variable.unit(), self.peek()) {
+            if ident == unit {
+                self.next();
+            }
+        }
+    }
+
+    fn condition(&mut self, offset: usize, ident: &str) -> Result<OpRef> {
+        let variable = self.property(offset, ident)?;
+
+        match self.peek().clone() {
+            Token::Eq => {
+                self.next();
+                let value = self.value(variable)?;
+                self.unit(variable);
+                self.expr.is(variable, value)
+            }
+            Token::Ne => {
+                self.next();
+                let value = self.value(variable)?;
+                self.unit(variable);
+                self.expr.is_not(variable, value)
+            }
+            Token::Ident(ref keyword) if keyword == "in" => {
+                self.next();
+                self.expect(Token::LBracket)?;
+                let mut values = Vec::new();
+                while *self.peek() != Token::RBracket {
+                    values.push(self.value(variable)?);
+                    if *self.peek() != Token::Comma {
+                        break;
+                    }
+                    self.next();
+                }
+                self.expect(Token::RBracket)?;
+                self.expr.is_in(variable, values)
+            }
+            // a bare boolean property is a shorthand for `property == true`
+            _ if variable.datatype() == Datatype::Bool => self.expr.is(variable, true),
+            token => Err(error(self.offset(), "'==', '!=' or 'in'", token)),
+        }
+    }
+}
+
+impl<Pid: Property> Expression<Pid> {
+    /// Parses an expression from infix text, for example
+    /// `AlbumArtist == "Radiohead" && (AlbumListeners in [100, 200] || !Released)`.
+    ///
+    /// Supports `==`, `!=`, `in [...]`, `!`, `&&` (binds tighter than `||`), `||` and parentheses.
+    /// Properties are resolved with `FromStr` first, then matched against `Property::name()`,
+    /// a bare `Bool` property is a shorthand for `Property == true`. Literals are checked against
+    /// the property datatype: strings are quoted (or bare words), ints and bools are plain.
+    /// A datatype annotation after a property, `Property (Int) == 42`, is checked and skipped,
+    /// so `Display` output of expressions built from these constructs can be parsed back.
+    ///
+    /// All syntax and type errors are reported as `Error::ExpressionParse` with a byte offset,
+    /// so is nesting deeper than `MAX_PARSE_DEPTH`.
+    pub fn parse(input: &str) -> Result<Expression<Pid>> {
+        let mut expr = Expression::new();
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            pos: 0,
+            depth: 0,
+            expr: &mut expr,
+        };
+
+        parser.or()?;
+        match parser.next() {
+            (_, Token::End) => Ok(expr),
+            (offset, token) => Err(error(offset, "'&&', '||' or end of input", token)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::expression::Context;
+    use crate::testproperty::{Measured, Property};
+
+    #[test]
+    fn parse_precedence() {
+        let expr =
+            Expression::<Property>::parse(r#"Str == "Radiohead" && (Int in [100, 200] || !Bool)"#)
+                .unwrap();
+
+        let mut manual = Expression::<Property>::new();
+        let a = manual.is(Property::Str, "Radiohead").unwrap();
+        let b = manual.is_in(Property::Int, vec![100, 200]).unwrap();
+        let c = manual.is(Property::Bool, true).unwrap();
+        let not_c = manual.not(c).unwrap();
+        let b_or_c = manual.or(b, not_c).unwrap();
+        let _ = manual.and(a, b_or_c).unwrap();
+        assert_eq!(expr, manual);
+
+        let expr = Expression::<Property>::parse("true || false && Int == -1").unwrap();
+        assert_eq!(
+            format!("{}", expr),
+            "(true || (false && Property::Int (Int) == -1))"
+        );
+    }
+
+    #[test]
+    fn parse_display_roundtrip() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is_not(Property::Str, "foo").unwrap();
+        let b = expr.is_in(Property::Int, vec![3, 1, 2]).unwrap();
+        let c = expr.is(Property::Bool, false).unwrap();
+        let not_c = expr.not(c).unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let _ = expr.and(a_or_b, not_c).unwrap();
+
+        let display = format!("{}", expr);
+        let parsed = Expression::<Property>::parse(&display).unwrap();
+        assert_eq!(parsed, expr);
+        assert_eq!(format!("{}", parsed), display);
+
+        let context = Context::from_values(vec![
+            (Property::Str, Value::Str("bar".to_owned())),
+            (Property::Int, Value::Int(2)),
+            (Property::Bool, Value::Bool(true)),
+        ])
+        .unwrap();
+        assert_eq!(
+            parsed.eval(&context).unwrap().as_bool(),
+            expr.eval(&context).unwrap().as_bool()
+        );
+    }
+
+    #[test]
+    fn parse_display_unit() {
+        let mut expr = Expression::<Measured>::new();
+        let _ = expr.is(Measured::Listeners, 1000).unwrap();
+
+        let display = format!("{}", expr);
+        assert_eq!(display, "Measured::Listeners (Int) == 1000 listeners");
+        assert_eq!(Expression::<Measured>::parse(&display).unwrap(), expr);
+        assert_eq!(
+            Expression::<Measured>::parse("Listeners == 1000").unwrap(),
+            expr
+        );
+    }
+
+    #[test]
+    fn parse_uint() {
+        let expr = Expression::<Property>::parse("UInt == 18446744073709551615").unwrap();
+        let mut manual = Expression::<Property>::new();
+        let _ = manual.is(Property::UInt, u64::MAX).unwrap();
+        assert_eq!(expr, manual);
+
+        for input in &[
+            "UInt == -1",
+            "UInt == 18446744073709551616",
+            "Int == 9223372036854775808",
+        ] {
+            assert!(
+                matches!(
+                    Expression::<Property>::parse(input),
+                    Err(Error::ExpressionParse { .. })
+                ),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn parse_type_error_offset() {
+        let result = Expression::<Property>::parse(r#"Bool && Int == "42""#);
+        assert!(
+            matches!(
+                &result,
+                Err(Error::ExpressionParse { offset: 15, expected, found })
+                    if expected == "Int" && found == "\"42\""
+            ),
+            "{:?}",
+            result
+        );
+
+        let result = Expression::<Property>::parse("Int (Str) == 42");
+        assert!(
+            matches!(&result, Err(Error::ExpressionParse { offset: 5, .. })),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn parse_syntax_errors() {
+        let cases = vec![
+            ("Int == 42 &&", 12, "condition", "end of input"),
+            ("(Int == 42", 10, "')'", "end of input"),
+            ("Int = 42", 4, "'=='", "'='"),
+            ("Int == 42 Str", 10, "'&&', '||' or end of input", "'Str'"),
+            ("Float == 4", 0, "property", "'Float'"),
+            ("Int in [1, 2", 12, "']'", "end of input"),
+            ("Str == 'foo", 11, "closing '", "end of input"),
+            ("Int 42", 4, "'==', '!=' or 'in'", "42"),
+        ];
+
+        for (input, offset, expected, found) in cases {
+            let result = Expression::<Property>::parse(input);
+            match result {
+                Err(Error::ExpressionParse {
+                    offset: actual_offset,
+                    expected: actual_expected,
+                    found: actual_found,
+                }) => {
+                    assert_eq!(
+                        (
+                            actual_offset,
+                            actual_expected.as_str(),
+                            actual_found.as_str()
+                        ),
+                        (offset, expected, found),
+                        "{}",
+                        input
+                    );
+                }
+                result => panic!("{}: unexpected result {:?}", input, result),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_depth_limit() {
+        let deepest = "!".repeat(MAX_PARSE_DEPTH) + "true";
+        assert!(Expression::<Property>::parse(&deepest).is_ok());
+        let deepest = "(".repeat(MAX_PARSE_DEPTH) + "true" + &")".repeat(MAX_PARSE_DEPTH);
+        assert!(Expression::<Property>::parse(&deepest).is_ok());
+
+        let inputs = vec![
+            "!".repeat(100_000) + "true",
+            "(".repeat(100_000) + "true" + &")".repeat(100_000),
+            "(!".repeat(100_000) + "true" + &")".repeat(100_000),
+        ];
+        for input in inputs {
+            let result = Expression::<Property>::parse(&input);
+            assert!(
+                matches!(&result, Err(Error::ExpressionParse { offset, found, .. })
+                    if *offset == MAX_PARSE_DEPTH && found == "deeper nesting"),
+                "{:?}",
+                result.map(|expr| expr.len())
+            );
+        }
+    }
+}
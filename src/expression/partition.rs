@@ -0,0 +1,185 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use super::{Expression, OpRef, Operation};
+use crate::domain::Property;
+use crate::error::{Error, Result};
+
+/// Result of `Expression::partition_by`: conjuncts grouped by key, plus whatever couldn't be
+/// attributed to a single key. The original expression is equivalent to all of them joined with `And`.
+#[derive(Debug, Clone)]
+pub struct Partitioned<Pid: Property, K: Eq + Hash> {
+    /// Conjuncts referencing properties of a single key, joined with `And`.
+    pub parts: HashMap<K, Expression<Pid>>,
+    /// Conjuncts spanning several keys or none at all, joined with `And`.
+    pub residual: Option<Expression<Pid>>,
+}
+
+/// Joins standalone expressions with `And`, left to right.
+fn conjoin<Pid: Property>(parts: Vec<Expression<Pid>>) -> Result<Expression<Pid>> {
+    let mut expr = Expression::new();
+    let mut acc = None;
+    for part in parts {
+        let root = expr.append(&part)?;
+        acc = Some(match acc {
+            Some(lhs) => expr.and(lhs, root)?,
+            None => root,
+        });
+    }
+    Ok(expr)
+}
+
+impl<Pid: Property> Expression<Pid> {
+    /// Operands of the `And` chain starting at `idx`, left to right, each one listed once.
+    fn conjuncts(&self, idx: OpRef, conjuncts: &mut Vec<OpRef>) {
+        match self.ops[idx].0 {
+            Operation::And(lhs, rhs) if lhs < idx && rhs < idx => {
+                self.conjuncts(lhs, conjuncts);
+                self.conjuncts(rhs, conjuncts);
+            }
+            _ if !conjuncts.contains(&idx) => conjuncts.push(idx),
+            _ => (),
+        }
+    }
+
+    /// Splits the top-level conjunction into standalone sub-expressions, so that conditions
+    /// on different entities (or any other `key` of a property) can be evaluated separately.
+    ///
+    /// Only `And` operations starting at the root are split: every conjunct whose conditions
+    /// all map to the same key goes to that key's part, the rest (`Or`, `Not`, `Implies`
+    /// or `IfElse` mixing keys, and conjuncts without conditions) goes to the residual.
+    /// Conjuncts are never split further, even if their own operands are single-key.
+    pub fn partition_by<K: Eq + Hash, F: Fn(Pid) -> K>(
+        &self,
+        key: F,
+    ) -> Result<Partitioned<Pid, K>> {
+        let root = self.valid(self.root().ok_or(Error::ExpressionNoop)?)?;
+        let mut conjuncts = Vec::new();
+        self.conjuncts(root, &mut conjuncts);
+
+        let mut grouped = HashMap::<K, Vec<Expression<Pid>>>::new();
+        let mut residual = Vec::new();
+        for conjunct in conjuncts {
+            let sub = self.subexpression(conjunct)?;
+            let mut keys = sub
+                .variables()
+                .requested()
+                .map(|prop| key(*prop))
+                .collect::<HashSet<_>>();
+
+            match keys.len() {
+                1 => {
+                    let key = keys.drain().next().expect("single key");
+                    grouped.entry(key).or_default().push(sub);
+                }
+                _ => residual.push(sub),
+            }
+        }
+
+        let parts = grouped
+            .into_iter()
+            .map(|(key, subs)| Ok((key, conjoin(subs)?)))
+            .collect::<Result<_>>()?;
+        let residual = if residual.is_empty() {
+            None
+        } else {
+            Some(conjoin(residual)?)
+        };
+        Ok(Partitioned { parts, residual })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::expression::Context;
+    use crate::testproperty::Property;
+    use crate::value::Value;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    enum Entity {
+        Album,
+        Track,
+    }
+
+    fn entity(prop: Property) -> Entity {
+        match prop {
+            Property::Bool => Entity::Track,
+            _ => Entity::Album,
+        }
+    }
+
+    #[test]
+    fn partition_by_entity() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Str, "foo").unwrap();
+        let b = expr.is(Property::Int, 1).unwrap();
+        let c = expr.is(Property::Bool, true).unwrap();
+        let d = expr.is(Property::Int, 2).unwrap();
+        let not_c = expr.not(c).unwrap();
+        let mixed = expr.or(d, not_c).unwrap();
+        let a_and_c = expr.and(a, c).unwrap();
+        let lhs = expr.and(a_and_c, mixed).unwrap();
+        let _ = expr.and(lhs, b).unwrap();
+
+        let partitioned = expr.partition_by(entity).unwrap();
+        assert_eq!(partitioned.parts.len(), 2);
+        assert_eq!(
+            partitioned.parts[&Entity::Album].to_string(),
+            "(Property::Str (Str) == foo && Property::Int (Int) == 1)"
+        );
+        assert_eq!(
+            partitioned.parts[&Entity::Track].to_string(),
+            "Property::Bool (Bool) == true"
+        );
+        let residual = partitioned.residual.unwrap();
+        assert_eq!(
+            residual.to_string(),
+            "(Property::Int (Int) == 2 || !(Property::Bool (Bool) == true))"
+        );
+
+        // every part is evaluable on its own, together they agree with the original
+        let context = Context::from_values(vec![
+            (Property::Str, Value::from("foo")),
+            (Property::Int, Value::Int(1)),
+            (Property::Bool, Value::Bool(true)),
+        ])
+        .unwrap();
+        let album = partitioned.parts[&Entity::Album].eval(&context).unwrap();
+        let track = partitioned.parts[&Entity::Track].eval(&context).unwrap();
+        let residual = residual.eval(&context).unwrap();
+        assert_eq!(
+            (album.as_bool(), track.as_bool(), residual.as_bool()),
+            (Some(true), Some(true), Some(false))
+        );
+        assert_eq!(expr.eval(&context).unwrap().as_bool(), Some(false));
+    }
+
+    #[test]
+    fn partition_by_not_conjunction() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Str, "foo").unwrap();
+        let b = expr.is(Property::Int, 1).unwrap();
+        let c = expr.is(Property::Bool, true).unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let _ = expr.or(a_or_b, c).unwrap();
+
+        // the root isn't a conjunction, so it's kept whole
+        let partitioned = expr.partition_by(entity).unwrap();
+        assert_eq!(partitioned.parts.len(), 0);
+        assert_eq!(partitioned.residual.unwrap().to_string(), expr.to_string());
+
+        // a single-key `Or` goes to its part whole
+        expr.set_root(a_or_b).unwrap();
+        let partitioned = expr.partition_by(entity).unwrap();
+        assert_eq!(
+            partitioned.parts[&Entity::Album].to_string(),
+            "(Property::Str (Str) == foo || Property::Int (Int) == 1)"
+        );
+        assert!(partitioned.residual.is_none());
+
+        let result = Expression::<Property>::new().partition_by(entity);
+        assert!(matches!(result, Err(Error::ExpressionNoop)));
+    }
+}
@@ -0,0 +1,135 @@
+use super::{Expression, OpRef, Operation};
+use crate::domain::Property;
+
+struct Printer<'e, Pid: Property> {
+    expr: &'e Expression<Pid>,
+    indent: usize,
+    // operations reachable through more than one reference, they're labeled with `#idx`
+    shared: Vec<bool>,
+    printed: Vec<bool>,
+    lines: Vec<String>,
+}
+
+impl<'e, Pid: Property> Printer<'e, Pid> {
+    fn print(&mut self, idx: OpRef, level: usize) {
+        let pad = " ".repeat(self.indent * level);
+        if self.printed[idx] {
+            self.lines.push(format!("{}@{}", pad, idx));
+            return;
+        }
+        self.printed[idx] = true;
+
+        let op = &self.expr.ops[idx].0;
+        let label = match op {
+            Operation::Const(val) => val.to_string(),
+            Operation::Is(cond) => cond.to_string(),
+            Operation::IsNot(cond) => cond.to_string(),
+            Operation::ApproxIs(cond) => cond.to_string(),
+            Operation::In(cond) => cond.to_string(),
+            Operation::Contains(cond) => cond.to_string(),
+            Operation::Quantified(cond) => cond.to_string(),
+            Operation::Not(_) => "NOT".to_owned(),
+            Operation::Or(..) => "OR".to_owned(),
+            Operation::And(..) => "AND".to_owned(),
+            Operation::Nand(..) => "NAND".to_owned(),
+            Operation::Nor(..) => "NOR".to_owned(),
+            Operation::Implies(..) => "IMPLIES".to_owned(),
+            Operation::IfElse(..) => "IF_ELSE".to_owned(),
+        };
+        if self.shared[idx] {
+            self.lines.push(format!("{}#{} {}", pad, idx, label));
+        } else {
+            self.lines.push(format!("{}{}", pad, label));
+        }
+
+        // operands can only be printed if they precede the operation, which also rules out cycles
+        for opref in op.operands() {
+            if opref < idx {
+                self.print(opref, level + 1);
+            } else {
+                let pad = " ".repeat(self.indent * (level + 1));
+                self.lines
+                    .push(format!("{}<badref: {}/{}>", pad, opref, idx));
+            }
+        }
+    }
+}
+
+impl<Pid: Property> Expression<Pid> {
+    /// Multi-line form of the expression, one operation per line with operands indented
+    /// by `indent` spaces beneath it. Operations referenced more than once are printed
+    /// at their first use as `#3 AND`, later uses are printed as `@3`.
+    ///
+    /// Same as the alternate `Display` (`{:#}`), which indents by 2 spaces.
+    pub fn pretty(&self, indent: usize) -> String {
+        let root = match self.root.or_else(|| self.ops.len().checked_sub(1)) {
+            Some(root) if root < self.ops.len() => root,
+            Some(root) => return format!("<badref: {0}/{1}>", root, self.ops.len()),
+            None => return "<empty>".to_owned(),
+        };
+
+        let reachable = self.reachable(root);
+        let mut refs = vec![0usize; self.ops.len()];
+        for (idx, (op, _)) in self.ops.iter().enumerate() {
+            if reachable[idx] {
+                for opref in op.operands().into_iter().filter(|&opref| opref < idx) {
+                    refs[opref] += 1;
+                }
+            }
+        }
+
+        let mut printer = Printer {
+            expr: self,
+            indent,
+            shared: refs.into_iter().map(|count| count > 1).collect(),
+            printed: vec![false; self.ops.len()],
+            lines: Vec::new(),
+        };
+        printer.print(root, 0);
+        printer.lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::testproperty::Property;
+
+    #[test]
+    fn pretty_shared() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Str, "foo").unwrap();
+        let b = expr.is_in(Property::Int, vec![2, 1]).unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let not = expr.not(a_or_b).unwrap();
+        let c = expr.contains(Property::Str, "o").unwrap();
+        let implies = expr.implies(a, c).unwrap();
+        let and = expr.and(a_or_b, implies).unwrap();
+        let _ = expr.if_else(and, not, c).unwrap();
+
+        assert_eq!(
+            expr.pretty(4),
+            "IF_ELSE
+    AND
+        #2 OR
+            #0 Property::Str (Str) == foo
+            Property::Int (Int) in [1, 2]
+        IMPLIES
+            @0
+            #4 Property::Str (Str) contains 'o'
+    NOT
+        @2
+    @4"
+        );
+        assert_eq!(format!("{:#}", expr), expr.pretty(2));
+        assert!(format!("{:#}", expr).starts_with("IF_ELSE\n  AND\n    #2 OR\n"));
+    }
+
+    #[test]
+    fn pretty_empty() {
+        let expr = Expression::<Property>::new();
+        assert_eq!(expr.pretty(2), "<empty>");
+        assert_eq!(format!("{:#}", expr), "<empty>");
+    }
+}
@@ -0,0 +1,848 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use super::{Context, Evaluated, Expression, OpRef, Operation, Operations};
+use crate::condition::Predicate;
+use crate::domain::Property;
+use crate::error::Result;
+use crate::value::Value;
+
+/// Formatting options for `Expression::render` and `Context::render`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// Print property datatypes after their names: `AlbumListeners (Int) == 100`.
+    pub datatypes: bool,
+    /// Print at most this many items of `In` sets and context lists, followed by `…and N more`.
+    pub max_values: Option<usize>,
+    /// Put string values in double quotes, escaping quotes and backslashes inside.
+    pub quote_strings: bool,
+}
+
+/// No datatypes, no truncation, quoted strings.
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            datatypes: false,
+            max_values: None,
+            quote_strings: true,
+        }
+    }
+}
+
+impl RenderOptions {
+    fn property<Pid: Property>(&self, prop: Pid) -> String {
+        if self.datatypes {
+            format!("{} ({})", prop.name(), prop.datatype())
+        } else {
+            prop.name().to_owned()
+        }
+    }
+
+    fn value(&self, value: &Value) -> String {
+        match value {
+            Value::Str(val) => self.string(val),
+            Value::InternedStr(val) => self.string(val),
+            Value::List(_, items) => {
+                let items = items.iter().map(|item| self.value(item)).collect();
+                format!("[{}]", self.list(items))
+            }
+            value => value.to_string(),
+        }
+    }
+
+    fn string(&self, val: &str) -> String {
+        if self.quote_strings {
+            format!("\"{}\"", val.replace('\\', "\\\\").replace('"', "\\\""))
+        } else {
+            val.to_owned()
+        }
+    }
+
+    fn list(&self, mut items: Vec<String>) -> String {
+        let total = items.len();
+        let shown = self.max_values.unwrap_or(total).min(total);
+        items.truncate(shown);
+        if shown < total {
+            items.push(format!("…and {} more", total - shown));
+        }
+        items.join(", ")
+    }
+
+    fn with_unit<Pid: Property>(&self, prop: Pid, rendered: String) -> String {
+        match prop.unit() {
+            Some(unit) => format!("{} {}", rendered, unit),
+            None => rendered,
+        }
+    }
+}
+
+// binding strength of operators, operands binding weaker than required are parenthesized
+const IF_ELSE: u8 = 0;
+const IMPLIES: u8 = 1;
+const OR: u8 = 2;
+const AND: u8 = 3;
+const UNARY: u8 = 4;
+
+impl<Pid: Property> Expression<Pid> {
+    fn render_op(&self, idx: OpRef, options: &RenderOptions, required: u8) -> String {
+        // operands can only be rendered if they precede the operation, which also rules out cycles
+        let operand = |opref: OpRef, required: u8| {
+            if opref < idx {
+                self.render_op(opref, options, required)
+            } else {
+                format!("<badref: {0}/{1}>", opref, idx)
+            }
+        };
+
+        let (rendered, strength) = match &self.ops[idx].0 {
+            Operation::Const(val) => (val.to_string(), UNARY),
+            Operation::Is(cond) => {
+                let rendered = format!(
+                    "{} == {}",
+                    options.property(cond.variable()),
+                    options.value(cond.expected())
+                );
+                (options.with_unit(cond.variable(), rendered), UNARY)
+            }
+            Operation::IsNot(cond) => {
+                let rendered = format!(
+                    "{} != {}",
+                    options.property(cond.variable()),
+                    options.value(cond.expected())
+                );
+                (options.with_unit(cond.variable(), rendered), UNARY)
+            }
+            Operation::ApproxIs(cond) => {
+                let rendered = format!(
+                    "{} ≈ {} ±{}",
+                    options.property(cond.variable()),
+                    cond.expected(),
+                    cond.epsilon()
+                );
+                (options.with_unit(cond.variable(), rendered), UNARY)
+            }
+            Operation::In(cond) => {
+                // sets don't keep any particular order, sort values to make the output stable
+                let mut values = cond.expected().iter().collect::<Vec<_>>();
+                values.sort();
+                let values = values.into_iter().map(|value| options.value(value));
+
+                let rendered = format!(
+                    "{} in [{}]",
+                    options.property(cond.variable()),
+                    options.list(values.collect())
+                );
+                (rendered, UNARY)
+            }
+            Operation::Contains(cond) => {
+                let rendered = format!(
+                    "{} {} {}",
+                    options.property(cond.variable()),
+                    if cond.case_insensitive() {
+                        "contains_ci"
+                    } else {
+                        "contains"
+                    },
+                    options.string(cond.needle())
+                );
+                (rendered, UNARY)
+            }
+            Operation::Quantified(cond) => {
+                let predicate = match cond.predicate() {
+                    Predicate::Is(expected) => format!("== {}", options.value(expected)),
+                    Predicate::In(expected) => {
+                        let values = expected.iter().map(|value| options.value(value));
+                        format!("in [{}]", options.list(values.collect()))
+                    }
+                    Predicate::Contains(needle, case_insensitive) => format!(
+                        "{} {}",
+                        if *case_insensitive {
+                            "contains_ci"
+                        } else {
+                            "contains"
+                        },
+                        options.string(needle)
+                    ),
+                };
+                let rendered = format!(
+                    "{} {}({})",
+                    options.property(cond.variable()),
+                    cond.quantifier(),
+                    predicate
+                );
+                (rendered, UNARY)
+            }
+            &Operation::Not(opref) => (format!("!{}", operand(opref, UNARY)), UNARY),
+            &Operation::Or(lhs, rhs) => {
+                (format!("{} || {}", operand(lhs, OR), operand(rhs, OR)), OR)
+            }
+            &Operation::And(lhs, rhs) => (
+                format!("{} && {}", operand(lhs, AND), operand(rhs, AND)),
+                AND,
+            ),
+            &Operation::Nand(lhs, rhs) => (
+                format!("!({} && {})", operand(lhs, AND), operand(rhs, AND)),
+                UNARY,
+            ),
+            &Operation::Nor(lhs, rhs) => (
+                format!("!({} || {})", operand(lhs, OR), operand(rhs, OR)),
+                UNARY,
+            ),
+            &Operation::Implies(premise, conclusion) => (
+                format!(
+                    "{} -> {}",
+                    operand(premise, OR),
+                    operand(conclusion, IMPLIES)
+                ),
+                IMPLIES,
+            ),
+            &Operation::IfElse(cond, then, otherwise) => (
+                format!(
+                    "if {} then {} else {}",
+                    operand(cond, IMPLIES),
+                    operand(then, IMPLIES),
+                    operand(otherwise, IMPLIES)
+                ),
+                IF_ELSE,
+            ),
+        };
+
+        if strength < required {
+            format!("({})", rendered)
+        } else {
+            rendered
+        }
+    }
+
+    /// Human-oriented form of the expression: property names instead of identifiers,
+    /// parentheses only where precedence requires them, for example
+    /// `AlbumArtist == "Radiohead" && AlbumListeners in [100, 200, …and 57 more]`.
+    ///
+    /// Unlike `Display`, the output isn't meant to be stable or parsed back.
+    pub fn render(&self, options: &RenderOptions) -> String {
+        match self.root() {
+            Some(root) if root < self.ops.len() => self.render_op(root, options, IF_ELSE),
+            Some(root) => format!("<badref: {0}/{1}>", root, self.ops.len()),
+            None => "<empty>".to_owned(),
+        }
+    }
+}
+
+impl<Pid: Property> Expression<Pid> {
+    /// Collects leaf operations that decided the folded result of `idx`: every operand
+    /// if they all had to agree, otherwise the first one that settled the result on its own.
+    fn blame(&self, log: &Operations<Pid>, idx: OpRef, culprits: &mut Vec<OpRef>) {
+        let folded = |opref: OpRef| match log[opref].0 {
+            Operation::Const(val) => Some(val),
+            _ => None,
+        };
+        let result = folded(idx);
+        // an operand equal to `settles` decides the result, which is negated for `Nand` / `Nor`
+        let mut decisive = |operands: &[OpRef], settles: bool, negated: bool| {
+            if result == Some(settles != negated) {
+                if let Some(&opref) = operands
+                    .iter()
+                    .find(|&&opref| folded(opref) == Some(settles))
+                {
+                    self.blame(log, opref, culprits);
+                }
+            } else {
+                for &opref in operands {
+                    self.blame(log, opref, culprits);
+                }
+            }
+        };
+
+        match self.ops[idx].0 {
+            Operation::Not(opref) => self.blame(log, opref, culprits),
+            Operation::And(lhs, rhs) => decisive(&[lhs, rhs], false, false),
+            Operation::Or(lhs, rhs) => decisive(&[lhs, rhs], true, false),
+            Operation::Nand(lhs, rhs) => decisive(&[lhs, rhs], false, true),
+            Operation::Nor(lhs, rhs) => decisive(&[lhs, rhs], true, true),
+            Operation::Implies(premise, conclusion) => match folded(premise) {
+                Some(false) => self.blame(log, premise, culprits),
+                _ if result == Some(true) => self.blame(log, conclusion, culprits),
+                _ => {
+                    self.blame(log, premise, culprits);
+                    self.blame(log, conclusion, culprits);
+                }
+            },
+            Operation::IfElse(cond, then, otherwise) => {
+                let taken = if folded(cond) == Some(true) {
+                    then
+                } else {
+                    otherwise
+                };
+                self.blame(log, cond, culprits);
+                self.blame(log, taken, culprits);
+            }
+            _ if !culprits.contains(&idx) => culprits.push(idx),
+            _ => (),
+        }
+    }
+
+    /// Evaluates the expression and explains the result in a sentence, naming the leaf
+    /// conditions that made it `false`: `failed because AlbumArtist == "Radiohead" was false`.
+    ///
+    /// True results are explained as `all conditions satisfied`, partial ones list
+    /// the properties that are still missing.
+    pub fn explain(&self, context: &Context<Pid>) -> Result<String> {
+        let options = RenderOptions::default();
+        let explanation = match self.eval(context)? {
+            Evaluated::Fully(true, _, _) => "all conditions satisfied".to_owned(),
+            // expressions without conditions are evaluated without a log
+            Evaluated::Fully(false, log, _) if log.is_empty() => {
+                "failed because it's constant false".to_owned()
+            }
+            Evaluated::Fully(false, log, _) => {
+                let mut culprits = Vec::new();
+                if let Some(root) = self.root() {
+                    self.blame(&log, root, &mut culprits);
+                }
+
+                let reasons = culprits
+                    .into_iter()
+                    .map(|idx| match (&self.ops[idx].0, &log[idx].0) {
+                        (Operation::Const(_), _) => "it's constant false".to_owned(),
+                        (_, Operation::Const(val)) => {
+                            format!("{} was {}", self.render_op(idx, &options, IF_ELSE), val)
+                        }
+                        _ => self.render_op(idx, &options, IF_ELSE),
+                    })
+                    .collect::<Vec<_>>();
+                format!("failed because {}", reasons.join(" and "))
+            }
+            Evaluated::Partially(expr) => {
+                let variables = expr.variables();
+                let mut missing = variables
+                    .requested()
+                    .map(|prop| prop.name())
+                    .collect::<Vec<_>>();
+                missing.sort_unstable();
+                format!("undecided, missing values for {}", missing.join(", "))
+            }
+        };
+        Ok(explanation)
+    }
+}
+
+/// Outcome of an operation in an `Explanation`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    True,
+    False,
+    /// Skipped or ignored: the result was decided without it.
+    NotNeeded,
+    /// Depends on values that weren't provided.
+    Unknown,
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Outcome::True => write!(f, "true"),
+            Outcome::False => write!(f, "false"),
+            Outcome::NotNeeded => write!(f, "not needed"),
+            Outcome::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Tree mirroring an evaluated expression, see `Evaluated::explain`.
+/// Operations shared by several parents appear under each of them.
+#[derive(Debug, Clone)]
+pub struct Explanation<Pid: Property> {
+    pub op: OpRef,
+    /// Rendered condition or constant, name of a logical operation.
+    pub description: String,
+    pub outcome: Outcome,
+    /// Property of a leaf condition along with the value it was compared with,
+    /// the value is only set if the condition was evaluated.
+    pub property: Option<Pid>,
+    pub value: Option<Value>,
+    /// Whether the operation decided the result of its parent, and that one of its own:
+    /// an `Or` that turned out `true` only depends on its first `true` operand.
+    pub critical: bool,
+    pub operands: Vec<Explanation<Pid>>,
+}
+
+impl<Pid: Property> Explanation<Pid> {
+    /// Leaf conditions on the critical path that turned out `false`, depth first.
+    pub fn failures(&self) -> impl Iterator<Item = &Explanation<Pid>> {
+        let mut failures = Vec::new();
+        self.collect_failures(&mut failures);
+        failures.into_iter()
+    }
+
+    fn collect_failures<'a>(&'a self, failures: &mut Vec<&'a Explanation<Pid>>) {
+        if !self.critical {
+            return;
+        }
+        if self.property.is_some() && self.outcome == Outcome::False {
+            failures.push(self);
+        }
+        for operand in &self.operands {
+            operand.collect_failures(failures);
+        }
+    }
+
+    fn fmt_indented(&self, f: &mut Formatter, depth: usize) -> FmtResult {
+        write!(
+            f,
+            "{:indent$}{}: {}",
+            "",
+            self.description,
+            self.outcome,
+            indent = depth * 2
+        )?;
+        if let Some(value) = &self.value {
+            write!(f, " (actual {})", RenderOptions::default().value(value))?;
+        }
+        for operand in &self.operands {
+            writeln!(f)?;
+            operand.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+/// One line per operation, operands indented under their parents:
+/// `Property::Int == 1: false (actual 2)`.
+impl<Pid: Property> Display for Explanation<Pid> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        self.fmt_indented(f, 0)
+    }
+}
+
+impl<Pid: Property> Expression<Pid> {
+    /// Explanation of the operation at `idx` given folded `outcomes`,
+    /// `decided` tells whether the parent's result was settled.
+    fn explanation(
+        &self,
+        idx: OpRef,
+        outcomes: &[Option<bool>],
+        context: &Context<Pid>,
+        decided: bool,
+        critical: bool,
+    ) -> Explanation<Pid> {
+        let op = &self.ops[idx].0;
+        let result = outcomes.get(idx).copied().flatten();
+        let outcome = match result {
+            Some(true) => Outcome::True,
+            Some(false) => Outcome::False,
+            None if decided => Outcome::NotNeeded,
+            None => Outcome::Unknown,
+        };
+
+        // operands the result depends on, same as in `blame`
+        let outcome_of = |opref: OpRef| outcomes.get(opref).copied().flatten();
+        let settling = |operands: &[OpRef], settles: bool, negated: bool| match result {
+            Some(result) if result == (settles != negated) => operands
+                .iter()
+                .copied()
+                .find(|&opref| outcome_of(opref) == Some(settles))
+                .into_iter()
+                .collect(),
+            _ => operands.to_vec(),
+        };
+        let decisive: Vec<OpRef> = match *op {
+            Operation::And(lhs, rhs) => settling(&[lhs, rhs], false, false),
+            Operation::Or(lhs, rhs) => settling(&[lhs, rhs], true, false),
+            Operation::Nand(lhs, rhs) => settling(&[lhs, rhs], false, true),
+            Operation::Nor(lhs, rhs) => settling(&[lhs, rhs], true, true),
+            Operation::Implies(premise, conclusion) => match (outcome_of(premise), result) {
+                (Some(false), _) => vec![premise],
+                (_, Some(true)) => vec![conclusion],
+                _ => vec![premise, conclusion],
+            },
+            Operation::IfElse(cond, then, otherwise) => match outcome_of(cond) {
+                Some(true) => vec![cond, then],
+                Some(false) => vec![cond, otherwise],
+                None => vec![cond, then, otherwise],
+            },
+            _ => op.operands(),
+        };
+
+        let operands = op
+            .operands()
+            .into_iter()
+            .map(|opref| {
+                let critical = critical && decisive.contains(&opref);
+                self.explanation(opref, outcomes, context, result.is_some(), critical)
+            })
+            .collect();
+
+        let description = match op {
+            Operation::Not(_) => "not".to_owned(),
+            Operation::Or(_, _) => "or".to_owned(),
+            Operation::And(_, _) => "and".to_owned(),
+            Operation::Nand(_, _) => "nand".to_owned(),
+            Operation::Nor(_, _) => "nor".to_owned(),
+            Operation::Implies(_, _) => "implies".to_owned(),
+            Operation::IfElse(_, _, _) => "if-else".to_owned(),
+            _ => self.render_op(idx, &RenderOptions::default(), IF_ELSE),
+        };
+        let property = op.variable();
+        let value = match result {
+            Some(_) => property.and_then(|prop| context.value(prop)).cloned(),
+            None => None,
+        };
+
+        Explanation {
+            op: idx,
+            description,
+            outcome,
+            property,
+            value,
+            critical,
+            operands,
+        }
+    }
+}
+
+impl<Pid: Property> Evaluated<Pid> {
+    /// Tree explaining the result of `expr` evaluated with `context`: outcome of every
+    /// operation and values compared by leaf conditions, for example to show users
+    /// why a record matched or which conditions failed, see `Explanation::failures`.
+    ///
+    /// `context` has to be the one `self` was evaluated with: `Evaluated` only keeps folded
+    /// results, not the values conditions compared, so they're looked up in `context` again.
+    /// Keeping them in `Evaluated` would cost every evaluation for the sake of explaining a few.
+    pub fn explain(&self, expr: &Expression<Pid>, context: &Context<Pid>) -> Explanation<Pid> {
+        let outcomes = |ops: &Operations<Pid>| -> Vec<Option<bool>> {
+            ops.iter()
+                .map(|(op, _)| match op {
+                    Operation::Const(val) => Some(*val),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let (root, outcomes) = match self {
+            // expressions without conditions are evaluated without a log, fold them again
+            Evaluated::Fully(_, log, _) if log.is_empty() => {
+                let mut folded = vec![None; expr.ops.len()];
+                if let Some(root) = expr.root().filter(|&root| root < expr.ops.len()) {
+                    let _ = expr.fold(root, &[], &mut |_, _| Ok(None), &mut folded);
+                }
+                (
+                    expr.root(),
+                    folded.into_iter().map(Option::flatten).collect(),
+                )
+            }
+            Evaluated::Fully(_, log, _) => (expr.root(), outcomes(log)),
+            Evaluated::Partially(partial) => (partial.root(), outcomes(&partial.ops)),
+        };
+
+        match root {
+            Some(root) if root < expr.ops.len() => {
+                expr.explanation(root, &outcomes, context, false, true)
+            }
+            _ => Explanation {
+                op: 0,
+                description: expr.render(&RenderOptions::default()),
+                outcome: Outcome::Unknown,
+                property: None,
+                value: None,
+                critical: false,
+                operands: Vec::new(),
+            },
+        }
+    }
+}
+
+impl<Pid: Property> Context<Pid> {
+    /// Human-oriented form of the context, `requested: [A, B], provided: [A = 1, B = "foo"]`,
+    /// properties are sorted by name. `max_values` limits both lists.
+    pub fn render(&self, options: &RenderOptions) -> String {
+        let mut requested = self.requested.iter().collect::<Vec<_>>();
+        requested.sort_unstable_by_key(|prop| prop.name());
+        let requested = requested
+            .into_iter()
+            .map(|prop| options.property(*prop))
+            .collect();
+
+        let mut provided = self.provided.iter().collect::<Vec<_>>();
+        provided.sort_unstable_by_key(|(prop, _)| prop.name());
+        let provided = provided
+            .into_iter()
+            .map(|(prop, val)| {
+                let rendered = format!("{} = {}", options.property(*prop), options.value(val));
+                options.with_unit(*prop, rendered)
+            })
+            .collect();
+
+        format!(
+            "requested: [{}], provided: [{}]",
+            options.list(requested),
+            options.list(provided)
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::testproperty::{Measured, Property};
+
+    #[test]
+    fn render_truncated() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Str, "Radio\"head").unwrap();
+        let b = expr.is_in(Property::Int, (1..=60).rev()).unwrap();
+        let _ = expr.and(a, b).unwrap();
+
+        let options = RenderOptions {
+            max_values: Some(3),
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            expr.render(&options),
+            r#"Property::Str == "Radio\"head" && Property::Int in [1, 2, 3, …and 57 more]"#
+        );
+
+        let options = RenderOptions {
+            max_values: Some(0),
+            ..RenderOptions::default()
+        };
+        assert!(expr.render(&options).ends_with("in […and 60 more]"));
+
+        let options = RenderOptions {
+            max_values: Some(60),
+            ..RenderOptions::default()
+        };
+        assert!(expr.render(&options).ends_with(", 59, 60]"));
+    }
+
+    #[test]
+    fn render_datatypes_and_quotes() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Str, "foo").unwrap();
+        let b = expr.contains_ci(Property::Str, "o").unwrap();
+        let c = expr.is_not(Property::Bool, true).unwrap();
+        let _ = expr.if_else(a, b, c).unwrap();
+
+        assert_eq!(
+            expr.render(&RenderOptions::default()),
+            r#"if Property::Str == "foo" then Property::Str contains_ci "o" else Property::Bool != true"#
+        );
+        let options = RenderOptions {
+            datatypes: true,
+            quote_strings: false,
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            expr.render(&options),
+            "if Property::Str (Str) == foo then Property::Str (Str) contains_ci o \
+             else Property::Bool (Bool) != true"
+        );
+    }
+
+    #[test]
+    fn render_precedence() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 1).unwrap();
+        let b = expr.is(Property::Bool, true).unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let not = expr.not(a_or_b).unwrap();
+        let not_a = expr.not(a).unwrap();
+        let lhs = expr.and(not, a_or_b).unwrap();
+        let rhs = expr.or(a_and_b, not_a).unwrap();
+        let implies = expr.implies(lhs, rhs).unwrap();
+        let t = expr.constant(true).unwrap();
+        let if_else = expr.if_else(implies, t, b).unwrap();
+        let _ = expr.and(if_else, a).unwrap();
+
+        assert_eq!(
+            expr.render(&RenderOptions::default()),
+            "(if !(Property::Int == 1 || Property::Bool == true) \
+             && (Property::Int == 1 || Property::Bool == true) \
+             -> Property::Int == 1 && Property::Bool == true || !Property::Int == 1 \
+             then true else Property::Bool == true) && Property::Int == 1"
+        );
+        assert_eq!(
+            Expression::<Property>::new().render(&RenderOptions::default()),
+            "<empty>"
+        );
+    }
+
+    #[test]
+    fn explain_and() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Str, "foo").unwrap();
+        let b = expr.is_in(Property::Int, vec![1, 2]).unwrap();
+        let _ = expr.and(a, b).unwrap();
+
+        let context = Context::from_values(vec![
+            (Property::Str, Value::from("foo")),
+            (Property::Int, Value::Int(3)),
+        ])
+        .unwrap();
+        assert_eq!(
+            expr.explain(&context).unwrap(),
+            "failed because Property::Int in [1, 2] was false"
+        );
+
+        let context = Context::from_values(vec![
+            (Property::Str, Value::from("foo")),
+            (Property::Int, Value::Int(2)),
+        ])
+        .unwrap();
+        assert_eq!(expr.explain(&context).unwrap(), "all conditions satisfied");
+
+        let context = Context::from_values(vec![(Property::Str, Value::from("foo"))]).unwrap();
+        assert_eq!(
+            expr.explain(&context).unwrap(),
+            "undecided, missing values for Property::Int"
+        );
+    }
+
+    #[test]
+    fn explain_minimal() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Str, "foo").unwrap();
+        let b = expr.is(Property::Int, 1).unwrap();
+        let c = expr.is(Property::Bool, true).unwrap();
+        let not_c = expr.not(c).unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let _ = expr.and(a_or_b, not_c).unwrap();
+
+        let context = Context::from_values(vec![
+            (Property::Str, Value::from("bar")),
+            (Property::Int, Value::Int(2)),
+            (Property::Bool, Value::Bool(true)),
+        ])
+        .unwrap();
+        assert_eq!(
+            expr.explain(&context).unwrap(),
+            r#"failed because Property::Str == "foo" was false and Property::Int == 1 was false"#
+        );
+
+        let context = Context::from_values(vec![
+            (Property::Str, Value::from("foo")),
+            (Property::Int, Value::Int(2)),
+            (Property::Bool, Value::Bool(true)),
+        ])
+        .unwrap();
+        assert_eq!(
+            expr.explain(&context).unwrap(),
+            "failed because Property::Bool == true was true"
+        );
+    }
+
+    #[test]
+    fn explanation_short_circuit() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Str, "Radiohead").unwrap();
+        let b = expr.is_in(Property::Int, vec![100, 200]).unwrap();
+        let c = expr.is(Property::Bool, true).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let _ = expr.or(a_and_b, c).unwrap();
+
+        // `Or` is decided by its right operand, the failed left one isn't on the critical path
+        let context = Context::from_values(vec![
+            (Property::Str, Value::from("Radiohead")),
+            (Property::Int, Value::Int(7)),
+            (Property::Bool, Value::Bool(true)),
+        ])
+        .unwrap();
+        let explanation = expr.eval(&context).unwrap().explain(&expr, &context);
+        assert_eq!(explanation.outcome, Outcome::True);
+        assert_eq!(explanation.failures().count(), 0);
+        assert_eq!(
+            explanation.to_string(),
+            r#"or: true
+  and: false
+    Property::Str == "Radiohead": true (actual "Radiohead")
+    Property::Int in [100, 200]: false (actual 7)
+  Property::Bool == true: true (actual true)"#
+        );
+
+        // both operands failed, so both are to blame
+        let context = Context::from_values(vec![
+            (Property::Str, Value::from("Radiohead")),
+            (Property::Int, Value::Int(7)),
+            (Property::Bool, Value::Bool(false)),
+        ])
+        .unwrap();
+        let explanation = expr.eval(&context).unwrap().explain(&expr, &context);
+        let failures = explanation
+            .failures()
+            .map(|node| node.op)
+            .collect::<Vec<_>>();
+        assert_eq!(failures, vec![b, c]);
+        assert_eq!(
+            explanation.failures().next().unwrap().value,
+            Some(Value::Int(7))
+        );
+
+        // the right operand isn't needed once the left one holds
+        let context = Context::from_values(vec![
+            (Property::Str, Value::from("Radiohead")),
+            (Property::Int, Value::Int(100)),
+            (Property::Bool, Value::Bool(false)),
+        ])
+        .unwrap();
+        let explanation = expr.eval(&context).unwrap().explain(&expr, &context);
+        let skipped = &explanation.operands[1];
+        assert_eq!((skipped.op, skipped.outcome), (c, Outcome::NotNeeded));
+        assert_eq!((skipped.value.as_ref(), skipped.critical), (None, false));
+        assert!(explanation.operands[0].critical);
+    }
+
+    #[test]
+    fn explanation_partial_and_constant() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Str, "foo").unwrap();
+        let b = expr.is(Property::Int, 1).unwrap();
+        let _ = expr.and(a, b).unwrap();
+
+        let context = Context::from_values(vec![(Property::Str, Value::from("foo"))]).unwrap();
+        let explanation = expr.eval(&context).unwrap().explain(&expr, &context);
+        assert_eq!(
+            explanation.to_string(),
+            r#"and: unknown
+  Property::Str == "foo": true (actual "foo")
+  Property::Int == 1: unknown"#
+        );
+
+        let mut expr = Expression::<Property>::new();
+        let t = expr.constant(true).unwrap();
+        let not_t = expr.not(t).unwrap();
+        let context = Context::empty();
+        let explanation = expr.eval(&context).unwrap().explain(&expr, &context);
+        assert_eq!(explanation.op, not_t);
+        assert_eq!(explanation.to_string(), "not: false\n  true: true");
+        assert_eq!(explanation.failures().count(), 0);
+    }
+
+    #[test]
+    fn render_context() {
+        let context = Context::from_values(vec![
+            (Property::Str, Value::Str("foo".to_owned())),
+            (Property::Int, Value::Int(42)),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            context.render(&RenderOptions::default()),
+            r#"requested: [Property::Int, Property::Str], provided: [Property::Int = 42, Property::Str = "foo"]"#
+        );
+        let options = RenderOptions {
+            datatypes: true,
+            max_values: Some(1),
+            quote_strings: true,
+        };
+        assert_eq!(
+            context.render(&options),
+            "requested: [Property::Int (Int), …and 1 more], provided: [Property::Int (Int) = 42, …and 1 more]"
+        );
+
+        let context = Context::from_values(vec![(Measured::Listeners, Value::Int(10))]).unwrap();
+        assert_eq!(
+            context.render(&RenderOptions::default()),
+            "requested: [Measured::Listeners], provided: [Measured::Listeners = 10 listeners]"
+        );
+    }
+}
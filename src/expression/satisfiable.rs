@@ -0,0 +1,466 @@
+use std::collections::{BTreeSet, HashMap};
+
+use super::{Context, Expression, Operation};
+use crate::condition::Predicate;
+use crate::domain::Property;
+use crate::error::Result;
+use crate::value::{Datatype, Value};
+
+/// Assignments of candidate values tried before giving up with `Satisfiability::Unknown`.
+const MAX_ASSIGNMENTS: usize = 1 << 16;
+
+#[derive(Debug)]
+pub enum Satisfiability<Pid: Property> {
+    /// Values of every property used by the expression that make it `true`.
+    Satisfiable(Context<Pid>),
+    Unsatisfiable,
+    /// Value space of some property can't be bounded, or is too large to search.
+    Unknown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Classification {
+    /// `true` for any values.
+    Tautology,
+    /// `false` for any values.
+    Contradiction,
+    /// `true` for some values and `false` for others.
+    Contingent,
+    /// Value space of some property can't be bounded, or is too large to search.
+    Unknown,
+}
+
+#[derive(Debug)]
+pub enum Equivalence<Pid: Property> {
+    Equivalent,
+    /// Values of every property used by either expression for which their results differ.
+    NotEquivalent(Context<Pid>),
+    /// Value space of some property can't be bounded, or is too large to search.
+    Unknown,
+}
+
+/// Values worth trying for a property, and whether they cover every case its conditions tell apart.
+struct Candidates {
+    values: BTreeSet<Value>,
+    bounded: bool,
+}
+
+/// Value different from every one in `values`, which conditions on a datatype
+/// compared by equality treat the same as any other value missing from them.
+fn sentinel(datatype: Datatype, values: &BTreeSet<Value>) -> Option<Value> {
+    let fresh = |make: &dyn Fn(usize) -> Value| {
+        (0..=values.len())
+            .map(make)
+            .find(|value| !values.contains(value))
+    };
+    match datatype {
+        Datatype::Int => fresh(&|idx| Value::Int(idx as i64)),
+        Datatype::UInt => fresh(&|idx| Value::UInt(idx as u64)),
+        Datatype::Float => fresh(&|idx| Value::Float(idx as f64)),
+        Datatype::Str => fresh(&|idx| Value::Str("~".repeat(idx))),
+        // lists aren't bounded anyway, one more candidate is enough
+        Datatype::List(element) => fresh(&|_| Value::List(element, Vec::new())),
+        Datatype::Bool | Datatype::Param => None,
+    }
+}
+
+impl<Pid: Property> Expression<Pid> {
+    /// Checks whether the expression can ever be `true`, by evaluating it against every
+    /// combination of candidate property values: ones used by conditions, plus one that's
+    /// none of them, which is enough to cover `Is`, `IsNot` and `In` conditions exactly.
+    ///
+    /// `Contains` and quantified conditions and list properties are only checked against values
+    /// derived from their needles and predicates, so the result is `Unknown` rather than
+    /// `Unsatisfiable` if none of those work out. It's `Unknown` as well for more than
+    /// 65536 combinations. The witness context of `Satisfiable` evaluates the expression to `true`.
+    pub fn is_satisfiable(&self) -> Result<Satisfiability<Pid>> {
+        self.satisfiable_as(true)
+    }
+
+    /// Tells rules that are always `true` or always `false` from ones that depend on values,
+    /// by checking whether the expression and its negation are satisfiable, see `is_satisfiable`.
+    pub fn classify(&self) -> Result<Classification> {
+        let classification = match (self.satisfiable_as(true)?, self.satisfiable_as(false)?) {
+            (Satisfiability::Satisfiable(_), Satisfiability::Satisfiable(_)) => {
+                Classification::Contingent
+            }
+            (Satisfiability::Satisfiable(_), Satisfiability::Unsatisfiable) => {
+                Classification::Tautology
+            }
+            (Satisfiability::Unsatisfiable, Satisfiability::Satisfiable(_)) => {
+                Classification::Contradiction
+            }
+            _ => Classification::Unknown,
+        };
+        Ok(classification)
+    }
+
+    /// Checks whether both expressions give the same result for any values, by searching
+    /// for values they disagree on the same way as `is_satisfiable` does.
+    pub fn equivalent_to(&self, other: &Expression<Pid>) -> Result<Equivalence<Pid>> {
+        let mut differ = Expression::new();
+        let lhs = differ.append(self)?;
+        let rhs = differ.append(other)?;
+        let not_rhs = differ.not(rhs)?;
+        let _ = differ.if_else(lhs, not_rhs, rhs)?;
+
+        let equivalence = match differ.is_satisfiable()? {
+            Satisfiability::Satisfiable(context) => Equivalence::NotEquivalent(context),
+            Satisfiability::Unsatisfiable => Equivalence::Equivalent,
+            Satisfiability::Unknown => Equivalence::Unknown,
+        };
+        Ok(equivalence)
+    }
+
+    /// Candidate values of every property used by `reachable` conditions, sorted by name,
+    /// and whether they cover every case those conditions tell apart, see `is_satisfiable`.
+    pub(super) fn candidates(&self, reachable: &[bool]) -> (Vec<(Pid, BTreeSet<Value>)>, bool) {
+        let mut candidates: HashMap<Pid, Candidates> = HashMap::new();
+        for ((op, _), _) in self
+            .ops
+            .iter()
+            .zip(reachable)
+            .filter(|(_, &reachable)| reachable)
+        {
+            let variable = match op.variable() {
+                Some(variable) => variable,
+                None => continue,
+            };
+            let entry = candidates.entry(variable).or_insert_with(|| Candidates {
+                values: BTreeSet::new(),
+                bounded: !matches!(variable.datatype(), Datatype::List(_)),
+            });
+            match op {
+                Operation::Is(cond) => {
+                    entry.values.insert(cond.expected().clone());
+                }
+                Operation::IsNot(cond) => {
+                    entry.values.insert(cond.expected().clone());
+                }
+                // values between the band edges aren't tried, so misses prove nothing
+                Operation::ApproxIs(cond) => {
+                    entry.bounded = false;
+                    entry.values.insert(Value::Float(cond.expected()));
+                    if let Some((low, high)) = cond.bounds() {
+                        entry
+                            .values
+                            .extend(vec![Value::Float(low), Value::Float(high)]);
+                    }
+                }
+                Operation::In(cond) => entry.values.extend(cond.expected().iter().cloned()),
+                Operation::Contains(cond) => {
+                    entry.bounded = false;
+                    entry.values.insert(Value::Str(cond.needle().to_owned()));
+                }
+                Operation::Quantified(cond) => {
+                    entry.bounded = false;
+                    let element = match variable.datatype() {
+                        Datatype::List(element) => element,
+                        _ => continue,
+                    };
+                    let singletons = match cond.predicate() {
+                        Predicate::Is(value) => vec![value.clone()],
+                        Predicate::In(values) => values.iter().cloned().collect(),
+                        Predicate::Contains(needle, _) => vec![Value::Str(needle.clone())],
+                    };
+                    entry.values.extend(
+                        singletons
+                            .into_iter()
+                            .map(|value| Value::List(element, vec![value])),
+                    );
+                }
+                _ => (),
+            }
+        }
+
+        // sorted by name to make the search, and so the witness, deterministic
+        let mut properties = candidates.into_iter().collect::<Vec<_>>();
+        properties.sort_by_key(|(prop, _)| prop.name());
+
+        let bounded = properties.iter().all(|(_, candidates)| candidates.bounded);
+        let properties = properties
+            .into_iter()
+            .map(|(prop, candidates)| {
+                let mut candidates = candidates.values;
+                match prop.datatype() {
+                    Datatype::Bool => {
+                        candidates.extend(vec![Value::Bool(false), Value::Bool(true)])
+                    }
+                    datatype => candidates.extend(sentinel(datatype, &candidates)),
+                }
+                (prop, candidates)
+            })
+            .collect();
+        (properties, bounded)
+    }
+
+    /// Same as `is_satisfiable`, for the expression evaluating to `wanted`.
+    fn satisfiable_as(&self, wanted: bool) -> Result<Satisfiability<Pid>> {
+        let root = self.connected()?;
+        let reachable = self.reachable(root);
+        self.checked(&reachable)?;
+
+        let (properties, bounded) = self.candidates(&reachable);
+        let assignments = properties.iter().fold(1usize, |total, (_, values)| {
+            total.saturating_mul(values.len())
+        });
+        let values = properties
+            .iter()
+            .map(|(_, values)| values.iter().cloned().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        if assignments > MAX_ASSIGNMENTS {
+            return Ok(Satisfiability::Unknown);
+        }
+
+        let index = properties
+            .iter()
+            .enumerate()
+            .map(|(idx, (prop, _))| (*prop, idx))
+            .collect::<HashMap<_, _>>();
+        let mut choice = vec![0; properties.len()];
+        let mut folded = vec![None; self.ops.len()];
+        loop {
+            for result in folded.iter_mut() {
+                *result = None;
+            }
+            let mut condition = |idx, variable| {
+                let pos = index[&variable];
+                self.test(idx, &values[pos][choice[pos]]).map(Some)
+            };
+            if self.fold(root, &[], &mut condition, &mut folded)? == Some(wanted) {
+                let witness = properties
+                    .iter()
+                    .zip(&choice)
+                    .enumerate()
+                    .map(|(pos, ((prop, _), &choice))| (*prop, values[pos][choice].clone()));
+                return Ok(Satisfiability::Satisfiable(Context::from_values(witness)?));
+            }
+
+            // next assignment, like an odometer
+            let mut pos = 0;
+            while pos < choice.len() {
+                choice[pos] += 1;
+                if choice[pos] < values[pos].len() {
+                    break;
+                }
+                choice[pos] = 0;
+                pos += 1;
+            }
+            if pos == choice.len() {
+                break;
+            }
+        }
+
+        if bounded {
+            Ok(Satisfiability::Unsatisfiable)
+        } else {
+            Ok(Satisfiability::Unknown)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::domain;
+    use crate::expression::Evaluated;
+    use crate::testproperty::{Listed, Property};
+
+    fn witness<Pid: domain::Property>(expr: &Expression<Pid>) -> Context<Pid> {
+        match expr.is_satisfiable().unwrap() {
+            Satisfiability::Satisfiable(context) => {
+                let result = expr.eval(&context).unwrap();
+                assert!(matches!(result, Evaluated::Fully(true, _, _)), "{}", result);
+                context
+            }
+            result => panic!("{}: unexpected result {:?}", expr, result),
+        }
+    }
+
+    #[test]
+    fn satisfiable_witness() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is_not(Property::Str, "a").unwrap();
+        let b = expr.is_in(Property::Int, vec![1, 2]).unwrap();
+        let c = expr.is(Property::Int, 1).unwrap();
+        let d = expr.is(Property::Bool, true).unwrap();
+        let not_c = expr.not(c).unwrap();
+        let b_and_not_c = expr.and(b, not_c).unwrap();
+        let a_and_b = expr.and(a, b_and_not_c).unwrap();
+        let _ = expr.implies(d, a_and_b).unwrap();
+        witness(&expr);
+
+        // only negative constraints, satisfied by a value none of them mention
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is_not(Property::Str, "").unwrap();
+        let b = expr.is_not(Property::Str, "~").unwrap();
+        let c = expr.is_not(Property::UInt, 0u64).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let _ = expr.and(a_and_b, c).unwrap();
+        let context = witness(&expr);
+        assert_eq!(context.value(Property::Str), Some(&Value::from("~~")));
+
+        let mut expr = Expression::<Property>::new();
+        let a = expr.contains_ci(Property::Str, "Head").unwrap();
+        let b = expr.is_not(Property::Str, "radiohead").unwrap();
+        let _ = expr.and(a, b).unwrap();
+        witness(&expr);
+
+        let mut expr = Expression::<Listed>::new();
+        let tags = Predicate::Contains("live".to_owned(), false);
+        let _ = expr.any_element(Listed::Tags, tags).unwrap();
+        witness(&expr);
+
+        let mut expr = Expression::<Property>::new();
+        let _ = expr.constant(true).unwrap();
+        witness(&expr);
+    }
+
+    #[test]
+    fn satisfiable_contradiction() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 1).unwrap();
+        let b = expr.is(Property::Int, 2).unwrap();
+        let _ = expr.and(a, b).unwrap();
+        assert!(matches!(
+            expr.is_satisfiable(),
+            Ok(Satisfiability::Unsatisfiable)
+        ));
+
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Bool, true).unwrap();
+        let b = expr.is_in(Property::Str, vec!["a", "b"]).unwrap();
+        let c = expr.is_not(Property::Str, "a").unwrap();
+        let d = expr.is_not(Property::Str, "b").unwrap();
+        let not_a = expr.not(a).unwrap();
+        let c_and_d = expr.and(c, d).unwrap();
+        let b_and_c_and_d = expr.and(b, c_and_d).unwrap();
+        let _ = expr.or(not_a, b_and_c_and_d).unwrap();
+        let _ = expr.and_with(a).unwrap();
+        assert!(matches!(
+            expr.is_satisfiable(),
+            Ok(Satisfiability::Unsatisfiable)
+        ));
+
+        let mut expr = Expression::<Property>::new();
+        let _ = expr.constant(false).unwrap();
+        assert!(matches!(
+            expr.is_satisfiable(),
+            Ok(Satisfiability::Unsatisfiable)
+        ));
+    }
+
+    #[test]
+    fn classify() {
+        // both operands reference the same condition
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 1).unwrap();
+        let not_a = expr.not(a).unwrap();
+        let _ = expr.or(a, not_a).unwrap();
+        assert_eq!(expr.classify().unwrap(), Classification::Tautology);
+
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 1).unwrap();
+        let b = expr.is(Property::Int, 2).unwrap();
+        let _ = expr.and(a, b).unwrap();
+        assert_eq!(expr.classify().unwrap(), Classification::Contradiction);
+
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is_in(Property::Int, vec![1, 2]).unwrap();
+        let b = expr.is(Property::Bool, true).unwrap();
+        let _ = expr.implies(b, a).unwrap();
+        assert_eq!(expr.classify().unwrap(), Classification::Contingent);
+
+        let mut expr = Expression::<Property>::new();
+        let _ = expr.constant(true).unwrap();
+        assert_eq!(expr.classify().unwrap(), Classification::Tautology);
+
+        // "xy" would make it true, but isn't a candidate
+        let mut expr = Expression::<Property>::new();
+        let a = expr.contains(Property::Str, "x").unwrap();
+        let b = expr.contains(Property::Str, "y").unwrap();
+        let _ = expr.and(a, b).unwrap();
+        assert_eq!(expr.classify().unwrap(), Classification::Unknown);
+    }
+
+    #[test]
+    fn equivalent_de_morgan() {
+        let mut lhs = Expression::<Property>::new();
+        let a = lhs.is(Property::Int, 1).unwrap();
+        let b = lhs.is_not(Property::Str, "x").unwrap();
+        let a_and_b = lhs.and(a, b).unwrap();
+        let _ = lhs.not(a_and_b).unwrap();
+
+        let mut rhs = Expression::<Property>::new();
+        let b = rhs.is(Property::Str, "x").unwrap();
+        let a = rhs.is_in(Property::Int, vec![1]).unwrap();
+        let not_a = rhs.not(a).unwrap();
+        let _ = rhs.or(b, not_a).unwrap();
+
+        assert!(matches!(
+            lhs.equivalent_to(&rhs),
+            Ok(Equivalence::Equivalent)
+        ));
+        assert!(matches!(
+            rhs.equivalent_to(&lhs),
+            Ok(Equivalence::Equivalent)
+        ));
+        assert!(matches!(
+            lhs.equivalent_to(&lhs),
+            Ok(Equivalence::Equivalent)
+        ));
+    }
+
+    #[test]
+    fn equivalent_counterexample() {
+        let build = |disjunction: bool| {
+            let mut expr = Expression::<Property>::new();
+            let a = expr.is(Property::Int, 1).unwrap();
+            let b = expr.is(Property::Bool, true).unwrap();
+            if disjunction {
+                let _ = expr.or(a, b).unwrap();
+            } else {
+                let _ = expr.and(a, b).unwrap();
+            }
+            expr
+        };
+        let (lhs, rhs) = (build(true), build(false));
+
+        let context = match lhs.equivalent_to(&rhs).unwrap() {
+            Equivalence::NotEquivalent(context) => context,
+            result => panic!("unexpected result {:?}", result),
+        };
+        match (lhs.eval(&context).unwrap(), rhs.eval(&context).unwrap()) {
+            (Evaluated::Fully(lval, _, _), Evaluated::Fully(rval, _, _)) => assert_ne!(lval, rval),
+            results => panic!("{}: unexpected results {:?}", context, results),
+        }
+
+        // no counterexample among candidates, but the search can't cover every string
+        let mut unbounded = Expression::<Property>::new();
+        let _ = unbounded.contains(Property::Str, "x").unwrap();
+        assert!(matches!(
+            unbounded.equivalent_to(&unbounded),
+            Ok(Equivalence::Unknown)
+        ));
+    }
+
+    #[test]
+    fn satisfiable_unknown() {
+        // no candidate contains both needles, though "xy" would
+        let mut expr = Expression::<Property>::new();
+        let a = expr.contains(Property::Str, "x").unwrap();
+        let b = expr.contains(Property::Str, "y").unwrap();
+        let _ = expr.and(a, b).unwrap();
+        assert!(matches!(expr.is_satisfiable(), Ok(Satisfiability::Unknown)));
+
+        // too many combinations to search
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is_in(Property::Int, 0..300).unwrap();
+        let b = expr.is_in(Property::UInt, 0u64..300).unwrap();
+        let _ = expr.and(a, b).unwrap();
+        assert!(matches!(expr.is_satisfiable(), Ok(Satisfiability::Unknown)));
+
+        assert!(Expression::<Property>::new().is_satisfiable().is_err());
+    }
+}
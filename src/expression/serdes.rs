@@ -0,0 +1,658 @@
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::result::Result as StdResult;
+
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value as Json};
+use strum::ParseError;
+
+use super::parse::MAX_PARSE_DEPTH;
+use super::{Expression, OpRef, Operation};
+use crate::condition::{ApproxIs, Contains, In, Is, IsNot, Predicate, Quantified, Quantifier};
+use crate::domain::Property;
+use crate::error::{Error, Result};
+use crate::value::{Datatype, Value};
+
+/// Maximum number of operations accepted by `Deserialize for Expression`,
+/// use `ExpressionSeed` to pick a different one.
+pub const MAX_OPS: usize = 10_000;
+
+/// Maximum depth accepted by `Deserialize for Expression`, same as for the text parsers:
+/// formatting and exporters walk operations recursively. Use `ExpressionSeed` to pick another one.
+pub const MAX_DEPTH: usize = MAX_PARSE_DEPTH;
+
+/// Serialized as a map tagged with `"op"`, operands are indices of other operations:
+/// `{"op": "is", "prop": "AlbumArtist", "value": {"type": "Str", "value": "Radiohead"}}`,
+/// `{"op": "and", "lhs": 0, "rhs": 1}`.
+impl<Pid: Property> Serialize for Operation<Pid> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> StdResult<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        match self {
+            Operation::Const(val) => {
+                map.serialize_entry("op", "const")?;
+                map.serialize_entry("value", val)?;
+            }
+            Operation::Is(cond) => {
+                map.serialize_entry("op", "is")?;
+                cond.serialize_fields(&mut map)?;
+            }
+            Operation::IsNot(cond) => {
+                map.serialize_entry("op", "is_not")?;
+                cond.serialize_fields(&mut map)?;
+            }
+            Operation::ApproxIs(cond) => {
+                map.serialize_entry("op", "approx_is")?;
+                cond.serialize_fields(&mut map)?;
+            }
+            Operation::In(cond) => {
+                map.serialize_entry("op", "in")?;
+                cond.serialize_fields(&mut map)?;
+            }
+            Operation::Contains(cond) => {
+                map.serialize_entry("op", "contains")?;
+                cond.serialize_fields(&mut map)?;
+            }
+            Operation::Quantified(cond) => {
+                map.serialize_entry("op", "quantified")?;
+                cond.serialize_fields(&mut map)?;
+            }
+            Operation::Not(operand) => {
+                map.serialize_entry("op", "not")?;
+                map.serialize_entry("operand", operand)?;
+            }
+            Operation::Or(lhs, rhs) => {
+                map.serialize_entry("op", "or")?;
+                map.serialize_entry("lhs", lhs)?;
+                map.serialize_entry("rhs", rhs)?;
+            }
+            Operation::And(lhs, rhs) => {
+                map.serialize_entry("op", "and")?;
+                map.serialize_entry("lhs", lhs)?;
+                map.serialize_entry("rhs", rhs)?;
+            }
+            Operation::Nand(lhs, rhs) => {
+                map.serialize_entry("op", "nand")?;
+                map.serialize_entry("lhs", lhs)?;
+                map.serialize_entry("rhs", rhs)?;
+            }
+            Operation::Nor(lhs, rhs) => {
+                map.serialize_entry("op", "nor")?;
+                map.serialize_entry("lhs", lhs)?;
+                map.serialize_entry("rhs", rhs)?;
+            }
+            Operation::Implies(premise, conclusion) => {
+                map.serialize_entry("op", "implies")?;
+                map.serialize_entry("premise", premise)?;
+                map.serialize_entry("conclusion", conclusion)?;
+            }
+            Operation::IfElse(cond, then, otherwise) => {
+                map.serialize_entry("op", "if_else")?;
+                map.serialize_entry("cond", cond)?;
+                map.serialize_entry("then", then)?;
+                map.serialize_entry("otherwise", otherwise)?;
+            }
+        }
+        map.end()
+    }
+}
+
+/// Serialized as `{"root": 2, "ops": [...]}`, where `root` is `null` unless set explicitly.
+///
+/// Reference counts and limits are not serialized: the former can be recomputed from operations,
+/// the latter are a property of the builder rather than the expression.
+impl<Pid: Property> Serialize for Expression<Pid> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> StdResult<S::Ok, S::Error> {
+        let ops = self.ops.iter().map(|(op, _)| op).collect::<Vec<_>>();
+
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("root", &self.root)?;
+        map.serialize_entry("ops", &ops)?;
+        map.end()
+    }
+}
+
+fn malformed<T: Display>(what: T) -> Error {
+    Error::ExpressionMalformed(what.to_string())
+}
+
+fn field<'a>(op: &'a Map<String, Json>, name: &str) -> Result<&'a Json> {
+    op.get(name)
+        .ok_or_else(|| malformed(format!("missing field '{}'", name)))
+}
+
+fn opref(op: &Map<String, Json>, name: &str) -> Result<OpRef> {
+    field(op, name)?
+        .as_u64()
+        .and_then(|opref| OpRef::try_from(opref).ok())
+        .ok_or_else(|| malformed(format!("field '{}' is not an operation reference", name)))
+}
+
+fn property<Pid: Property>(op: &Map<String, Json>) -> Result<Pid> {
+    match field(op, "prop")? {
+        Json::String(name) => Pid::from_str(name)
+            .ok()
+            .ok_or(Error::IdentifierNotFound(ParseError::VariantNotFound)),
+        _ => Err(malformed("field 'prop' is not a string")),
+    }
+}
+
+fn scalar(name: &str) -> Option<Datatype> {
+    match name {
+        "Bool" => Some(Datatype::Bool),
+        "Int" => Some(Datatype::Int),
+        "UInt" => Some(Datatype::UInt),
+        "Float" => Some(Datatype::Float),
+        "Str" => Some(Datatype::Str),
+        _ => None,
+    }
+}
+
+fn value(json: &Json) -> Result<Value> {
+    let parsed = match (json.get("type").and_then(Json::as_str), json.get("value")) {
+        (Some("Bool"), Some(Json::Bool(val))) => Some(Value::Bool(*val)),
+        (Some("Int"), Some(Json::Number(val))) => val.as_i64().map(Value::Int),
+        (Some("UInt"), Some(Json::Number(val))) => val.as_u64().map(Value::UInt),
+        (Some("Float"), Some(Json::Number(val))) => val.as_f64().map(Value::Float),
+        (Some("Str"), Some(Json::String(val))) => Some(Value::Str(val.clone())),
+        (Some("Param"), Some(Json::String(name))) => Some(Value::Param(name.clone())),
+        (Some(datatype), Some(Json::Array(items)))
+            if datatype.starts_with("List<") && datatype.ends_with('>') =>
+        {
+            let element = &datatype[5..datatype.len() - 1];
+            items
+                .iter()
+                .map(|item| value(&json!({ "type": element, "value": item })).ok())
+                .collect::<Option<Vec<_>>>()
+                .and_then(|items| Value::list(scalar(element)?, items))
+        }
+        _ => None,
+    };
+    parsed.ok_or_else(|| malformed(format!("invalid value {}", json)))
+}
+
+fn values<C: FromIterator<Value>>(op: &Map<String, Json>) -> Result<C> {
+    match field(op, "values")? {
+        Json::Array(values) => values.iter().map(value).collect(),
+        _ => Err(malformed("field 'values' is not an array")),
+    }
+}
+
+fn number(op: &Map<String, Json>, name: &str) -> Result<f64> {
+    field(op, name)?
+        .as_f64()
+        .ok_or_else(|| malformed(format!("field '{}' is not a number", name)))
+}
+
+fn needle(op: &Map<String, Json>) -> Result<(String, bool)> {
+    let needle = field(op, "needle")?
+        .as_str()
+        .ok_or_else(|| malformed("field 'needle' is not a string"))?;
+    let case_insensitive = field(op, "case_insensitive")?
+        .as_bool()
+        .ok_or_else(|| malformed("field 'case_insensitive' is not a bool"))?;
+    Ok((needle.to_owned(), case_insensitive))
+}
+
+/// Builds a single operation, conditions are validated against their properties.
+fn operation<Pid: Property>(op: &Map<String, Json>) -> Result<Operation<Pid>> {
+    let tag = field(op, "op")?
+        .as_str()
+        .ok_or_else(|| malformed("field 'op' is not a string"))?;
+
+    let operation = match tag {
+        "const" => Operation::Const(
+            field(op, "value")?
+                .as_bool()
+                .ok_or_else(|| malformed("field 'value' is not a bool"))?,
+        ),
+        "is" => Operation::Is(Is::new(property(op)?, value(field(op, "value")?)?)?),
+        "is_not" => Operation::IsNot(IsNot::new(property(op)?, value(field(op, "value")?)?)?),
+        "approx_is" => Operation::ApproxIs(ApproxIs::new(
+            property(op)?,
+            number(op, "value")?,
+            number(op, "epsilon")?,
+        )?),
+        "in" => Operation::In(In::new(property(op)?, values(op)?)?),
+        "contains" => {
+            let (needle, case_insensitive) = needle(op)?;
+            Operation::Contains(Contains::new(property(op)?, needle, case_insensitive)?)
+        }
+        "quantified" => {
+            let quantifier = match field(op, "quantifier")?.as_str() {
+                Some("any") => Quantifier::Any,
+                Some("all") => Quantifier::All,
+                _ => return Err(malformed("field 'quantifier' is not 'any' or 'all'")),
+            };
+            // predicates are told apart by their fields, same as scalar conditions
+            let predicate = if op.contains_key("value") {
+                Predicate::Is(value(field(op, "value")?)?)
+            } else if op.contains_key("values") {
+                Predicate::In(values(op)?)
+            } else {
+                let (needle, case_insensitive) = needle(op)?;
+                Predicate::Contains(needle, case_insensitive)
+            };
+            Operation::Quantified(Quantified::new(property(op)?, quantifier, predicate)?)
+        }
+        "not" => Operation::Not(opref(op, "operand")?),
+        "or" => Operation::Or(opref(op, "lhs")?, opref(op, "rhs")?),
+        "and" => Operation::And(opref(op, "lhs")?, opref(op, "rhs")?),
+        "nand" => Operation::Nand(opref(op, "lhs")?, opref(op, "rhs")?),
+        "nor" => Operation::Nor(opref(op, "lhs")?, opref(op, "rhs")?),
+        "implies" => Operation::Implies(opref(op, "premise")?, opref(op, "conclusion")?),
+        "if_else" => Operation::IfElse(
+            opref(op, "cond")?,
+            opref(op, "then")?,
+            opref(op, "otherwise")?,
+        ),
+        tag => return Err(malformed(format!("unknown operation '{}'", tag))),
+    };
+    Ok(operation)
+}
+
+/// Checks operation references and the root, recomputes reference counts and applies limits.
+fn expression<Pid: Property>(
+    ops: Vec<Operation<Pid>>,
+    root: Option<OpRef>,
+    limits: Option<(usize, usize)>,
+) -> Result<Expression<Pid>> {
+    let mut expr = Expression {
+        ops: ops.into_iter().map(|op| (op, 0)).collect(),
+        root: None,
+        limits,
+        relaxed: false,
+        unique_conditions: false,
+        depths: Vec::new(),
+    };
+
+    let refs = expr.references()?;
+    for (op, refs) in expr.ops.iter_mut().zip(refs) {
+        op.1 = refs;
+    }
+    if let Some(root) = root {
+        expr.set_root(root)?;
+    }
+
+    // unreachable operations count too, walks may start anywhere
+    let depth = expr.depths().into_iter().max().unwrap_or(0);
+    let (max_ops, max_depth) = limits.unwrap_or((MAX_OPS, MAX_DEPTH));
+    if expr.len() > max_ops || depth > max_depth {
+        return Err(Error::ExpressionTooLarge {
+            ops: expr.len(),
+            depth,
+        });
+    }
+    Ok(expr)
+}
+
+struct OpsVisitor<Pid: Property> {
+    max_ops: usize,
+    marker: PhantomData<Pid>,
+}
+
+impl<'de, Pid: Property> Visitor<'de> for OpsVisitor<Pid> {
+    type Value = Vec<Operation<Pid>>;
+
+    fn expecting(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "a list of at most {} operations", self.max_ops)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> StdResult<Self::Value, A::Error> {
+        // never trust the size hint, the payload might lie about it
+        let hint = seq.size_hint().unwrap_or(0).min(self.max_ops);
+        let mut ops = Vec::with_capacity(hint);
+
+        while let Some(op) = seq.next_element::<Map<String, Json>>()? {
+            if ops.len() == self.max_ops {
+                let err = Error::ExpressionTooLarge {
+                    ops: ops.len() + 1,
+                    depth: 0,
+                };
+                return Err(de::Error::custom(err));
+            }
+
+            match operation(&op) {
+                Ok(operation) => ops.push(operation),
+                Err(err) => {
+                    let msg = format!("{} (operation {}: {})", err, ops.len(), Json::Object(op));
+                    return Err(de::Error::custom(msg));
+                }
+            }
+        }
+        Ok(ops)
+    }
+}
+
+impl<'de, Pid: Property> DeserializeSeed<'de> for OpsVisitor<Pid> {
+    type Value = Vec<Operation<Pid>>;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> StdResult<Self::Value, D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+/// Deserializes an expression with limits instead of the default `MAX_OPS` and `MAX_DEPTH`
+/// caps, for example
+/// `ExpressionSeed::with_limits(100, 10).deserialize(&mut serde_json::Deserializer::from_str(..))`.
+/// The limits stay with the deserialized expression, see `Expression::with_limits`.
+pub struct ExpressionSeed<Pid: Property> {
+    limits: Option<(usize, usize)>,
+    marker: PhantomData<Pid>,
+}
+
+impl<Pid: Property> ExpressionSeed<Pid> {
+    pub fn with_limits(max_ops: usize, max_depth: usize) -> Self {
+        ExpressionSeed {
+            limits: Some((max_ops, max_depth)),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'de, Pid: Property> Visitor<'de> for ExpressionSeed<Pid> {
+    type Value = Expression<Pid>;
+
+    fn expecting(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "an expression")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> StdResult<Self::Value, A::Error> {
+        let mut root = None;
+        let mut ops = None;
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "root" if root.is_none() => root = Some(map.next_value::<Option<OpRef>>()?),
+                "ops" if ops.is_none() => {
+                    ops = Some(map.next_value_seed(OpsVisitor {
+                        max_ops: self.limits.map_or(MAX_OPS, |(max_ops, _)| max_ops),
+                        marker: PhantomData,
+                    })?)
+                }
+                "root" | "ops" => {
+                    return Err(de::Error::custom(format!("duplicate field '{}'", key)))
+                }
+                key => return Err(de::Error::unknown_field(key, &["root", "ops"])),
+            }
+        }
+
+        let ops = ops.ok_or_else(|| de::Error::missing_field("ops"))?;
+        expression(ops, root.flatten(), self.limits).map_err(de::Error::custom)
+    }
+}
+
+impl<'de, Pid: Property> DeserializeSeed<'de> for ExpressionSeed<Pid> {
+    type Value = Expression<Pid>;
+
+    fn deserialize<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> StdResult<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+/// Accepts the format produced by `Serialize`, without trusting it: properties are resolved with
+/// `FromStr`, values are validated against them, operation references are checked and reference
+/// counts are recomputed. Payloads with more than `MAX_OPS` operations or deeper than `MAX_DEPTH`
+/// are rejected.
+///
+/// Errors from this crate keep their messages, e.g. `Error::TypeMismatch` or
+/// `Error::ExpressionFutureReference`, along with the offending operation.
+impl<'de, Pid: Property> Deserialize<'de> for Expression<Pid> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> StdResult<Self, D::Error> {
+        ExpressionSeed {
+            limits: None,
+            marker: PhantomData,
+        }
+        .deserialize(deserializer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::condition::Predicate;
+    use crate::testproperty::{Listed, Property};
+
+    #[test]
+    fn serialize_golden() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Str, "Radiohead").unwrap();
+        let b = expr.is_in(Property::Int, vec![3, 1, 2]).unwrap();
+        let c = expr.contains_ci(Property::Str, "head").unwrap();
+        let t = expr.constant(true).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let not_c = expr.not(c).unwrap();
+        let a_or_c = expr.or(a, not_c).unwrap();
+        let implies = expr.implies(a_and_b, a_or_c).unwrap();
+        let _ = expr.if_else(t, implies, a).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&expr).unwrap(),
+            json!({
+                "root": null,
+                "ops": [
+                    {"op": "is", "prop": "Str", "value": {"type": "Str", "value": "Radiohead"}},
+                    {"op": "in", "prop": "Int", "values": [
+                        {"type": "Int", "value": 1},
+                        {"type": "Int", "value": 2},
+                        {"type": "Int", "value": 3},
+                    ]},
+                    {"op": "contains", "prop": "Str", "needle": "head", "case_insensitive": true},
+                    {"op": "const", "value": true},
+                    {"op": "and", "lhs": 0, "rhs": 1},
+                    {"op": "not", "operand": 2},
+                    {"op": "or", "lhs": 0, "rhs": 5},
+                    {"op": "implies", "premise": 4, "conclusion": 6},
+                    {"op": "if_else", "cond": 3, "then": 7, "otherwise": 0},
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn serialize_golden_string() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Bool, true).unwrap();
+        let _ = expr.not(a).unwrap();
+        let _ = expr.constant(false).unwrap();
+        expr.set_root(1).unwrap();
+
+        assert_eq!(
+            serde_json::to_string(&expr).unwrap(),
+            r#"{"root":1,"ops":[{"op":"is","prop":"Bool","value":{"type":"Bool","value":true}},{"op":"not","operand":0},{"op":"const","value":false}]}"#
+        );
+    }
+
+    fn deserialize_err(json: serde_json::Value) -> String {
+        serde_json::from_value::<Expression<Property>>(json)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn deserialize_roundtrip() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Str, "Radiohead").unwrap();
+        let b = expr.is_in(Property::Int, vec![3, 1, 2]).unwrap();
+        let c = expr.contains(Property::Str, "head").unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let a_or_c = expr.or(a, c).unwrap();
+        let implies = expr.implies(a_and_b, a_or_c).unwrap();
+        let not = expr.not(implies).unwrap();
+        let _ = expr.constant(false).unwrap();
+        expr.set_root(not).unwrap();
+
+        let json = serde_json::to_value(&expr).unwrap();
+        let parsed: Expression<Property> = serde_json::from_value(json.clone()).unwrap();
+        assert!(parsed.validate().is_ok());
+        assert_eq!(parsed, expr);
+        assert_eq!(parsed.root(), Some(not));
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), json);
+
+        let empty: Expression<Property> =
+            serde_json::from_str(r#"{"root": null, "ops": []}"#).unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn deserialize_bad_prop() {
+        let err = deserialize_err(json!({"ops": [
+            {"op": "is", "prop": "Nope", "value": {"type": "Int", "value": 42}},
+        ]}));
+        assert!(err.contains("not found"), "{}", err);
+        assert!(err.contains("\"Nope\""), "{}", err);
+    }
+
+    #[test]
+    fn deserialize_bad_type() {
+        let err = deserialize_err(json!({"ops": [
+            {"op": "is", "prop": "Int", "value": {"type": "Str", "value": "42"}},
+        ]}));
+        assert!(err.contains("type mismatch"), "{}", err);
+
+        let err = deserialize_err(json!({"ops": [
+            {"op": "is", "prop": "Int", "value": {"type": "Int", "value": "42"}},
+        ]}));
+        assert!(err.contains("malformed: invalid value"), "{}", err);
+    }
+
+    #[test]
+    fn deserialize_forward_ref() {
+        let err = deserialize_err(json!({"ops": [
+            {"op": "not", "operand": 1},
+            {"op": "const", "value": true},
+        ]}));
+        assert!(err.contains("Operation reference 1 is invalid"), "{}", err);
+
+        let err = deserialize_err(json!({"ops": [
+            {"op": "const", "value": true},
+            {"op": "or", "lhs": 0, "rhs": 1},
+        ]}));
+        assert!(err.contains("Operation reference 1 is invalid"), "{}", err);
+    }
+
+    #[test]
+    fn deserialize_dangling_ref() {
+        let err = deserialize_err(json!({"ops": [
+            {"op": "const", "value": true},
+            {"op": "and", "lhs": 0, "rhs": 7},
+        ]}));
+        assert!(
+            err.contains("Operation reference 7/1 is out of bounds"),
+            "{}",
+            err
+        );
+
+        let err = deserialize_err(json!({"root": 3, "ops": [{"op": "const", "value": true}]}));
+        assert!(
+            err.contains("Operation reference 3/0 is out of bounds"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn deserialize_malformed() {
+        let err = deserialize_err(json!({"ops": [{"op": "xor", "lhs": 0, "rhs": 0}]}));
+        assert!(err.contains("unknown operation 'xor'"), "{}", err);
+
+        let err = deserialize_err(json!({"ops": [{"op": "not"}]}));
+        assert!(err.contains("missing field 'operand'"), "{}", err);
+
+        let err = deserialize_err(json!({"ops": [{"op": "not", "operand": -1}]}));
+        assert!(err.contains("not an operation reference"), "{}", err);
+
+        let err = deserialize_err(json!({"root": 0}));
+        assert!(err.contains("missing field `ops`"), "{}", err);
+    }
+
+    #[test]
+    fn deserialize_limits() {
+        let ops = vec![json!({"op": "const", "value": true}); MAX_OPS + 1];
+        let err = deserialize_err(json!({ "ops": ops }));
+        assert!(err.contains("too large"), "{}", err);
+
+        let payload = r#"{"ops": [
+            {"op": "const", "value": true},
+            {"op": "not", "operand": 0},
+            {"op": "not", "operand": 1}
+        ]}"#;
+        let seeded = |max_ops, max_depth| {
+            ExpressionSeed::<Property>::with_limits(max_ops, max_depth)
+                .deserialize(&mut serde_json::Deserializer::from_str(payload))
+        };
+
+        assert!(seeded(2, 10).unwrap_err().to_string().contains("too large"));
+        assert!(seeded(3, 2).unwrap_err().to_string().contains("too large"));
+
+        let mut expr = seeded(3, 3).unwrap();
+        assert_eq!(expr.len(), 3);
+        assert!(matches!(
+            expr.constant(false),
+            Err(Error::ExpressionTooLarge { ops: 4, depth: 1 })
+        ));
+    }
+
+    #[test]
+    fn deserialize_depth() {
+        let chain = |len: usize| {
+            let mut ops = vec![json!({"op": "const", "value": true})];
+            ops.extend((1..len).map(|idx| json!({"op": "not", "operand": idx - 1})));
+            json!({ "ops": ops })
+        };
+
+        let err = deserialize_err(chain(MAX_OPS));
+        assert!(err.contains("too large"), "{}", err);
+
+        let expr: Expression<Property> = serde_json::from_value(chain(MAX_DEPTH)).unwrap();
+        assert_eq!(expr.depth(), MAX_DEPTH);
+        assert!(expr.to_string().starts_with("!(!("));
+
+        let payload = chain(MAX_OPS).to_string();
+        let expr = ExpressionSeed::<Property>::with_limits(MAX_OPS, MAX_OPS)
+            .deserialize(&mut serde_json::Deserializer::from_str(&payload))
+            .unwrap();
+        assert_eq!(expr.depth(), MAX_OPS);
+    }
+
+    #[test]
+    fn deserialize_roundtrip_quantified() {
+        let mut expr = Expression::<Listed>::new();
+        let any = expr
+            .any_element(Listed::Tags, Predicate::Is("live".into()))
+            .unwrap();
+        let all = expr
+            .all_elements(Listed::Tags, Predicate::Contains("rock".to_owned(), true))
+            .unwrap();
+        let in_set = Predicate::In(vec!["a".into(), "b".into()].into_iter().collect());
+        let none = expr.all_elements(Listed::Tags, in_set).unwrap();
+        let any_or_all = expr.or(any, all).unwrap();
+        let _ = expr.and(any_or_all, none).unwrap();
+
+        let json = serde_json::to_value(&expr).unwrap();
+        assert_eq!(
+            json["ops"][1],
+            json!({
+                "op": "quantified",
+                "prop": "Tags",
+                "quantifier": "all",
+                "needle": "rock",
+                "case_insensitive": true,
+            })
+        );
+        let parsed: Expression<Listed> = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, expr);
+    }
+}
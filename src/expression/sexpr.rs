@@ -0,0 +1,574 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use super::parse::{error, Nesting};
+use super::{Expression, OpRef, Operation};
+use crate::condition::{Predicate, Quantifier};
+use crate::domain::Property;
+use crate::error::Result;
+use crate::value::{Datatype, Value};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    // keywords, property names, numbers, `true` / `false` and `$placeholders`
+    Atom(String),
+    Str(String),
+    LParen,
+    RParen,
+    End,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Token::Atom(atom) => write!(f, "'{}'", atom),
+            Token::Str(val) => write!(f, "{}", quote(val)),
+            Token::LParen => write!(f, "'('"),
+            Token::RParen => write!(f, "')'"),
+            Token::End => write!(f, "end of input"),
+        }
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some((offset, ch)) = chars.next() {
+        let token = match ch {
+            _ if ch.is_whitespace() => continue,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            // backslash escapes the next character: "Rock \"n\" Roll"
+            '"' => {
+                let mut val = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, next)) => val.push(next),
+                            None => return Err(error(input.len(), "'\"'", Token::End)),
+                        },
+                        Some((_, next)) => val.push(next),
+                        None => return Err(error(input.len(), "'\"'", Token::End)),
+                    }
+                }
+                Token::Str(val)
+            }
+            _ => {
+                let mut end = offset + ch.len_utf8();
+                while let Some((idx, next)) = chars.peek() {
+                    if next.is_whitespace() || "()\"".contains(*next) {
+                        break;
+                    }
+                    end = idx + next.len_utf8();
+                    chars.next();
+                }
+                Token::Atom(input[offset..end].to_owned())
+            }
+        };
+        tokens.push((offset, token));
+    }
+
+    tokens.push((input.len(), Token::End));
+    Ok(tokens)
+}
+
+struct Parser<'e, Pid: Property> {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    depth: usize,
+    expr: &'e mut Expression<Pid>,
+}
+
+impl<'e, Pid: Property> Nesting for Parser<'e, Pid> {
+    fn depth(&mut self) -> &mut usize {
+        &mut self.depth
+    }
+}
+
+impl<'e, Pid: Property> Parser<'e, Pid> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].1
+    }
+
+    fn next(&mut self) -> (usize, Token) {
+        let token = self.tokens[self.pos].clone();
+        if token.1 != Token::End {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            (_, token) if token == expected => Ok(()),
+            (offset, token) => Err(error(offset, expected, token)),
+        }
+    }
+
+    fn expr(&mut self) -> Result<OpRef> {
+        match self.next() {
+            (_, Token::Atom(ref atom)) if atom == "true" => self.expr.constant(true),
+            (_, Token::Atom(ref atom)) if atom == "false" => self.expr.constant(false),
+            (offset, Token::LParen) => {
+                let opref = self.nested(offset, Self::form)?;
+                self.expect(Token::RParen)?;
+                Ok(opref)
+            }
+            (offset, token) => Err(error(offset, "expression", token)),
+        }
+    }
+
+    /// Contents of a parenthesized form, starting with its keyword.
+    fn form(&mut self) -> Result<OpRef> {
+        let (offset, keyword) = match self.next() {
+            (offset, Token::Atom(keyword)) => (offset, keyword),
+            (offset, token) => return Err(error(offset, "keyword", token)),
+        };
+
+        match keyword.as_str() {
+            "not" => {
+                let operand = self.expr()?;
+                self.expr.not(operand)
+            }
+            // left-associative for more than two operands: `(and a b c)` is `(a && b) && c`
+            "and" | "or" => {
+                let mut acc = self.expr()?;
+                loop {
+                    let rhs = self.expr()?;
+                    acc = match keyword.as_str() {
+                        "and" => self.expr.and(acc, rhs)?,
+                        _ => self.expr.or(acc, rhs)?,
+                    };
+                    if *self.peek() == Token::RParen {
+                        return Ok(acc);
+                    }
+                }
+            }
+            "nand" | "nor" => {
+                let lhs = self.expr()?;
+                let rhs = self.expr()?;
+                match keyword.as_str() {
+                    "nand" => self.expr.nand(lhs, rhs),
+                    _ => self.expr.nor(lhs, rhs),
+                }
+            }
+            "implies" => {
+                let premise = self.expr()?;
+                let conclusion = self.expr()?;
+                self.expr.implies(premise, conclusion)
+            }
+            "if" => {
+                let cond = self.expr()?;
+                let then = self.expr()?;
+                let otherwise = self.expr()?;
+                self.expr.if_else(cond, then, otherwise)
+            }
+            "is" | "is-not" | "approx" | "in" | "contains" | "contains-ci" | "any" | "all" => {
+                let variable = self.property()?;
+                self.condition(&keyword, variable)
+            }
+            _ => Err(error(offset, "keyword", Token::Atom(keyword))),
+        }
+    }
+
+    fn condition(&mut self, keyword: &str, variable: Pid) -> Result<OpRef> {
+        let datatype = variable.datatype();
+        match keyword {
+            "is" => {
+                let value = self.value(datatype)?;
+                self.expr.is(variable, value)
+            }
+            "is-not" => {
+                let value = self.value(datatype)?;
+                self.expr.is_not(variable, value)
+            }
+            "approx" => {
+                let value = self.float()?;
+                let epsilon = self.float()?;
+                self.expr.approx_is(variable, value, epsilon)
+            }
+            "in" => {
+                let values = self.values(datatype)?;
+                self.expr.is_in(variable, values)
+            }
+            "contains" => {
+                let needle = self.string()?;
+                self.expr.contains(variable, &needle)
+            }
+            "contains-ci" => {
+                let needle = self.string()?;
+                self.expr.contains_ci(variable, &needle)
+            }
+            _ => {
+                let element = match datatype {
+                    Datatype::List(element) => *element,
+                    _ => datatype,
+                };
+                let predicate = self.predicate(element)?;
+                match keyword {
+                    "any" => self.expr.any_element(variable, predicate),
+                    _ => self.expr.all_elements(variable, predicate),
+                }
+            }
+        }
+    }
+
+    fn predicate(&mut self, element: Datatype) -> Result<Predicate> {
+        self.expect(Token::LParen)?;
+        let predicate = match self.next() {
+            (_, Token::Atom(ref atom)) if atom == "is" => Predicate::Is(self.value(element)?),
+            (_, Token::Atom(ref atom)) if atom == "in" => {
+                Predicate::In(self.values(element)?.into_iter().collect())
+            }
+            (_, Token::Atom(ref atom)) if atom == "contains" => {
+                Predicate::Contains(self.string()?, false)
+            }
+            (_, Token::Atom(ref atom)) if atom == "contains-ci" => {
+                Predicate::Contains(self.string()?, true)
+            }
+            (offset, token) => return Err(error(offset, "'is', 'in' or 'contains'", token)),
+        };
+        self.expect(Token::RParen)?;
+        Ok(predicate)
+    }
+
+    fn property(&mut self) -> Result<Pid> {
+        match self.next() {
+            (offset, Token::Atom(name)) => Pid::from_str(&name)
+                .ok()
+                .ok_or_else(|| error(offset, "property", format!("'{}'", name))),
+            (offset, token) => Err(error(offset, "property", token)),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        match self.next() {
+            (_, Token::Str(val)) => Ok(val),
+            (offset, token) => Err(error(offset, "string", token)),
+        }
+    }
+
+    fn float(&mut self) -> Result<f64> {
+        match self.next() {
+            (offset, Token::Atom(atom)) => atom
+                .parse()
+                .map_err(|_| error(offset, Datatype::Float, Token::Atom(atom))),
+            (offset, token) => Err(error(offset, Datatype::Float, token)),
+        }
+    }
+
+    fn values(&mut self, datatype: Datatype) -> Result<Vec<Value>> {
+        let mut values = Vec::new();
+        while *self.peek() != Token::RParen {
+            values.push(self.value(datatype)?);
+        }
+        Ok(values)
+    }
+
+    fn value(&mut self, datatype: Datatype) -> Result<Value> {
+        let (offset, token) = self.next();
+        let value = match (datatype, &token) {
+            (_, Token::Atom(atom)) if atom.len() > 1 && atom.starts_with('$') => {
+                Some(Value::Param(atom[1..].to_owned()))
+            }
+            (Datatype::Bool, Token::Atom(atom)) => atom.parse().map(Value::Bool).ok(),
+            (Datatype::Int, Token::Atom(atom)) => atom.parse().map(Value::Int).ok(),
+            (Datatype::UInt, Token::Atom(atom)) => atom.parse().map(Value::UInt).ok(),
+            (Datatype::Float, Token::Atom(atom)) => atom.parse().map(Value::Float).ok(),
+            (Datatype::Str, Token::Str(val)) => Some(Value::Str(val.clone())),
+            (Datatype::List(element), Token::LParen) => {
+                match self.next() {
+                    (_, Token::Atom(ref atom)) if atom == "list" => (),
+                    (offset, token) => return Err(error(offset, "'list'", token)),
+                }
+                let items = self.values(*element)?;
+                self.expect(Token::RParen)?;
+                Some(Value::List(element, items))
+            }
+            _ => None,
+        };
+        value.ok_or_else(|| error(offset, datatype, token))
+    }
+}
+
+fn value(value: &Value) -> String {
+    match value {
+        Value::Str(_) | Value::InternedStr(_) => quote(value.as_str().unwrap_or_default()),
+        Value::List(_, items) => {
+            let items = items.iter().map(self::value).collect::<Vec<_>>();
+            format!(
+                "(list{}{})",
+                if items.is_empty() { "" } else { " " },
+                items.join(" ")
+            )
+        }
+        Value::Param(name) => format!("${}", name),
+        value => value.to_string(),
+    }
+}
+
+/// Values of a form, sorted to make the output stable, with a leading space unless empty.
+fn values<'v>(values: impl Iterator<Item = &'v Value>) -> String {
+    let mut values = values.collect::<Vec<_>>();
+    values.sort();
+    values
+        .into_iter()
+        .map(|item| format!(" {}", value(item)))
+        .collect()
+}
+
+fn predicate(predicate: &Predicate) -> String {
+    match predicate {
+        Predicate::Is(expected) => format!("(is {})", value(expected)),
+        Predicate::In(expected) => format!("(in{})", values(expected.iter())),
+        Predicate::Contains(needle, false) => format!("(contains {})", quote(needle)),
+        Predicate::Contains(needle, true) => format!("(contains-ci {})", quote(needle)),
+    }
+}
+
+fn render<Pid: Property>(expr: &Expression<Pid>, opref: OpRef) -> String {
+    let render = |opref| render(expr, opref);
+    match &expr.ops[opref].0 {
+        Operation::Const(val) => val.to_string(),
+        Operation::Is(cond) => format!("(is {:?} {})", cond.variable(), value(cond.expected())),
+        Operation::IsNot(cond) => {
+            format!("(is-not {:?} {})", cond.variable(), value(cond.expected()))
+        }
+        Operation::ApproxIs(cond) => format!(
+            "(approx {:?} {} {})",
+            cond.variable(),
+            cond.expected(),
+            cond.epsilon()
+        ),
+        Operation::In(cond) => format!(
+            "(in {:?}{})",
+            cond.variable(),
+            values(cond.expected().iter())
+        ),
+        Operation::Contains(cond) => format!(
+            "({} {:?} {})",
+            if cond.case_insensitive() {
+                "contains-ci"
+            } else {
+                "contains"
+            },
+            cond.variable(),
+            quote(cond.needle())
+        ),
+        Operation::Quantified(cond) => format!(
+            "({} {:?} {})",
+            match cond.quantifier() {
+                Quantifier::Any => "any",
+                Quantifier::All => "all",
+            },
+            cond.variable(),
+            predicate(cond.predicate())
+        ),
+        &Operation::Not(operand) => format!("(not {})", render(operand)),
+        &Operation::Or(lhs, rhs) => format!("(or {} {})", render(lhs), render(rhs)),
+        &Operation::And(lhs, rhs) => format!("(and {} {})", render(lhs), render(rhs)),
+        &Operation::Nand(lhs, rhs) => format!("(nand {} {})", render(lhs), render(rhs)),
+        &Operation::Nor(lhs, rhs) => format!("(nor {} {})", render(lhs), render(rhs)),
+        &Operation::Implies(premise, conclusion) => {
+            format!("(implies {} {})", render(premise), render(conclusion))
+        }
+        &Operation::IfElse(cond, then, otherwise) => {
+            format!(
+                "(if {} {} {})",
+                render(cond),
+                render(then),
+                render(otherwise)
+            )
+        }
+    }
+}
+
+/// Renders the expression tree reachable from the root as an s-expression, for example
+/// `(and (is AlbumName "foo") (in AlbumListeners 1 2 3))`, empty expressions as an empty string.
+///
+/// Forms are `(is P v)`, `(is-not P v)`, `(approx P v eps)`, `(in P v..)`, `(contains P "s")`,
+/// `(contains-ci P "s")`, `(any P predicate)`, `(all P predicate)` with predicates `(is v)`,
+/// `(in v..)`, `(contains "s")` and `(contains-ci "s")`, `(not e)`, `(and e e)`, `(or e e)`,
+/// `(nand e e)`, `(nor e e)`, `(implies e e)`, `(if e e e)`,
+/// plus `true` and `false`. Properties are written as their `Debug` form, strings are quoted
+/// with `"` and `\` escaped, lists are written as `(list v..)` and placeholders as `$name`.
+pub fn to_sexpr<Pid: Property>(expr: &Expression<Pid>) -> String {
+    match expr.root() {
+        Some(root) if root < expr.ops.len() => render(expr, root),
+        _ => String::new(),
+    }
+}
+
+/// Parses an s-expression written by `to_sexpr`, `and` and `or` also accept more than two
+/// operands. Properties are resolved with `FromStr`, values are typed by property datatype.
+///
+/// Syntax errors and values that don't match their property are reported
+/// as `Error::ExpressionParse` with the offending token and its byte offset, so are forms
+/// nested deeper than `MAX_PARSE_DEPTH`.
+pub fn from_sexpr<Pid: Property>(input: &str) -> Result<Expression<Pid>> {
+    let mut expr = Expression::new();
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+        depth: 0,
+        expr: &mut expr,
+    };
+
+    parser.expr()?;
+    match parser.next() {
+        (_, Token::End) => Ok(expr),
+        (offset, token) => Err(error(offset, "end of input", token)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::error::Error;
+    use crate::expression::parse::MAX_PARSE_DEPTH;
+    use crate::testproperty::{Listed, Property, Rated};
+
+    #[test]
+    fn sexpr_roundtrip() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Str, "Rock \"n\" Roll \\o/").unwrap();
+        let b = expr.is_in(Property::Int, vec![3, -1, 2]).unwrap();
+        let c = expr.contains_ci(Property::Str, "head").unwrap();
+        let d = expr.is_not(Property::UInt, 7u64).unwrap();
+        let e = expr
+            .is(Property::Bool, Value::Param("live".to_owned()))
+            .unwrap();
+        let f = expr.constant(false).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let not_c = expr.not(c).unwrap();
+        let implies = expr.implies(a_and_b, not_c).unwrap();
+        let d_or_f = expr.or(d, f).unwrap();
+        let _ = expr.if_else(e, implies, d_or_f).unwrap();
+
+        let sexpr = to_sexpr(&expr);
+        assert_eq!(
+            sexpr,
+            r#"(if (is Bool $live) (implies (and (is Str "Rock \"n\" Roll \\o/") (in Int -1 2 3)) (not (contains-ci Str "head"))) (or (is-not UInt 7) false))"#
+        );
+        assert_eq!(from_sexpr::<Property>(&sexpr).unwrap(), expr);
+
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 1).unwrap();
+        let b = expr.is(Property::Bool, true).unwrap();
+        let nand = expr.nand(a, b).unwrap();
+        let _ = expr.nor(nand, a).unwrap();
+        let sexpr = to_sexpr(&expr);
+        assert_eq!(sexpr, "(nor (nand (is Int 1) (is Bool true)) (is Int 1))");
+        assert_eq!(
+            from_sexpr::<Property>(&sexpr).unwrap().to_string(),
+            expr.to_string()
+        );
+
+        let mut expr = Expression::<Listed>::new();
+        let a = expr
+            .any_element(
+                Listed::Tags,
+                Predicate::In(vec!["b".into(), "a".into()].into_iter().collect()),
+            )
+            .unwrap();
+        let b = expr
+            .all_elements(Listed::Tags, Predicate::Contains("live".to_owned(), false))
+            .unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let empty = Value::list(Datatype::Str, Vec::<Value>::new()).unwrap();
+        let c = expr.is_not(Listed::Tags, empty).unwrap();
+        let _ = expr.and(a_or_b, c).unwrap();
+
+        let sexpr = to_sexpr(&expr);
+        assert_eq!(
+            sexpr,
+            r#"(and (or (any Tags (in "a" "b")) (all Tags (contains "live"))) (is-not Tags (list)))"#
+        );
+        assert_eq!(from_sexpr::<Listed>(&sexpr).unwrap(), expr);
+        assert_eq!(to_sexpr(&Expression::<Listed>::new()), "");
+    }
+
+    #[test]
+    fn sexpr_approx() {
+        let mut expr = Expression::<Rated>::new();
+        let _ = expr.approx_is(Rated::Rating, 4.5, 0.1).unwrap();
+        let text = to_sexpr(&expr);
+        assert_eq!(text, "(approx Rating 4.5 0.1)");
+        assert_eq!(from_sexpr::<Rated>(&text).unwrap(), expr);
+
+        let result = from_sexpr::<Rated>("(approx Rating 4.5 $eps)");
+        assert!(
+            matches!(result, Err(Error::ExpressionParse { offset: 19, .. })),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn sexpr_nary_and_whitespace() {
+        let expr =
+            from_sexpr::<Property>("(or\n  (is Int 1)\n  (in Int)\n  (is Str \"a\"))").unwrap();
+        assert_eq!(
+            to_sexpr(&expr),
+            r#"(or (or (is Int 1) (in Int)) (is Str "a"))"#
+        );
+    }
+
+    #[test]
+    fn sexpr_errors() {
+        let cases = vec![
+            ("", 0, "expression", "end of input"),
+            ("(is Int 1", 9, "')'", "end of input"),
+            ("(is Nope 1)", 4, "property", "'Nope'"),
+            ("(is Int \"1\")", 8, "Int", "\"1\""),
+            ("(is UInt -1)", 9, "UInt", "'-1'"),
+            ("(xor true false)", 1, "keyword", "'xor'"),
+            ("(and true)", 9, "expression", "')'"),
+            ("(is Str \"open", 13, "'\"'", "end of input"),
+            ("true false", 5, "end of input", "'false'"),
+            ("(not (is Bool yes))", 14, "Bool", "'yes'"),
+        ];
+
+        for (input, offset, expected, found) in cases {
+            match from_sexpr::<Property>(input) {
+                Err(Error::ExpressionParse {
+                    offset: actual_offset,
+                    expected: actual_expected,
+                    found: actual_found,
+                }) => {
+                    assert_eq!(
+                        (
+                            actual_offset,
+                            actual_expected.as_str(),
+                            actual_found.as_str()
+                        ),
+                        (offset, expected, found),
+                        "{}",
+                        input
+                    );
+                }
+                result => panic!("{}: unexpected result {:?}", input, result),
+            }
+        }
+    }
+
+    #[test]
+    fn sexpr_depth_limit() {
+        let nested = |depth: usize| "(not ".repeat(depth) + "true" + &")".repeat(depth);
+        assert!(from_sexpr::<Property>(&nested(MAX_PARSE_DEPTH)).is_ok());
+
+        let result = from_sexpr::<Property>(&nested(100_000));
+        assert!(
+            matches!(&result, Err(Error::ExpressionParse { offset, found, .. })
+                if *offset == 5 * MAX_PARSE_DEPTH && found == "deeper nesting"),
+            "{:?}",
+            result.map(|expr| expr.len())
+        );
+    }
+}
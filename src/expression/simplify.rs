@@ -0,0 +1,281 @@
+use super::{Expression, OpRef, Operation};
+use crate::domain::Property;
+use crate::error::{Error, Result};
+
+impl<Pid: Property> Expression<Pid> {
+    fn as_const(&self, opref: OpRef) -> Option<bool> {
+        match self.ops[opref].0 {
+            Operation::Const(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    fn negation(&mut self, opref: OpRef) -> Result<OpRef> {
+        match self.ops[opref].0 {
+            Operation::Const(val) => self.constant(!val),
+            Operation::Not(operand) => Ok(operand),
+            _ => self.not(opref),
+        }
+    }
+
+    fn conjunction(&mut self, lhs: OpRef, rhs: OpRef) -> Result<OpRef> {
+        match (self.as_const(lhs), self.as_const(rhs)) {
+            (Some(false), _) | (_, Some(true)) => Ok(lhs),
+            (Some(true), _) | (_, Some(false)) => Ok(rhs),
+            _ if lhs == rhs => Ok(lhs),
+            _ => self.and(lhs, rhs),
+        }
+    }
+
+    fn disjunction(&mut self, lhs: OpRef, rhs: OpRef) -> Result<OpRef> {
+        match (self.as_const(lhs), self.as_const(rhs)) {
+            (Some(true), _) | (_, Some(false)) => Ok(lhs),
+            (Some(false), _) | (_, Some(true)) => Ok(rhs),
+            _ if lhs == rhs => Ok(lhs),
+            _ => self.or(lhs, rhs),
+        }
+    }
+
+    /// Folds constants and removes redundant operations, leaving only the ones reachable
+    /// from the root. Evaluation results stay the same for every context.
+    ///
+    /// Applies identity and annihilator laws (`true && x` is `x`, `false && x` is `false`, ...),
+    /// idempotence for operands referencing the same operation (`x || x` is `x`),
+    /// removes double negations and picks the taken branch of constant `IfElse` conditions.
+    /// Empty `In` sets become `false` and single-value ones become `Is`.
+    /// Works well on partially evaluated expressions, where evaluated operations became constants.
+    pub fn simplify(&self) -> Result<Expression<Pid>> {
+        let root = match self.root() {
+            Some(root) => self.valid(root)?,
+            // nothing to simplify, modes carry over all the same
+            None => return Ok(self.clone()),
+        };
+
+        let reachable = self.reachable(root);
+        let mut simple = Expression::new();
+        let mut mapped: Vec<OpRef> = Vec::with_capacity(root + 1);
+
+        for (idx, (op, _)) in self.ops.iter().enumerate().take(root + 1) {
+            if !reachable[idx] {
+                // never referenced by reachable operations, any placeholder will do
+                mapped.push(0);
+                continue;
+            }
+
+            let operand = |opref: OpRef| {
+                mapped
+                    .get(opref)
+                    .copied()
+                    .ok_or_else(|| Error::ExpressionFutureReference(opref, idx, self.summary()))
+            };
+
+            let opref = match *op {
+                Operation::Not(opref) => {
+                    let opref = operand(opref)?;
+                    simple.negation(opref)?
+                }
+                Operation::Or(lhs, rhs) => {
+                    let (lhs, rhs) = (operand(lhs)?, operand(rhs)?);
+                    simple.disjunction(lhs, rhs)?
+                }
+                Operation::And(lhs, rhs) => {
+                    let (lhs, rhs) = (operand(lhs)?, operand(rhs)?);
+                    simple.conjunction(lhs, rhs)?
+                }
+                Operation::Nand(lhs, rhs) => {
+                    let (lhs, rhs) = (operand(lhs)?, operand(rhs)?);
+                    let conjunction = simple.conjunction(lhs, rhs)?;
+                    simple.negation(conjunction)?
+                }
+                Operation::Nor(lhs, rhs) => {
+                    let (lhs, rhs) = (operand(lhs)?, operand(rhs)?);
+                    let disjunction = simple.disjunction(lhs, rhs)?;
+                    simple.negation(disjunction)?
+                }
+                Operation::Implies(premise, conclusion) => {
+                    let (premise, conclusion) = (operand(premise)?, operand(conclusion)?);
+                    if premise == conclusion {
+                        simple.constant(true)?
+                    } else {
+                        let not_premise = simple.negation(premise)?;
+                        simple.disjunction(not_premise, conclusion)?
+                    }
+                }
+                Operation::IfElse(cond, then, otherwise) => {
+                    let (cond, then, otherwise) =
+                        (operand(cond)?, operand(then)?, operand(otherwise)?);
+                    match (
+                        simple.as_const(cond),
+                        simple.as_const(then),
+                        simple.as_const(otherwise),
+                    ) {
+                        (Some(true), _, _) => then,
+                        (Some(false), _, _) => otherwise,
+                        _ if then == otherwise => then,
+                        (_, Some(true), Some(false)) => cond,
+                        (_, Some(false), Some(true)) => simple.negation(cond)?,
+                        _ => simple.if_else(cond, then, otherwise)?,
+                    }
+                }
+                Operation::In(ref cond) if cond.expected().is_empty() => simple.constant(false)?,
+                Operation::In(ref cond) => match cond.as_is() {
+                    Some(cond) => simple.push(Operation::Is(cond))?,
+                    None => simple.push(op.clone())?,
+                },
+                ref leaf => simple.push(leaf.clone())?,
+            };
+            mapped.push(opref);
+        }
+
+        // folding leaves constants and negations nobody references anymore
+        let mut simple = simple.subexpression(mapped[root])?;
+        simple.limits = self.limits;
+        simple.relaxed = self.relaxed;
+        simple.unique_conditions = self.unique_conditions;
+        Ok(simple)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::expression::{Context, Evaluated};
+    use crate::testproperty::Property;
+    use crate::value::Value;
+
+    fn contexts() -> Vec<Context<Property>> {
+        let mut contexts = Vec::new();
+        for int in &[42, 24] {
+            for boolean in &[true, false] {
+                contexts.push(
+                    Context::from_values(vec![
+                        (Property::Int, Value::Int(*int)),
+                        (Property::Bool, Value::Bool(*boolean)),
+                    ])
+                    .unwrap(),
+                );
+            }
+        }
+        contexts
+    }
+
+    fn assert_simplified(expr: &Expression<Property>, expected: &str) {
+        let simple = expr.simplify().unwrap();
+        assert_eq!(format!("{}", simple), expected);
+        assert!(simple.validate().is_ok(), "{}", simple);
+        assert_eq!(simple.simplify().unwrap(), simple);
+        assert_eq!(simple.len(), simple.iter_reachable().count());
+
+        for context in contexts().iter() {
+            assert_eq!(
+                expr.eval(context).unwrap().as_bool(),
+                simple.eval(context).unwrap().as_bool(),
+                "{} / {}",
+                simple,
+                context
+            );
+        }
+    }
+
+    #[test]
+    fn simplify_identities() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 42).unwrap();
+        let b = expr.is(Property::Bool, true).unwrap();
+        let t = expr.constant(true).unwrap();
+        let f = expr.constant(false).unwrap();
+        let not_f = expr.not(f).unwrap();
+        let a_and_t = expr.and(not_f, a).unwrap();
+        let b_or_f = expr.or(b, f).unwrap();
+        let b_or_b = expr.or(b_or_f, b).unwrap();
+        let not_b = expr.not(b_or_b).unwrap();
+        let not_not_b = expr.not(not_b).unwrap();
+        let x = expr.and(a_and_t, not_not_b).unwrap();
+        let x_or_f = expr.or(x, f).unwrap();
+        let _ = expr.and(t, x_or_f).unwrap();
+
+        assert_simplified(
+            &expr,
+            "(Property::Int (Int) == 42 && Property::Bool (Bool) == true)",
+        );
+    }
+
+    #[test]
+    fn simplify_annihilators() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 42).unwrap();
+        let f = expr.constant(false).unwrap();
+        let a_and_f = expr.and(a, f).unwrap();
+        let not_a_and_f = expr.not(a_and_f).unwrap();
+        let _ = expr.or(a, not_a_and_f).unwrap();
+
+        assert_simplified(&expr, "true");
+    }
+
+    #[test]
+    fn simplify_implies_if_else() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 42).unwrap();
+        let b = expr.is(Property::Bool, true).unwrap();
+        let t = expr.constant(true).unwrap();
+        let f = expr.constant(false).unwrap();
+        let t_then_a = expr.implies(t, a).unwrap();
+        let b_then_f = expr.implies(b, f).unwrap();
+        let choice = expr.if_else(a, t, f).unwrap();
+        let negated = expr.if_else(b, f, t).unwrap();
+        let same = expr.if_else(a, b, b).unwrap();
+        let lhs = expr.and(t_then_a, b_then_f).unwrap();
+        let rhs = expr.or(choice, negated).unwrap();
+        let lhs_or_rhs = expr.or(lhs, rhs).unwrap();
+        let _ = expr.and(lhs_or_rhs, same).unwrap();
+
+        assert_simplified(
+            &expr,
+            "(((Property::Int (Int) == 42 && !(Property::Bool (Bool) == true)) \
+             || (Property::Int (Int) == 42 || !(Property::Bool (Bool) == true))) \
+             && Property::Bool (Bool) == true)",
+        );
+    }
+
+    #[test]
+    fn simplify_partially_evaluated() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 42).unwrap();
+        let b = expr.is(Property::Bool, true).unwrap();
+        let c = expr.is(Property::Str, "foo").unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let _ = expr.or(a_and_b, c).unwrap();
+
+        let context = Context::from_values(vec![(Property::Int, Value::Int(42))]).unwrap();
+        let partial = match expr.eval(&context).unwrap() {
+            Evaluated::Partially(partial) => partial,
+            evaluated => panic!("unexpected result: {}", evaluated),
+        };
+
+        let simple = partial.simplify().unwrap();
+        assert_eq!(
+            format!("{}", simple),
+            "(Property::Bool (Bool) == true || Property::Str (Str) == foo)"
+        );
+        assert_eq!(simple.len(), 3);
+    }
+
+    #[test]
+    fn simplify_single_value_in() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is_in(Property::Int, vec![42]).unwrap();
+        let b = expr.is_in(Property::Int, vec![1, 2]).unwrap();
+        let _ = expr.or(a, b).unwrap();
+
+        assert_simplified(
+            &expr,
+            "(Property::Int (Int) == 42 || Property::Int (Int) in [1, 2])",
+        );
+    }
+
+    #[test]
+    fn simplify_empty() {
+        assert!(Expression::<Property>::new().simplify().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,354 @@
+use super::{Expression, OpRef, Operation};
+use crate::condition::{Predicate, Quantified, Quantifier};
+use crate::domain::Property;
+use crate::error::{Error, Result};
+use crate::value::Value;
+
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+/// Escapes `LIKE` wildcards, backslash is the default escape character in Postgres.
+fn like_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if "\\%_".contains(ch) {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+struct Renderer<'a, Pid: Property, F: Fn(Pid) -> String> {
+    expr: &'a Expression<Pid>,
+    mapper: &'a F,
+    // values are inlined as literals when there are no parameters to collect them into
+    params: Option<Vec<Value>>,
+    // first placeholder found, its name can't be trusted in SQL text
+    unbound: Option<String>,
+}
+
+impl<'a, Pid: Property, F: Fn(Pid) -> String> Renderer<'a, Pid, F> {
+    fn literal(&mut self, value: &Value) -> String {
+        match (value, self.params.as_mut()) {
+            (Value::Param(name), _) => {
+                self.unbound.get_or_insert_with(|| name.clone());
+                "NULL".to_owned()
+            }
+            (value, Some(params)) => {
+                params.push(value.clone());
+                format!("${}", params.len())
+            }
+            (Value::Bool(true), None) => "TRUE".to_owned(),
+            (Value::Bool(false), None) => "FALSE".to_owned(),
+            (Value::Int(val), None) => val.to_string(),
+            (Value::UInt(val), None) => val.to_string(),
+            (Value::Float(val), None) if val.is_nan() => "'NaN'::float8".to_owned(),
+            (Value::Float(val), None) if val.is_infinite() && *val > 0.0 => {
+                "'Infinity'::float8".to_owned()
+            }
+            (Value::Float(val), None) if val.is_infinite() => "'-Infinity'::float8".to_owned(),
+            (Value::Float(val), None) => val.to_string(),
+            (Value::Str(val), None) => quote(val),
+            (Value::InternedStr(val), None) => quote(val),
+            (Value::List(_, items), None) => {
+                let items = items
+                    .iter()
+                    .map(|item| self.literal(item))
+                    .collect::<Vec<_>>();
+                format!("ARRAY[{}]", items.join(", "))
+            }
+        }
+    }
+
+    /// Array operators where Postgres has them, a subquery over `unnest` for substrings.
+    fn quantified(&mut self, cond: &Quantified<Pid>) -> String {
+        let column = (self.mapper)(cond.variable());
+        match (cond.quantifier(), cond.predicate()) {
+            (Quantifier::Any, Predicate::Is(expected)) => {
+                format!("{} = ANY({})", self.literal(expected), column)
+            }
+            (Quantifier::All, Predicate::Is(expected)) => {
+                format!("{} = ALL({})", self.literal(expected), column)
+            }
+            (Quantifier::Any, Predicate::In(expected)) if expected.is_empty() => "FALSE".to_owned(),
+            (Quantifier::All, Predicate::In(expected)) if expected.is_empty() => {
+                format!("cardinality({}) = 0", column)
+            }
+            (quantifier, Predicate::In(expected)) => {
+                let values = expected
+                    .iter()
+                    .map(|value| self.literal(value))
+                    .collect::<Vec<_>>();
+                let operator = match quantifier {
+                    Quantifier::Any => "&&",
+                    Quantifier::All => "<@",
+                };
+                format!("{} {} ARRAY[{}]", column, operator, values.join(", "))
+            }
+            (quantifier, Predicate::Contains(needle, case_insensitive)) => {
+                let pattern = format!("%{}%", like_escape(needle));
+                let pattern = self.literal(&Value::Str(pattern));
+                let like = if *case_insensitive { "ILIKE" } else { "LIKE" };
+                let (exists, not) = match quantifier {
+                    Quantifier::Any => ("EXISTS", ""),
+                    Quantifier::All => ("NOT EXISTS", "NOT "),
+                };
+                format!(
+                    "{} (SELECT 1 FROM unnest({}) AS elem WHERE {}elem {} {})",
+                    exists, column, not, like, pattern
+                )
+            }
+        }
+    }
+
+    fn render(&mut self, opref: OpRef) -> String {
+        let expr = self.expr;
+        match &expr.ops[opref].0 {
+            Operation::Const(true) => "TRUE".to_owned(),
+            Operation::Const(false) => "FALSE".to_owned(),
+            Operation::Is(cond) => {
+                let value = self.literal(cond.expected());
+                format!("{} = {}", (self.mapper)(cond.variable()), value)
+            }
+            Operation::IsNot(cond) => {
+                let value = self.literal(cond.expected());
+                format!("{} <> {}", (self.mapper)(cond.variable()), value)
+            }
+            Operation::ApproxIs(cond) => {
+                let expected = self.literal(&Value::Float(cond.expected()));
+                let epsilon = self.literal(&Value::Float(cond.epsilon()));
+                format!(
+                    "ABS({} - {}) <= {}",
+                    (self.mapper)(cond.variable()),
+                    expected,
+                    epsilon
+                )
+            }
+            Operation::In(cond) if cond.expected().is_empty() => "FALSE".to_owned(),
+            Operation::In(cond) => {
+                // sets don't keep any particular order, sort values to make the output stable
+                let mut values = cond.expected().iter().collect::<Vec<_>>();
+                values.sort();
+
+                let values = values
+                    .into_iter()
+                    .map(|value| self.literal(value))
+                    .collect::<Vec<_>>();
+                format!(
+                    "{} IN ({})",
+                    (self.mapper)(cond.variable()),
+                    values.join(", ")
+                )
+            }
+            Operation::Contains(cond) => {
+                let pattern = format!("%{}%", like_escape(cond.needle()));
+                let pattern = self.literal(&Value::Str(pattern));
+                let like = if cond.case_insensitive() {
+                    "ILIKE"
+                } else {
+                    "LIKE"
+                };
+                format!("{} {} {}", (self.mapper)(cond.variable()), like, pattern)
+            }
+            Operation::Quantified(cond) => self.quantified(cond),
+            &Operation::Not(opref) => format!("(NOT {})", self.render(opref)),
+            &Operation::Or(lhs, rhs) => {
+                format!("({} OR {})", self.render(lhs), self.render(rhs))
+            }
+            &Operation::And(lhs, rhs) => {
+                format!("({} AND {})", self.render(lhs), self.render(rhs))
+            }
+            &Operation::Nand(lhs, rhs) => {
+                format!("(NOT ({} AND {}))", self.render(lhs), self.render(rhs))
+            }
+            &Operation::Nor(lhs, rhs) => {
+                format!("(NOT ({} OR {}))", self.render(lhs), self.render(rhs))
+            }
+            &Operation::Implies(premise, conclusion) => format!(
+                "((NOT {}) OR {})",
+                self.render(premise),
+                self.render(conclusion)
+            ),
+            &Operation::IfElse(cond, then, otherwise) => format!(
+                "(CASE WHEN {} THEN {} ELSE {} END)",
+                self.render(cond),
+                self.render(then),
+                self.render(otherwise)
+            ),
+        }
+    }
+}
+
+impl<Pid: Property> Expression<Pid> {
+    fn sql<F: Fn(Pid) -> String>(
+        &self,
+        mapper: &F,
+        params: Option<Vec<Value>>,
+    ) -> Result<(String, Option<Vec<Value>>)> {
+        let root = self.root().ok_or(Error::ExpressionNoop)?;
+        self.valid(root)?;
+
+        let mut renderer = Renderer {
+            expr: self,
+            mapper,
+            params,
+            unbound: None,
+        };
+        let sql = renderer.render(root);
+        match renderer.unbound {
+            Some(name) => Err(Error::UnboundParameter(name)),
+            None => Ok((sql, renderer.params)),
+        }
+    }
+
+    /// Renders the expression as a SQL `WHERE` clause (Postgres dialect), `mapper` turns
+    /// properties into column names, which are inserted as is.
+    ///
+    /// Values are inlined as literals, strings are quoted with single quotes doubled.
+    /// Empty `In` sets are rendered as `FALSE`, substring conditions as `LIKE` / `ILIKE`
+    /// with wildcards in the needle escaped. Conditions on list elements use `= ANY(..)`,
+    /// `= ALL(..)`, `&&` and `<@` array operators, substrings check `unnest`ed elements.
+    ///
+    /// Placeholders have to be bound with `Expression::bind` first, otherwise
+    /// `Error::UnboundParameter` is returned.
+    pub fn to_sql(&self, mapper: &impl Fn(Pid) -> String) -> Result<String> {
+        let (sql, _) = self.sql(mapper, None)?;
+        Ok(sql)
+    }
+
+    /// Same as `to_sql`, but values are replaced with `$1`, `$2`, ... placeholders
+    /// and returned separately, in placeholder order.
+    pub fn to_sql_params(&self, mapper: &impl Fn(Pid) -> String) -> Result<(String, Vec<Value>)> {
+        let (sql, params) = self.sql(mapper, Some(Vec::new()))?;
+        Ok((sql, params.unwrap_or_default()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::condition::Predicate;
+    use crate::testproperty::{Listed, Property};
+
+    fn column(prop: Property) -> String {
+        format!("{:?}", prop).to_lowercase()
+    }
+
+    #[test]
+    fn to_sql_literals() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Str, "Rock 'n' Roll").unwrap();
+        let b = expr.is_in(Property::Int, vec![3, 1, 2]).unwrap();
+        let c = expr.is(Property::Bool, false).unwrap();
+        let not_c = expr.not(c).unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let _ = expr.and(a_or_b, not_c).unwrap();
+
+        assert_eq!(
+            expr.to_sql(&column).unwrap(),
+            "((str = 'Rock ''n'' Roll' OR int IN (1, 2, 3)) AND (NOT bool = FALSE))"
+        );
+    }
+
+    #[test]
+    fn to_sql_params() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Str, "Rock 'n' Roll").unwrap();
+        let b = expr.is_in(Property::Int, vec![2, 1]).unwrap();
+        let c = expr.contains_ci(Property::Str, "100%_").unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let _ = expr.implies(a_or_b, c).unwrap();
+
+        let (sql, params) = expr.to_sql_params(&column).unwrap();
+        assert_eq!(sql, "((NOT (str = $1 OR int IN ($2, $3))) OR str ILIKE $4)");
+        assert_eq!(
+            params,
+            vec![
+                Value::Str("Rock 'n' Roll".to_owned()),
+                Value::Int(1),
+                Value::Int(2),
+                Value::Str("%100\\%\\_%".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn to_sql_constants_and_empty_in() {
+        let mut expr = Expression::<Property>::new();
+        let t = expr.constant(true).unwrap();
+        let empty = expr.is_in(Property::Int, Vec::<Value>::new()).unwrap();
+        let c = expr.contains(Property::Str, "it's").unwrap();
+        let _ = expr.if_else(t, empty, c).unwrap();
+
+        assert_eq!(
+            expr.to_sql(&column).unwrap(),
+            "(CASE WHEN TRUE THEN FALSE ELSE str LIKE '%it''s%' END)"
+        );
+
+        let (sql, params) = expr.to_sql_params(&column).unwrap();
+        assert_eq!(sql, "(CASE WHEN TRUE THEN FALSE ELSE str LIKE $1 END)");
+        assert_eq!(params, vec![Value::Str("%it's%".to_owned())]);
+    }
+
+    #[test]
+    fn to_sql_empty() {
+        let expr = Expression::<Property>::new();
+        assert!(matches!(expr.to_sql(&column), Err(Error::ExpressionNoop)));
+        assert!(matches!(
+            expr.to_sql_params(&column),
+            Err(Error::ExpressionNoop)
+        ));
+    }
+
+    #[test]
+    fn to_sql_unbound_params() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Bool, true).unwrap();
+        let b = expr
+            .is(Property::Str, Value::Param("x OR 1=1 --".to_owned()))
+            .unwrap();
+        let _ = expr.and(a, b).unwrap();
+
+        let unbound =
+            |result| matches!(result, Err(Error::UnboundParameter(name)) if name == "x OR 1=1 --");
+        assert!(unbound(expr.to_sql(&column)));
+        assert!(unbound(expr.to_sql_params(&column).map(|(sql, _)| sql)));
+
+        let params = vec![("x OR 1=1 --", Value::from("x"))]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            expr.bind(&params).unwrap().to_sql(&column).unwrap(),
+            "(bool = TRUE AND str = 'x')"
+        );
+    }
+
+    #[test]
+    fn to_sql_quantified() {
+        let mut expr = Expression::<Listed>::new();
+        let a = expr
+            .any_element(Listed::Tags, Predicate::Is("live".into()))
+            .unwrap();
+        let in_set = Predicate::In(vec!["b".into(), "a".into()].into_iter().collect());
+        let b = expr.all_elements(Listed::Tags, in_set).unwrap();
+        let c = expr
+            .all_elements(Listed::Tags, Predicate::Contains("rock".to_owned(), true))
+            .unwrap();
+        let empty = Predicate::In(Default::default());
+        let d = expr.all_elements(Listed::Tags, empty).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let c_or_d = expr.or(c, d).unwrap();
+        let _ = expr.and(a_and_b, c_or_d).unwrap();
+
+        let column = |_| "tags".to_owned();
+        assert_eq!(
+            expr.to_sql(&column).unwrap(),
+            "(('live' = ANY(tags) AND tags <@ ARRAY['a', 'b']) AND \
+             (NOT EXISTS (SELECT 1 FROM unnest(tags) AS elem WHERE NOT elem ILIKE '%rock%') \
+             OR cardinality(tags) = 0))"
+        );
+    }
+}
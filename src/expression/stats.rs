@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use super::{Context, Evaluated, Expression, Operation};
+use crate::domain::Property;
+use crate::error::Result;
+use crate::value::Value;
+
+/// What evaluating an expression took, see `Expression::eval_instrumented`.
+#[derive(Debug, Clone)]
+pub struct EvalStats<Pid: Property> {
+    /// Operations evaluated, shared ones are counted once.
+    pub visited: usize,
+    /// Operations reachable from the root that short-circuiting left unevaluated.
+    pub skipped: usize,
+    /// Conditions tested against a value, per property. Conditions on properties
+    /// missing from the context are visited, but not counted here.
+    pub conditions: HashMap<Pid, usize>,
+    /// Time spent testing conditions, per property.
+    pub condition_time: HashMap<Pid, Duration>,
+    /// Operations folded into a constant, by kind: `"is"`, `"in"`, `"and"`, `"if_else"` etc.
+    pub folds: HashMap<&'static str, usize>,
+    /// Wall time of the whole evaluation, checks of the expression included.
+    pub elapsed: Duration,
+}
+
+fn kind<Pid: Property>(op: &Operation<Pid>) -> &'static str {
+    match op {
+        Operation::Const(_) => "const",
+        Operation::Is(_) => "is",
+        Operation::IsNot(_) => "is_not",
+        Operation::ApproxIs(_) => "approx_is",
+        Operation::In(_) => "in",
+        Operation::Contains(_) => "contains",
+        Operation::Quantified(_) => "quantified",
+        Operation::Not(_) => "not",
+        Operation::Or(_, _) => "or",
+        Operation::And(_, _) => "and",
+        Operation::Nand(_, _) => "nand",
+        Operation::Nor(_, _) => "nor",
+        Operation::Implies(_, _) => "implies",
+        Operation::IfElse(_, _, _) => "if_else",
+    }
+}
+
+impl<Pid: Property> Expression<Pid> {
+    /// Same as `eval`, but also counts what the evaluation did, see `EvalStats`.
+    pub fn eval_instrumented(
+        &self,
+        context: &Context<Pid>,
+    ) -> Result<(Evaluated<Pid>, EvalStats<Pid>)> {
+        self.eval_instrumented_with(context, &mut |_, _, _| ())
+    }
+
+    /// Same as `eval_instrumented`, `on_condition` is called with the property, its value and
+    /// the result every time a condition is tested, for example to feed a metrics system.
+    pub fn eval_instrumented_with(
+        &self,
+        context: &Context<Pid>,
+        on_condition: &mut dyn FnMut(&Pid, &Value, bool),
+    ) -> Result<(Evaluated<Pid>, EvalStats<Pid>)> {
+        let started = Instant::now();
+        let root = self.connected()?;
+        let reachable = self.reachable(root);
+        self.checked(&reachable)?;
+        let constant = self.no_conditions(&reachable);
+
+        let mut conditions = HashMap::new();
+        let mut condition_time = HashMap::new();
+        let mut folded = vec![None; self.ops.len()];
+        let evaluated = {
+            let mut condition = |idx, variable| {
+                let actual = match context.value(variable) {
+                    Some(actual) => actual,
+                    None => return Ok(None),
+                };
+                let testing = Instant::now();
+                let result = self.test(idx, actual)?;
+                *condition_time.entry(variable).or_default() += testing.elapsed();
+                *conditions.entry(variable).or_default() += 1;
+                on_condition(&variable, actual, result);
+                Ok(Some(result))
+            };
+            self.eval_into(root, &reachable, constant, &mut condition, &mut folded)?
+        };
+
+        let mut folds = HashMap::new();
+        for ((op, _), result) in self.ops.iter().zip(&folded) {
+            if let Some(Some(_)) = result {
+                *folds.entry(kind(op)).or_default() += 1;
+            }
+        }
+        let stats = EvalStats {
+            visited: folded.iter().filter(|result| result.is_some()).count(),
+            skipped: folded
+                .iter()
+                .zip(&reachable)
+                .filter(|(result, &reachable)| reachable && result.is_none())
+                .count(),
+            conditions,
+            condition_time,
+            folds,
+            elapsed: started.elapsed(),
+        };
+        Ok((evaluated, stats))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::testproperty::Property;
+
+    fn expression() -> Expression<Property> {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 42).unwrap();
+        let b = expr.is_in(Property::Str, vec!["x", "y"]).unwrap();
+        let c = expr.is(Property::Bool, true).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let not_c = expr.not(c).unwrap();
+        let _ = expr.or(a_and_b, not_c).unwrap();
+        expr
+    }
+
+    fn counts<K: std::hash::Hash + Eq>(counts: Vec<(K, usize)>) -> HashMap<K, usize> {
+        counts.into_iter().collect()
+    }
+
+    #[test]
+    fn eval_instrumented_short_circuit() {
+        let expr = expression();
+        let context = Context::from_values(vec![
+            (Property::Int, Value::Int(1)),
+            (Property::Bool, Value::Bool(false)),
+        ])
+        .unwrap();
+
+        let mut tested = Vec::new();
+        let (evaluated, stats) = expr
+            .eval_instrumented_with(&context, &mut |prop, value, result| {
+                tested.push((*prop, value.clone(), result))
+            })
+            .unwrap();
+        assert_eq!(
+            evaluated.to_string(),
+            expr.eval(&context).unwrap().to_string()
+        );
+        assert_eq!(evaluated.as_bool(), Some(true));
+
+        // `In` on Str is never reached once `Is` on Int is false
+        assert_eq!(stats.visited, 5);
+        assert_eq!(stats.skipped, 1);
+        assert_eq!(
+            stats.conditions,
+            counts(vec![(Property::Int, 1), (Property::Bool, 1)])
+        );
+        assert_eq!(stats.condition_time.len(), 2);
+        assert_eq!(
+            stats.folds,
+            counts(vec![("is", 2), ("and", 1), ("not", 1), ("or", 1)])
+        );
+        assert!(stats.elapsed >= stats.condition_time.values().sum());
+        assert_eq!(
+            tested,
+            vec![
+                (Property::Int, Value::Int(1), false),
+                (Property::Bool, Value::Bool(false), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn eval_instrumented_partial() {
+        let expr = expression();
+        let context = Context::from_values(vec![
+            (Property::Int, Value::Int(42)),
+            (Property::Bool, Value::Bool(true)),
+        ])
+        .unwrap();
+
+        let (evaluated, stats) = expr.eval_instrumented(&context).unwrap();
+        assert_eq!(
+            evaluated.to_string(),
+            expr.eval(&context).unwrap().to_string()
+        );
+        assert!(matches!(evaluated, Evaluated::Partially(_)));
+
+        // the condition on Str is visited, but there's nothing to test it against
+        assert_eq!(stats.visited, 6);
+        assert_eq!(stats.skipped, 0);
+        assert_eq!(
+            stats.conditions,
+            counts(vec![(Property::Int, 1), (Property::Bool, 1)])
+        );
+        assert_eq!(stats.folds, counts(vec![("is", 2), ("not", 1)]));
+
+        let result = Expression::<Property>::new().eval_instrumented(&context);
+        assert!(result.is_err());
+    }
+}
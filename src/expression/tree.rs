@@ -0,0 +1,214 @@
+use super::{Expression, OpRef, Operation};
+use crate::condition::{ApproxIs, Contains, In, Is, IsNot, Quantified};
+use crate::domain::Property;
+use crate::error::{Error, Result};
+
+/// Recursive form of an expression, convenient for pattern matching.
+///
+/// Unlike `Expression`, a tree can't share subtrees: every reference is expanded into a copy.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Tree<Pid: Property> {
+    Const(bool),
+    Is(Is<Pid>),
+    IsNot(IsNot<Pid>),
+    ApproxIs(ApproxIs<Pid>),
+    In(In<Pid>),
+    Contains(Contains<Pid>),
+    Quantified(Quantified<Pid>),
+    Not(Box<Tree<Pid>>),
+    Or(Box<Tree<Pid>>, Box<Tree<Pid>>),
+    And(Box<Tree<Pid>>, Box<Tree<Pid>>),
+    Nand(Box<Tree<Pid>>, Box<Tree<Pid>>),
+    Nor(Box<Tree<Pid>>, Box<Tree<Pid>>),
+    Implies(Box<Tree<Pid>>, Box<Tree<Pid>>),
+    IfElse(Box<Tree<Pid>>, Box<Tree<Pid>>, Box<Tree<Pid>>),
+}
+
+impl<Pid: Property> Expression<Pid> {
+    fn expand(&self, idx: OpRef) -> Result<Tree<Pid>> {
+        let op = &self.ops[idx].0;
+        let operand = |opref: OpRef| {
+            if opref < idx {
+                self.expand(opref).map(Box::new)
+            } else {
+                Err(Error::ExpressionFutureReference(opref, idx, self.summary()))
+            }
+        };
+
+        Ok(match *op {
+            Operation::Const(val) => Tree::Const(val),
+            Operation::Is(ref cond) => Tree::Is(cond.clone()),
+            Operation::IsNot(ref cond) => Tree::IsNot(cond.clone()),
+            Operation::ApproxIs(ref cond) => Tree::ApproxIs(cond.clone()),
+            Operation::In(ref cond) => Tree::In(cond.clone()),
+            Operation::Contains(ref cond) => Tree::Contains(cond.clone()),
+            Operation::Quantified(ref cond) => Tree::Quantified(cond.clone()),
+            Operation::Not(opref) => Tree::Not(operand(opref)?),
+            Operation::Or(lhs, rhs) => Tree::Or(operand(lhs)?, operand(rhs)?),
+            Operation::And(lhs, rhs) => Tree::And(operand(lhs)?, operand(rhs)?),
+            Operation::Nand(lhs, rhs) => Tree::Nand(operand(lhs)?, operand(rhs)?),
+            Operation::Nor(lhs, rhs) => Tree::Nor(operand(lhs)?, operand(rhs)?),
+            Operation::Implies(premise, conclusion) => {
+                Tree::Implies(operand(premise)?, operand(conclusion)?)
+            }
+            Operation::IfElse(cond, then, otherwise) => {
+                Tree::IfElse(operand(cond)?, operand(then)?, operand(otherwise)?)
+            }
+        })
+    }
+
+    fn flatten(&mut self, tree: &Tree<Pid>) -> OpRef {
+        let op = match tree {
+            Tree::Const(val) => Operation::Const(*val),
+            Tree::Is(cond) => Operation::Is(cond.clone()),
+            Tree::IsNot(cond) => Operation::IsNot(cond.clone()),
+            Tree::ApproxIs(cond) => Operation::ApproxIs(cond.clone()),
+            Tree::In(cond) => Operation::In(cond.clone()),
+            Tree::Contains(cond) => Operation::Contains(cond.clone()),
+            Tree::Quantified(cond) => Operation::Quantified(cond.clone()),
+            Tree::Not(operand) => Operation::Not(self.flatten(operand)),
+            Tree::Or(lhs, rhs) => Operation::Or(self.flatten(lhs), self.flatten(rhs)),
+            Tree::And(lhs, rhs) => Operation::And(self.flatten(lhs), self.flatten(rhs)),
+            Tree::Nand(lhs, rhs) => Operation::Nand(self.flatten(lhs), self.flatten(rhs)),
+            Tree::Nor(lhs, rhs) => Operation::Nor(self.flatten(lhs), self.flatten(rhs)),
+            Tree::Implies(premise, conclusion) => {
+                Operation::Implies(self.flatten(premise), self.flatten(conclusion))
+            }
+            Tree::IfElse(cond, then, otherwise) => Operation::IfElse(
+                self.flatten(cond),
+                self.flatten(then),
+                self.flatten(otherwise),
+            ),
+        };
+
+        for opref in op.operands() {
+            self.ops[opref].1 += 1;
+        }
+        self.ops.push((op, 0));
+        self.ops.len() - 1
+    }
+
+    /// Expands the operations reachable from the root into a tree,
+    /// shared operations are copied for every reference.
+    pub fn to_tree(&self) -> Result<Tree<Pid>> {
+        let root = self.root().ok_or(Error::ExpressionNoop)?;
+        self.expand(self.valid(root)?)
+    }
+
+    /// Flattens a tree back into an expression, the tree root becomes the last operation.
+    pub fn from_tree(tree: &Tree<Pid>) -> Expression<Pid> {
+        let mut expr = Expression::new();
+        expr.flatten(tree);
+        expr
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::expression::Context;
+    use crate::random::Random;
+    use crate::testproperty::Property;
+    use crate::value::Value;
+
+    fn random(expr: &mut Expression<Property>, rng: &mut Random, ops: usize) -> OpRef {
+        let leaf = |expr: &mut Expression<Property>, rng: &mut Random| match rng.below(4) {
+            0 => expr.constant(rng.below(2) == 0),
+            1 => expr.is(Property::Int, Value::Int(rng.below(3) as i64)),
+            2 => expr.is_in(
+                Property::Int,
+                vec![
+                    Value::Int(rng.below(3) as i64),
+                    Value::Int(rng.below(3) as i64),
+                ],
+            ),
+            _ => expr.is(Property::Bool, Value::Bool(rng.below(2) == 0)),
+        };
+
+        for _ in 0..ops {
+            // reuse existing operations now and then to get shared subtrees
+            let operand = |expr: &mut Expression<Property>, rng: &mut Random| {
+                if !expr.is_empty() && rng.below(3) == 0 {
+                    Ok(rng.below(expr.len()))
+                } else {
+                    leaf(expr, rng)
+                }
+            };
+            let lhs = operand(expr, rng).unwrap();
+            let rhs = operand(expr, rng).unwrap();
+            let cond = operand(expr, rng).unwrap();
+            match rng.below(5) {
+                0 => expr.not(lhs),
+                1 => expr.or(lhs, rhs),
+                2 => expr.and(lhs, rhs),
+                3 => expr.implies(lhs, rhs),
+                _ => expr.if_else(cond, lhs, rhs),
+            }
+            .unwrap();
+        }
+        expr.root().unwrap()
+    }
+
+    #[test]
+    fn tree_roundtrip_randomized() {
+        let mut rng = Random::new(42);
+
+        for _ in 0..50 {
+            let mut expr = Expression::<Property>::new();
+            let root = random(&mut expr, &mut rng, 6);
+            let expr = expr.subexpression(root).unwrap();
+
+            let roundtrip = Expression::from_tree(&expr.to_tree().unwrap());
+            assert!(roundtrip.validate().is_ok(), "{}", roundtrip);
+            assert_eq!(roundtrip, expr);
+
+            for int in 0..3 {
+                for boolean in &[true, false] {
+                    let context = Context::from_values(vec![
+                        (Property::Int, Value::Int(int)),
+                        (Property::Bool, Value::Bool(*boolean)),
+                    ])
+                    .unwrap();
+
+                    let expected = expr.eval(&context).unwrap().as_bool();
+                    let actual = roundtrip.eval(&context).unwrap().as_bool();
+                    assert!(expected.is_some());
+                    assert_eq!(expected, actual, "{} / {}", expr, context);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tree_diamond() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let b = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+        let not_a = expr.not(a).unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let _ = expr.and(not_a, a_or_b).unwrap();
+
+        let tree = expr.to_tree().unwrap();
+        let leaf = Box::new(Tree::Is(Is::new(Property::Int, Value::Int(42)).unwrap()));
+        match &tree {
+            Tree::And(lhs, rhs) => {
+                assert_eq!(**lhs, Tree::Not(leaf.clone()));
+                assert!(matches!(&**rhs, Tree::Or(lhs, _) if *lhs == leaf));
+            }
+            _ => panic!("unexpected tree: {:?}", tree),
+        }
+
+        // the shared leaf is duplicated when flattened back
+        let flat = Expression::from_tree(&tree);
+        assert!(flat.validate().is_ok());
+        assert_eq!(flat.len(), expr.len() + 1);
+        assert_eq!(flat, expr);
+    }
+
+    #[test]
+    fn tree_empty() {
+        let result = Expression::<Property>::new().to_tree();
+        assert!(matches!(result, Err(Error::ExpressionNoop)));
+    }
+}
@@ -0,0 +1,523 @@
+use std::collections::HashMap;
+
+use super::{Expression, OpRef, Operation};
+use crate::condition::{ApproxIs, Contains, In, Is, IsNot, Quantified};
+use crate::domain::Property;
+use crate::error::{Error, Result};
+
+/// Callbacks for a depth-first walk over an expression, see `Expression::visit`.
+///
+/// Every callback does nothing by default, so a visitor only implements the ones it cares about.
+/// Operators get an `enter_*` call before their operands are walked and an `exit_*` call after.
+///
+/// `visit_*` callbacks are the post-order subset for `Expression::accept`: the default `constant`,
+/// `is`, `is_in`, `exit_not`, `exit_or` and `exit_and` forward to them, overriding those
+/// skips the matching `visit_*` call.
+#[allow(unused_variables)]
+pub trait Visitor<Pid: Property> {
+    fn visit_const(&mut self, opref: OpRef, value: bool) {}
+    fn visit_is(&mut self, opref: OpRef, cond: &Is<Pid>) {}
+    fn visit_in(&mut self, opref: OpRef, cond: &In<Pid>) {}
+    fn visit_not(&mut self, opref: OpRef, operand: OpRef) {}
+    fn visit_or(&mut self, opref: OpRef, lhs: OpRef, rhs: OpRef) {}
+    fn visit_and(&mut self, opref: OpRef, lhs: OpRef, rhs: OpRef) {}
+
+    fn constant(&mut self, opref: OpRef, value: bool) {
+        self.visit_const(opref, value)
+    }
+    fn is(&mut self, opref: OpRef, cond: &Is<Pid>) {
+        self.visit_is(opref, cond)
+    }
+    fn is_not(&mut self, opref: OpRef, cond: &IsNot<Pid>) {}
+    fn approx_is(&mut self, opref: OpRef, cond: &ApproxIs<Pid>) {}
+    fn is_in(&mut self, opref: OpRef, cond: &In<Pid>) {
+        self.visit_in(opref, cond)
+    }
+    fn contains(&mut self, opref: OpRef, cond: &Contains<Pid>) {}
+    fn quantified(&mut self, opref: OpRef, cond: &Quantified<Pid>) {}
+
+    fn enter_not(&mut self, opref: OpRef, operand: OpRef) {}
+    fn exit_not(&mut self, opref: OpRef, operand: OpRef) {
+        self.visit_not(opref, operand)
+    }
+    fn enter_or(&mut self, opref: OpRef, lhs: OpRef, rhs: OpRef) {}
+    fn exit_or(&mut self, opref: OpRef, lhs: OpRef, rhs: OpRef) {
+        self.visit_or(opref, lhs, rhs)
+    }
+    fn enter_and(&mut self, opref: OpRef, lhs: OpRef, rhs: OpRef) {}
+    fn exit_and(&mut self, opref: OpRef, lhs: OpRef, rhs: OpRef) {
+        self.visit_and(opref, lhs, rhs)
+    }
+    fn enter_nand(&mut self, opref: OpRef, lhs: OpRef, rhs: OpRef) {}
+    fn exit_nand(&mut self, opref: OpRef, lhs: OpRef, rhs: OpRef) {}
+    fn enter_nor(&mut self, opref: OpRef, lhs: OpRef, rhs: OpRef) {}
+    fn exit_nor(&mut self, opref: OpRef, lhs: OpRef, rhs: OpRef) {}
+    fn enter_implies(&mut self, opref: OpRef, premise: OpRef, conclusion: OpRef) {}
+    fn exit_implies(&mut self, opref: OpRef, premise: OpRef, conclusion: OpRef) {}
+    fn enter_if_else(&mut self, opref: OpRef, cond: OpRef, then: OpRef, otherwise: OpRef) {}
+    fn exit_if_else(&mut self, opref: OpRef, cond: OpRef, then: OpRef, otherwise: OpRef) {}
+
+    /// Called instead of walking an operation again when it's referenced more than once.
+    fn revisit(&mut self, opref: OpRef) {}
+}
+
+/// Renders operations for `Display`, operations referenced more than once are rendered once.
+pub(super) struct Printer<'a, Pid: Property> {
+    expr: &'a Expression<Pid>,
+    stack: Vec<String>,
+    memo: HashMap<OpRef, String>,
+}
+
+impl<'a, Pid: Property> Printer<'a, Pid> {
+    pub(super) fn new(expr: &'a Expression<Pid>) -> Self {
+        Printer {
+            expr,
+            stack: Vec::new(),
+            memo: HashMap::new(),
+        }
+    }
+
+    /// Rendered operation the walk started from.
+    pub(super) fn finish(mut self) -> String {
+        self.pop()
+    }
+
+    fn push(&mut self, opref: OpRef, rendered: String) {
+        if self.expr.ops[opref].1 > 1 {
+            self.memo.insert(opref, rendered.clone());
+        }
+        self.stack.push(rendered);
+    }
+
+    fn pop(&mut self) -> String {
+        self.stack.pop().unwrap_or_default()
+    }
+
+    fn binary(&mut self, opref: OpRef, template: fn(String, String) -> String) {
+        let rhs = self.pop();
+        let lhs = self.pop();
+        self.push(opref, template(lhs, rhs));
+    }
+}
+
+impl<'a, Pid: Property> Visitor<Pid> for Printer<'a, Pid> {
+    fn constant(&mut self, opref: OpRef, value: bool) {
+        self.push(opref, format!("{}", value));
+    }
+    fn is(&mut self, opref: OpRef, cond: &Is<Pid>) {
+        self.push(opref, format!("{}", cond));
+    }
+    fn is_not(&mut self, opref: OpRef, cond: &IsNot<Pid>) {
+        self.push(opref, format!("{}", cond));
+    }
+    fn approx_is(&mut self, opref: OpRef, cond: &ApproxIs<Pid>) {
+        self.push(opref, format!("{}", cond));
+    }
+    fn is_in(&mut self, opref: OpRef, cond: &In<Pid>) {
+        self.push(opref, format!("{}", cond));
+    }
+    fn contains(&mut self, opref: OpRef, cond: &Contains<Pid>) {
+        self.push(opref, format!("{}", cond));
+    }
+    fn quantified(&mut self, opref: OpRef, cond: &Quantified<Pid>) {
+        self.push(opref, format!("{}", cond));
+    }
+
+    fn exit_not(&mut self, opref: OpRef, _: OpRef) {
+        let operand = self.pop();
+        self.push(opref, format!("!({})", operand));
+    }
+    fn exit_or(&mut self, opref: OpRef, _: OpRef, _: OpRef) {
+        self.binary(opref, |lhs, rhs| format!("({0} || {1})", lhs, rhs));
+    }
+    fn exit_and(&mut self, opref: OpRef, _: OpRef, _: OpRef) {
+        self.binary(opref, |lhs, rhs| format!("({0} && {1})", lhs, rhs));
+    }
+    fn exit_nand(&mut self, opref: OpRef, _: OpRef, _: OpRef) {
+        self.binary(opref, |lhs, rhs| format!("!({0} && {1})", lhs, rhs));
+    }
+    fn exit_nor(&mut self, opref: OpRef, _: OpRef, _: OpRef) {
+        self.binary(opref, |lhs, rhs| format!("!({0} || {1})", lhs, rhs));
+    }
+    fn exit_implies(&mut self, opref: OpRef, _: OpRef, _: OpRef) {
+        self.binary(opref, |premise, conclusion| {
+            format!("({0} -> {1})", premise, conclusion)
+        });
+    }
+    fn exit_if_else(&mut self, opref: OpRef, _: OpRef, _: OpRef, _: OpRef) {
+        let otherwise = self.pop();
+        let then = self.pop();
+        let cond = self.pop();
+        self.push(
+            opref,
+            format!("(if {0} then {1} else {2})", cond, then, otherwise),
+        );
+    }
+
+    fn revisit(&mut self, opref: OpRef) {
+        // reference counts may be off in an inconsistent expression, render it again then
+        let rendered = match self.memo.get(&opref) {
+            Some(rendered) => rendered.clone(),
+            None => self.expr.display(Some(opref)),
+        };
+        self.stack.push(rendered);
+    }
+}
+
+impl<Pid: Property> Expression<Pid> {
+    fn walk<V: Visitor<Pid>>(&self, idx: OpRef, visitor: &mut V, seen: &mut [bool]) -> Result<()> {
+        if seen[idx] {
+            visitor.revisit(idx);
+            return Ok(());
+        }
+        seen[idx] = true;
+
+        let op = &self.ops[idx].0;
+        for opref in op.operands() {
+            if opref >= idx {
+                return Err(Error::ExpressionFutureReference(opref, idx, self.summary()));
+            }
+        }
+
+        match *op {
+            Operation::Const(value) => visitor.constant(idx, value),
+            Operation::Is(ref cond) => visitor.is(idx, cond),
+            Operation::IsNot(ref cond) => visitor.is_not(idx, cond),
+            Operation::ApproxIs(ref cond) => visitor.approx_is(idx, cond),
+            Operation::In(ref cond) => visitor.is_in(idx, cond),
+            Operation::Contains(ref cond) => visitor.contains(idx, cond),
+            Operation::Quantified(ref cond) => visitor.quantified(idx, cond),
+            Operation::Not(operand) => {
+                visitor.enter_not(idx, operand);
+                self.walk(operand, visitor, seen)?;
+                visitor.exit_not(idx, operand);
+            }
+            Operation::Or(lhs, rhs) => {
+                visitor.enter_or(idx, lhs, rhs);
+                self.walk(lhs, visitor, seen)?;
+                self.walk(rhs, visitor, seen)?;
+                visitor.exit_or(idx, lhs, rhs);
+            }
+            Operation::And(lhs, rhs) => {
+                visitor.enter_and(idx, lhs, rhs);
+                self.walk(lhs, visitor, seen)?;
+                self.walk(rhs, visitor, seen)?;
+                visitor.exit_and(idx, lhs, rhs);
+            }
+            Operation::Nand(lhs, rhs) => {
+                visitor.enter_nand(idx, lhs, rhs);
+                self.walk(lhs, visitor, seen)?;
+                self.walk(rhs, visitor, seen)?;
+                visitor.exit_nand(idx, lhs, rhs);
+            }
+            Operation::Nor(lhs, rhs) => {
+                visitor.enter_nor(idx, lhs, rhs);
+                self.walk(lhs, visitor, seen)?;
+                self.walk(rhs, visitor, seen)?;
+                visitor.exit_nor(idx, lhs, rhs);
+            }
+            Operation::Implies(premise, conclusion) => {
+                visitor.enter_implies(idx, premise, conclusion);
+                self.walk(premise, visitor, seen)?;
+                self.walk(conclusion, visitor, seen)?;
+                visitor.exit_implies(idx, premise, conclusion);
+            }
+            Operation::IfElse(cond, then, otherwise) => {
+                visitor.enter_if_else(idx, cond, then, otherwise);
+                self.walk(cond, visitor, seen)?;
+                self.walk(then, visitor, seen)?;
+                self.walk(otherwise, visitor, seen)?;
+                visitor.exit_if_else(idx, cond, then, otherwise);
+            }
+        }
+        Ok(())
+    }
+
+    /// Same as `visit`, but starts from `idx` instead of the root.
+    pub(super) fn visit_from<V: Visitor<Pid>>(&self, idx: OpRef, visitor: &mut V) -> Result<()> {
+        self.valid(idx)?;
+
+        let mut seen = vec![false; self.ops.len()];
+        self.walk(idx, visitor, &mut seen)
+    }
+
+    /// Walks the expression depth-first from the root, operands left to right.
+    ///
+    /// Shared operations are walked once, every further reference to them only calls `revisit`.
+    pub fn visit<V: Visitor<Pid>>(&self, visitor: &mut V) -> Result<()> {
+        let root = self.root().ok_or(Error::ExpressionNoop)?;
+        self.visit_from(root, visitor)
+    }
+
+    /// Post-order walk from the root for visitors written against the `visit_*` callbacks:
+    /// every operation is visited after its operands. Same walk as `visit`.
+    pub fn accept<V: Visitor<Pid>>(&self, visitor: &mut V) -> Result<()> {
+        self.visit(visitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::testproperty::Property;
+    use crate::value::Value;
+
+    #[derive(Default)]
+    struct Counter {
+        nodes: usize,
+        revisits: usize,
+    }
+
+    impl Visitor<Property> for Counter {
+        fn constant(&mut self, _: OpRef, _: bool) {
+            self.nodes += 1;
+        }
+        fn is(&mut self, _: OpRef, _: &Is<Property>) {
+            self.nodes += 1;
+        }
+        fn is_in(&mut self, _: OpRef, _: &In<Property>) {
+            self.nodes += 1;
+        }
+        fn enter_not(&mut self, _: OpRef, _: OpRef) {
+            self.nodes += 1;
+        }
+        fn enter_or(&mut self, _: OpRef, _: OpRef, _: OpRef) {
+            self.nodes += 1;
+        }
+        fn enter_and(&mut self, _: OpRef, _: OpRef, _: OpRef) {
+            self.nodes += 1;
+        }
+        fn revisit(&mut self, _: OpRef) {
+            self.revisits += 1;
+        }
+    }
+
+    /// Counts operations by kind as they're finished, i.e. in post-order
+    #[derive(Default)]
+    struct Kinds {
+        counts: HashMap<&'static str, usize>,
+        order: Vec<OpRef>,
+    }
+
+    impl Kinds {
+        fn count(&mut self, opref: OpRef, kind: &'static str) {
+            *self.counts.entry(kind).or_insert(0) += 1;
+            self.order.push(opref);
+        }
+    }
+
+    impl Visitor<Property> for Kinds {
+        fn constant(&mut self, opref: OpRef, _: bool) {
+            self.count(opref, "const");
+        }
+        fn is(&mut self, opref: OpRef, _: &Is<Property>) {
+            self.count(opref, "is");
+        }
+        fn is_not(&mut self, opref: OpRef, _: &IsNot<Property>) {
+            self.count(opref, "is_not");
+        }
+        fn is_in(&mut self, opref: OpRef, _: &In<Property>) {
+            self.count(opref, "in");
+        }
+        fn contains(&mut self, opref: OpRef, _: &Contains<Property>) {
+            self.count(opref, "contains");
+        }
+        fn exit_not(&mut self, opref: OpRef, _: OpRef) {
+            self.count(opref, "not");
+        }
+        fn exit_or(&mut self, opref: OpRef, _: OpRef, _: OpRef) {
+            self.count(opref, "or");
+        }
+        fn exit_and(&mut self, opref: OpRef, _: OpRef, _: OpRef) {
+            self.count(opref, "and");
+        }
+        fn exit_implies(&mut self, opref: OpRef, _: OpRef, _: OpRef) {
+            self.count(opref, "implies");
+        }
+        fn exit_if_else(&mut self, opref: OpRef, _: OpRef, _: OpRef, _: OpRef) {
+            self.count(opref, "if_else");
+        }
+    }
+
+    /// Rebuilds the `Display` output, shared operations are rendered from memo on revisit
+    #[derive(Default)]
+    struct Printer {
+        stack: Vec<String>,
+        memo: HashMap<OpRef, String>,
+    }
+
+    impl Printer {
+        fn push(&mut self, opref: OpRef, rendered: String) {
+            self.memo.insert(opref, rendered.clone());
+            self.stack.push(rendered);
+        }
+    }
+
+    impl Visitor<Property> for Printer {
+        fn constant(&mut self, opref: OpRef, value: bool) {
+            self.push(opref, format!("{}", value));
+        }
+        fn is(&mut self, opref: OpRef, cond: &Is<Property>) {
+            self.push(opref, format!("{}", cond));
+        }
+        fn is_in(&mut self, opref: OpRef, cond: &In<Property>) {
+            self.push(opref, format!("{}", cond));
+        }
+        fn exit_not(&mut self, opref: OpRef, _: OpRef) {
+            let operand = self.stack.pop().unwrap();
+            self.push(opref, format!("!({})", operand));
+        }
+        fn exit_or(&mut self, opref: OpRef, _: OpRef, _: OpRef) {
+            let rhs = self.stack.pop().unwrap();
+            let lhs = self.stack.pop().unwrap();
+            self.push(opref, format!("({0} || {1})", lhs, rhs));
+        }
+        fn exit_and(&mut self, opref: OpRef, _: OpRef, _: OpRef) {
+            let rhs = self.stack.pop().unwrap();
+            let lhs = self.stack.pop().unwrap();
+            self.push(opref, format!("({0} && {1})", lhs, rhs));
+        }
+        fn revisit(&mut self, opref: OpRef) {
+            let rendered = self.memo[&opref].clone();
+            self.stack.push(rendered);
+        }
+    }
+
+    fn diamond() -> Expression<Property> {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, Value::Int(42)).unwrap();
+        let b = expr.is(Property::Bool, Value::Bool(true)).unwrap();
+        let _orphan = expr.constant(false).unwrap();
+        let not_a = expr.not(a).unwrap();
+        let a_or_b = expr.or(a, b).unwrap();
+        let _ = expr.and(not_a, a_or_b).unwrap();
+        expr
+    }
+
+    #[test]
+    fn visit_counts_shared_nodes_once() {
+        let mut counter = Counter::default();
+        diamond().visit(&mut counter).unwrap();
+
+        assert_eq!(counter.nodes, 5);
+        assert_eq!(counter.revisits, 1);
+    }
+
+    #[test]
+    fn visit_counts_kinds() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 1).unwrap();
+        let b = expr.is_not(Property::Bool, true).unwrap();
+        let c = expr.is_in(Property::Str, vec!["foo", "bar"]).unwrap();
+        let d = expr.contains(Property::Str, "o").unwrap();
+        let t = expr.constant(true).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let not_c = expr.not(c).unwrap();
+        let c_or_d = expr.or(not_c, d).unwrap();
+        let implies = expr.implies(a_and_b, c_or_d).unwrap();
+        let _ = expr.if_else(implies, t, a_and_b).unwrap();
+
+        let mut kinds = Kinds::default();
+        expr.visit(&mut kinds).unwrap();
+
+        let expected = vec![
+            ("is", 1),
+            ("is_not", 1),
+            ("in", 1),
+            ("contains", 1),
+            ("const", 1),
+            ("and", 1),
+            ("not", 1),
+            ("or", 1),
+            ("implies", 1),
+            ("if_else", 1),
+        ];
+        assert_eq!(kinds.counts, expected.into_iter().collect());
+        // operands are always finished before the operations referencing them
+        assert_eq!(kinds.order, vec![0, 1, 5, 2, 6, 3, 7, 8, 4, 9]);
+    }
+
+    /// Counts the kinds `visit_*` callbacks are there for, in the order they're called
+    #[derive(Default)]
+    struct PostOrder {
+        counts: HashMap<&'static str, usize>,
+        order: Vec<OpRef>,
+    }
+
+    impl PostOrder {
+        fn count(&mut self, opref: OpRef, kind: &'static str) {
+            *self.counts.entry(kind).or_insert(0) += 1;
+            self.order.push(opref);
+        }
+    }
+
+    impl Visitor<Property> for PostOrder {
+        fn visit_const(&mut self, opref: OpRef, _: bool) {
+            self.count(opref, "const");
+        }
+        fn visit_is(&mut self, opref: OpRef, _: &Is<Property>) {
+            self.count(opref, "is");
+        }
+        fn visit_in(&mut self, opref: OpRef, _: &In<Property>) {
+            self.count(opref, "in");
+        }
+        fn visit_not(&mut self, opref: OpRef, _: OpRef) {
+            self.count(opref, "not");
+        }
+        fn visit_or(&mut self, opref: OpRef, _: OpRef, _: OpRef) {
+            self.count(opref, "or");
+        }
+        fn visit_and(&mut self, opref: OpRef, _: OpRef, _: OpRef) {
+            self.count(opref, "and");
+        }
+    }
+
+    #[test]
+    fn accept_counts_kinds() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 1).unwrap();
+        let b = expr.is(Property::Bool, true).unwrap();
+        let c = expr.is_in(Property::Str, vec!["foo", "bar"]).unwrap();
+        let d = expr.contains(Property::Str, "o").unwrap();
+        let t = expr.constant(true).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let not_c = expr.not(c).unwrap();
+        let c_or_d = expr.or(not_c, d).unwrap();
+        let implies = expr.implies(a_and_b, c_or_d).unwrap();
+        let a_or_t = expr.or(a, t).unwrap();
+        let _ = expr.and(implies, a_or_t).unwrap();
+
+        let mut post = PostOrder::default();
+        expr.accept(&mut post).unwrap();
+
+        let expected = vec![
+            ("is", 2),
+            ("in", 1),
+            ("const", 1),
+            ("and", 2),
+            ("not", 1),
+            ("or", 2),
+        ];
+        assert_eq!(post.counts, expected.into_iter().collect());
+        // contains and implies have no `visit_*` callback, `a` is visited once
+        assert_eq!(post.order, vec![0, 1, 5, 2, 6, 7, 4, 9, 10]);
+    }
+
+    #[test]
+    fn visit_matches_display() {
+        let expr = diamond();
+        let mut printer = Printer::default();
+        expr.visit(&mut printer).unwrap();
+
+        assert_eq!(printer.stack, vec![format!("{}", expr)]);
+    }
+
+    #[test]
+    fn visit_empty() {
+        let result = Expression::<Property>::new().visit(&mut Counter::default());
+        assert!(matches!(result, Err(Error::ExpressionNoop)));
+    }
+}
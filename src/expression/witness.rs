@@ -0,0 +1,187 @@
+use std::collections::{BTreeSet, HashMap};
+
+use super::{Context, Expression};
+use crate::domain::Property;
+use crate::error::Result;
+use crate::random::Random;
+use crate::value::{Datatype, Value};
+
+/// Assignments tried by `find_witness` before giving up.
+pub const MAX_WITNESS_ATTEMPTS: usize = 1 << 12;
+
+/// Value just outside of `values`: the largest integer plus one, or the largest string
+/// with a character appended. `None` for other datatypes or if there's nothing to start from.
+fn near_miss(datatype: Datatype, values: &BTreeSet<Value>) -> Option<Value> {
+    let largest = values.iter().next_back()?;
+    match (datatype, largest) {
+        (Datatype::Int, &Value::Int(val)) => val.checked_add(1).map(Value::Int),
+        (Datatype::UInt, &Value::UInt(val)) => val.checked_add(1).map(Value::UInt),
+        (Datatype::Str, val) => val.as_str().map(|val| Value::Str(format!("{}~", val))),
+        _ => None,
+    }
+}
+
+impl<Pid: Property> Expression<Pid> {
+    /// Sample values the expression evaluates to `want` for, see `find_witness_within`.
+    /// Tries at most `MAX_WITNESS_ATTEMPTS` assignments.
+    pub fn find_witness(&self, want: bool, seed: u64) -> Result<Option<Context<Pid>>> {
+        self.find_witness_within(want, seed, MAX_WITNESS_ATTEMPTS)
+    }
+
+    /// Sample values of every property used by the expression that evaluate it to `want`.
+    ///
+    /// Values are drawn from the expression's own conditions, plus ones that none of them
+    /// mention: the largest integer incremented, the largest string with a character appended
+    /// and a fresh value like `is_satisfiable` uses. If there are no more than `max_attempts`
+    /// assignments of those, all of them are tried, otherwise `max_attempts` random ones.
+    /// The result only depends on `seed` and the expression. `None` if nothing worked out.
+    pub fn find_witness_within(
+        &self,
+        want: bool,
+        seed: u64,
+        max_attempts: usize,
+    ) -> Result<Option<Context<Pid>>> {
+        let root = self.connected()?;
+        let reachable = self.reachable(root);
+        self.checked(&reachable)?;
+
+        let (properties, _) = self.candidates(&reachable);
+        let values = properties
+            .iter()
+            .map(|(prop, candidates)| {
+                let mut candidates = candidates.clone();
+                candidates.extend(near_miss(prop.datatype(), &candidates));
+                candidates.into_iter().collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let assignments = values
+            .iter()
+            .fold(1usize, |total, values| total.saturating_mul(values.len()));
+
+        let index = properties
+            .iter()
+            .enumerate()
+            .map(|(pos, (prop, _))| (*prop, pos))
+            .collect::<HashMap<_, _>>();
+        let mut random = Random::new(seed);
+        // few enough assignments to try them all, starting from a random one
+        let exhaustive = assignments <= max_attempts;
+        let start = match assignments {
+            0 => 0,
+            _ => random.below(assignments),
+        };
+
+        let mut choice = vec![0; values.len()];
+        let mut folded = vec![None; self.ops.len()];
+        for attempt in 0..max_attempts.min(assignments) {
+            if exhaustive {
+                let mut number = (start + attempt) % assignments;
+                for (choice, values) in choice.iter_mut().zip(&values) {
+                    *choice = number % values.len();
+                    number /= values.len();
+                }
+            } else {
+                for (choice, values) in choice.iter_mut().zip(&values) {
+                    *choice = random.below(values.len());
+                }
+            }
+
+            for result in folded.iter_mut() {
+                *result = None;
+            }
+            let mut condition = |idx, variable| {
+                let pos = index[&variable];
+                self.test(idx, &values[pos][choice[pos]]).map(Some)
+            };
+            if self.fold(root, &[], &mut condition, &mut folded)? == Some(want) {
+                let witness = properties
+                    .iter()
+                    .zip(&choice)
+                    .enumerate()
+                    .map(|(pos, ((prop, _), &choice))| (*prop, values[pos][choice].clone()));
+                return Ok(Some(Context::from_values(witness)?));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::expression::Evaluated;
+    use crate::testproperty::Property;
+
+    fn rule() -> Expression<Property> {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is_in(Property::Int, vec![1, 2]).unwrap();
+        let b = expr.is_not(Property::Str, "a").unwrap();
+        let c = expr.is(Property::Int, 1).unwrap();
+        let d = expr.is(Property::Bool, true).unwrap();
+        let not_c = expr.not(c).unwrap();
+        let a_and_b = expr.and(a, b).unwrap();
+        let a_and_b_and_not_c = expr.and(a_and_b, not_c).unwrap();
+        let _ = expr.or(a_and_b_and_not_c, d).unwrap();
+        expr
+    }
+
+    #[test]
+    fn find_witness_outcomes() {
+        let expr = rule();
+        for &want in &[true, false] {
+            for seed in 0..8 {
+                let context = expr.find_witness(want, seed).unwrap().unwrap();
+                match expr.eval(&context).unwrap() {
+                    Evaluated::Fully(result, _, _) => assert_eq!(result, want, "{}", context),
+                    result => panic!("{}: unexpected result {}", context, result),
+                }
+
+                // same seed, same witness
+                let again = expr.find_witness(want, seed).unwrap().unwrap();
+                assert_eq!(again.to_string(), context.to_string());
+            }
+        }
+
+        // too many assignments to try them all, random ones are picked the same way
+        let sampled = |seed| {
+            let context = expr.find_witness_within(true, seed, 3).unwrap();
+            context.map(|context| context.to_string())
+        };
+        for seed in 0..8 {
+            assert_eq!(sampled(seed), sampled(seed));
+        }
+    }
+
+    #[test]
+    fn find_witness_near_miss() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 41).unwrap();
+        let b = expr.is(Property::Str, "abc").unwrap();
+        let _ = expr.or(a, b).unwrap();
+        let (properties, _) = expr.candidates(&[true; 3]);
+        let near = properties
+            .iter()
+            .zip(&[Datatype::Int, Datatype::Str])
+            .map(|((_, values), &datatype)| near_miss(datatype, values))
+            .collect::<Vec<_>>();
+        assert_eq!(near, vec![Some(Value::Int(42)), Some(Value::from("abc~"))]);
+
+        let context = expr.find_witness(false, 1).unwrap().unwrap();
+        assert!(matches!(
+            expr.eval(&context).unwrap(),
+            Evaluated::Fully(false, _, _)
+        ));
+    }
+
+    #[test]
+    fn find_witness_unsatisfiable() {
+        let mut expr = Expression::<Property>::new();
+        let a = expr.is(Property::Int, 1).unwrap();
+        let b = expr.is(Property::Int, 2).unwrap();
+        let _ = expr.and(a, b).unwrap();
+        assert!(expr.find_witness(true, 7).unwrap().is_none());
+        assert!(expr.find_witness(false, 7).unwrap().is_some());
+        assert!(expr.find_witness_within(false, 7, 0).unwrap().is_none());
+    }
+}
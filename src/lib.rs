@@ -6,4 +6,8 @@ pub mod domain;
 pub mod condition;
 pub mod expression;
 
+mod macros;
+mod random;
+
+#[cfg(test)]
 mod testproperty;
@@ -0,0 +1,156 @@
+/// Builds an `Expression` from an inline infix DSL.
+///
+/// Supported syntax, from the loosest to the tightest binding:
+/// `a || b`, `a && b`, `!a`, `(a)`, `Prop == literal`, `Prop in [literal, ...]`, `true`/`false`.
+/// Property identifiers are resolved by name via the property's `FromStr` implementation,
+/// literals are converted with `Value::from`.
+///
+/// Expands into the regular builder calls, so the expression is built in the same order
+/// as a hand-written one would be. Evaluates to `Result<Expression<Pid>>` carrying the first
+/// builder error (unknown property, type mismatch, ...).
+///
+/// ```
+/// # use domain_query::{domain, expr, expression::Expression, value};
+/// # use std::fmt::{Display, Formatter, Result as FmtResult};
+/// # use strum_macros::{EnumIter, EnumString};
+/// # #[derive(PartialEq, Clone, Copy, Hash, Eq, Debug, EnumIter, EnumString)]
+/// # enum Property { AlbumArtist, AlbumListeners, TrackName }
+/// # impl Display for Property {
+/// #     fn fmt(&self, f: &mut Formatter) -> FmtResult { write!(f, "{:?}", self) }
+/// # }
+/// # impl domain::DomainEnum for Property {}
+/// # impl domain::Property for Property {
+/// #     fn name(&self) -> &'static str { "property" }
+/// #     fn datatype(&self) -> value::Datatype {
+/// #         match self {
+/// #             Property::AlbumListeners => value::Datatype::Int,
+/// #             _ => value::Datatype::Str,
+/// #         }
+/// #     }
+/// # }
+/// # fn main() -> domain_query::error::Result<()> {
+/// let e: Expression<Property> = expr! {
+///     (AlbumArtist == "Radiohead") && (AlbumListeners in [1, 2, 3]) || !(TrackName == "x")
+/// }?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Malformed input is rejected at compile time:
+///
+/// ```compile_fail
+/// # use domain_query::{domain, expr, expression::Expression, value};
+/// # use std::fmt::{Display, Formatter, Result as FmtResult};
+/// # use strum_macros::{EnumIter, EnumString};
+/// # #[derive(PartialEq, Clone, Copy, Hash, Eq, Debug, EnumIter, EnumString)]
+/// # enum Property { AlbumArtist, AlbumListeners, TrackName }
+/// # impl Display for Property {
+/// #     fn fmt(&self, f: &mut Formatter) -> FmtResult { write!(f, "{:?}", self) }
+/// # }
+/// # impl domain::DomainEnum for Property {}
+/// # impl domain::Property for Property {
+/// #     fn name(&self) -> &'static str { "property" }
+/// #     fn datatype(&self) -> value::Datatype { value::Datatype::Str }
+/// # }
+/// # fn main() -> domain_query::error::Result<()> {
+/// let e: Expression<Property> = expr! { (AlbumArtist == "Radiohead") && }?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// ```compile_fail
+/// # use domain_query::{domain, expr, expression::Expression, value};
+/// # use std::fmt::{Display, Formatter, Result as FmtResult};
+/// # use strum_macros::{EnumIter, EnumString};
+/// # #[derive(PartialEq, Clone, Copy, Hash, Eq, Debug, EnumIter, EnumString)]
+/// # enum Property { AlbumArtist, AlbumListeners, TrackName }
+/// # impl Display for Property {
+/// #     fn fmt(&self, f: &mut Formatter) -> FmtResult { write!(f, "{:?}", self) }
+/// # }
+/// # impl domain::DomainEnum for Property {}
+/// # impl domain::Property for Property {
+/// #     fn name(&self) -> &'static str { "property" }
+/// #     fn datatype(&self) -> value::Datatype { value::Datatype::Str }
+/// # }
+/// # fn main() -> domain_query::error::Result<()> {
+/// let e: Expression<Property> = expr! { AlbumArtist = "Radiohead" }?;
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! expr {
+    // ||: lowest precedence, left associative
+    (@or $e:ident $acc:expr; [$($cur:tt)+] || $($rest:tt)+) => {{
+        let rhs = $crate::expr!(@and $e None; [] $($cur)+);
+        let acc = match $acc {
+            Some(lhs) => $e.or(lhs, rhs)?,
+            None => rhs,
+        };
+        $crate::expr!(@or $e Some(acc); [] $($rest)+)
+    }};
+    (@or $e:ident $acc:expr; [$($cur:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::expr!(@or $e $acc; [$($cur)* $next] $($rest)*)
+    };
+    (@or $e:ident $acc:expr; [$($cur:tt)+]) => {{
+        let rhs = $crate::expr!(@and $e None; [] $($cur)+);
+        match $acc {
+            Some(lhs) => $e.or(lhs, rhs)?,
+            None => rhs,
+        }
+    }};
+
+    // &&: binds tighter than ||, left associative
+    (@and $e:ident $acc:expr; [$($cur:tt)+] && $($rest:tt)+) => {{
+        let rhs = $crate::expr!(@unary $e $($cur)+);
+        let acc = match $acc {
+            Some(lhs) => $e.and(lhs, rhs)?,
+            None => rhs,
+        };
+        $crate::expr!(@and $e Some(acc); [] $($rest)+)
+    }};
+    (@and $e:ident $acc:expr; [$($cur:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::expr!(@and $e $acc; [$($cur)* $next] $($rest)*)
+    };
+    (@and $e:ident $acc:expr; [$($cur:tt)+]) => {{
+        let rhs = $crate::expr!(@unary $e $($cur)+);
+        match $acc {
+            Some(lhs) => $e.and(lhs, rhs)?,
+            None => rhs,
+        }
+    }};
+
+    // negation, grouping and leaf conditions
+    (@unary $e:ident ! $($rest:tt)+) => {{
+        let opref = $crate::expr!(@unary $e $($rest)+);
+        $e.not(opref)?
+    }};
+    (@unary $e:ident ($($inner:tt)+)) => {
+        $crate::expr!(@or $e None; [] $($inner)+)
+    };
+    (@unary $e:ident true) => {
+        $e.constant(true)?
+    };
+    (@unary $e:ident false) => {
+        $e.constant(false)?
+    };
+    (@unary $e:ident $prop:ident == $val:literal) => {
+        $e.is(
+            $crate::expression::lookup(stringify!($prop))?,
+            $crate::value::Value::from($val),
+        )?
+    };
+    (@unary $e:ident $prop:ident in [$($val:literal),* $(,)?]) => {
+        $e.is_in(
+            $crate::expression::lookup(stringify!($prop))?,
+            vec![$($crate::value::Value::from($val)),*],
+        )?
+    };
+
+    ($($tokens:tt)+) => {
+        (|| -> $crate::error::Result<$crate::expression::Expression<_>> {
+            let mut expr = $crate::expression::Expression::new();
+            let _ = $crate::expr!(@or expr None; [] $($tokens)+);
+            Ok(expr)
+        })()
+    };
+}
@@ -0,0 +1,23 @@
+/// Splitmix64 sequence, enough to pick candidates reproducibly for a given seed.
+/// Not meant for anything that needs unpredictable numbers.
+#[derive(Debug, Clone)]
+pub(crate) struct Random(u64);
+
+impl Random {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut val = self.0;
+        val = (val ^ (val >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        val = (val ^ (val >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        val ^ (val >> 31)
+    }
+
+    /// Number in `0..bound`, `bound` must not be zero.
+    pub(crate) fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
@@ -6,6 +6,7 @@ use strum_macros::{EnumIter, EnumString};
 pub enum Property {
     Bool,
     Int,
+    UInt,
     Str,
 }
 
@@ -17,6 +18,7 @@ impl Display for Property {
             match &self {
                 Property::Bool => "Property::Bool",
                 Property::Int => "Property::Int",
+                Property::UInt => "Property::UInt",
                 Property::Str => "Property::Str",
             }
         )
@@ -30,6 +32,7 @@ impl domain::Property for Property {
         match &self {
             Property::Bool => "Property::Bool",
             Property::Int => "Property::Int",
+            Property::UInt => "Property::UInt",
             Property::Str => "Property::Str",
         }
     }
@@ -38,7 +41,143 @@ impl domain::Property for Property {
         match &self {
             Property::Bool => value::Datatype::Bool,
             Property::Int => value::Datatype::Int,
+            Property::UInt => value::Datatype::UInt,
             Property::Str => value::Datatype::Str,
         }
     }
 }
+
+/// Property with a unit of measurement.
+#[derive(PartialEq, Clone, Copy, Hash, Eq, Debug, EnumIter, EnumString)]
+pub enum Measured {
+    Listeners,
+}
+
+impl Display for Measured {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "Measured::Listeners")
+    }
+}
+
+impl domain::DomainEnum for Measured {}
+
+impl domain::Property for Measured {
+    fn name(&self) -> &'static str {
+        "Measured::Listeners"
+    }
+
+    fn datatype(&self) -> value::Datatype {
+        value::Datatype::Int
+    }
+
+    fn unit(&self) -> Option<&'static str> {
+        Some("listeners")
+    }
+}
+
+/// Property accepting values of other datatypes if they can be coerced.
+#[derive(PartialEq, Clone, Copy, Hash, Eq, Debug, EnumIter, EnumString)]
+pub enum Coercible {
+    Plays,
+}
+
+impl Display for Coercible {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "Coercible::Plays")
+    }
+}
+
+impl domain::DomainEnum for Coercible {}
+
+impl domain::Property for Coercible {
+    fn name(&self) -> &'static str {
+        "Coercible::Plays"
+    }
+
+    fn datatype(&self) -> value::Datatype {
+        value::Datatype::UInt
+    }
+
+    fn coercible(&self) -> bool {
+        true
+    }
+}
+
+/// List-valued property.
+#[derive(PartialEq, Clone, Copy, Hash, Eq, Debug, EnumIter, EnumString)]
+pub enum Listed {
+    Tags,
+}
+
+impl Display for Listed {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "Listed::Tags")
+    }
+}
+
+impl domain::DomainEnum for Listed {}
+
+impl domain::Property for Listed {
+    fn name(&self) -> &'static str {
+        "Listed::Tags"
+    }
+
+    fn datatype(&self) -> value::Datatype {
+        value::Datatype::List(&value::Datatype::Str)
+    }
+}
+
+/// Float property.
+#[derive(PartialEq, Clone, Copy, Hash, Eq, Debug, EnumIter, EnumString)]
+pub enum Rated {
+    Rating,
+}
+
+impl Display for Rated {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "Rated::Rating")
+    }
+}
+
+impl domain::DomainEnum for Rated {}
+
+impl domain::Property for Rated {
+    fn name(&self) -> &'static str {
+        "Rated::Rating"
+    }
+
+    fn datatype(&self) -> value::Datatype {
+        value::Datatype::Float
+    }
+}
+
+/// Entity owning some of `Property` variants.
+#[derive(PartialEq, Clone, Copy, Hash, Eq, Debug, EnumIter, EnumString)]
+pub enum Entity {
+    Album,
+    Track,
+}
+
+impl Display for Entity {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl domain::DomainEnum for Entity {}
+
+impl domain::Entity<Property> for Entity {
+    fn name(&self) -> &str {
+        match &self {
+            Entity::Album => "Entity::Album",
+            Entity::Track => "Entity::Track",
+        }
+    }
+
+    fn properties(&self) -> &[Property] {
+        match &self {
+            Entity::Album => &[Property::Int, Property::Str],
+            Entity::Track => &[Property::Bool, Property::UInt],
+        }
+    }
+}
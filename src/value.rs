@@ -1,28 +1,335 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::convert::TryFrom;
 use std::fmt::{Display, Formatter, Result as FmtResult};
-use strum_macros::Display as StrumDisplay;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, StrumDisplay)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Datatype {
     Bool,
     Int,
+    UInt,
+    Float,
     Str,
+    /// List of scalar values of the given datatype, e.g. `Datatype::List(&Datatype::Str)`.
+    List(&'static Datatype),
+    /// Datatype of `Value::Param` placeholders, properties aren't supposed to use it.
+    Param,
 }
 
+impl Datatype {
+    /// Static counterpart of a scalar datatype, `None` for lists: lists can't be nested.
+    pub fn scalar(self) -> Option<&'static Datatype> {
+        match self {
+            Datatype::Bool => Some(&Datatype::Bool),
+            Datatype::Int => Some(&Datatype::Int),
+            Datatype::UInt => Some(&Datatype::UInt),
+            Datatype::Float => Some(&Datatype::Float),
+            Datatype::Str => Some(&Datatype::Str),
+            Datatype::List(_) | Datatype::Param => None,
+        }
+    }
+}
+
+/// Scalars are printed as their names, lists as `List<Str>`.
+impl Display for Datatype {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            Datatype::Bool => write!(f, "Bool"),
+            Datatype::Int => write!(f, "Int"),
+            Datatype::UInt => write!(f, "UInt"),
+            Datatype::Float => write!(f, "Float"),
+            Datatype::Str => write!(f, "Str"),
+            Datatype::List(element) => write!(f, "List<{}>", element),
+            Datatype::Param => write!(f, "Param"),
+        }
+    }
+}
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// Values of different datatypes are ordered by datatype:
+/// `Bool` < `Int` < `UInt` < `Float` < `Str` < `List` < `Param`.
+///
+/// `Int` and `UInt` are distinct datatypes: `Int(1)` and `UInt(1)` aren't equal, and they can
+/// only be used with the same property if it's `Property::coercible`.
+///
+/// `Float` values are ordered like `f64::total_cmp`, except that `-0.0` and `0.0` are equal,
+/// so NaN equals itself and floats can be kept in sets like other values.
+///
+/// `Str` and `InternedStr` are the same datatype and are compared and hashed by content.
+#[derive(Debug, Clone)]
 pub enum Value {
     Bool(bool),
     Int(i64),
+    UInt(u64),
+    Float(f64),
     Str(String),
+    /// String shared with other values, clones don't copy it. See `Interner`.
+    InternedStr(Arc<str>),
+    /// Element datatype and elements, the datatype keeps empty lists typed.
+    /// Use `Value::list` to build one with the elements checked.
+    List(&'static Datatype, Vec<Value>),
+    /// Named placeholder for a value that's provided later with `Expression::bind`,
+    /// its datatype is checked against the property only then.
+    Param(String),
+}
+
+/// Borrowed view of a value for comparisons and hashing, both string variants look the same.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Key<'v> {
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(i64),
+    Str(&'v str),
+    List(&'static Datatype, &'v [Value]),
+    Param(&'v str),
+}
+
+/// Integer ordered the same way as `val`, see `Value`.
+fn float_key(val: f64) -> i64 {
+    let bits = if val == 0.0 { 0 } else { val.to_bits() as i64 };
+    // negative floats are ordered backwards by their bits, flip all but the sign bit
+    bits ^ (((bits >> 63) as u64) >> 1) as i64
 }
 
 impl Value {
+    fn key(&self) -> Key<'_> {
+        match self {
+            Value::Bool(val) => Key::Bool(*val),
+            Value::Int(val) => Key::Int(*val),
+            Value::UInt(val) => Key::UInt(*val),
+            Value::Float(val) => Key::Float(float_key(*val)),
+            Value::Str(val) => Key::Str(val),
+            Value::InternedStr(val) => Key::Str(val),
+            Value::List(element, items) => Key::List(element, items),
+            Value::Param(name) => Key::Param(name),
+        }
+    }
+
+    /// Contents of either string variant, `None` for other values.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(val) => Some(val),
+            Value::InternedStr(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    /// List of `element` values, `None` if `element` is a list itself
+    /// or any of the items is of another datatype.
+    pub fn list<I, V>(element: Datatype, items: I) -> Option<Value>
+    where
+        I: IntoIterator<Item = V>,
+        V: Into<Value>,
+    {
+        let list = Value::List(element.scalar()?, items.into_iter().map(Into::into).collect());
+        Some(list).filter(Value::is_consistent)
+    }
+
+    /// Parses `raw` as a scalar value of `datatype`, `None` if it isn't one or `datatype` isn't scalar.
+    pub fn parse(raw: &str, datatype: Datatype) -> Option<Value> {
+        match datatype {
+            Datatype::Bool => raw.parse().map(Value::Bool).ok(),
+            Datatype::Int => raw.parse().map(Value::Int).ok(),
+            Datatype::UInt => raw.parse().map(Value::UInt).ok(),
+            Datatype::Float => raw.parse().map(Value::Float).ok(),
+            Datatype::Str => Some(Value::Str(raw.to_owned())),
+            Datatype::List(_) | Datatype::Param => None,
+        }
+    }
+
+    /// Best-effort value of `raw` without a datatype: `Bool` for `true` / `false`, `Int` if it is
+    /// an `i64`, `Str` otherwise. Note that `"42"` always becomes `Int`, use `Value::parse`
+    /// for string properties that may hold numbers.
+    pub fn infer(raw: &str) -> Value {
+        Value::parse(raw, Datatype::Bool)
+            .or_else(|| Value::parse(raw, Datatype::Int))
+            .unwrap_or_else(|| Value::Str(raw.to_owned()))
+    }
+
     pub fn datatype(&self) -> Datatype {
         match *self {
             Value::Bool(_) => Datatype::Bool,
             Value::Int(_) => Datatype::Int,
-            Value::Str(_) => Datatype::Str,
+            Value::UInt(_) => Datatype::UInt,
+            Value::Float(_) => Datatype::Float,
+            Value::Str(_) | Value::InternedStr(_) => Datatype::Str,
+            Value::List(element, _) => Datatype::List(element),
+            Value::Param(_) => Datatype::Param,
+        }
+    }
+
+    /// Whether every element of a list is of its element datatype, always true for scalars.
+    pub fn is_consistent(&self) -> bool {
+        match self {
+            Value::List(element, items) => {
+                element.scalar().is_some() && items.iter().all(|item| item.datatype() == **element)
+            }
+            _ => true,
+        }
+    }
+
+    /// Converts the value into `target` datatype if that loses nothing: `Bool` into `0` / `1`
+    /// integers, `Int` into `UInt` and back if it fits. Returns `None` for any other conversion.
+    pub fn coerce(&self, target: Datatype) -> Option<Value> {
+        match (self, target) {
+            (value, target) if value.datatype() == target => Some(value.clone()),
+            (&Value::Bool(val), Datatype::Int) => Some(Value::Int(i64::from(val))),
+            (&Value::Bool(val), Datatype::UInt) => Some(Value::UInt(u64::from(val))),
+            (&Value::Int(val), Datatype::UInt) => u64::try_from(val).ok().map(Value::UInt),
+            (&Value::UInt(val), Datatype::Int) => i64::try_from(val).ok().map(Value::Int),
+            _ => None,
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Value {}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state)
+    }
+}
+
+/// Pool of shared strings: interning the same content twice yields values sharing its storage.
+#[derive(Debug, Default)]
+pub struct Interner {
+    pool: HashSet<Arc<str>>,
+}
+
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `Value::InternedStr` with `text`, stored in the pool the first time it's seen.
+    pub fn intern(&mut self, text: &str) -> Value {
+        if let Some(shared) = self.pool.get(text) {
+            return Value::InternedStr(Arc::clone(shared));
+        }
+
+        let shared: Arc<str> = Arc::from(text);
+        self.pool.insert(Arc::clone(&shared));
+        Value::InternedStr(shared)
+    }
+
+    /// Number of distinct strings in the pool.
+    pub fn len(&self) -> usize {
+        self.pool.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pool.is_empty()
+    }
+}
+
+impl From<bool> for Value {
+    fn from(val: bool) -> Self {
+        Value::Bool(val)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(val: i64) -> Self {
+        Value::Int(val)
+    }
+}
+
+/// Unsuffixed integer literals default to `i32`, this lets them be passed where `Into<Value>` is expected
+impl From<i32> for Value {
+    fn from(val: i32) -> Self {
+        Value::Int(i64::from(val))
+    }
+}
+
+impl From<u64> for Value {
+    fn from(val: u64) -> Self {
+        Value::UInt(val)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(val: f64) -> Self {
+        Value::Float(val)
+    }
+}
+
+impl From<String> for Value {
+    fn from(val: String) -> Self {
+        Value::Str(val)
+    }
+}
+
+impl From<Arc<str>> for Value {
+    fn from(val: Arc<str>) -> Self {
+        Value::InternedStr(val)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(val: &str) -> Self {
+        Value::Str(val.to_owned())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<&Value> for serde_json::Value {
+    fn from(val: &Value) -> Self {
+        match val {
+            Value::Bool(val) => serde_json::Value::from(*val),
+            Value::Int(val) => serde_json::Value::from(*val),
+            Value::UInt(val) => serde_json::Value::from(*val),
+            // JSON has no NaN or infinities, they become `null`
+            Value::Float(val) => serde_json::Value::from(*val),
+            Value::Str(val) => serde_json::Value::from(val.as_str()),
+            Value::InternedStr(val) => serde_json::Value::from(&**val),
+            Value::List(_, items) => items.iter().map(serde_json::Value::from).collect(),
+            // placeholders have no JSON counterpart, expressions are expected to be bound first
+            Value::Param(_) => serde_json::Value::Null,
+        }
+    }
+}
+
+/// Serialized as `{"type": "Int", "value": 42}`, so that the datatype survives formats
+/// that can't tell values of different types apart. List elements are serialized as plain values:
+/// `{"type": "List<Str>", "value": ["a", "b"]}`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Value {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(2))?;
+        map.serialize_entry("type", &self.datatype().to_string())?;
+        match self {
+            Value::Bool(val) => map.serialize_entry("value", val)?,
+            Value::Int(val) => map.serialize_entry("value", val)?,
+            Value::UInt(val) => map.serialize_entry("value", val)?,
+            Value::Float(val) => map.serialize_entry("value", val)?,
+            Value::Str(val) => map.serialize_entry("value", val)?,
+            Value::InternedStr(val) => map.serialize_entry("value", &**val)?,
+            Value::List(_, _) => map.serialize_entry("value", &serde_json::Value::from(self))?,
+            Value::Param(name) => map.serialize_entry("value", name)?,
         }
+        map.end()
     }
 }
 
@@ -31,7 +338,21 @@ impl Display for Value {
         match *self {
             Value::Bool(val) => write!(f, "{}", val),
             Value::Int(val) => write!(f, "{}", val),
+            Value::UInt(val) => write!(f, "{}", val),
+            Value::Float(val) => write!(f, "{}", val),
             Value::Str(ref val) => write!(f, "{}", val),
+            Value::InternedStr(ref val) => write!(f, "{}", val),
+            Value::List(_, ref items) => {
+                write!(f, "[")?;
+                for (idx, item) in items.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Param(ref name) => write!(f, "${}", name),
         }
     }
 }
@@ -0,0 +1,50 @@
+//! Fixture shared by integration tests and benches.
+
+use domain_query::domain;
+use domain_query::expression::Expression;
+use domain_query::value::Datatype;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use strum_macros::{EnumIter, EnumString};
+
+#[derive(PartialEq, Clone, Copy, Hash, Eq, Debug, EnumIter, EnumString)]
+pub enum Property {
+    Listeners,
+    Artist,
+    Released,
+}
+
+impl Display for Property {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl domain::DomainEnum for Property {}
+
+impl domain::Property for Property {
+    fn name(&self) -> &'static str {
+        match self {
+            Property::Listeners => "listeners",
+            Property::Artist => "artist",
+            Property::Released => "released",
+        }
+    }
+
+    fn datatype(&self) -> Datatype {
+        match self {
+            Property::Listeners => Datatype::Int,
+            Property::Artist => Datatype::Str,
+            Property::Released => Datatype::Bool,
+        }
+    }
+}
+
+pub fn expression() -> Expression<Property> {
+    let mut expr = Expression::new();
+    let a = expr.is_in(Property::Listeners, vec![100, 200, 300]).unwrap();
+    let b = expr.contains_ci(Property::Artist, "head").unwrap();
+    let c = expr.is(Property::Released, true).unwrap();
+    let a_or_b = expr.or(a, b).unwrap();
+    let _ = expr.and(a_or_b, c).unwrap();
+    expr
+}
@@ -1,6 +1,4 @@
-#![feature(associated_type_defaults)]
-
-use domain_query::{domain, value};
+use domain_query::{domain, error, value};
 use std::fmt::{Display, Formatter, Result as FmtResult};
 use strum_macros::{EnumIter, EnumString};
 
@@ -64,41 +62,139 @@ impl domain::Entity<Property> for Entity {
     fn properties(&self) -> &[Property] {
         Entity::PROPS
     }
+
+    fn relations(&self) -> &[(&'static str, &'static str)] {
+        match self {
+            Entity::Album => &[],
+            Entity::Track => &[("album", "Album")],
+        }
+    }
+}
+
+type Music = domain::Lookup<Property, Entity>;
+
+#[derive(PartialEq, Clone, Copy, Hash, Eq, Debug, EnumIter, EnumString)]
+enum Labeled {
+    AlbumListeners,
 }
 
-//type Music = domain::Domain<Property, Entity>;
-
-//#[test]
-//fn domain_property() {
-//    let alname = Music::property("AlbumName");
-//    assert!(alname.is_ok());
-//    assert_eq!(alname.unwrap(), Property::AlbumName);
-//}
-
-//#[test]
-//fn domain_property_notfound() {
-//    let notf = Music::property("Notfound");
-//    assert!(notf.is_err());
-//    match notf.err().unwrap() {
-//        error::Error::IdentifierNotFound(_) => {},
-//        _ => panic!("Unexpected error type"),
-//    };
-//}
-
-//#[test]
-//fn domain_entity() {
-//    let track = Music::entity("Track");
-//    assert!(track.is_ok());
-//    assert_eq!(track.unwrap(), Entity::Track);
-//}
-
-//#[test]
-//fn domain_entity_notfound() {
-//    let notf = Music::entity("Notfound");
-//    assert!(notf.is_err());
-//    match notf.err().unwrap() {
-//        error::Error::IdentifierNotFound(_) => {},
-//        _ => panic!("Unexpected error type"),
-//    };
-//}
+impl Display for Labeled {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "whatever")
+    }
+}
 
+impl domain::DomainEnum for Labeled {}
+
+impl domain::Property for Labeled {
+    fn name(&self) -> &'static str {
+        "album_listeners"
+    }
+
+    fn datatype(&self) -> value::Datatype {
+        value::Datatype::Int
+    }
+
+    fn label(&self) -> &'static str {
+        "Album listeners"
+    }
+
+    fn description(&self) -> Option<&'static str> {
+        Some("Number of unique listeners of the album")
+    }
+}
+
+#[test]
+fn domain_property() {
+    let alname = Music::property("AlbumName");
+    assert!(alname.is_ok());
+    assert_eq!(alname.unwrap(), Property::AlbumName);
+}
+
+#[test]
+fn domain_property_notfound() {
+    let notf = Music::property("Notfound");
+    assert!(notf.is_err());
+    match notf.err().unwrap() {
+        error::Error::IdentifierNotFound(_) => {},
+        _ => panic!("Unexpected error type"),
+    };
+}
+
+#[test]
+fn domain_entity() {
+    let track = Music::entity("Track");
+    assert!(track.is_ok());
+    assert_eq!(track.unwrap(), Entity::Track);
+}
+
+#[test]
+fn domain_entity_notfound() {
+    let notf = Music::entity("Notfound");
+    assert!(notf.is_err());
+    match notf.err().unwrap() {
+        error::Error::IdentifierNotFound(_) => {},
+        _ => panic!("Unexpected error type"),
+    };
+}
+
+#[test]
+fn domain_relation() {
+    let album = Music::relation(Entity::Track, "album");
+    assert!(album.is_ok());
+    assert_eq!(album.unwrap(), Entity::Album);
+}
+
+#[test]
+fn domain_relation_notfound() {
+    for (entity, name) in &[(Entity::Track, "artist"), (Entity::Album, "album")] {
+        let notf = Music::relation(*entity, name);
+        assert!(notf.is_err());
+        match notf.err().unwrap() {
+            error::Error::IdentifierNotFound(_) => {},
+            _ => panic!("Unexpected error type"),
+        };
+    }
+}
+
+#[test]
+fn property_metadata_defaults() {
+    use domain::Property as _;
+
+    assert_eq!(Property::AlbumName.label(), "property");
+    assert_eq!(Property::AlbumName.description(), None);
+}
+
+#[test]
+fn property_metadata_overridden() {
+    use domain::Property as _;
+
+    assert_eq!(Labeled::AlbumListeners.name(), "album_listeners");
+    assert_eq!(Labeled::AlbumListeners.label(), "Album listeners");
+    assert_eq!(Labeled::AlbumListeners.description(), Some("Number of unique listeners of the album"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn domain_schema() {
+    let schema = domain::schema::<Property, Entity>();
+    assert_eq!(schema.entities.len(), 2);
+    assert_eq!(schema.properties.len(), 7);
+
+    let json = serde_json::to_value(&schema).unwrap();
+    assert_eq!(json["entities"].as_array().unwrap().len(), 2);
+    assert_eq!(json["properties"].as_array().unwrap().len(), 7);
+    assert_eq!(json["entities"][0], serde_json::json!({
+        "id": "Album",
+        "name": "entity",
+        "properties": ["AlbumName", "AlbumArtist", "AlbumReleaseDate"],
+    }));
+    assert_eq!(json["properties"][3], serde_json::json!({
+        "id": "AlbumListeners",
+        "name": "property",
+        "datatype": "Int",
+        "label": "property",
+        "description": null,
+        "unit": null,
+    }));
+}
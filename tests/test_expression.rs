@@ -0,0 +1,66 @@
+mod common;
+
+use common::{expression, Property};
+use domain_query::expression::{Context, Evaluated};
+use domain_query::value::Value;
+
+fn context(values: Vec<(Property, Value)>) -> Context<Property> {
+    Context::from_values(values).unwrap()
+}
+
+#[test]
+fn resume_multiple_rounds() {
+    let expr = expression();
+
+    let first = expr.eval(&context(vec![(Property::Listeners, Value::Int(5))])).unwrap();
+    assert!(matches!(first, Evaluated::Partially(_)));
+
+    let second = first.resume(&context(vec![(Property::Released, Value::Bool(true))])).unwrap();
+    assert!(matches!(second, Evaluated::Partially(_)));
+    assert_eq!(second.unresolved_variables(), vec![Property::Artist]);
+
+    let third = second
+        .resume(&context(vec![(Property::Artist, Value::from("Radiohead"))]))
+        .unwrap();
+    let all = expr
+        .eval(&context(vec![
+            (Property::Listeners, Value::Int(5)),
+            (Property::Released, Value::Bool(true)),
+            (Property::Artist, Value::from("Radiohead")),
+        ]))
+        .unwrap();
+    assert_eq!(third.as_bool(), Some(true));
+    assert_eq!(third.as_bool(), all.as_bool());
+}
+
+#[test]
+fn resume_folded_property() {
+    let expr = expression();
+    let partial = expr.eval(&context(vec![(Property::Released, Value::Bool(true))])).unwrap();
+
+    // `Released` already folded, a different value for it doesn't change anything
+    let resumed = partial
+        .resume(&context(vec![
+            (Property::Released, Value::Bool(false)),
+            (Property::Listeners, Value::Int(200)),
+        ]))
+        .unwrap();
+    assert_eq!(resumed.as_bool(), Some(true));
+}
+
+#[test]
+fn resume_fully_evaluated() {
+    let expr = expression();
+    let evaluated = expr.eval(&context(vec![(Property::Released, Value::Bool(false))])).unwrap();
+    assert_eq!(evaluated.as_bool(), Some(false));
+
+    let resumed = evaluated
+        .resume(&context(vec![(Property::Released, Value::Bool(true))]))
+        .unwrap();
+    assert_eq!(resumed.as_bool(), Some(false));
+
+    // nothing new, still partial
+    let partial = expr.eval(&Context::empty()).unwrap();
+    let resumed = partial.resume(&Context::empty()).unwrap();
+    assert_eq!(resumed.missing().len(), 3);
+}